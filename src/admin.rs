@@ -1,76 +1,199 @@
-use axum::{extract::{Query, State, Extension}, response::{IntoResponse, Sse, sse::Event}, Json};
+use axum::{extract::{Query, State, Extension}, response::{IntoResponse, Response, Sse, sse::Event}, Json};
 use serde::{Deserialize, Serialize};
-use sqlx::{SqlitePool, Row};
+use sqlx::{Row, SqlitePool};
 use tokio_stream::StreamExt;
+use std::collections::BTreeMap;
 use std::convert::Infallible;
-use log::error;
-use crate::config::AppConfig;
+use axum::http::StatusCode;
+use log::{error, info, warn};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use crate::{apps::admin::TunnelInfo, config::AppConfig, db::{self, UploadData}, error::ApiError, events, registry, notify::Notifiers, policy::{self, Policy}, tunnels::TunnelStats, upload::{ClientDirective, HeartbeatAction}};
 
 #[derive(Deserialize)]
 pub struct ListQuery {
-    page: Option<usize>,
-    q: Option<String>,
+    pub page: Option<usize>,
+    pub q: Option<String>,
+    /// One row per client or sender instead of one per upload
+    pub group: Option<Grouping>,
 }
 
-#[derive(Serialize)]
-pub struct UploadData {
-    pub id: i64,
-    pub filename: String,
-    pub size: i64,
-    pub status: String,
-    pub client_ip: String,
-    pub started_at: String,
-    pub updated_at: String,
-    pub completed_at: Option<String>,
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Grouping {
+    /// By client IP
+    Client,
+    /// By client IP and the name the sender gave
+    Sender,
 }
 
+/// `GET /data`: a page of uploads, most recently updated first, or with `group` a page of
+/// [`UploadGroup`]s.
 pub async fn admin_data(
     State(pool): State<SqlitePool>,
     Extension(config): Extension<AppConfig>,
     Query(params): Query<ListQuery>,
-) -> impl IntoResponse {
-    let page = params.page.unwrap_or(1).max(1);
+) -> Response {
+    let (page, q) = (params.page.unwrap_or(1), params.q.unwrap_or_default());
+    match params.group {
+        Some(grouping) => Json(list_upload_groups(&pool, &config, grouping, page, &q).await).into_response(),
+        None => Json(list_uploads(&pool, &config, page, &q).await).into_response(),
+    }
+}
+
+pub async fn list_uploads(pool: &SqlitePool, config: &AppConfig, page: usize, q: &str) -> Vec<UploadData> {
+    let page = page.max(1);
     let offset: i64 = ((page - 1) * config.default_page_size as usize) as i64;
-    let q = params.q.unwrap_or_default();
 
     let rows = if q.is_empty() {
         sqlx::query(
-            r#"SELECT id, filename, size, status, client_ip, started_at, updated_at, completed_at
-               FROM uploads ORDER BY updated_at DESC LIMIT ?1 OFFSET ?2"#)
+            &format!("SELECT {} FROM uploads ORDER BY updated_at DESC LIMIT ?1 OFFSET ?2", db::UPLOAD_COLUMNS))
             .bind(config.default_page_size)
             .bind(offset)
-            .fetch_all(&pool).await.unwrap_or_else(|e| {
+            .fetch_all(pool).await.unwrap_or_else(|e| {
                 error!("Database error in admin_data: {}", e);
                 Vec::new()
             })
     } else {
         sqlx::query(
-            r#"SELECT id, filename, size, status, client_ip, started_at, updated_at, completed_at
-               FROM uploads WHERE filename LIKE ?1 OR client_ip LIKE ?1
-               ORDER BY updated_at DESC LIMIT ?2 OFFSET ?3"#)
+            &format!(
+                "SELECT {} FROM uploads WHERE filename LIKE ?1 OR client_ip LIKE ?1 ORDER BY updated_at DESC LIMIT ?2 OFFSET ?3",
+                db::UPLOAD_COLUMNS))
             .bind(format!("%{}%", q))
             .bind(config.default_page_size)
             .bind(offset)
-            .fetch_all(&pool).await.unwrap_or_else(|e| {
+            .fetch_all(pool).await.unwrap_or_else(|e| {
                 error!("Database error in admin_data: {}", e);
                 Vec::new()
             })
     };
 
-    let out: Vec<serde_json::Value> = rows.into_iter().map(|r| {
-        serde_json::json!({
-            "id":           r.get::<i64, _>("id"),
-            "filename":     r.get::<String, _>("filename"),
-            "size":         r.get::<i64, _>("size"),
-            "status":       r.get::<String, _>("status"),
-            "client_ip":    r.get::<String, _>("client_ip"),
-            "started_at":   r.get::<String, _>("started_at"),
-            "updated_at":   r.get::<String, _>("updated_at"),
-            "completed_at": r.try_get::<String, _>("completed_at").ok(),
-        })
-    }).collect();
+    // Uploads in progress here are more current in memory than their last checkpoint
+    rows.iter()
+        .map(|row| registry::overlay(UploadData::from_row(row)))
+        .collect()
+}
 
-    Json(out)
+/// The uploads of one client, or one sender with `group=sender`.
+#[derive(Serialize)]
+pub struct UploadGroup {
+    pub client_ip: String,
+    /// Only with `group=sender`; `null` for uploads without a name
+    pub sender_name: Option<String>,
+    pub files: i64,
+    /// Bytes of the files, abandoned uploads aside
+    pub bytes: i64,
+    /// Files in each status, e.g. `{"complete": 3, "uploading": 1}`
+    pub statuses: BTreeMap<String, i64>,
+    pub first_started_at: String,
+    pub last_updated_at: String,
+}
+
+/// Groups with the most recent activity first; `q` filters the uploads like [`list_uploads`].
+/// Sizes of uploads in progress are as of their last checkpoint.
+pub async fn list_upload_groups(pool: &SqlitePool, config: &AppConfig, grouping: Grouping, page: usize, q: &str) -> Vec<UploadGroup> {
+    let offset: i64 = ((page.max(1) - 1) * config.default_page_size as usize) as i64;
+    let (key, sender_name) = match grouping {
+        Grouping::Client => ("client_ip", "NULL"),
+        Grouping::Sender => ("client_ip, sender_name", "sender_name"),
+    };
+    // Counted per status first, so the status summary can be one JSON object per group
+    let sql = format!(
+        r#"SELECT client_ip, {sender_name} AS sender_name, SUM(n) AS files, SUM(bytes) AS bytes,
+                  json_group_object(status, n) AS statuses, MIN(first_started_at) AS first_started_at, MAX(last_updated_at) AS last_updated_at
+           FROM (SELECT {key}, status, COUNT(*) AS n, SUM(CASE WHEN status != 'abandoned' THEN size ELSE 0 END) AS bytes,
+                        MIN(started_at) AS first_started_at, MAX(updated_at) AS last_updated_at
+                 FROM uploads WHERE ?1 = '' OR filename LIKE ?2 OR client_ip LIKE ?2
+                 GROUP BY {key}, status)
+           GROUP BY {key}
+           ORDER BY last_updated_at DESC LIMIT ?3 OFFSET ?4"#);
+    let rows = sqlx::query(&sql)
+        .bind(q)
+        .bind(format!("%{}%", q))
+        .bind(config.default_page_size)
+        .bind(offset)
+        .fetch_all(pool).await.unwrap_or_else(|e| {
+            error!("Database error in admin_data: {}", e);
+            Vec::new()
+        });
+    rows.iter().map(|row| UploadGroup {
+        client_ip: row.get("client_ip"),
+        sender_name: row.try_get::<Option<String>, _>("sender_name").ok().flatten(),
+        files: row.get("files"),
+        bytes: row.get("bytes"),
+        statuses: serde_json::from_str(row.get("statuses")).unwrap_or_default(),
+        first_started_at: row.get("first_started_at"),
+        last_updated_at: row.get("last_updated_at"),
+    }).collect()
+}
+
+#[derive(Serialize)]
+pub struct DashboardTunnel {
+    pub hostname: Option<String>,
+    pub url: Option<String>,
+    /// Missing when the provider has none or they couldn't be fetched
+    pub stats: Option<TunnelStats>,
+}
+
+/// The settings the admin page shows, as in effect.
+#[derive(Serialize)]
+pub struct DashboardSettings {
+    pub upload_dir: String,
+    pub max_file_size: u64,
+    pub chunk_size: u64,
+    pub page_size: i64,
+    pub finalize_required: bool,
+    pub policy: Policy,
+}
+
+#[derive(Serialize)]
+pub struct Dashboard {
+    pub uploads: Vec<UploadData>,
+    pub clients: Vec<serde_json::Value>,
+    pub tunnel: DashboardTunnel,
+    pub stats: db::UploadStats,
+    pub events: Vec<serde_json::Value>,
+    pub settings: DashboardSettings,
+}
+
+/// `GET /dashboard`: everything the admin page loads at startup in one document, i.e. the
+/// first page of `/data`, `/clients`, `/tunnel` with its stats and `/event-log`, plus totals
+/// and settings.
+pub async fn admin_dashboard(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(tunnel_info): Extension<Arc<RwLock<TunnelInfo>>>,
+) -> Json<Dashboard> {
+    let (hostname, url, stats_source) = {
+        let info = tunnel_info.read().await;
+        (info.hostname.clone(), info.url.clone(), info.stats.clone())
+    };
+    let tunnel_stats = match stats_source {
+        Some(source) => source.stats().await.ok(),
+        None => None,
+    };
+    Json(Dashboard {
+        uploads: list_uploads(&pool, &config, 1, "").await,
+        clients: db::get_connected_clients(&pool).await,
+        tunnel: DashboardTunnel { hostname, url, stats: tunnel_stats },
+        stats: db::get_upload_stats(&pool).await,
+        events: db::get_recent_events(&pool, 50).await,
+        settings: DashboardSettings {
+            upload_dir: config.upload_dir.clone(),
+            max_file_size: config.max_file_size,
+            chunk_size: config.chunk_size,
+            page_size: config.default_page_size,
+            finalize_required: config.require_finalize,
+            policy: policy::describe(&config),
+        },
+    })
+}
+
+/// `GET /stats`: upload totals.
+pub async fn admin_stats(
+    State(pool): State<SqlitePool>,
+) -> Json<db::UploadStats> {
+    Json(db::get_upload_stats(&pool).await)
 }
 
 pub async fn admin_clients(
@@ -80,6 +203,133 @@ pub async fn admin_clients(
     Json(clients)
 }
 
+#[derive(Deserialize)]
+pub struct DirectiveRequest {
+    pub client_ip: String,
+    #[serde(flatten)]
+    pub directive: ClientDirective,
+}
+
+pub async fn admin_client_directive(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+    Json(request): Json<DirectiveRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if let Some(chunk_size) = request.directive.chunk_size {
+        // The upload page recounts total_chunks when its chunk size changes
+        if config.strict_chunks {
+            return Err(ApiError::conflict("chunk_size_locked", "Changing the chunk size mid-upload changes total_chunks, which --strict-chunks rejects"));
+        }
+        if chunk_size == 0 || chunk_size > config.chunk_size {
+            return Err(ApiError::bad_request("invalid_chunk_size", format!("chunk_size must be between 1 and {} bytes", config.chunk_size)));
+        }
+    }
+
+    let directive = if request.directive.action == HeartbeatAction::Continue && request.directive.chunk_size.is_none() {
+        None
+    } else {
+        Some(serde_json::to_string(&request.directive).unwrap())
+    };
+
+    if !crate::db::set_client_directive(&pool, &request.client_ip, directive.as_deref()).await {
+        return Err(ApiError::not_found("client_not_found", format!("Unknown client: {}", request.client_ip)));
+    }
+    info!("📣 Directive for {}: {}", request.client_ip, directive.as_deref().unwrap_or("cleared"));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct ResumeRequest {
+    pub upload_id: i64,
+}
+
+#[derive(Serialize)]
+pub struct ResumeRequestResult {
+    pub resume_url: Option<String>,
+    pub emailed: bool,
+}
+
+/// Asks the sender of a disconnected upload to come back and finish it: their open upload
+/// pages get a `resume` message, and they get an email if they left an address.
+pub async fn admin_resume_request(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(notifiers): Extension<Notifiers>,
+    Extension(tunnel_info): Extension<Arc<RwLock<TunnelInfo>>>,
+    Json(request): Json<ResumeRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let upload = db::get_upload(&pool, request.upload_id).await
+        .ok_or_else(|| ApiError::not_found("upload_not_found", format!("Unknown upload: {}", request.upload_id)))?;
+    if upload.status != "disconnected" {
+        return Err(ApiError::conflict("upload_not_disconnected", format!("Upload {} is {}, not disconnected", upload.id, upload.status)));
+    }
+
+    let share_url = tunnel_info.read().await.url.clone().or(config.public_url.clone());
+    let resume_url = share_url.map(|url| format!("{}/?resume={}",
+        url.trim_end_matches('/'),
+        url::form_urlencoded::byte_serialize(upload.filename.as_bytes()).collect::<String>()));
+    db::mark_resume_requested(&pool, upload.id).await;
+    events::publish(events::Event::ResumeRequested {
+        id: upload.id,
+        filename: upload.filename.clone(),
+        client_ip: upload.client_ip.clone(),
+        resume_url: resume_url.clone(),
+    });
+
+    let mut emailed = false;
+    if let (Some(to), Some(mailer)) = (&upload.sender_email, notifiers.current().mailer()) {
+        let subject = format!("Your upload of {} was interrupted", upload.filename);
+        let body = format!(
+            "Your upload of {} stopped after {} bytes.\n\nOpen {} and select the file again to resume where it left off.\n",
+            upload.filename, upload.size, resume_url.as_deref().unwrap_or("the upload page"));
+        match mailer.send_to(to, &subject, &body).await {
+            Ok(()) => emailed = true,
+            Err(e) => warn!("⚠️ Failed to email {}: {}", to, e),
+        }
+    }
+    info!("📨 Asked {} to resume {}{}", upload.client_ip, upload.filename, if emailed { " (emailed)" } else { "" });
+    Ok(Json(ResumeRequestResult { resume_url, emailed }))
+}
+
+#[derive(Deserialize)]
+pub struct EventLogQuery {
+    limit: Option<i64>,
+}
+
+pub async fn admin_event_log(
+    State(pool): State<SqlitePool>,
+    Query(params): Query<EventLogQuery>,
+) -> impl IntoResponse {
+    let limit = params.limit.unwrap_or(50).clamp(1, 500);
+    Json(crate::db::get_recent_events(&pool, limit).await)
+}
+
+pub async fn admin_notifiers(
+    Extension(notifiers): Extension<Notifiers>,
+) -> impl IntoResponse {
+    Json(notifiers.current().statuses())
+}
+
+#[derive(Deserialize, Default)]
+pub struct NotifierTestRequest {
+    /// `index` from `GET /notifiers`; all of them when unset
+    pub index: Option<usize>,
+    pub message: Option<String>,
+}
+
+pub async fn admin_notifiers_test(
+    Extension(notifiers): Extension<Notifiers>,
+    request: Option<Json<NotifierTestRequest>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    let event = events::Event::Test { message: request.message.unwrap_or_else(|| "Test notification from drcv".to_string()) };
+    let results = notifiers.current().deliver(&event, request.index).await;
+    if results.is_empty() {
+        return Err(ApiError::not_found("no_notifiers", "No matching notifiers configured"));
+    }
+    Ok(Json(results))
+}
+
 pub async fn admin_events(
     State(pool): State<SqlitePool>,
 ) -> Sse<impl tokio_stream::Stream<Item = Result<Event, Infallible>>> {
@@ -90,7 +340,7 @@ pub async fn admin_events(
     use tokio::sync::Mutex;
     
     let last_check = Arc::new(Mutex::new(Utc::now().to_rfc3339()));
-    let interval_stream = IntervalStream::new(interval(Duration::from_secs(1)));
+    let interval_stream = IntervalStream::new(interval(Duration::from_millis(500)));
     
     let stream = interval_stream.then({
         let last_check = last_check.clone();
@@ -105,34 +355,24 @@ pub async fn admin_events(
                 *check_time_guard = current_time;
                 drop(check_time_guard);
                 
-                // 마지막 체크 이후 업데이트된 레코드들 조회
-                if let Ok(rows) = sqlx::query(
-                    r#"SELECT id, filename, size, status, client_ip, started_at, updated_at, completed_at
-                       FROM uploads 
-                       WHERE updated_at > ?1 
-                       ORDER BY updated_at ASC"#)
+                // 마지막 체크 이후 업데이트된 레코드들 조회 (상태 전환은 DB, 진행 중인 업로드는 메모리)
+                let mut updates: Vec<UploadData> = sqlx::query(&format!(
+                    "SELECT {} FROM uploads WHERE updated_at > ?1 ORDER BY updated_at ASC", db::UPLOAD_COLUMNS))
                     .bind(&check_time)
-                    .fetch_all(&pool).await {
-                    
-                    if !rows.is_empty() {
-                        let updates: Vec<UploadData> = rows.into_iter().map(|row| {
-                            UploadData {
-                                id: row.get("id"),
-                                filename: row.get("filename"),
-                                size: row.get("size"),
-                                status: row.get("status"),
-                                client_ip: row.get("client_ip"),
-                                started_at: row.get("started_at"),
-                                updated_at: row.get("updated_at"),
-                                completed_at: row.try_get("completed_at").ok(),
-                            }
-                        }).collect();
-                        
-                        return Ok(Event::default()
-                            .event("updates")
-                            .data(serde_json::to_string(&updates).unwrap()));
+                    .fetch_all(&pool).await
+                    .map(|rows| rows.iter().map(|row| registry::overlay(UploadData::from_row(row))).collect())
+                    .unwrap_or_default();
+                for live in registry::changed_since(&check_time) {
+                    if !updates.iter().any(|u| u.id == live.id) {
+                        updates.push(live);
                     }
                 }
+
+                if !updates.is_empty() {
+                    return Ok(Event::default()
+                        .event("updates")
+                        .data(serde_json::to_string(&updates).unwrap()));
+                }
                 
                 // 업데이트가 없으면 heartbeat
                 Ok(Event::default().data("heartbeat"))
@@ -0,0 +1,48 @@
+use once_cell::sync::OnceCell;
+use rand::{distributions::Alphanumeric, Rng};
+use sqlx::SqlitePool;
+use log::info;
+use crate::{config::AppConfig, db};
+
+/// Prefix of anonymized client identities, which also keeps them from being hashed twice.
+const PREFIX: &str = "anon-";
+
+static SALT: OnceCell<String> = OnceCell::new();
+
+/// Turns on `--anonymize-ips`: from here on clients are known by a salted hash of their
+/// address. The salt is kept in the database so identities, and with them resumable
+/// uploads, survive a restart. Rows stored before anonymization was turned on are rewritten.
+pub async fn init(pool: &SqlitePool, config: &AppConfig) {
+    if !config.anonymize_ips {
+        return;
+    }
+    let salt = match db::kv_get(pool, "ip_salt").await {
+        Some(salt) => salt,
+        None => {
+            let salt: String = rand::thread_rng().sample_iter(&Alphanumeric).take(32).map(char::from).collect();
+            db::kv_set(pool, "ip_salt", &salt).await;
+            salt
+        }
+    };
+    let _ = SALT.set(salt);
+
+    let raw_ips = db::get_raw_client_ips(pool, PREFIX).await;
+    for ip in &raw_ips {
+        db::rename_client_ip(pool, ip, &identity(ip.clone())).await;
+    }
+    if !raw_ips.is_empty() {
+        info!("🕶️ Anonymized {} stored client addresses", raw_ips.len());
+    }
+}
+
+/// What a client is stored and shown as: its address, or with `--anonymize-ips` a salted
+/// hash of it. The raw address is only used while handling the request (e.g. for ACLs).
+pub fn identity(ip: String) -> String {
+    use sha2::{Digest, Sha256};
+    let Some(salt) = SALT.get() else { return ip };
+    if ip.starts_with(PREFIX) {
+        return ip;
+    }
+    let digest = Sha256::new().chain_update(salt).chain_update(&ip).finalize();
+    format!("{}{}", PREFIX, &format!("{:x}", digest)[..12])
+}
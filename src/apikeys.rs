@@ -0,0 +1,118 @@
+use axum::{extract::{ConnectInfo, Extension, Request, State}, http::{header, Method, StatusCode}, middleware::Next, response::Response};
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::net::SocketAddr;
+use log::warn;
+use crate::{config::{ApiKeyAction, ApiKeyArgs, AppConfig}, db, error::ApiError};
+
+pub const SCOPES: [&str; 2] = ["read:data", "read:stats"];
+/// Keys start with this so they're easy to spot in configs and secret scanners.
+const PREFIX: &str = "drcv_";
+
+fn hash(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// Scope a key needs for a request; `None` for everything keys can't do (changes, pages).
+fn required_scope(method: &Method, path: &str) -> Option<&'static str> {
+    if method != Method::GET {
+        return None;
+    }
+    match path {
+        "/data" | "/search" | "/clients" | "/stats/clients" | "/dashboard" | "/uploads/missing" | "/uploads/moving" | "/events" | "/event-log" => Some("read:data"),
+        p if p.starts_with("/uploads/") && (p.ends_with("/file") || p.ends_with("/trace")) => Some("read:data"),
+        p if p == "/crashes" || p.starts_with("/crashes/") => Some("read:data"),
+        "/stats" | "/progress" | "/metrics/timeseries" | "/tunnel" | "/tunnel/stats" | "/integrations/hass" => Some("read:stats"),
+        _ => None,
+    }
+}
+
+fn presented_key(request: &Request) -> Option<&str> {
+    let headers = request.headers();
+    headers.get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+        .map(str::trim)
+}
+
+/// Admin requests from this machine are trusted as before, except with `--container`, where
+/// the orchestrator's port forwarding makes everything look local. Requests with an API key,
+/// and any from elsewhere (`--admin-bind`), may only read what the key's scopes allow;
+/// `--admin-token` may do anything.
+pub async fn authorize(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let Some(key) = presented_key(&request) else {
+        if addr.ip().is_loopback() && !config.container {
+            return Ok(next.run(request).await);
+        }
+        return Err(ApiError::new(StatusCode::UNAUTHORIZED, "api_key_required", "An API key is required (Authorization: Bearer <key>)"));
+    };
+    // Compared as hashes, so response times don't tell how much of the token was right
+    if config.admin_token.as_deref().is_some_and(|token| hash(token) == hash(key)) {
+        return Ok(next.run(request).await);
+    }
+    let Some(api_key) = db::find_api_key(&pool, &hash(key)).await else {
+        warn!("🔑 Rejected an unknown API key from {}", addr.ip());
+        return Err(ApiError::new(StatusCode::UNAUTHORIZED, "api_key_unknown", "Unknown API key"));
+    };
+    let allowed = required_scope(request.method(), request.uri().path())
+        .is_some_and(|scope| api_key.scopes.split(',').any(|s| s == scope));
+    if !allowed {
+        return Err(ApiError::new(StatusCode::FORBIDDEN, "api_key_scope", format!("API key {} may not {} {}", api_key.name, request.method(), request.uri().path())));
+    }
+    db::touch_api_key(&pool, api_key.id).await;
+    Ok(next.run(request).await)
+}
+
+/// With `--container` every admin request needs a key; warns at startup when there's none.
+pub async fn check_container_access(pool: &SqlitePool, config: &AppConfig) {
+    if config.container && config.admin_token.is_none() && db::get_api_keys(pool).await.is_empty() {
+        warn!("🔑 The admin API is locked: set DRCV_ADMIN_TOKEN or create a key with `drcv api-key create`");
+    }
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("❌ {}", message);
+    std::process::exit(1);
+}
+
+/// `drcv api-key create|list|revoke`.
+pub async fn run(args: &ApiKeyArgs, config: &AppConfig) {
+    let pool = db::init_pool(config).await.unwrap_or_else(|e| fail(format!("Failed to open {}: {}", db::DB_FILE, e)));
+    match &args.action {
+        ApiKeyAction::Create { name, scope } => {
+            if let Some(unknown) = scope.iter().find(|s| !SCOPES.contains(&s.as_str())) {
+                fail(format!("Unknown scope {}; available: {}", unknown, SCOPES.join(", ")));
+            }
+            let secret: String = rand::thread_rng().sample_iter(&Alphanumeric).take(32).map(char::from).collect();
+            let key = format!("{}{}", PREFIX, secret);
+            db::insert_api_key(&pool, name, &hash(&key), &scope.join(",")).await
+                .unwrap_or_else(|e| fail(format!("Failed to create key {}: {}", name, e)));
+            println!("✅ Created API key {} ({})", name, scope.join(", "));
+            println!("   {}", key);
+            println!("💡 Store it now, it can't be shown again. Send it as `Authorization: Bearer <key>`.");
+        }
+        ApiKeyAction::List => {
+            let keys = db::get_api_keys(&pool).await;
+            if keys.is_empty() {
+                println!("No API keys. Create one with `drcv api-key create <name> --scope read:stats`.");
+            }
+            for key in keys {
+                println!("🔑 {}  {}  created {}  last used {}", key.name, key.scopes, key.created_at, key.last_used_at.as_deref().unwrap_or("never"));
+            }
+        }
+        ApiKeyAction::Revoke { name } => {
+            if db::delete_api_key(&pool, name).await == 0 {
+                fail(format!("No API key named {}", name));
+            }
+            println!("✅ Revoked API key {}", name);
+        }
+    }
+}
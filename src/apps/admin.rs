@@ -1,35 +1,93 @@
-use axum::{routing::get, Router, Extension};
+use axum::{middleware, routing::{get, post, put, delete}, Router, Extension};
 use sqlx::SqlitePool;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tokio::net::TcpListener;
-use crate::{admin, config::AppConfig};
+use crate::{admin, apikeys, bandwidth, basic, config::AppConfig, crash, download, error::{self, ApiError}, etag, fairness, hass, health, links, mover, notify::Notifiers, page, progress, purge, reload, resume_report, search, timeseries, trace, tunnels::{TunnelStatsSource, TunnelSupervisor}};
 
 #[derive(Clone)]
 pub struct TunnelInfo {
     pub hostname: Option<String>,
+    pub url: Option<String>,
+    pub stats: Option<Arc<dyn TunnelStatsSource>>,
 }
 
-pub async fn create_app(pool: &SqlitePool, config: &AppConfig, tunnel_info: &Arc<RwLock<TunnelInfo>>, shutdown_tx: &tokio::sync::broadcast::Sender<()>) -> tokio::task::JoinHandle<()> {
+pub async fn create_app(pool: &SqlitePool, config: &AppConfig, tunnel_info: &Arc<RwLock<TunnelInfo>>, tunnel: &Arc<TunnelSupervisor>, notifiers: &Notifiers, shutdown_tx: &tokio::sync::broadcast::Sender<()>) -> tokio::task::JoinHandle<()> {
     let router = Router::new()
         .route("/", get(|| async {
             axum::response::Html(include_str!("../static/admin.html"))
         }))
-        .route("/data", get(admin::admin_data))
-        .route("/clients", get(admin::admin_clients))
+        .route("/basic", get(basic::basic_uploads))
+        .route("/basic/clients", get(basic::basic_clients))
+        .route("/dashboard", get(admin::admin_dashboard).layer(middleware::from_fn(etag::conditional)))
+        .route("/data", get(admin::admin_data).layer(middleware::from_fn(etag::conditional)))
+        .route("/clients", get(admin::admin_clients).layer(middleware::from_fn(etag::conditional)))
+        .route("/stats", get(admin::admin_stats).layer(middleware::from_fn(etag::conditional)))
+        .route("/stats/clients", get(fairness::admin_client_shares))
+        .route("/progress", get(progress::handle_progress))
+        .route("/clients/directive", post(admin::admin_client_directive))
+        .route("/clients/:ip/data", delete(purge::admin_purge_client))
+        .route("/uploads/resume-request", post(admin::admin_resume_request))
+        .route("/uploads/missing", get(resume_report::admin_missing))
+        .route("/uploads/moving", get(mover::admin_moving))
+        .route("/uploads/:id/file", get(download::admin_download))
+        .route("/uploads/:id/trace", get(trace::admin_trace))
+        .route("/search", get(search::admin_search))
         .route("/tunnel", get({
             let tunnel_info = Arc::clone(tunnel_info);
             move |_: axum::extract::State<SqlitePool>| async move {
                 let info = tunnel_info.read().await;
-                axum::Json(serde_json::json!({ "hostname": info.hostname }))
+                axum::Json(serde_json::json!({ "hostname": info.hostname, "url": info.url }))
             }
         }))
+        .route("/tunnel/stats", get({
+            let tunnel_info = Arc::clone(tunnel_info);
+            move || async move {
+                let source = tunnel_info.read().await.stats.clone();
+                let Some(source) = source else {
+                    return Err(ApiError::not_found("no_tunnel_stats", "No tunnel statistics available"));
+                };
+                source.stats().await
+                    .map(axum::Json)
+                    .map_err(|e| ApiError::new(axum::http::StatusCode::BAD_GATEWAY, "tunnel_error", e.to_string()))
+            }
+        }).layer(middleware::from_fn(etag::conditional)))
+        .route("/tunnel/rotate", post({
+            let tunnel = Arc::clone(tunnel);
+            move || async move {
+                tunnel.rotate().await
+                    .map(|hostname| axum::Json(serde_json::json!({ "hostname": hostname })))
+                    .map_err(|e| ApiError::new(axum::http::StatusCode::BAD_GATEWAY, "tunnel_error", e.to_string()))
+            }
+        }))
+        .route("/page", get(page::admin_page))
+        .route("/page", put(page::admin_update_page))
+        .route("/bandwidth", get(bandwidth::admin_bandwidth))
+        .route("/bandwidth", put(bandwidth::admin_update_bandwidth))
+        .route("/links", get(links::admin_links).post(links::admin_create_link))
+        .route("/links/:id", delete(links::admin_revoke_link))
+        .route("/metrics/timeseries", get(timeseries::handle_timeseries).layer(middleware::from_fn(etag::conditional)))
+        .route("/integrations/hass", get(hass::handle_state))
         .route("/events", get(admin::admin_events))
+        .route("/event-log", get(admin::admin_event_log))
+        .route("/notifiers", get(admin::admin_notifiers))
+        .route("/notifiers/test", post(admin::admin_notifiers_test))
+        .route("/reload", post(reload::admin_reload))
+        .route("/crashes", get(crash::admin_crashes))
+        .route("/crashes/:id", get(crash::admin_crash))
+        .route("/crashes/dismiss", post(crash::admin_dismiss_crashes))
+        .layer(middleware::from_fn_with_state(pool.clone(), apikeys::authorize))
+        .route("/healthz", get(health::handle_healthz))
+        .layer(middleware::from_fn(error::envelope))
+        .layer(middleware::from_fn(reload::latest))
         .layer(Extension(config.clone()))
+        .layer(Extension(notifiers.clone()))
+        .layer(Extension(Arc::clone(tunnel_info)))
         .with_state(pool.clone());
     
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", config.admin_port)).await.unwrap();
-    let service = router.into_make_service();
+    let listener = TcpListener::bind((config.admin_bind, config.admin_port)).await
+        .unwrap_or_else(|e| crate::guard::exit_on_bind_error(config.admin_port, "Admin", e));
+    let service = router.into_make_service_with_connect_info::<std::net::SocketAddr>();
     
     let mut shutdown_rx = shutdown_tx.subscribe();
     tokio::spawn(async move {
@@ -1,26 +1,62 @@
-use axum::{routing::{get, post, head}, Router, Extension};
+use axum::{routing::{get, patch, post}, Router, Extension};
 use sqlx::SqlitePool;
-use tokio::net::TcpListener;
 use std::net::SocketAddr;
-use crate::{upload, config::AppConfig};
+use crate::{bandwidth, chaos, compression, consent, error, finalize, guard, health, limits, links, net, protocol, reload, signing, status, trace, upload, config::AppConfig};
 
-pub async fn create_app(pool: &SqlitePool, config: &AppConfig, shutdown_tx: &tokio::sync::broadcast::Sender<()>) -> tokio::task::JoinHandle<()> {
-    let router = Router::new()
+pub fn create_router(pool: &SqlitePool, config: &AppConfig) -> Router {
+    Router::new()
         .route("/", get(|| async {
             axum::response::Html(include_str!("../static/index.html"))
         }))
-        .route("/upload", post(upload::handle_chunk_upload))
-        .route("/upload", head(upload::handle_upload_head))
+        .route("/upload", post(upload::handle_chunk_upload)
+            .layer(axum::middleware::from_fn(signing::sign))
+            .layer(axum::middleware::from_fn(chaos::disturb))
+            .head(upload::handle_upload_head)
+            .delete(upload::handle_upload_cancel)
+            .layer(axum::middleware::from_fn_with_state(pool.clone(), trace::record)))
+        .route("/upload/finalize", post(finalize::handle_finalize)
+            .layer(axum::middleware::from_fn(signing::sign))
+            .layer(axum::middleware::from_fn_with_state(pool.clone(), trace::record)))
+        .route("/files", post(upload::handle_tus_create)
+            .options(upload::handle_tus_options)
+            .layer(axum::middleware::from_fn(upload::tus_protocol))
+            .layer(axum::middleware::from_fn_with_state(pool.clone(), trace::record)))
+        .route("/files/:id", patch(upload::handle_tus_patch)
+            .head(upload::handle_tus_head)
+            .delete(upload::handle_tus_terminate)
+            .layer(axum::middleware::from_fn(upload::tus_protocol))
+            .layer(axum::middleware::from_fn_with_state(pool.clone(), trace::record)))
+        .route("/.well-known/drcv.json", get(signing::handle_well_known))
         .route("/heartbeat", post(upload::handle_heartbeat))
+        .route("/notifications", get(upload::handle_notifications))
+        .route("/capabilities", get(protocol::handle_capabilities))
+        .route("/terms", get(consent::handle_terms))
+        .route("/consent", post(consent::handle_consent))
+        .route("/u/:token", get(links::handle_link_page))
+        .route("/status", get(status::handle_status_page))
+        .route("/status/data", get(status::handle_status_data))
+        .route("/my/uploads", get(status::handle_my_uploads))
         .layer(axum::extract::DefaultBodyLimit::max({
             let overhead: u64 = 1024 * 1024; // 1 MiB
             let max = config.chunk_size.saturating_add(overhead);
             max as usize
         }))
+        .layer(axum::middleware::from_fn(compression::decompress_body))
+        .layer(axum::middleware::from_fn(bandwidth::throttle))
+        .layer(axum::middleware::from_fn(net::enforce_acl))
+        .layer(axum::middleware::from_fn(limits::check_headers))
+        // Probes come from the orchestrator, which --allow-cidr may not cover
+        .route("/healthz", get(health::handle_healthz))
+        .layer(axum::middleware::from_fn(error::envelope))
+        .layer(axum::middleware::from_fn(reload::latest))
         .layer(Extension(config.clone()))
-        .with_state(pool.clone());
-    
-    let listener = TcpListener::bind(format!("0.0.0.0:{}", config.upload_port)).await.unwrap();
+        .with_state(pool.clone())
+}
+
+pub async fn create_app(pool: &SqlitePool, config: &AppConfig, shutdown_tx: &tokio::sync::broadcast::Sender<()>) -> tokio::task::JoinHandle<()> {
+    let router = create_router(pool, config);
+    let listener = net::bind_dual_stack(config.upload_port)
+        .unwrap_or_else(|e| guard::exit_on_bind_error(config.upload_port, "Upload", e));
     let service = router.into_make_service_with_connect_info::<SocketAddr>();
     
     let mut shutdown_rx = shutdown_tx.subscribe();
@@ -0,0 +1,181 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use rand::{distributions::Alphanumeric, Rng};
+use sqlx::SqlitePool;
+use tokio::io::AsyncWriteExt;
+use log::{warn, error};
+use crate::{coalescer, db, finalize, registry, sessions, config::AppConfig, error::ApiError, upload::ChunkUploadRequest};
+
+pub fn chunk_dir(spool_dir: &str, id: i64) -> PathBuf {
+    PathBuf::from(spool_dir).join(id.to_string())
+}
+
+pub fn chunk_path(dir: &Path, index: u32) -> PathBuf {
+    dir.join(format!("{:08}.chunk", index))
+}
+
+/// Stores one chunk as its own spool file; any instance can accept any chunk.
+pub async fn spool_chunk(pool: &SqlitePool, spool_dir: &str, id: i64, upload_data: &ChunkUploadRequest, wire_bytes: i64) -> Result<(), ApiError> {
+    let dir = chunk_dir(spool_dir, id);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| ApiError::internal("storage_error", format!("Failed to create spool directory: {}", e)))?;
+
+    let path = chunk_path(&dir, upload_data.chunk_index);
+    let previous_len = tokio::fs::metadata(&path).await.map(|m| m.len() as i64).unwrap_or(0);
+    // A name of its own, so concurrent retries of the chunk don't write into each other's file
+    let suffix: String = rand::thread_rng().sample_iter(&Alphanumeric).take(8).map(char::from).collect();
+    let tmp_path = path.with_extension(format!("chunk.{}.tmp", suffix));
+    let stored = match tokio::fs::write(&tmp_path, &upload_data.chunk.contents).await {
+        Ok(()) => tokio::fs::rename(&tmp_path, &path).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = stored {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        return Err(ApiError::internal("storage_error", format!("Failed to store chunk: {}", e)));
+    }
+
+    registry::record_progress(pool, id, upload_data.chunk.contents.len() as i64 - previous_len, wire_bytes).await;
+
+    if upload_data.chunk_index + 1 == upload_data.total_chunks {
+        // Checked once the chunks are assembled
+        if let Some(sha256) = &upload_data.sha256 {
+            db::set_sha256(pool, id, &sha256.trim().to_ascii_lowercase()).await;
+        }
+        coalescer::flush(pool).await;
+        db::mark_assembling(pool, id, upload_data.total_chunks).await;
+        registry::finish(id);
+    }
+    Ok(())
+}
+
+/// Total size of the chunks spooled so far for an upload.
+pub async fn spooled_bytes(spool_dir: &str, id: i64) -> i64 {
+    spooled_bytes_in(&chunk_dir(spool_dir, id)).await as i64
+}
+
+/// Lowest chunk index not spooled yet for an upload.
+pub async fn first_missing_chunk(spool_dir: &str, id: i64) -> u32 {
+    let dir = chunk_dir(spool_dir, id);
+    let mut index = 0;
+    while tokio::fs::try_exists(chunk_path(&dir, index)).await.unwrap_or(false) {
+        index += 1;
+    }
+    index
+}
+
+/// Indexes of the chunks spooled so far for an upload.
+pub async fn spooled_chunks(spool_dir: &str, id: i64) -> HashSet<u32> {
+    let mut indexes = HashSet::new();
+    let Ok(mut entries) = tokio::fs::read_dir(chunk_dir(spool_dir, id)).await else { return indexes };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "chunk") {
+            indexes.extend(path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse::<u32>().ok()));
+        }
+    }
+    indexes
+}
+
+/// Merges spooled chunks of uploads whose final chunk has arrived. Run by a single instance.
+pub async fn assemble_spooled_uploads(pool: &SqlitePool, config: &AppConfig) {
+    let Some(spool_dir) = &config.chunk_spool_dir else { return };
+
+    for (id, filename, total_chunks) in db::get_assembling_uploads(pool).await {
+        let dir = chunk_dir(spool_dir, id);
+        let mut missing = 0;
+        for index in 0..total_chunks {
+            if tokio::fs::metadata(chunk_path(&dir, index)).await.is_err() {
+                missing += 1;
+            }
+        }
+        if missing > 0 {
+            warn!("⏳ Waiting for {} of {} chunks before assembling {}", missing, total_chunks, filename);
+            continue;
+        }
+
+        let assembled = match assemble(&dir, config, id, &filename, total_chunks).await {
+            Ok(()) if !verified(pool, config, id, &filename).await => Ok(()),
+            Ok(()) if config.require_finalize => {
+                finalize::await_confirmation(pool, id, &filename).await;
+                Ok(())
+            }
+            Ok(()) => finalize::complete(pool, config, id, &filename).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = assembled {
+            error!("Failed to assemble {}: {}", filename, e);
+        }
+    }
+}
+
+/// Whether an assembled upload matches the SHA-256 sent with its final chunk, if any; one
+/// that doesn't is discarded.
+async fn verified(pool: &SqlitePool, config: &AppConfig, id: i64, filename: &str) -> bool {
+    let Some(expected) = db::get_upload(pool, id).await.and_then(|upload| upload.sha256) else { return true };
+    finalize::verify(pool, config, id, filename, &expected).await.is_ok()
+}
+
+/// Concatenates the spooled chunks into the upload's `.part` file, through a memory map
+/// from `--mmap-assembly-threshold` on.
+async fn assemble(dir: &Path, config: &AppConfig, id: i64, filename: &str, total_chunks: u32) -> std::io::Result<()> {
+    let tmp_path = sessions::part_path(config.scratch_dir(), id, filename).await?;
+    let threshold = config.mmap_assembly_threshold;
+    if threshold > 0 && spooled_bytes_in(dir).await >= threshold {
+        concat_mmap(dir.to_path_buf(), tmp_path, total_chunks).await
+    } else {
+        concat_buffered(dir, &tmp_path, total_chunks).await
+    }
+}
+
+async fn spooled_bytes_in(dir: &Path) -> u64 {
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else { return 0 };
+    let mut total = 0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.path().extension().is_some_and(|ext| ext == "chunk") {
+            total += entry.metadata().await.map(|m| m.len()).unwrap_or(0);
+        }
+    }
+    total
+}
+
+pub async fn concat_buffered(dir: &Path, out_path: &Path, total_chunks: u32) -> std::io::Result<()> {
+    let mut out = tokio::fs::File::create(out_path).await?;
+    for index in 0..total_chunks {
+        let mut chunk = tokio::fs::File::open(chunk_path(dir, index)).await?;
+        tokio::io::copy(&mut chunk, &mut out).await?;
+    }
+    out.flush().await
+}
+
+/// Sizes the output up front, maps it and reads each chunk straight into the mapping, so no
+/// bytes pass through an intermediate buffer. The kernel is told the writes are sequential,
+/// letting it write pages back early instead of holding the whole file in the page cache.
+pub async fn concat_mmap(dir: PathBuf, out_path: PathBuf, total_chunks: u32) -> std::io::Result<()> {
+    tokio::task::spawn_blocking(move || {
+        use std::io::Read;
+        let chunks = (0..total_chunks)
+            .map(|index| {
+                let path = chunk_path(&dir, index);
+                std::fs::metadata(&path).map(|m| (path, m.len() as usize))
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let total: usize = chunks.iter().map(|(_, len)| len).sum();
+        let out = std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&out_path)?;
+        out.set_len(total as u64)?;
+        if total == 0 {
+            return Ok(());
+        }
+        // SAFETY: the .part file belongs to this upload's session and nothing else opens or
+        // resizes it until assembly has finished.
+        let mut map = unsafe { memmap2::MmapMut::map_mut(&out)? };
+        #[cfg(unix)]
+        map.advise(memmap2::Advice::Sequential)?;
+        let mut offset = 0;
+        for (path, len) in chunks {
+            std::fs::File::open(&path)?.read_exact(&mut map[offset..offset + len])?;
+            offset += len;
+        }
+        map.flush_async()
+    }).await.map_err(std::io::Error::other)?
+}
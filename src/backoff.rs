@@ -0,0 +1,39 @@
+use std::time::Duration;
+use axum::http::StatusCode;
+use rand::Rng;
+use crate::{config::AppConfig, error::ApiError, fairness, registry};
+
+/// Bounds on a computed Retry-After, in seconds
+const MIN_RETRY_AFTER: u64 = 2;
+const MAX_RETRY_AFTER: u64 = 600;
+
+/// A 503 telling the sender when to come back, in `Retry-After` and in `details.retry_after`
+/// for the page, which waits that long and tries again.
+pub fn retry_later(code: &'static str, message: String, retry_after: u64) -> ApiError {
+    ApiError::new(StatusCode::SERVICE_UNAVAILABLE, code, message)
+        .with_details(serde_json::json!({ "retry_after": retry_after }))
+        .retry_after(retry_after)
+}
+
+/// Refusal of a new upload while the server is under load, with a wait derived from it.
+pub fn busy(config: &AppConfig, message: String) -> ApiError {
+    retry_later("server_busy", message, estimate(config))
+}
+
+/// Seconds until there should be room for another upload: the time for the first upload in
+/// progress to finish at its current speed, or to work through the queued chunks at the
+/// combined receive rate, whichever is longer. With neither to go by it is
+/// `pressure_retry_after`. Up to a fifth is added at random so refused senders don't all
+/// come back at once.
+pub fn estimate(config: &AppConfig) -> u64 {
+    let (speed_bps, soonest) = registry::throughput();
+    let queued = fairness::queued();
+    let drain = (queued > 0 && speed_bps > 0.0)
+        .then(|| Duration::from_secs_f64(queued as f64 * config.chunk_size as f64 / speed_bps));
+    let base = match soonest.max(drain) {
+        Some(wait) => wait.as_secs().max(MIN_RETRY_AFTER),
+        None => config.pressure_retry_after,
+    };
+    let jitter = rand::thread_rng().gen_range(0..=base / 5);
+    (base + jitter).clamp(MIN_RETRY_AFTER, MAX_RETRY_AFTER)
+}
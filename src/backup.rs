@@ -0,0 +1,136 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Row};
+use crate::{config::{AppConfig, BackupArgs, RestoreArgs}, db::DB_FILE, doctor, guard, utils};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    created_at: String,
+    drcv_version: String,
+    upload_dir: String,
+    files: Vec<ManifestFile>,
+}
+
+/// A completed upload, or an external file, as stored in the upload directory.
+#[derive(Serialize, Deserialize)]
+struct ManifestFile {
+    id: i64,
+    name: String,
+    size: i64,
+    completed_at: Option<String>,
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("❌ {}", message);
+    std::process::exit(1);
+}
+
+/// `drcv backup <DIR>`: writes a consistent snapshot of the database and a manifest of the
+/// completed files to DIR. Safe while drcv is serving; the files themselves are left for
+/// rsync or similar.
+pub async fn backup(args: &BackupArgs, config: &AppConfig) {
+    if !Path::new(DB_FILE).exists() {
+        fail(format!("No {} in the current directory", DB_FILE));
+    }
+    let dir = PathBuf::from(&args.path);
+    if std::fs::read_dir(&dir).is_ok_and(|mut entries| entries.next().is_some()) {
+        fail(format!("{} exists and isn't empty", dir.display()));
+    }
+    std::fs::create_dir_all(&dir).unwrap_or_else(|e| fail(format!("Failed to create {}: {}", dir.display(), e)));
+
+    let options = SqliteConnectOptions::from_str(&format!("sqlite:{}", DB_FILE))
+        .unwrap_or_else(|e| fail(format!("Failed to open {}: {}", DB_FILE, e)))
+        .read_only(true);
+    let mut conn = options.connect().await.unwrap_or_else(|e| fail(format!("Failed to open {}: {}", DB_FILE, e)));
+
+    // VACUUM INTO copies a consistent snapshot through SQLite itself, so concurrent writers are fine
+    let snapshot = dir.join(DB_FILE);
+    sqlx::query("VACUUM INTO ?1")
+        .bind(snapshot.display().to_string())
+        .execute(&mut conn).await
+        .unwrap_or_else(|e| fail(format!("Failed to snapshot the database: {}", e)));
+
+    // SELECT * so a database from before stored_name existed still works
+    let files: Vec<ManifestFile> = sqlx::query("SELECT * FROM uploads WHERE status IN ('complete', 'suspicious', 'external') ORDER BY id")
+        .fetch_all(&mut conn).await
+        .unwrap_or_else(|e| fail(format!("Failed to list uploads: {}", e)))
+        .iter()
+        .map(|row| ManifestFile {
+            id: row.get("id"),
+            name: row.try_get::<Option<String>, _>("stored_name").ok().flatten().unwrap_or_else(|| row.get("filename")),
+            size: row.get("size"),
+            completed_at: row.try_get::<Option<String>, _>("completed_at").ok().flatten(),
+        })
+        .collect();
+    let total: i64 = files.iter().map(|f| f.size).sum();
+    let count = files.len();
+
+    let manifest = Manifest {
+        created_at: utils::now(),
+        drcv_version: env!("CARGO_PKG_VERSION").to_string(),
+        upload_dir: config.upload_dir.clone(),
+        files,
+    };
+    let json = serde_json::to_string_pretty(&manifest).unwrap_or_default();
+    std::fs::write(dir.join(MANIFEST_FILE), json).unwrap_or_else(|e| fail(format!("Failed to write the manifest: {}", e)));
+
+    println!("✅ Backed up {} and a manifest of {} files ({} bytes) to {}", DB_FILE, count, total, dir.display());
+    println!("   Copy the files too, e.g. rsync -a {}/ <host>:<upload dir>/", config.upload_dir.trim_end_matches('/'));
+}
+
+/// `drcv restore <DIR>`: puts a backup's database in place, including the share hostname,
+/// and checks the upload directory holds the files the manifest lists.
+pub async fn restore(args: &RestoreArgs, config: &AppConfig) {
+    let dir = PathBuf::from(&args.path);
+    let snapshot = dir.join(DB_FILE);
+    let manifest: Manifest = std::fs::read_to_string(dir.join(MANIFEST_FILE))
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| fail(format!("Failed to read {} in {}: {}", MANIFEST_FILE, dir.display(), e)));
+    if !snapshot.exists() {
+        fail(format!("No {} in {}", DB_FILE, dir.display()));
+    }
+
+    // Exits if a drcv is serving from here
+    let _guard = guard::acquire(config);
+    if Path::new(DB_FILE).exists() {
+        if !args.force {
+            fail(format!("{} already exists; pass --force to replace it (it is kept as {}.bak)", DB_FILE, DB_FILE));
+        }
+        std::fs::rename(DB_FILE, format!("{}.bak", DB_FILE)).unwrap_or_else(|e| fail(format!("Failed to move {} aside: {}", DB_FILE, e)));
+    }
+    // A journal left by the old database must not be applied to the restored one
+    for suffix in ["-wal", "-shm", "-journal"] {
+        let _ = std::fs::remove_file(format!("{}{}", DB_FILE, suffix));
+    }
+    std::fs::copy(&snapshot, DB_FILE).unwrap_or_else(|e| fail(format!("Failed to copy {}: {}", snapshot.display(), e)));
+    println!("✅ Restored {} from the backup of {}", DB_FILE, manifest.created_at);
+
+    let mut missing = 0;
+    for file in &manifest.files {
+        let path = Path::new(&config.upload_dir).join(&file.name);
+        match std::fs::metadata(&path) {
+            Ok(meta) if meta.len() as i64 == file.size => {}
+            Ok(meta) => {
+                missing += 1;
+                println!("⚠️  {} is {} bytes, expected {}", path.display(), meta.len(), file.size);
+            }
+            Err(_) => {
+                missing += 1;
+                println!("⚠️  {} is missing", path.display());
+            }
+        }
+    }
+    if missing == 0 {
+        println!("✅ All {} files listed in the manifest are in {}", manifest.files.len(), config.upload_dir);
+    } else {
+        println!("⚠️  {} of {} files are missing or differ; copy them from {} into {}", missing, manifest.files.len(), manifest.upload_dir, config.upload_dir);
+    }
+    if let Some(hash) = doctor::share_hash().await {
+        println!("🔗 The share URL stays https://{}.{}", hash, config.tunnel_domain);
+    }
+}
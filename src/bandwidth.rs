@@ -0,0 +1,200 @@
+//! Upload bandwidth limits that follow a weekly schedule, e.g. 10MiB/s during work hours and
+//! unlimited at night. The schedule lives in the settings store and is edited from the admin
+//! app; the throttle reads it for every frame of a request body, so a change or a window
+//! boundary takes effect in the middle of an upload.
+use std::time::Duration;
+use axum::{body::Body, extract::{Request, State}, middleware::Next, response::Response, Json};
+use byte_unit::{Byte, UnitType};
+use chrono::{Datelike, Timelike, Weekday};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::{sync::{watch, Mutex}, time::Instant};
+use tokio_stream::StreamExt;
+use log::info;
+use crate::{db, error::ApiError};
+
+const MAX_WINDOWS: usize = 50;
+
+/// A part of the week with its own limit.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Window {
+    /// Days the window starts on, e.g. `["mon", "tue"]`; every day when empty
+    #[serde(default)]
+    pub days: Vec<String>,
+    /// Local time it starts, `HH:MM`
+    pub from: String,
+    /// Local time it ends, `HH:MM`; before `from` for a window that runs past midnight
+    pub to: String,
+    /// Bytes per second across all uploads, e.g. `10MiB`; unlimited when unset
+    #[serde(default)]
+    pub limit: Option<String>,
+}
+
+/// What the admin can set; the first window that covers the current time wins.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct BandwidthSchedule {
+    /// Bytes per second outside every window; unlimited when unset
+    #[serde(default)]
+    pub limit: Option<String>,
+    #[serde(default)]
+    pub windows: Vec<Window>,
+}
+
+/// The schedule with its times and limits parsed, as the throttle uses it.
+#[derive(Default)]
+struct Resolved {
+    limit: Option<u64>,
+    windows: Vec<(Vec<Weekday>, u32, u32, Option<u64>)>,
+}
+
+fn parse_limit(limit: Option<String>, name: &str) -> Result<(Option<String>, Option<u64>), String> {
+    let Some(limit) = limit.map(|l| l.trim().trim_end_matches("/s").to_string()).filter(|l| !l.is_empty()) else {
+        return Ok((None, None));
+    };
+    match Byte::parse_str(&limit, true).map(|b| b.as_u64()) {
+        Ok(bytes) if bytes > 0 => Ok((Some(limit), Some(bytes))),
+        _ => Err(format!("{} {:?} is not a rate like 10MiB", name, limit)),
+    }
+}
+
+/// Minutes since midnight of an `HH:MM` time.
+fn parse_time(time: &str, name: &str) -> Result<u32, String> {
+    chrono::NaiveTime::parse_from_str(time.trim(), "%H:%M")
+        .map(|t| t.hour() * 60 + t.minute())
+        .map_err(|_| format!("{} {:?} is not a time like 09:00", name, time))
+}
+
+impl BandwidthSchedule {
+    /// Limits are normalised to plain sizes and days to their three-letter names; a window
+    /// must not start and end at the same time.
+    fn validated(self) -> Result<(Self, Resolved), String> {
+        if self.windows.len() > MAX_WINDOWS {
+            return Err(format!("at most {} windows are allowed", MAX_WINDOWS));
+        }
+        let (limit, limit_bytes) = parse_limit(self.limit, "limit")?;
+        let mut schedule = Self { limit, windows: Vec::new() };
+        let mut resolved = Resolved { limit: limit_bytes, windows: Vec::new() };
+        for (i, window) in self.windows.into_iter().enumerate() {
+            let days = window.days.iter()
+                .map(|d| d.trim().parse::<Weekday>().map_err(|_| format!("window {}: {:?} is not a day", i + 1, d)))
+                .collect::<Result<Vec<_>, _>>()?;
+            let from = parse_time(&window.from, &format!("window {}: from", i + 1))?;
+            let to = parse_time(&window.to, &format!("window {}: to", i + 1))?;
+            if from == to {
+                return Err(format!("window {} starts and ends at {}", i + 1, window.from.trim()));
+            }
+            let (limit, limit_bytes) = parse_limit(window.limit, &format!("window {}: limit", i + 1))?;
+            schedule.windows.push(Window {
+                days: days.iter().map(|d| d.to_string().to_lowercase()).collect(),
+                from: format!("{:02}:{:02}", from / 60, from % 60),
+                to: format!("{:02}:{:02}", to / 60, to % 60),
+                limit,
+            });
+            resolved.windows.push((days, from, to, limit_bytes));
+        }
+        Ok((schedule, resolved))
+    }
+}
+
+impl Resolved {
+    /// The limit at `day` and `minute` (since midnight): a window that runs past midnight
+    /// still applies the next morning to the day it started on.
+    fn limit_at(&self, day: Weekday, minute: u32) -> Option<u64> {
+        let on = |days: &[Weekday], day: Weekday| days.is_empty() || days.contains(&day);
+        self.windows.iter()
+            .find(|(days, from, to, _)| if from < to {
+                on(days, day) && (*from..*to).contains(&minute)
+            } else {
+                (minute >= *from && on(days, day)) || (minute < *to && on(days, day.pred()))
+            })
+            .map_or(self.limit, |&(_, _, _, limit)| limit)
+    }
+
+    fn is_unlimited(&self) -> bool {
+        self.limit.is_none() && self.windows.iter().all(|w| w.3.is_none())
+    }
+}
+
+static SETTINGS: Lazy<watch::Sender<(BandwidthSchedule, Resolved)>> = Lazy::new(|| watch::channel(Default::default()).0);
+
+/// When the next byte may pass; shared by every upload so the limit holds for all of them together.
+static NEXT_SLOT: Lazy<Mutex<Instant>> = Lazy::new(|| Mutex::new(Instant::now()));
+
+/// Restores the schedule last saved from the admin app.
+pub async fn init(pool: &SqlitePool) {
+    let saved = db::kv_get(pool, "bandwidth_schedule").await.and_then(|json| serde_json::from_str::<BandwidthSchedule>(&json).ok());
+    if let Some(Ok(settings)) = saved.map(BandwidthSchedule::validated) {
+        SETTINGS.send_replace(settings);
+    }
+}
+
+/// Bytes per second uploads are limited to right now, if at all.
+pub fn current_limit() -> Option<u64> {
+    let now = chrono::Local::now();
+    SETTINGS.borrow().1.limit_at(now.weekday(), now.hour() * 60 + now.minute())
+}
+
+/// Waits until `len` more bytes fit under the current limit.
+async fn pace(len: usize) {
+    let Some(limit) = current_limit() else { return };
+    let at = {
+        let mut next = NEXT_SLOT.lock().await;
+        let at = (*next).max(Instant::now());
+        *next = at + Duration::from_secs_f64(len as f64 / limit as f64);
+        at
+    };
+    tokio::time::sleep_until(at).await;
+}
+
+/// Middleware for the upload app: feeds request bodies to the handlers no faster than the
+/// schedule allows. Passes everything through untouched while no limit is set at all.
+pub async fn throttle(request: Request, next: Next) -> Response {
+    if SETTINGS.borrow().1.is_unlimited() {
+        return next.run(request).await;
+    }
+    let (parts, body) = request.into_parts();
+    let mut stream = body.into_data_stream();
+    let (tx, rx) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(async move {
+        while let Some(frame) = stream.next().await {
+            if let Ok(data) = &frame {
+                pace(data.len()).await;
+            }
+            if tx.send(frame).await.is_err() {
+                return;
+            }
+        }
+    });
+    next.run(Request::from_parts(parts, Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)))).await
+}
+
+/// The schedule as the admin app shows it.
+#[derive(Serialize)]
+pub struct BandwidthStatus {
+    #[serde(flatten)]
+    pub schedule: BandwidthSchedule,
+    /// Bytes per second in effect right now; `null` when unlimited
+    pub current_limit: Option<u64>,
+}
+
+/// `GET /bandwidth`
+pub async fn admin_bandwidth() -> Json<BandwidthStatus> {
+    Json(BandwidthStatus { schedule: SETTINGS.borrow().0.clone(), current_limit: current_limit() })
+}
+
+/// `PUT /bandwidth`: replaces the schedule. Uploads in progress slow down or speed up with their
+/// next few kilobytes, and the schedule is kept in the database across restarts.
+pub async fn admin_update_bandwidth(
+    State(pool): State<SqlitePool>,
+    Json(schedule): Json<BandwidthSchedule>,
+) -> Result<Json<BandwidthStatus>, ApiError> {
+    let settings = schedule.validated().map_err(|e| ApiError::bad_request("invalid_bandwidth_schedule", e))?;
+    db::kv_set(&pool, "bandwidth_schedule", &serde_json::to_string(&settings.0).unwrap()).await;
+    let schedule = settings.0.clone();
+    SETTINGS.send_replace(settings);
+    let current = current_limit();
+    info!("🚦 Bandwidth schedule changed ({} windows); uploads are {}", schedule.windows.len(),
+        current.map(|l| format!("limited to {}/s", Byte::from_u64(l).get_appropriate_unit(UnitType::Binary))).unwrap_or_else(|| "unlimited".into()));
+    Ok(Json(BandwidthStatus { schedule, current_limit: current }))
+}
@@ -0,0 +1,54 @@
+use axum::{extract::{Query, State, Extension}, response::Html};
+use byte_unit::{Byte, UnitType};
+use minijinja::{context, Environment};
+use once_cell::sync::Lazy;
+use sqlx::SqlitePool;
+use log::error;
+use crate::{admin::{self, ListQuery}, config::AppConfig, db, error::ApiError};
+
+/// The no-JS admin views, for text browsers and kiosks that can't run the dashboard. The
+/// `.html` names turn on minijinja's HTML escaping for filenames and sender details.
+static TEMPLATES: Lazy<Environment<'static>> = Lazy::new(|| {
+    let mut env = Environment::new();
+    env.add_template("uploads.html", include_str!("static/basic/uploads.html")).unwrap();
+    env.add_template("clients.html", include_str!("static/basic/clients.html")).unwrap();
+    env.add_filter("bytes", |n: i64| format!("{:.1}", Byte::from_u64(n.max(0) as u64).get_appropriate_unit(UnitType::Binary)));
+    env
+});
+
+fn render(name: &str, ctx: minijinja::Value) -> Result<Html<String>, ApiError> {
+    TEMPLATES.get_template(name)
+        .and_then(|template| template.render(ctx))
+        .map(Html)
+        .map_err(|e| {
+            error!("Failed to render {}: {}", name, e);
+            ApiError::internal("render_failed", "Failed to render the page")
+        })
+}
+
+/// `GET /basic`: the uploads list of `/data` as a plain HTML page, with the same paging and
+/// search.
+pub async fn basic_uploads(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+    Query(params): Query<ListQuery>,
+) -> Result<Html<String>, ApiError> {
+    let page = params.page.unwrap_or(1).max(1);
+    let q = params.q.unwrap_or_default();
+    let uploads = admin::list_uploads(&pool, &config, page, &q).await;
+    let more = uploads.len() as i64 >= config.default_page_size;
+    render("uploads.html", context! {
+        uploads,
+        stats => db::get_upload_stats(&pool).await,
+        page,
+        q,
+        more,
+    })
+}
+
+/// `GET /basic/clients`: the connected clients of `/clients` as a plain HTML page.
+pub async fn basic_clients(
+    State(pool): State<SqlitePool>,
+) -> Result<Html<String>, ApiError> {
+    render("clients.html", context! { clients => db::get_connected_clients(&pool).await })
+}
@@ -0,0 +1,176 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use byte_unit::{Byte, UnitType};
+use clap::Parser;
+use rand::RngCore;
+use tokio::net::TcpListener;
+use log::{info, error};
+use crate::{apps, assembly, coalescer, config::{self, Args, BenchArgs}, db};
+
+struct SenderReport {
+    bytes: u64,
+    chunk_latencies: Vec<Duration>,
+}
+
+/// `drcv bench`: a throwaway server on a loopback port, driven through the real upload handlers.
+pub async fn run(bench: &BenchArgs) {
+    let file_size = config::parse_file_size(&bench.file_size);
+    let chunk_size = config::parse_file_size(&bench.chunk_size).max(1);
+    let scratch = std::env::temp_dir().join(format!("drcv-bench-{}", std::process::id()));
+    let upload_dir = scratch.join("uploads");
+    if let Err(e) = std::fs::create_dir_all(&upload_dir) {
+        error!("Failed to create scratch directory {:?}: {}", scratch, e);
+        std::process::exit(1);
+    }
+
+    let config = Args::parse_from([
+        "drcv",
+        "--chunk-size", &bench.chunk_size,
+        "--upload-dir", &upload_dir.display().to_string(),
+    ]).to_config();
+    let pool = db::init_pool_at(&format!("sqlite:{}", scratch.join("drcv.db").display()), &config).await
+        .unwrap_or_else(|e| {
+            error!("Failed to initialize database: {}", e);
+            std::process::exit(1);
+        });
+
+    coalescer::spawn(pool.clone(), config.db_flush_interval);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let service = apps::upload::create_router(&pool, &config).into_make_service_with_connect_info::<SocketAddr>();
+    tokio::spawn(async move { axum::serve(listener, service).await.unwrap() });
+
+    info!("🏁 Benchmark: {} file(s) of {} in {} chunks, {} in parallel",
+        bench.files, format_bytes(file_size), format_bytes(chunk_size), bench.parallel);
+
+    let mut payload = vec![0u8; chunk_size as usize];
+    rand::thread_rng().fill_bytes(&mut payload);
+    let payload = bytes::Bytes::from(payload);
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/upload", addr);
+    let permits = Arc::new(tokio::sync::Semaphore::new(bench.parallel.max(1)));
+
+    let started = Instant::now();
+    let mut tasks = Vec::new();
+    for n in 0..bench.files {
+        let (client, url, payload, permits) = (client.clone(), url.clone(), payload.clone(), Arc::clone(&permits));
+        tasks.push(tokio::spawn(async move {
+            let _permit = permits.acquire_owned().await.unwrap();
+            send_file(&client, &url, &format!("bench-{}.bin", n), file_size, payload).await
+        }));
+    }
+
+    let mut total_bytes = 0;
+    let mut latencies = Vec::new();
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(report) => {
+                total_bytes += report.bytes;
+                latencies.extend(report.chunk_latencies);
+            }
+            Err(e) => {
+                error!("❌ Benchmark upload failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let elapsed = started.elapsed();
+
+    let db_per_chunk = measure_db_overhead(&pool, latencies.len().clamp(1, 200)).await;
+    latencies.sort();
+    let percentile = |p: f64| latencies.get(((latencies.len() as f64 - 1.0) * p) as usize).copied().unwrap_or_default();
+    let mean_latency = latencies.iter().sum::<Duration>() / latencies.len().max(1) as u32;
+
+    info!("📊 Uploaded {} in {:.2?}: {}/s", format_bytes(total_bytes), elapsed,
+        format_bytes((total_bytes as f64 / elapsed.as_secs_f64().max(0.001)) as u64));
+    info!("   Chunk latency: p50 {:.2?}, p99 {:.2?}, max {:.2?} ({} chunks)",
+        percentile(0.5), percentile(0.99), percentile(1.0), latencies.len());
+    info!("   DB writes per chunk if written through: {:.2?} ({:.1}% of mean chunk latency)",
+        db_per_chunk, 100.0 * db_per_chunk.as_secs_f64() / mean_latency.as_secs_f64().max(f64::EPSILON));
+
+    if bench.assembly {
+        if let Err(e) = bench_assembly(&scratch, file_size, &payload).await {
+            error!("❌ Assembly benchmark failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    pool.close().await;
+    if bench.keep {
+        info!("   Scratch directory kept at {:?}", scratch);
+    } else {
+        let _ = std::fs::remove_dir_all(&scratch);
+    }
+}
+
+async fn send_file(client: &reqwest::Client, url: &str, filename: &str, file_size: u64, payload: bytes::Bytes) -> Result<SenderReport, String> {
+    let chunk_size = payload.len() as u64;
+    let total_chunks = file_size.div_ceil(chunk_size).max(1);
+    let mut report = SenderReport { bytes: 0, chunk_latencies: Vec::new() };
+
+    for index in 0..total_chunks {
+        let len = chunk_size.min(file_size - index * chunk_size) as usize;
+        let form = reqwest::multipart::Form::new()
+            .text("filename", filename.to_string())
+            .text("chunk_index", index.to_string())
+            .text("total_chunks", total_chunks.to_string())
+            .part("chunk", reqwest::multipart::Part::stream(payload.slice(..len)).file_name("blob"));
+
+        let sent = Instant::now();
+        let resp = client.post(url).multipart(form).send().await.map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("{}: {}", resp.status(), resp.text().await.unwrap_or_default()));
+        }
+        report.chunk_latencies.push(sent.elapsed());
+        report.bytes += len as u64;
+    }
+    Ok(report)
+}
+
+/// Spools one file of `file_size` as chunks, then assembles it both ways, as the leader of a
+/// `--chunk-spool-dir` setup would. The chunks were just written, so both runs read them from
+/// the page cache and mostly measure the copy into the output.
+async fn bench_assembly(scratch: &std::path::Path, file_size: u64, payload: &bytes::Bytes) -> std::io::Result<()> {
+    let dir = assembly::chunk_dir(&scratch.join("spool").display().to_string(), 1);
+    tokio::fs::create_dir_all(&dir).await?;
+    let chunk_size = payload.len() as u64;
+    let total_chunks = file_size.div_ceil(chunk_size).max(1) as u32;
+    for index in 0..total_chunks {
+        let len = chunk_size.min(file_size - index as u64 * chunk_size) as usize;
+        tokio::fs::write(assembly::chunk_path(&dir, index), &payload[..len]).await?;
+    }
+
+    let buffered_path = scratch.join("assembled-buffered.bin");
+    let started = Instant::now();
+    assembly::concat_buffered(&dir, &buffered_path, total_chunks).await?;
+    let buffered = started.elapsed();
+    tokio::fs::remove_file(&buffered_path).await?;
+
+    let mmap_path = scratch.join("assembled-mmap.bin");
+    let started = Instant::now();
+    assembly::concat_mmap(dir.clone(), mmap_path.clone(), total_chunks).await?;
+    let mapped = started.elapsed();
+    tokio::fs::remove_file(&mmap_path).await?;
+
+    let rate = |elapsed: Duration| format_bytes((file_size as f64 / elapsed.as_secs_f64().max(0.001)) as u64);
+    info!("   Assembly of {} in {} chunks: buffered {:.2?} ({}/s), mmap {:.2?} ({}/s)",
+        format_bytes(file_size), total_chunks, buffered, rate(buffered), mapped, rate(mapped));
+    Ok(())
+}
+
+/// Times the bookkeeping each chunk costs (progress update plus wire byte count) on its own.
+async fn measure_db_overhead(pool: &sqlx::SqlitePool, samples: usize) -> Duration {
+    let id = db::init_upload(pool, "bench-db-probe", "127.0.0.1", None, "http").await;
+    let started = Instant::now();
+    for _ in 0..samples {
+        db::add_wire_bytes(pool, id, 0).await;
+        db::mark_uploading(pool, id, 0).await;
+    }
+    started.elapsed() / samples as u32
+}
+
+fn format_bytes(n: u64) -> String {
+    format!("{:.2}", Byte::from_u64(n).get_appropriate_unit(UnitType::Binary))
+}
@@ -0,0 +1,99 @@
+//! `--chaos`: fault injection for development. Chunk requests are randomly delayed, failed or
+//! dropped mid-response and the tunnel client is killed now and then, all drawn from one seeded
+//! generator so a run that shows a resume bug can be repeated with `--chaos-seed`.
+use std::sync::Mutex;
+use std::time::Duration;
+use axum::{body::Body, extract::Request, http::StatusCode, middleware::Next, response::{IntoResponse, Response}};
+use once_cell::sync::OnceCell;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use log::warn;
+use crate::{config::AppConfig, error::ApiError};
+
+/// Longest delay added to a chunk request
+const MAX_DELAY: Duration = Duration::from_secs(8);
+/// How long the tunnel client runs before it is killed, at least and at most
+const TUNNEL_LIFETIME: (u64, u64) = (60, 600);
+
+struct Chaos {
+    rate: f64,
+    rng: Mutex<StdRng>,
+}
+
+static CHAOS: OnceCell<Chaos> = OnceCell::new();
+
+enum Fault {
+    Delay(Duration),
+    Fail(StatusCode),
+    /// The connection is cut before the chunk reaches the handler
+    DropRequest,
+    /// The chunk is stored but the connection is cut before the response
+    DropResponse,
+}
+
+pub fn init(config: &AppConfig) {
+    if !config.chaos {
+        return;
+    }
+    let seed = config.chaos_seed.unwrap_or_else(rand::random);
+    warn!("🐒 Chaos mode: disturbing {:.0}% of chunk requests, seed {} (repeat with --chaos-seed {})", config.chaos_rate * 100.0, seed, seed);
+    let _ = CHAOS.set(Chaos { rate: config.chaos_rate, rng: Mutex::new(StdRng::seed_from_u64(seed)) });
+}
+
+pub fn enabled() -> bool {
+    CHAOS.get().is_some()
+}
+
+fn draw() -> Option<Fault> {
+    let chaos = CHAOS.get()?;
+    let mut rng = chaos.rng.lock().unwrap();
+    if !rng.gen_bool(chaos.rate) {
+        return None;
+    }
+    Some(match rng.gen_range(0..5) {
+        0 | 1 => Fault::Delay(rng.gen_range(Duration::from_millis(200)..MAX_DELAY)),
+        2 => Fault::Fail(if rng.gen_bool(0.5) { StatusCode::INTERNAL_SERVER_ERROR } else { StatusCode::SERVICE_UNAVAILABLE }),
+        3 => Fault::DropRequest,
+        _ => Fault::DropResponse,
+    })
+}
+
+/// A body that fails on its first read, so the server aborts the connection instead of
+/// answering.
+fn cut_connection() -> Response {
+    let stream = tokio_stream::once(Err::<bytes::Bytes, _>(std::io::Error::new(std::io::ErrorKind::ConnectionAborted, "chaos")));
+    Response::new(Body::from_stream(stream))
+}
+
+/// Middleware for the chunk upload route; passes everything through unless `--chaos` is on.
+pub async fn disturb(request: Request, next: Next) -> Response {
+    match draw() {
+        None => next.run(request).await,
+        Some(Fault::Delay(delay)) => {
+            warn!("🐒 Delaying a chunk by {:?}", delay);
+            tokio::time::sleep(delay).await;
+            next.run(request).await
+        }
+        Some(Fault::Fail(status)) => {
+            warn!("🐒 Failing a chunk with {}", status);
+            ApiError::new(status, "chaos", "Failed on purpose by --chaos").into_response()
+        }
+        Some(Fault::DropRequest) => {
+            warn!("🐒 Dropping a chunk before storing it");
+            cut_connection()
+        }
+        Some(Fault::DropResponse) => {
+            let response = next.run(request).await;
+            warn!("🐒 Dropping the response to a chunk ({})", response.status());
+            cut_connection()
+        }
+    }
+}
+
+/// Resolves once the tunnel client should be killed; never while `--chaos` is off.
+pub async fn tunnel_kill() {
+    let Some(chaos) = CHAOS.get() else {
+        return std::future::pending().await;
+    };
+    let after = chaos.rng.lock().unwrap().gen_range(TUNNEL_LIFETIME.0..=TUNNEL_LIFETIME.1);
+    tokio::time::sleep(Duration::from_secs(after)).await;
+}
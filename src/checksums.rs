@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use log::warn;
+use crate::{config::AppConfig, utils};
+
+/// Hashes of the files completed each day, one `sha256sum` line each, in the upload directory
+/// as `SHA256SUMS-<YYYY-MM-DD>` (local date).
+const SUMS_PREFIX: &str = "SHA256SUMS";
+const SIDECAR_EXTENSION: &str = ".sha256";
+
+/// `<file>.sha256` next to a completed file.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(SIDECAR_EXTENSION);
+    path.with_file_name(name)
+}
+
+/// The sums file for files completed on `date`.
+pub fn sums_file_name(date: chrono::NaiveDate) -> String {
+    format!("{}-{}", SUMS_PREFIX, date.format("%Y-%m-%d"))
+}
+
+/// Whether drcv wrote this file in the upload directory, rather than a sender. A plain
+/// `SHA256SUMS` is from versions that kept one list for every day.
+pub fn is_checksum_file(name: &str) -> bool {
+    let dated = name.strip_prefix(SUMS_PREFIX)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|date| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok());
+    name == SUMS_PREFIX || dated || name.ends_with(SIDECAR_EXTENSION)
+}
+
+/// With `--checksum-sidecars`, hashes a completed file in the background and writes its
+/// sidecar and the line in that day's sums file, for consumers that verify files straight from the disk.
+/// The sidecar only appears once the hash is known, so it also marks the file as final.
+pub fn spawn_write(config: &AppConfig, path: PathBuf) {
+    if !config.checksum_sidecars {
+        return;
+    }
+    let upload_dir = PathBuf::from(&config.upload_dir);
+    tokio::spawn(async move {
+        if let Err(e) = write(&upload_dir, &path).await {
+            warn!("⚠️ Failed to write checksums for {}: {}", path.display(), e);
+        }
+    });
+}
+
+async fn write(upload_dir: &Path, path: &Path) -> std::io::Result<()> {
+    let sha256 = utils::sha256_file(&path.display().to_string()).await?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    tokio::fs::write(sidecar_path(path), format!("{}  {}\n", sha256, name)).await?;
+    // The sums file names files in --route-script subdirectories by their relative path
+    let relative = path.strip_prefix(upload_dir).map(|p| p.to_string_lossy().replace('\\', "/")).unwrap_or_else(|_| name.into_owned());
+    let line = format!("{}  {}\n", sha256, relative);
+    // One write per line, so lines of files finishing together don't interleave
+    let sums_file = upload_dir.join(sums_file_name(chrono::Local::now().date_naive()));
+    let mut sums = tokio::fs::OpenOptions::new().create(true).append(true).open(sums_file).await?;
+    sums.write_all(line.as_bytes()).await
+}
@@ -0,0 +1,93 @@
+//! Where v1 chunks go in an upload's `.part` file. Chunks are written at their own offset, so
+//! they may arrive out of order or twice without corrupting the file: a chunk follows its
+//! predecessor if that one is journaled, and otherwise sits at `chunk_index` times its own
+//! size, the final chunk at the end of the declared file. Chunks arriving ahead of a gap must
+//! therefore all be the same size, except the final one.
+//!
+//! Journaled chunks are `(chunk_index, byte_offset, size)` ordered by offset, as
+//! `db::get_upload_chunks` returns them.
+use crate::upload::SequenceViolation;
+
+type Chunk = (u32, i64, i64);
+
+/// Bytes at the start of the file written by a version that didn't journal chunks: what the
+/// recorded size holds beyond the journaled chunks, if it reaches the first of them. Anything
+/// else before the first chunk is a hole left by chunks still missing.
+pub fn legacy_prefix(chunks: &[Chunk], recorded_size: i64) -> i64 {
+    let journaled: i64 = chunks.iter().map(|&(_, _, size)| size).sum();
+    match chunks.first() {
+        Some(&(_, first, _)) if recorded_size - journaled >= first => first,
+        Some(_) => 0,
+        None => recorded_size.max(0),
+    }
+}
+
+/// Where the unbroken run of bytes from the start of the file ends, and the chunk that comes
+/// next (`None` when the run ends in bytes no chunk index is known for).
+pub fn contiguous(chunks: &[Chunk], recorded_size: i64) -> (i64, Option<u32>) {
+    let mut end = legacy_prefix(chunks, recorded_size);
+    let mut next = (end == 0).then_some(0);
+    for &(index, offset, size) in chunks {
+        if offset != end || next.is_some_and(|next| next != index) {
+            break;
+        }
+        end += size;
+        next = Some(index + 1);
+    }
+    (end, next)
+}
+
+/// Whether the journaled chunks, with their recorded size, make up the whole file of
+/// `total_chunks` chunks: one run from the start with nothing received beyond it.
+pub fn is_complete(chunks: &[Chunk], recorded_size: i64, total_chunks: u32) -> bool {
+    contiguous(chunks, recorded_size) == (recorded_size, Some(total_chunks))
+}
+
+/// The offset chunk `index` of `len` bytes is written at, or why it can't be placed: it would
+/// overlap bytes already received, leave a gap before the chunk after it, or run past the
+/// declared `file_size`.
+pub fn place(chunks: &[Chunk], recorded_size: i64, index: u32, len: i64, total_chunks: u32, file_size: Option<u64>) -> Result<i64, SequenceViolation> {
+    let prefix = legacy_prefix(chunks, recorded_size);
+    let violation = |rule, message| {
+        let next_expected_index = contiguous(chunks, recorded_size).1.unwrap_or(0);
+        Err(SequenceViolation { rule, message, next_expected_index, total_chunks })
+    };
+    let is_last = index + 1 == total_chunks;
+    let offset = match chunks.iter().find(|&&(i, _, _)| i + 1 == index) {
+        Some(&(_, offset, size)) => offset + size,
+        // A partial from before the journal continues where it ends
+        None if chunks.is_empty() && prefix > 0 => prefix,
+        None if index == 0 => 0,
+        None if is_last => match file_size {
+            Some(file_size) => file_size as i64 - len,
+            None => return violation("gap", format!("Chunk {} is the last; without file_size it can only follow chunk {}", index, index - 1)),
+        },
+        None => index as i64 * len,
+    };
+    let end = offset + len;
+    if offset < prefix || chunks.iter().any(|&(_, start, size)| offset < start + size && start < end) {
+        return violation("overlap", format!("Chunk {} at byte {} overlaps bytes already received", index, offset));
+    }
+    if let Some(&(_, next, _)) = chunks.iter().find(|&&(i, _, _)| i == index + 1) {
+        if next != end {
+            return violation("misplaced", format!("Chunk {} ends at byte {} but chunk {} starts at {}", index, end, index + 1, next));
+        }
+    }
+    if file_size.is_some_and(|size| end as u64 > size) {
+        return violation("past_end", format!("Chunk {} ends at byte {}, past the file_size", index, end));
+    }
+    Ok(offset)
+}
+
+/// Chunks of a file of `total_chunks` that `has` says aren't there yet, as inclusive index
+/// ranges, e.g. `[(2, 4), (7, 7)]`.
+pub fn missing_ranges(total_chunks: u32, has: impl Fn(u32) -> bool) -> Vec<(u32, u32)> {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for index in (0..total_chunks).filter(|&index| !has(index)) {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == index => *last = index,
+            _ => ranges.push((index, index)),
+        }
+    }
+    ranges
+}
@@ -0,0 +1,84 @@
+use once_cell::sync::Lazy;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use log::error;
+use crate::db;
+
+#[derive(Default)]
+struct Pending {
+    // upload id -> (size delta, wire bytes)
+    progress: HashMap<i64, (i64, i64)>,
+    heartbeats: HashSet<(i64, String)>,
+}
+
+static PENDING: Lazy<Mutex<Pending>> = Lazy::new(|| Mutex::new(Pending::default()));
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// Serializes flushes so a caller waiting on `flush` sees everything queued before it
+static FLUSH_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// Starts flushing accumulated chunk progress and heartbeats every `interval`.
+/// Without it (interval 0) every update is written through immediately.
+pub fn spawn(pool: SqlitePool, interval: Duration) {
+    if interval.is_zero() {
+        return;
+    }
+    ENABLED.store(true, Ordering::Relaxed);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            flush(&pool).await;
+        }
+    });
+}
+
+pub async fn record_progress(pool: &SqlitePool, id: i64, delta_size: i64, wire_bytes: i64) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        db::mark_uploading(pool, id, delta_size).await;
+        db::add_wire_bytes(pool, id, wire_bytes).await;
+        return;
+    }
+    let mut pending = PENDING.lock().unwrap();
+    let entry = pending.progress.entry(id).or_default();
+    entry.0 += delta_size;
+    entry.1 += wire_bytes;
+}
+
+/// Returns whether the heartbeat counted. A queued one isn't written yet, so it counts when
+/// `live`: the upload is tracked here, uploading and sent from `client_ip`.
+pub async fn record_heartbeat(pool: &SqlitePool, id: i64, client_ip: &str, live: bool) -> bool {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return db::touch_upload(pool, id, client_ip).await;
+    }
+    PENDING.lock().unwrap().heartbeats.insert((id, client_ip.to_string()));
+    live
+}
+
+/// Writes everything queued so far. Call before reading sizes that must be exact (resume
+/// offsets) and before status transitions.
+pub async fn flush(pool: &SqlitePool) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let _guard = FLUSH_LOCK.lock().await;
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+    if pending.progress.is_empty() && pending.heartbeats.is_empty() {
+        return;
+    }
+
+    let progress: Vec<(i64, i64, i64)> = pending.progress.iter().map(|(id, (size, wire))| (*id, *size, *wire)).collect();
+    let heartbeats: Vec<(i64, String)> = pending.heartbeats.into_iter().collect();
+    if let Err(e) = db::apply_batch(pool, &progress, &heartbeats).await {
+        error!("Failed to flush upload progress: {}", e);
+        // Put the deltas back so sizes stay exact; heartbeats are simply retried by the client
+        let mut pending = PENDING.lock().unwrap();
+        for (id, size, wire) in progress {
+            let entry = pending.progress.entry(id).or_default();
+            entry.0 += size;
+            entry.1 += wire;
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use axum::{body::Body, extract::{Extension, Request}, http::{header, StatusCode}, middleware::Next, response::Response};
+use std::io::Read;
+use crate::{config::AppConfig, error::ApiError};
+
+/// Size of the request body as received, before `Content-Encoding` was undone.
+#[derive(Clone, Copy)]
+pub struct WireBytes(pub u64);
+
+/// Undoes `Content-Encoding: gzip|zstd` on upload requests so handlers always see plain bodies.
+pub async fn decompress_body(
+    Extension(config): Extension<AppConfig>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let encoding = request.headers().get(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_lowercase());
+    let Some(encoding) = encoding.filter(|e| e != "identity") else {
+        return Ok(next.run(request).await);
+    };
+    if encoding != "gzip" && encoding != "zstd" {
+        return Err(ApiError::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_encoding", format!("Unsupported Content-Encoding: {}", encoding)));
+    }
+
+    // Same ceiling as the plain body limit, applied to both sides so a small body can't expand without bound
+    let limit = config.chunk_size.saturating_add(1024 * 1024);
+    let (mut parts, body) = request.into_parts();
+    let compressed = axum::body::to_bytes(body, limit as usize).await
+        .map_err(|e| ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "body_too_large", format!("Failed to read body: {}", e)))?;
+    let wire_bytes = compressed.len() as u64;
+
+    let decoded = tokio::task::spawn_blocking(move || decode(&encoding, &compressed, limit))
+        .await
+        .map_err(|e| ApiError::internal("decompression_failed", format!("Decompression failed: {}", e)))??;
+
+    parts.headers.remove(header::CONTENT_ENCODING);
+    parts.headers.insert(header::CONTENT_LENGTH, decoded.len().into());
+    parts.extensions.insert(WireBytes(wire_bytes));
+    Ok(next.run(Request::from_parts(parts, Body::from(decoded))).await)
+}
+
+fn decode(encoding: &str, data: &[u8], limit: u64) -> Result<Vec<u8>, ApiError> {
+    let reader: Box<dyn Read> = match encoding {
+        "gzip" => Box::new(flate2::read::GzDecoder::new(data)),
+        _ => Box::new(zstd::stream::read::Decoder::new(data)
+            .map_err(|e| ApiError::bad_request("invalid_body", format!("Invalid zstd body: {}", e)))?),
+    };
+    let mut decoded = Vec::new();
+    reader.take(limit + 1).read_to_end(&mut decoded)
+        .map_err(|e| ApiError::bad_request("invalid_body", format!("Invalid {} body: {}", encoding, e)))?;
+    if decoded.len() as u64 > limit {
+        return Err(ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "body_too_large", "Decompressed body exceeds the chunk size limit"));
+    }
+    Ok(decoded)
+}
@@ -0,0 +1,77 @@
+use axum::{extract::{State, ConnectInfo, Extension}, http::HeaderMap, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::SocketAddr;
+use log::info;
+use crate::{config::AppConfig, db, error::ApiError, net::extract_client_ip};
+
+/// Terms of use senders must accept before uploading (`--terms-file`).
+#[derive(Clone)]
+pub struct Terms {
+    pub text: String,
+    /// Short hash of the text, so a consent always names the exact wording accepted
+    pub version: String,
+}
+
+impl Terms {
+    pub fn load(path: &str) -> Result<Terms, String> {
+        use sha2::{Digest, Sha256};
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read terms file {}: {}", path, e))?;
+        let version = format!("{:x}", Sha256::digest(text.as_bytes()))[..12].to_string();
+        Ok(Terms { text, version })
+    }
+}
+
+#[derive(Serialize)]
+pub struct TermsResponse {
+    pub version: String,
+    pub text: String,
+    /// Whether the caller has accepted this version already
+    pub accepted: bool,
+}
+
+/// `GET /terms`: the text the upload page must show; `404` without `--terms-file`.
+pub async fn handle_terms(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(config): Extension<AppConfig>,
+    headers: HeaderMap,
+) -> Result<Json<TermsResponse>, ApiError> {
+    let terms = config.terms.ok_or(ApiError::not_found("no_terms", "No terms of use configured"))?;
+    let client_ip = extract_client_ip(&headers, &addr);
+    let accepted = db::find_consent(&pool, &client_ip, &terms.version).await.is_some();
+    Ok(Json(TermsResponse { version: terms.version, text: terms.text, accepted }))
+}
+
+#[derive(Deserialize)]
+pub struct ConsentRequest {
+    pub version: String,
+}
+
+#[derive(Serialize)]
+pub struct ConsentResponse {
+    pub consent_id: i64,
+    pub accepted_at: String,
+}
+
+/// `POST /consent`: records that the caller accepted the terms shown to them. A stale
+/// version gets `409`, so the page reloads the current text instead.
+pub async fn handle_consent(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(config): Extension<AppConfig>,
+    headers: HeaderMap,
+    Json(request): Json<ConsentRequest>,
+) -> Result<Json<ConsentResponse>, ApiError> {
+    let terms = config.terms.ok_or(ApiError::not_found("no_terms", "No terms of use configured"))?;
+    if request.version != terms.version {
+        return Err(ApiError::conflict("terms_outdated", format!("Terms version {} is outdated; the current one is {}", request.version, terms.version)));
+    }
+    let client_ip = extract_client_ip(&headers, &addr);
+    let user_agent = headers.get("user-agent").and_then(|v| v.to_str().ok());
+    let (consent_id, accepted_at) = db::record_consent(&pool, &client_ip, &terms.version, user_agent).await
+        .ok_or(ApiError::internal("database_error", "Failed to record consent"))?;
+    info!("📜 {} accepted terms version {}", client_ip, terms.version);
+    Ok(Json(ConsentResponse { consent_id, accepted_at }))
+}
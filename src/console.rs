@@ -0,0 +1,113 @@
+use std::sync::Arc;
+use byte_unit::{Byte, UnitType};
+use qrcode::{render::unicode, QrCode};
+use sqlx::SqlitePool;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::RwLock;
+use log::{info, warn};
+use crate::{apps::admin::TunnelInfo, config::AppConfig, db, fairness, registry, share, tunnels::TunnelSupervisor, window};
+
+const HELP: &str = "Commands (type a letter and Enter):
+  u  show the share link and its QR code
+  c  copy the share link to the clipboard
+  p  pause or resume taking new uploads
+  r  rotate the tunnel hostname
+  s  show upload statistics
+  q  shut down";
+
+/// Everything the console commands act on.
+pub struct Console {
+    pub pool: SqlitePool,
+    pub config: AppConfig,
+    pub tunnel_info: Arc<RwLock<TunnelInfo>>,
+    pub tunnel: Arc<TunnelSupervisor>,
+}
+
+impl Console {
+    /// Reads commands from stdin until `q` or EOF.
+    pub async fn run(&self) {
+        let mut reader = BufReader::new(tokio::io::stdin());
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line).await {
+                Ok(0) | Err(_) => break, // EOF
+                Ok(_) => {}
+            }
+            match line.trim().to_ascii_lowercase().as_str() {
+                "" => {}
+                "q" => break,
+                "u" => self.share().await,
+                "c" => self.copy().await,
+                "p" => self.toggle_paused(),
+                "r" => self.rotate().await,
+                "s" => self.stats().await,
+                "h" | "?" => println!("{}", HELP),
+                other => println!("Unknown command {:?}; type h for help", other),
+            }
+        }
+    }
+
+    async fn share_url(&self) -> Option<String> {
+        let url = self.tunnel_info.read().await.url.clone().or(self.config.public_url.clone());
+        if url.is_none() {
+            println!("No share link yet");
+        }
+        url
+    }
+
+    async fn share(&self) {
+        let Some(url) = self.share_url().await else { return };
+        match QrCode::new(url.as_bytes()) {
+            Ok(code) => println!("{}", code.render::<unicode::Dense1x2>().quiet_zone(true).build()),
+            Err(e) => warn!("⚠️ Failed to draw a QR code for {}: {}", url, e),
+        }
+        println!("Share: {}", url);
+        if let Some(short) = share::short_link(&self.pool, &self.config, &url).await {
+            println!("Short: {}", short);
+        }
+    }
+
+    /// Copies the short link if there is one, as that's what `--copy-url` copies too.
+    async fn copy(&self) {
+        let Some(url) = self.share_url().await else { return };
+        let short = share::short_link(&self.pool, &self.config, &url).await;
+        share::copy(short.unwrap_or(url));
+    }
+
+    fn toggle_paused(&self) {
+        if window::toggle_paused() {
+            info!("⏸️ Paused: new uploads are turned away ({} in progress continue)", registry::active_count());
+        } else {
+            info!("▶️ Resumed: accepting new uploads");
+        }
+    }
+
+    async fn rotate(&self) {
+        info!("🔄 Rotating the tunnel hostname…");
+        match self.tunnel.rotate().await {
+            Ok(hostname) => info!("🔗 New hostname: {}", hostname),
+            Err(e) => warn!("⚠️ Failed to rotate the tunnel: {}", e),
+        }
+    }
+
+    async fn stats(&self) {
+        let stats = db::get_upload_stats(&self.pool).await;
+        let clients = db::get_connected_clients(&self.pool).await.len();
+        println!("Uploads: {} total, {} in progress ({} live here), {} complete, {} disconnected",
+            stats.uploads, stats.in_progress, registry::active_count(), stats.complete, stats.disconnected);
+        println!("Stored: {:.2}", Byte::from_u64(stats.bytes.max(0) as u64).get_appropriate_unit(UnitType::Binary));
+        println!("Clients connected: {}", clients);
+        let slots = fairness::snapshot();
+        if let Some(limit) = slots.limit {
+            println!("Chunk slots: {} of {} busy, {} queued", slots.busy, limit, slots.clients.iter().map(|c| c.queued).sum::<usize>());
+        }
+        println!("New uploads: {}", if window::is_closed() {
+            "closed"
+        } else if window::is_paused() {
+            "paused"
+        } else {
+            "accepted"
+        });
+    }
+}
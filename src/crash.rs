@@ -0,0 +1,176 @@
+//! Crash reports: a panic hook writes what happened to `crashes/<id>.json` next to the
+//! database, with the backtrace, the version, the options drcv ran with (secrets redacted) and
+//! the last log lines. Reports nobody dismissed yet are logged on the next start and shown on
+//! the admin dashboard.
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use axum::{extract::Path as UrlPath, http::StatusCode, Json};
+use chrono::Utc;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use log::warn;
+use crate::{config, error::ApiError};
+
+pub const CRASH_DIR: &str = "crashes";
+/// Holds the id of the newest report dismissed in the admin dashboard
+const DISMISSED: &str = "dismissed";
+/// Log lines kept for the next report
+const LOG_LINES: usize = 200;
+
+static LOG: Lazy<Mutex<VecDeque<String>>> = Lazy::new(Default::default);
+static ARGS: OnceCell<Vec<String>> = OnceCell::new();
+
+#[derive(Serialize, Deserialize)]
+pub struct CrashReport {
+    /// `20261015-020430-123`, also the file name; sorts by time
+    pub id: String,
+    pub time: String,
+    pub version: String,
+    pub os: String,
+    pub arch: String,
+    pub thread: String,
+    pub message: String,
+    pub location: Option<String>,
+    pub backtrace: Vec<String>,
+    pub options: Vec<String>,
+    pub log: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct CrashSummary {
+    pub id: String,
+    pub time: String,
+    pub version: String,
+    pub message: String,
+    pub location: Option<String>,
+    pub dismissed: bool,
+}
+
+/// Passes log records on to `inner` and keeps the last few for crash reports.
+struct Recorder(env_logger::Logger);
+
+impl log::Log for Recorder {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.0.matches(record) {
+            return;
+        }
+        // A panic while the ring is locked must not deadlock the hook
+        if let Ok(mut log) = LOG.try_lock() {
+            log.push_back(format!("{} {} {}] {}", Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true), record.level(), record.target(), record.args()));
+            while log.len() > LOG_LINES {
+                log.pop_front();
+            }
+        }
+        self.0.log(record);
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
+}
+
+/// Installs `logger` as the global logger, recording its lines for crash reports.
+pub fn install_logger(logger: env_logger::Logger) {
+    let max_level = logger.filter();
+    if log::set_boxed_logger(Box::new(Recorder(logger))).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Writes a report for every panic from now on, then reports it as before.
+pub fn install_hook() {
+    let _ = ARGS.set(config::redacted_args());
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "(no message)".to_string());
+        let now = Utc::now();
+        let report = CrashReport {
+            id: now.format("%Y%m%d-%H%M%S-%3f").to_string(),
+            time: now.to_rfc3339(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            thread: std::thread::current().name().unwrap_or("unnamed").to_string(),
+            message,
+            location: info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column())),
+            backtrace: std::backtrace::Backtrace::force_capture().to_string().lines().map(str::to_string).collect(),
+            options: ARGS.get().cloned().unwrap_or_default(),
+            log: LOG.try_lock().map(|log| log.iter().cloned().collect()).unwrap_or_default(),
+        };
+        match write(&report) {
+            Ok(path) => eprintln!("💥 Crash report written to {}", path.display()),
+            Err(e) => eprintln!("💥 Failed to write a crash report: {}", e),
+        }
+        previous(info);
+    }));
+}
+
+fn write(report: &CrashReport) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(CRASH_DIR)?;
+    let path = Path::new(CRASH_DIR).join(format!("{}.json", report.id));
+    std::fs::write(&path, serde_json::to_vec_pretty(report)?)?;
+    Ok(path)
+}
+
+fn dismissed_up_to() -> String {
+    std::fs::read_to_string(Path::new(CRASH_DIR).join(DISMISSED)).unwrap_or_default().trim().to_string()
+}
+
+/// All reports, newest first.
+fn reports() -> Vec<CrashReport> {
+    let Ok(entries) = std::fs::read_dir(CRASH_DIR) else { return Vec::new() };
+    let mut reports: Vec<CrashReport> = entries.flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| std::fs::read(entry.path()).ok())
+        .filter_map(|bytes| serde_json::from_slice(&bytes).ok())
+        .collect();
+    reports.sort_by(|a, b| b.id.cmp(&a.id));
+    reports
+}
+
+fn summaries() -> Vec<CrashSummary> {
+    let dismissed = dismissed_up_to();
+    reports().into_iter().map(|report| CrashSummary {
+        dismissed: report.id <= dismissed,
+        id: report.id,
+        time: report.time,
+        version: report.version,
+        message: report.message,
+        location: report.location,
+    }).collect()
+}
+
+/// Logs the reports not dismissed yet, at startup.
+pub fn announce() {
+    for crash in summaries().into_iter().filter(|crash| !crash.dismissed) {
+        warn!("💥 drcv crashed at {}: {} (see {}/{}.json)", crash.time, crash.message, CRASH_DIR, crash.id);
+    }
+}
+
+/// `GET /crashes`: newest first.
+pub async fn admin_crashes() -> Json<Vec<CrashSummary>> {
+    Json(summaries())
+}
+
+/// `GET /crashes/:id`
+pub async fn admin_crash(UrlPath(id): UrlPath<String>) -> Result<Json<CrashReport>, ApiError> {
+    reports().into_iter().find(|report| report.id == id)
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("crash_not_found", format!("Unknown crash report: {}", id)))
+}
+
+/// `POST /crashes/dismiss`: hides the current reports from the dashboard and startup log.
+pub async fn admin_dismiss_crashes() -> Result<StatusCode, ApiError> {
+    let Some(newest) = reports().into_iter().next() else { return Ok(StatusCode::NO_CONTENT) };
+    std::fs::write(Path::new(CRASH_DIR).join(DISMISSED), newest.id)
+        .map_err(|e| ApiError::internal("crash_dismiss_failed", format!("Failed to dismiss crash reports: {}", e)))?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,16 +1,37 @@
-use sqlx::{SqlitePool, sqlite::SqlitePoolOptions};
+use sqlx::{SqlitePool, sqlite::{SqliteAutoVacuum, SqliteConnectOptions, SqlitePoolOptions, SqliteRow, SqliteSynchronous}};
+use serde::{Deserialize, Serialize};
 use sqlx::Row;
 use std::str::FromStr;
-use log::{error, warn};
-use crate::utils;
+use log::{debug, error, warn};
+use crate::{config::{AppConfig, DbSynchronous}, events::{self, Event}, utils};
 
-pub async fn init_pool() -> Result<SqlitePool, sqlx::Error> {
+pub const DB_FILE: &str = "drcv.db";
+
+pub async fn init_pool(config: &AppConfig) -> Result<SqlitePool, sqlx::Error> {
+    init_pool_at(&format!("sqlite:{}", DB_FILE), config).await
+}
+
+/// Opens the database with `--db-pool-size` connections, each set up with the `--db-synchronous`,
+/// `--db-cache-size` and `--db-mmap-size` pragmas.
+pub async fn init_pool_at(url: &str, config: &AppConfig) -> Result<SqlitePool, sqlx::Error> {
+    let synchronous = match config.db_synchronous {
+        DbSynchronous::Off => SqliteSynchronous::Off,
+        DbSynchronous::Normal => SqliteSynchronous::Normal,
+        DbSynchronous::Full => SqliteSynchronous::Full,
+        DbSynchronous::Extra => SqliteSynchronous::Extra,
+    };
     let pool = SqlitePoolOptions::new()
-        .max_connections(5)
+        .max_connections(config.db_pool_size)
         .connect_with(
-            sqlx::sqlite::SqliteConnectOptions::from_str("sqlite:drcv.db")
+            SqliteConnectOptions::from_str(url)
                 .unwrap()
                 .create_if_missing(true)
+                .synchronous(synchronous)
+                // Only takes effect for new databases; `drcv db compact` converts older ones
+                .auto_vacuum(SqliteAutoVacuum::Incremental)
+                // A negative cache_size is in KiB rather than pages
+                .pragma("cache_size", format!("-{}", (config.db_cache_size / 1024).max(1)))
+                .pragma("mmap_size", config.db_mmap_size.to_string())
         ).await?;
 
     sqlx::query(r#"
@@ -18,7 +39,7 @@ pub async fn init_pool() -> Result<SqlitePool, sqlx::Error> {
             id           INTEGER PRIMARY KEY AUTOINCREMENT,
             filename     TEXT NOT NULL,
             size         INTEGER NOT NULL DEFAULT 0,
-            status       TEXT NOT NULL,         -- init | uploading | complete | disconnected
+            status       TEXT NOT NULL,         -- init | uploading | assembling | received | quarantined | moving | complete | suspicious | disconnected | abandoned | external
             client_ip    TEXT NOT NULL,
             started_at   TEXT NOT NULL,
             updated_at   TEXT NOT NULL,
@@ -28,6 +49,87 @@ pub async fn init_pool() -> Result<SqlitePool, sqlx::Error> {
 
     sqlx::query("CREATE INDEX IF NOT EXISTS idx_uploads_updated_at ON uploads(updated_at)")
         .execute(&pool).await?;
+    // --max-files-per-client and --max-files-per-client-per-day
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_uploads_client_ip_started_at ON uploads(client_ip, started_at)")
+        .execute(&pool).await?;
+
+    ensure_column(&pool, "uploads", "instance_id", "TEXT").await?;
+    ensure_column(&pool, "uploads", "total_chunks", "INTEGER").await?;
+    // Bytes received on the wire, smaller than size when chunks arrive compressed
+    ensure_column(&pool, "uploads", "wire_bytes", "INTEGER NOT NULL DEFAULT 0").await?;
+    // Size of the whole file as announced by the sender, when it does
+    ensure_column(&pool, "uploads", "declared_size", "INTEGER").await?;
+    // Optional address the sender left for resume reminders
+    ensure_column(&pool, "uploads", "sender_email", "TEXT").await?;
+    ensure_column(&pool, "uploads", "resume_requested_at", "TEXT").await?;
+    // Name the sender gave, required by --require-sender-name
+    ensure_column(&pool, "uploads", "sender_name", "TEXT").await?;
+    // Name the file was stored under in the upload directory, after --rename-template
+    ensure_column(&pool, "uploads", "stored_name", "TEXT").await?;
+    // Consent to --terms-file the upload was started under
+    ensure_column(&pool, "uploads", "consent_id", "INTEGER").await?;
+    // Why --validate-cmd or a plugin is holding the upload back, once it has said
+    ensure_column(&pool, "uploads", "quarantine_reason", "TEXT").await?;
+    // Notes --plugin hooks left on the upload
+    ensure_column(&pool, "uploads", "annotation", "TEXT").await?;
+    // Subdirectory and comma-separated tags --route-script gave the upload
+    ensure_column(&pool, "uploads", "dest_dir", "TEXT").await?;
+    ensure_column(&pool, "uploads", "tags", "TEXT").await?;
+    // The file's modification time on the sender's side, and the page it was sent from
+    ensure_column(&pool, "uploads", "last_modified", "TEXT").await?;
+    ensure_column(&pool, "uploads", "origin", "TEXT").await?;
+    // What the services --virustotal-api-key and friends enable said about the file's hash
+    ensure_column(&pool, "uploads", "hash_verdict", "TEXT").await?;
+    ensure_column(&pool, "uploads", "hash_verdict_detail", "TEXT").await?;
+    // Why --check-file-types marked a stored upload suspicious
+    ensure_column(&pool, "uploads", "suspicious_reason", "TEXT").await?;
+    // The sender's SHA-256 of the whole file; one that doesn't match it is discarded, not stored
+    ensure_column(&pool, "uploads", "sha256", "TEXT").await?;
+    // How the file was sent: `http` (POST /upload) or `tus`; none for files copied in by hand
+    ensure_column(&pool, "uploads", "transport", "TEXT").await?;
+    // The upload link (`/u/<token>`) the file was sent through, if any
+    ensure_column(&pool, "uploads", "link_id", "INTEGER").await?;
+
+    // Chunks appended to each upload's .part file, so a restart can tell what's on disk whole
+    sqlx::query(r#"
+        CREATE TABLE IF NOT EXISTS upload_chunks (
+            upload_id    INTEGER NOT NULL,
+            chunk_index  INTEGER NOT NULL,
+            byte_offset  INTEGER NOT NULL,
+            size         INTEGER NOT NULL,
+            received_at  TEXT NOT NULL,
+            PRIMARY KEY (upload_id, chunk_index)
+        )
+    "#).execute(&pool).await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_upload_chunks_received_at ON upload_chunks(received_at)")
+        .execute(&pool).await?;
+
+    // Chunks being received right now, so --parallel-chunks holds for an upload whichever
+    // instance its chunks reach; rows a crash left behind expire with the chunk's timeout
+    sqlx::query(r#"
+        CREATE TABLE IF NOT EXISTS chunks_in_flight (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            upload_id   INTEGER NOT NULL,
+            started_at  TEXT NOT NULL
+        )
+    "#).execute(&pool).await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_chunks_in_flight_upload_id ON chunks_in_flight(upload_id)")
+        .execute(&pool).await?;
+
+    sqlx::query(r#"
+        CREATE TABLE IF NOT EXISTS consents (
+            id             INTEGER PRIMARY KEY AUTOINCREMENT,
+            client_ip      TEXT NOT NULL,
+            terms_version  TEXT NOT NULL,
+            user_agent     TEXT,
+            accepted_at    TEXT NOT NULL
+        )
+    "#).execute(&pool).await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_consents_client_ip ON consents(client_ip)")
+        .execute(&pool).await?;
 
     sqlx::query(r#"
         CREATE TABLE IF NOT EXISTS clients (
@@ -40,6 +142,41 @@ pub async fn init_pool() -> Result<SqlitePool, sqlx::Error> {
         )
     "#).execute(&pool).await?;
 
+    ensure_column(&pool, "clients", "directive", "TEXT").await?;
+    ensure_column(&pool, "clients", "instance_id", "TEXT").await?;
+    // How the client last sent a chunk, like uploads.transport
+    ensure_column(&pool, "clients", "transport", "TEXT").await?;
+
+    sqlx::query(r#"
+        CREATE TABLE IF NOT EXISTS instances (
+            instance_id  TEXT PRIMARY KEY,
+            internal_url TEXT,
+            last_seen    TEXT NOT NULL
+        )
+    "#).execute(&pool).await?;
+
+    sqlx::query(r#"
+        CREATE TABLE IF NOT EXISTS leases (
+            name         TEXT PRIMARY KEY,
+            holder       TEXT NOT NULL,
+            expires_at   TEXT NOT NULL
+        )
+    "#).execute(&pool).await?;
+
+    sqlx::query(r#"
+        CREATE TABLE IF NOT EXISTS events (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind         TEXT NOT NULL,
+            client_ip    TEXT,
+            message      TEXT NOT NULL,
+            data         TEXT NOT NULL,
+            created_at   TEXT NOT NULL
+        )
+    "#).execute(&pool).await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_events_created_at ON events(created_at)")
+        .execute(&pool).await?;
+
     sqlx::query(r#"
         CREATE TABLE IF NOT EXISTS kv (
             k TEXT PRIMARY KEY,
@@ -47,9 +184,129 @@ pub async fn init_pool() -> Result<SqlitePool, sqlx::Error> {
         )
     "#).execute(&pool).await?;
 
+    // Read-only keys for external dashboards; only a hash of each key is kept
+    sqlx::query(r#"
+        CREATE TABLE IF NOT EXISTS api_keys (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            name         TEXT NOT NULL UNIQUE,
+            key_hash     TEXT NOT NULL UNIQUE,
+            scopes       TEXT NOT NULL,
+            created_at   TEXT NOT NULL,
+            last_used_at TEXT
+        )
+    "#).execute(&pool).await?;
+
+    // Shareable upload links minted from the admin app, each with its own limits
+    sqlx::query(r#"
+        CREATE TABLE IF NOT EXISTS links (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            token        TEXT NOT NULL UNIQUE,
+            label        TEXT,
+            created_at   TEXT NOT NULL,
+            expires_at   TEXT,
+            max_bytes    INTEGER,
+            max_files    INTEGER,
+            allowed_extensions TEXT,
+            revoked_at   TEXT
+        )
+    "#).execute(&pool).await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_uploads_link_id ON uploads(link_id)")
+        .execute(&pool).await?;
+
+    // With --trace-uploads, every request made about an upload, for GET /uploads/:id/trace
+    sqlx::query(r#"
+        CREATE TABLE IF NOT EXISTS upload_traces (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            upload_id    INTEGER NOT NULL,
+            method       TEXT NOT NULL,
+            uri          TEXT NOT NULL,
+            request_bytes INTEGER,
+            status       INTEGER NOT NULL,
+            code         TEXT,
+            chunk_index  INTEGER,
+            byte_offset  INTEGER,
+            bytes        INTEGER,
+            started_at   TEXT NOT NULL,
+            duration_ms  INTEGER NOT NULL
+        )
+    "#).execute(&pool).await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_upload_traces_upload_id ON upload_traces(upload_id)")
+        .execute(&pool).await?;
+
+    ensure_search_index(&pool).await?;
+
     Ok(pool)
 }
 
+const SEARCH_COLUMNS: &str = "filename, stored_name, tags, sender_name, annotation";
+
+/// Full-text index over the names, tags and notes of uploads for `GET /search`, kept in step
+/// with the uploads table by triggers. Built from the existing rows when first created.
+async fn ensure_search_index(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let exists = sqlx::query("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'uploads_fts'")
+        .fetch_optional(pool).await?
+        .is_some();
+    if exists {
+        return Ok(());
+    }
+    let new_values = SEARCH_COLUMNS.split(", ").map(|c| format!("new.{}", c)).collect::<Vec<_>>().join(", ");
+    let old_values = SEARCH_COLUMNS.split(", ").map(|c| format!("old.{}", c)).collect::<Vec<_>>().join(", ");
+    let mut tx = pool.begin().await?;
+    // Letters are folded to ASCII without accents; prefix indexes keep `report*` queries fast
+    sqlx::query(&format!(
+        r#"CREATE VIRTUAL TABLE uploads_fts USING fts5(
+               {}, content = 'uploads', content_rowid = 'id',
+               tokenize = 'unicode61 remove_diacritics 2', prefix = '2 3'
+           )"#, SEARCH_COLUMNS))
+        .execute(&mut *tx).await?;
+    for statement in [
+        format!("CREATE TRIGGER uploads_fts_insert AFTER INSERT ON uploads BEGIN
+                     INSERT INTO uploads_fts(rowid, {0}) VALUES (new.id, {1});
+                 END", SEARCH_COLUMNS, new_values),
+        format!("CREATE TRIGGER uploads_fts_delete AFTER DELETE ON uploads BEGIN
+                     INSERT INTO uploads_fts(uploads_fts, rowid, {0}) VALUES ('delete', old.id, {1});
+                 END", SEARCH_COLUMNS, old_values),
+        format!("CREATE TRIGGER uploads_fts_update AFTER UPDATE OF {0} ON uploads BEGIN
+                     INSERT INTO uploads_fts(uploads_fts, rowid, {0}) VALUES ('delete', old.id, {1});
+                     INSERT INTO uploads_fts(rowid, {0}) VALUES (new.id, {2});
+                 END", SEARCH_COLUMNS, old_values, new_values),
+        "INSERT INTO uploads_fts(uploads_fts) VALUES ('rebuild')".to_string(),
+    ] {
+        sqlx::query(&statement).execute(&mut *tx).await?;
+    }
+    tx.commit().await
+}
+
+/// Completed uploads matching the FTS5 query `query`, best match first, with the matching part
+/// of the first column that has one marked up as `[...]`.
+pub async fn search_uploads(pool: &SqlitePool, query: &str, limit: i64, offset: i64) -> Result<Vec<(UploadData, String)>, sqlx::Error> {
+    let columns = UPLOAD_COLUMNS.split(", ").map(|c| format!("u.{}", c)).collect::<Vec<_>>().join(", ");
+    let rows = sqlx::query(&format!(
+        r#"SELECT {}, snippet(uploads_fts, -1, '[', ']', '…', 12) AS snippet
+           FROM uploads_fts JOIN uploads u ON u.id = uploads_fts.rowid
+           WHERE uploads_fts MATCH ?1 AND u.status IN ('complete', 'suspicious')
+           ORDER BY bm25(uploads_fts, 10.0, 10.0, 5.0, 5.0, 1.0)
+           LIMIT ?2 OFFSET ?3"#, columns))
+        .bind(query)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool).await?;
+    Ok(rows.iter().map(|row| (UploadData::from_row(row), row.get("snippet"))).collect())
+}
+
+async fn ensure_column(pool: &SqlitePool, table: &str, column: &str, decl: &str) -> Result<(), sqlx::Error> {
+    let rows = sqlx::query(&format!("PRAGMA table_info({})", table))
+        .fetch_all(pool).await?;
+    if rows.iter().any(|r| r.get::<String, _>("name") == column) {
+        return Ok(());
+    }
+    sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl))
+        .execute(pool).await?;
+    Ok(())
+}
+
 pub async fn kv_get(pool: &SqlitePool, key: &str) -> Option<String> {
     if let Ok(row) = sqlx::query("SELECT v FROM kv WHERE k = ?1")
         .bind(key)
@@ -68,17 +325,260 @@ pub async fn kv_set(pool: &SqlitePool, key: &str, value: &str) {
         .execute(pool).await;
 }
 
-pub async fn init_upload(pool: &SqlitePool, filename: &str, client_ip: &str) -> i64 {
-    match sqlx::query("SELECT id FROM uploads WHERE filename = ?1 AND client_ip = ?2 AND status != 'complete'")
+pub async fn touch_instance(pool: &SqlitePool, instance_id: &str, internal_url: Option<&str>) {
+    sqlx::query(
+        r#"INSERT INTO instances (instance_id, internal_url, last_seen)
+           VALUES (?1, ?2, ?3)
+           ON CONFLICT(instance_id) DO UPDATE SET
+           internal_url = ?2,
+           last_seen = ?3"#)
+        .bind(instance_id)
+        .bind(internal_url)
+        .bind(utils::now())
+        .execute(pool).await.map_err(|e| {
+            error!("Failed to register instance: {}", e);
+            e
+        }).ok();
+}
+
+/// Takes or renews the named lease; returns whether `holder` owns it afterwards.
+pub async fn acquire_lease(pool: &SqlitePool, name: &str, holder: &str, ttl_seconds: i64) -> bool {
+    let now = chrono::Utc::now();
+    let expires_at = (now + chrono::Duration::seconds(ttl_seconds)).to_rfc3339();
+
+    if let Err(e) = sqlx::query(
+        r#"INSERT INTO leases (name, holder, expires_at)
+           VALUES (?1, ?2, ?3)
+           ON CONFLICT(name) DO UPDATE SET
+           holder = excluded.holder,
+           expires_at = excluded.expires_at
+           WHERE leases.holder = excluded.holder OR leases.expires_at < ?4"#)
+        .bind(name)
+        .bind(holder)
+        .bind(&expires_at)
+        .bind(now.to_rfc3339())
+        .execute(pool).await {
+        error!("Failed to acquire lease {}: {}", name, e);
+        return false;
+    }
+
+    sqlx::query("SELECT holder FROM leases WHERE name = ?1")
+        .bind(name)
+        .fetch_optional(pool).await
+        .ok()
+        .flatten()
+        .is_some_and(|r| r.get::<String, _>("holder") == holder)
+}
+
+pub struct UploadOwner {
+    pub instance_id: String,
+    pub internal_url: Option<String>,
+    pub last_seen: Option<String>,
+}
+
+pub async fn get_upload_owner(pool: &SqlitePool, filename: &str, client_ip: &str) -> Option<UploadOwner> {
+    let row = sqlx::query(
+        r#"SELECT u.instance_id, i.internal_url, i.last_seen
+           FROM uploads u LEFT JOIN instances i ON i.instance_id = u.instance_id
+           WHERE u.filename = ?1 AND u.client_ip = ?2 AND u.status NOT IN ('complete', 'suspicious', 'moving', 'abandoned') AND u.instance_id IS NOT NULL"#)
         .bind(filename)
         .bind(client_ip)
-        .fetch_optional(pool).await {
-        Ok(Some(row)) => {
-            return row.try_get::<i64, _>("id").unwrap_or_else(|e| {
+        .fetch_optional(pool).await
+        .map_err(|e| error!("Database error in get_upload_owner: {}", e))
+        .ok()??;
+
+    Some(UploadOwner {
+        instance_id: row.get("instance_id"),
+        internal_url: row.try_get::<Option<String>, _>("internal_url").ok().flatten(),
+        last_seen: row.try_get::<Option<String>, _>("last_seen").ok().flatten(),
+    })
+}
+
+#[derive(Serialize, Clone)]
+pub struct UploadData {
+    pub id: i64,
+    pub filename: String,
+    pub size: i64,
+    pub status: String,
+    pub client_ip: String,
+    pub started_at: String,
+    pub updated_at: String,
+    pub completed_at: Option<String>,
+    pub instance_id: Option<String>,
+    pub wire_bytes: i64,
+    pub declared_size: Option<i64>,
+    pub sender_email: Option<String>,
+    pub sender_name: Option<String>,
+    /// Set once complete; differs from `filename` with `--rename-template`
+    pub stored_name: Option<String>,
+    /// Set while `--validate-cmd` or a plugin holds back a `quarantined` upload
+    pub quarantine_reason: Option<String>,
+    /// Notes `--plugin` hooks left on the upload
+    pub annotation: Option<String>,
+    /// Subdirectory of the upload directory `--route-script` sends the file to
+    pub dest_dir: Option<String>,
+    /// Comma-separated tags from `--route-script`
+    pub tags: Option<String>,
+    /// Modification time of the file on the sender's side, if the page sent it
+    pub last_modified: Option<String>,
+    /// Origin of the page the file was sent from, e.g. `https://drop.example.com`
+    pub origin: Option<String>,
+    /// `clean`, `suspicious`, `malicious`, `unknown` or `error`, once the file's hash was looked up
+    pub hash_verdict: Option<String>,
+    /// What each service said, e.g. `VirusTotal: no engine of 72 flags it`
+    pub hash_verdict_detail: Option<String>,
+    /// Set on a `suspicious` upload: how its content contradicts its extension
+    pub suspicious_reason: Option<String>,
+    /// SHA-256 of the whole file, if the sender sent it; a stored file matched it
+    pub sha256: Option<String>,
+    /// `http` or `tus`, the protocol the sender used
+    pub transport: Option<String>,
+    /// The upload link it was sent through, if any
+    pub link_id: Option<i64>,
+    /// Only known for uploads live in this process
+    pub speed_bps: Option<f64>,
+}
+
+pub const UPLOAD_COLUMNS: &str = "id, filename, size, status, client_ip, started_at, updated_at, completed_at, instance_id, wire_bytes, declared_size, sender_email, sender_name, stored_name, quarantine_reason, annotation, dest_dir, tags, last_modified, origin, hash_verdict, hash_verdict_detail, suspicious_reason, sha256, transport, link_id";
+
+impl UploadData {
+    pub fn from_row(row: &SqliteRow) -> Self {
+        UploadData {
+            id: row.get("id"),
+            filename: row.get("filename"),
+            size: row.get("size"),
+            status: row.get("status"),
+            client_ip: row.get("client_ip"),
+            started_at: row.get("started_at"),
+            updated_at: row.get("updated_at"),
+            completed_at: row.try_get("completed_at").ok(),
+            instance_id: row.try_get("instance_id").ok(),
+            wire_bytes: row.get("wire_bytes"),
+            declared_size: row.try_get::<Option<i64>, _>("declared_size").ok().flatten(),
+            sender_email: row.try_get::<Option<String>, _>("sender_email").ok().flatten(),
+            sender_name: row.try_get::<Option<String>, _>("sender_name").ok().flatten(),
+            stored_name: row.try_get::<Option<String>, _>("stored_name").ok().flatten(),
+            quarantine_reason: row.try_get::<Option<String>, _>("quarantine_reason").ok().flatten(),
+            annotation: row.try_get::<Option<String>, _>("annotation").ok().flatten(),
+            dest_dir: row.try_get::<Option<String>, _>("dest_dir").ok().flatten(),
+            tags: row.try_get::<Option<String>, _>("tags").ok().flatten(),
+            last_modified: row.try_get::<Option<String>, _>("last_modified").ok().flatten(),
+            origin: row.try_get::<Option<String>, _>("origin").ok().flatten(),
+            hash_verdict: row.try_get::<Option<String>, _>("hash_verdict").ok().flatten(),
+            hash_verdict_detail: row.try_get::<Option<String>, _>("hash_verdict_detail").ok().flatten(),
+            suspicious_reason: row.try_get::<Option<String>, _>("suspicious_reason").ok().flatten(),
+            sha256: row.try_get::<Option<String>, _>("sha256").ok().flatten(),
+            transport: row.try_get::<Option<String>, _>("transport").ok().flatten(),
+            link_id: row.try_get::<Option<i64>, _>("link_id").ok().flatten(),
+            speed_bps: None,
+        }
+    }
+
+    /// In the upload directory: `complete`, or `suspicious` with `--check-file-types`.
+    pub fn is_stored(&self) -> bool {
+        matches!(self.status.as_str(), "complete" | "suspicious")
+    }
+}
+
+pub async fn get_upload(pool: &SqlitePool, id: i64) -> Option<UploadData> {
+    sqlx::query(&format!("SELECT {} FROM uploads WHERE id = ?1", UPLOAD_COLUMNS))
+        .bind(id)
+        .fetch_optional(pool).await
+        .map_err(|e| error!("Database error in get_upload: {}", e))
+        .ok()?
+        .map(|row| UploadData::from_row(&row))
+}
+
+pub async fn find_unfinished_upload(pool: &SqlitePool, filename: &str, client_ip: &str) -> Option<i64> {
+    sqlx::query("SELECT id FROM uploads WHERE filename = ?1 AND client_ip = ?2 AND status NOT IN ('complete', 'suspicious', 'moving', 'abandoned')")
+        .bind(filename)
+        .bind(client_ip)
+        .fetch_optional(pool).await
+        .map_err(|e| error!("Database error in find_unfinished_upload: {}", e))
+        .ok()?
+        .map(|row| row.get("id"))
+}
+
+/// Uploads ever accepted into this drop, finished or in progress, but not abandoned ones.
+pub async fn count_kept_uploads(pool: &SqlitePool) -> i64 {
+    sqlx::query("SELECT COUNT(*) AS n FROM uploads WHERE status != 'abandoned'")
+        .fetch_one(pool).await
+        .map(|row| row.get("n"))
+        .unwrap_or_else(|e| {
+            error!("Database error in count_kept_uploads: {}", e);
+            0
+        })
+}
+
+/// Uploads `client_ip` started at or after `since` (ever, without it), abandoned ones aside.
+pub async fn count_client_uploads(pool: &SqlitePool, client_ip: &str, since: Option<&str>) -> i64 {
+    sqlx::query("SELECT COUNT(*) AS n FROM uploads WHERE client_ip = ?1 AND started_at >= ?2 AND status != 'abandoned'")
+        .bind(client_ip)
+        .bind(since.unwrap_or(""))
+        .fetch_one(pool).await
+        .map(|row| row.get("n"))
+        .unwrap_or_else(|e| {
+            error!("Database error in count_client_uploads: {}", e);
+            0
+        })
+}
+
+/// Most recent upload of `filename` by `client_ip`, finished or not.
+pub async fn find_latest_upload(pool: &SqlitePool, filename: &str, client_ip: &str) -> Option<UploadData> {
+    sqlx::query(&format!("SELECT {} FROM uploads WHERE filename = ?1 AND client_ip = ?2 ORDER BY id DESC LIMIT 1", UPLOAD_COLUMNS))
+        .bind(filename)
+        .bind(client_ip)
+        .fetch_optional(pool).await
+        .map_err(|e| error!("Database error in find_latest_upload: {}", e))
+        .ok()?
+        .map(|row| UploadData::from_row(&row))
+}
+
+/// Disconnected uploads of the same file started by other clients, which a sender that
+/// switched devices may take over.
+pub async fn find_recyclable_uploads(pool: &SqlitePool, filename: &str, declared_size: u64, client_ip: &str) -> Vec<UploadData> {
+    sqlx::query(&format!(
+        "SELECT {} FROM uploads WHERE filename = ?1 AND declared_size = ?2 AND client_ip != ?3 AND status = 'disconnected' AND size > 0 ORDER BY size DESC",
+        UPLOAD_COLUMNS))
+        .bind(filename)
+        .bind(declared_size as i64)
+        .bind(client_ip)
+        .fetch_all(pool).await
+        .map_err(|e| error!("Database error in find_recyclable_uploads: {}", e))
+        .unwrap_or_default()
+        .iter()
+        .map(UploadData::from_row)
+        .collect()
+}
+
+/// Hands a disconnected upload over to another client; false if it's no longer disconnected.
+pub async fn reassign_upload(pool: &SqlitePool, id: i64, client_ip: &str) -> bool {
+    sqlx::query("UPDATE uploads SET client_ip = ?1, updated_at = ?2 WHERE id = ?3 AND status = 'disconnected'")
+        .bind(client_ip)
+        .bind(utils::now())
+        .bind(id)
+        .execute(pool).await
+        .map(|r| r.rows_affected() > 0)
+        .unwrap_or_else(|e| {
+            error!("Failed to reassign upload: {}", e);
+            false
+        })
+}
+
+pub async fn init_upload(pool: &SqlitePool, filename: &str, client_ip: &str, instance_id: Option<&str>, transport: &str) -> i64 {
+    const OPEN: &str = "filename = ?1 AND client_ip = ?2 AND status NOT IN ('complete', 'suspicious', 'moving', 'abandoned')";
+    let find_open = || async {
+        sqlx::query(&format!("SELECT id FROM uploads WHERE {}", OPEN))
+            .bind(filename)
+            .bind(client_ip)
+            .fetch_optional(pool).await
+            .map(|row| row.map(|row| row.try_get::<i64, _>("id").unwrap_or_else(|e| {
                 error!("Error getting upload id: {}", e);
                 0
-            });
-        },
+            })))
+    };
+    match find_open().await {
+        Ok(Some(id)) => return id,
         Ok(None) => {},
         Err(e) => {
             error!("Database error in init_upload: {}", e);
@@ -86,15 +586,19 @@ pub async fn init_upload(pool: &SqlitePool, filename: &str, client_ip: &str) ->
         }
     }
 
+    // Chunks of a new file sent in parallel must all land in one upload
     let now = utils::now();
-    match sqlx::query(
-        r#"INSERT INTO uploads(filename,size,status,client_ip,started_at,updated_at)
-           VALUES(?1, 0, 'init', ?2, ?3, ?3)"#)
+    match sqlx::query(&format!(
+        r#"INSERT INTO uploads(filename,size,status,client_ip,started_at,updated_at,instance_id,transport)
+           SELECT ?1, 0, 'init', ?2, ?3, ?3, ?4, ?5 WHERE NOT EXISTS (SELECT 1 FROM uploads WHERE {})"#, OPEN))
         .bind(filename)
         .bind(client_ip)
         .bind(&now)
+        .bind(instance_id)
+        .bind(transport)
         .execute(pool).await {
-        Ok(result) => result.last_insert_rowid(),
+        Ok(result) if result.rows_affected() == 1 => result.last_insert_rowid(),
+        Ok(_) => find_open().await.ok().flatten().unwrap_or(0),
         Err(e) => {
             error!("Failed to insert upload: {}", e);
             0
@@ -102,11 +606,202 @@ pub async fn init_upload(pool: &SqlitePool, filename: &str, client_ip: &str) ->
     }
 }
 
+pub async fn set_declared_size(pool: &SqlitePool, id: i64, declared_size: u64) {
+    sqlx::query("UPDATE uploads SET declared_size = ?1 WHERE id = ?2")
+        .bind(declared_size as i64)
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to set declared size: {}", e))
+        .ok();
+}
+
+pub async fn set_source_metadata(pool: &SqlitePool, id: i64, last_modified: Option<&str>, origin: Option<&str>) {
+    sqlx::query("UPDATE uploads SET last_modified = COALESCE(?1, last_modified), origin = COALESCE(?2, origin) WHERE id = ?3")
+        .bind(last_modified)
+        .bind(origin)
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to set the file's source metadata: {}", e))
+        .ok();
+}
+
+pub async fn set_sender_email(pool: &SqlitePool, id: i64, sender_email: &str) {
+    sqlx::query("UPDATE uploads SET sender_email = ?1 WHERE id = ?2")
+        .bind(sender_email)
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to set sender email: {}", e))
+        .ok();
+}
+
+pub async fn set_sender_name(pool: &SqlitePool, id: i64, sender_name: &str) {
+    sqlx::query("UPDATE uploads SET sender_name = ?1 WHERE id = ?2")
+        .bind(sender_name)
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to set sender name: {}", e))
+        .ok();
+}
+
+/// Records an acceptance of the terms; returns the consent id and timestamp.
+pub async fn record_consent(pool: &SqlitePool, client_ip: &str, terms_version: &str, user_agent: Option<&str>) -> Option<(i64, String)> {
+    let now = utils::now();
+    sqlx::query("INSERT INTO consents (client_ip, terms_version, user_agent, accepted_at) VALUES (?1, ?2, ?3, ?4)")
+        .bind(client_ip)
+        .bind(terms_version)
+        .bind(user_agent)
+        .bind(&now)
+        .execute(pool).await
+        .map(|r| (r.last_insert_rowid(), now))
+        .map_err(|e| error!("Failed to record consent: {}", e))
+        .ok()
+}
+
+/// Latest consent of `client_ip` to this version of the terms.
+pub async fn find_consent(pool: &SqlitePool, client_ip: &str, terms_version: &str) -> Option<i64> {
+    sqlx::query("SELECT id FROM consents WHERE client_ip = ?1 AND terms_version = ?2 ORDER BY id DESC LIMIT 1")
+        .bind(client_ip)
+        .bind(terms_version)
+        .fetch_optional(pool).await
+        .map_err(|e| error!("Database error in find_consent: {}", e))
+        .ok()?
+        .map(|row| row.get("id"))
+}
+
+pub async fn set_consent(pool: &SqlitePool, id: i64, consent_id: i64) {
+    sqlx::query("UPDATE uploads SET consent_id = ?1 WHERE id = ?2")
+        .bind(consent_id)
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to link consent: {}", e))
+        .ok();
+}
+
+pub async fn mark_resume_requested(pool: &SqlitePool, id: i64) {
+    sqlx::query("UPDATE uploads SET resume_requested_at = ?1 WHERE id = ?2")
+        .bind(utils::now())
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to record resume request: {}", e))
+        .ok();
+}
+
+/// Disconnected uploads of `client_ip` the admin asked to be resumed since they disconnected.
+pub async fn get_resume_requests(pool: &SqlitePool, client_ip: &str) -> Vec<UploadData> {
+    sqlx::query(&format!(
+        "SELECT {} FROM uploads WHERE client_ip = ?1 AND status = 'disconnected' AND resume_requested_at >= updated_at",
+        UPLOAD_COLUMNS))
+        .bind(client_ip)
+        .fetch_all(pool).await
+        .map_err(|e| error!("Database error in get_resume_requests: {}", e))
+        .unwrap_or_default()
+        .iter()
+        .map(UploadData::from_row)
+        .collect()
+}
+
+/// Overwrites the received size, e.g. with what was found on disk after a restart.
+pub async fn set_size(pool: &SqlitePool, id: i64, size: i64) {
+    sqlx::query("UPDATE uploads SET size = ?1, updated_at = ?2 WHERE id = ?3")
+        .bind(size)
+        .bind(utils::now())
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to set upload size: {}", e))
+        .ok();
+}
+
+/// Records a chunk appended at `byte_offset`; without an index it's numbered after the last one.
+pub async fn record_chunk(pool: &SqlitePool, id: i64, chunk_index: Option<u32>, byte_offset: i64, size: i64) {
+    sqlx::query(
+        r#"INSERT OR REPLACE INTO upload_chunks(upload_id, chunk_index, byte_offset, size, received_at)
+           SELECT ?1, COALESCE(?2, MAX(chunk_index) + 1, 0), ?3, ?4, ?5 FROM upload_chunks WHERE upload_id = ?1"#)
+        .bind(id)
+        .bind(chunk_index)
+        .bind(byte_offset)
+        .bind(size)
+        .bind(utils::now())
+        .execute(pool).await
+        .map_err(|e| error!("Failed to record chunk: {}", e))
+        .ok();
+}
+
+pub async fn has_chunk(pool: &SqlitePool, id: i64, chunk_index: u32) -> bool {
+    sqlx::query("SELECT 1 FROM upload_chunks WHERE upload_id = ?1 AND chunk_index = ?2")
+        .bind(id)
+        .bind(chunk_index)
+        .fetch_optional(pool).await
+        .map_err(|e| error!("Database error in has_chunk: {}", e))
+        .ok()
+        .flatten()
+        .is_some()
+}
+
+/// Recorded chunks of an upload as `(chunk_index, byte_offset, size)`, in file order.
+pub async fn get_upload_chunks(pool: &SqlitePool, id: i64) -> Vec<(u32, i64, i64)> {
+    sqlx::query("SELECT chunk_index, byte_offset, size FROM upload_chunks WHERE upload_id = ?1 ORDER BY byte_offset")
+        .bind(id)
+        .fetch_all(pool).await
+        .map_err(|e| error!("Database error in get_upload_chunks: {}", e))
+        .unwrap_or_default()
+        .iter()
+        .map(|r| (r.get::<i64, _>("chunk_index") as u32, r.get("byte_offset"), r.get("size")))
+        .collect()
+}
+
+/// Counts a chunk of upload `id` as in flight unless `limit` already are, returning the row
+/// to pass to `end_chunk`. Rows older than `timeout_seconds` are of requests that have timed
+/// out by now and no longer count.
+pub async fn begin_chunk(pool: &SqlitePool, id: i64, limit: u32, timeout_seconds: i64) -> Option<i64> {
+    let cutoff = (chrono::Utc::now() - chrono::Duration::seconds(timeout_seconds)).to_rfc3339();
+    sqlx::query("DELETE FROM chunks_in_flight WHERE upload_id = ?1 AND started_at < ?2")
+        .bind(id)
+        .bind(&cutoff)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to expire chunks in flight: {}", e))
+        .ok();
+    let inserted = sqlx::query(
+        r#"INSERT INTO chunks_in_flight(upload_id, started_at)
+           SELECT ?1, ?2 WHERE (SELECT COUNT(*) FROM chunks_in_flight WHERE upload_id = ?1) < ?3"#)
+        .bind(id)
+        .bind(utils::now())
+        .bind(limit)
+        .execute(pool).await;
+    match inserted {
+        Ok(result) => (result.rows_affected() == 1).then(|| result.last_insert_rowid()),
+        Err(e) => {
+            // Accounting is no reason to refuse a chunk
+            error!("Failed to count chunk in flight: {}", e);
+            Some(0)
+        }
+    }
+}
+
+pub async fn end_chunk(pool: &SqlitePool, slot: i64) {
+    sqlx::query("DELETE FROM chunks_in_flight WHERE id = ?1")
+        .bind(slot)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to end chunk in flight: {}", e))
+        .ok();
+}
+
+/// Forgets recorded chunks starting at or after `byte_offset`.
+pub async fn forget_chunks_from(pool: &SqlitePool, id: i64, byte_offset: i64) {
+    sqlx::query("DELETE FROM upload_chunks WHERE upload_id = ?1 AND byte_offset >= ?2")
+        .bind(id)
+        .bind(byte_offset)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to forget chunks: {}", e))
+        .ok();
+}
+
 pub async fn mark_uploading(pool: &SqlitePool, id: i64, delta_size: i64) {
     let now = utils::now();
     sqlx::query(
         r#"UPDATE uploads
-           SET size = size + ?1, status = 'uploading', updated_at = ?2
+           SET size = size + ?1,
+               status = CASE WHEN status IN ('assembling', 'received', 'quarantined', 'moving', 'complete', 'suspicious', 'abandoned') THEN status ELSE 'uploading' END,
+               updated_at = ?2
            WHERE id = ?3"#)
         .bind(delta_size)
         .bind(&now)
@@ -117,42 +812,390 @@ pub async fn mark_uploading(pool: &SqlitePool, id: i64, delta_size: i64) {
         }).ok();
 }
 
-pub async fn mark_complete(pool: &SqlitePool, id: i64) {
+pub async fn add_wire_bytes(pool: &SqlitePool, id: i64, wire_bytes: i64) {
+    sqlx::query("UPDATE uploads SET wire_bytes = wire_bytes + ?1 WHERE id = ?2")
+        .bind(wire_bytes)
+        .bind(id)
+        .execute(pool).await.map_err(|e| {
+            error!("Failed to record wire bytes: {}", e);
+            e
+        }).ok();
+}
+
+/// Keeps an upload from going stale; returns whether the upload is still in progress.
+pub async fn touch_upload(pool: &SqlitePool, id: i64, client_ip: &str) -> bool {
+    sqlx::query("UPDATE uploads SET updated_at = ?1 WHERE id = ?2 AND client_ip = ?3 AND status = 'uploading'")
+        .bind(utils::now())
+        .bind(id)
+        .bind(client_ip)
+        .execute(pool).await
+        .map(|r| r.rows_affected() > 0)
+        .map_err(|e| debug!("Heartbeat error for upload {}: {}", id, e))
+        .unwrap_or(false)
+}
+
+/// Applies accumulated progress `(id, size delta, wire bytes)` and heartbeats `(id, client_ip)` in one transaction.
+pub async fn apply_batch(pool: &SqlitePool, progress: &[(i64, i64, i64)], heartbeats: &[(i64, String)]) -> Result<(), sqlx::Error> {
+    let now = utils::now();
+    let mut tx = pool.begin().await?;
+    for (id, delta_size, wire_bytes) in progress {
+        sqlx::query(
+            r#"UPDATE uploads
+               SET size = size + ?1,
+                   wire_bytes = wire_bytes + ?2,
+                   status = CASE WHEN status IN ('assembling', 'received', 'quarantined', 'moving', 'complete', 'suspicious', 'abandoned') THEN status ELSE 'uploading' END,
+                   updated_at = ?3
+               WHERE id = ?4"#)
+            .bind(delta_size)
+            .bind(wire_bytes)
+            .bind(&now)
+            .bind(id)
+            .execute(&mut *tx).await?;
+    }
+    for (id, client_ip) in heartbeats {
+        sqlx::query("UPDATE uploads SET updated_at = ?1 WHERE id = ?2 AND client_ip = ?3 AND status = 'uploading'")
+            .bind(&now)
+            .bind(id)
+            .bind(client_ip)
+            .execute(&mut *tx).await?;
+    }
+    tx.commit().await
+}
+
+/// Complete, but still being moved from `--spool-dir` to `stored_name` in the upload directory.
+pub async fn mark_moving(pool: &SqlitePool, id: i64, stored_name: &str) {
+    sqlx::query("UPDATE uploads SET status = 'moving', updated_at = ?1, stored_name = ?2 WHERE id = ?3")
+        .bind(utils::now())
+        .bind(stored_name)
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to mark upload moving: {}", e))
+        .ok();
+}
+
+pub async fn get_moving_uploads(pool: &SqlitePool) -> Vec<UploadData> {
+    sqlx::query(&format!("SELECT {} FROM uploads WHERE status = 'moving' ORDER BY id", UPLOAD_COLUMNS))
+        .fetch_all(pool).await
+        .map(|rows| rows.iter().map(UploadData::from_row).collect())
+        .unwrap_or_else(|e| {
+            error!("Database error in get_moving_uploads: {}", e);
+            Vec::new()
+        })
+}
+
+pub async fn mark_complete(pool: &SqlitePool, id: i64, stored_name: &str) {
     let now = utils::now();
     sqlx::query(
         r#"UPDATE uploads
-           SET status = 'complete', updated_at = ?1, completed_at = ?1
+           SET status = 'complete', updated_at = ?1, completed_at = ?1, stored_name = ?3
            WHERE id = ?2"#)
         .bind(&now)
         .bind(id)
+        .bind(stored_name)
         .execute(pool).await.map_err(|e| {
             error!("Failed to mark upload complete: {}", e);
             e
         }).ok();
 }
 
-pub async fn update_client_heartbeat(pool: &SqlitePool, client_ip: &str, user_agent: Option<&str>) {
+/// Marks a stored upload `suspicious`: it's in the upload directory like a `complete` one.
+pub async fn mark_suspicious(pool: &SqlitePool, id: i64, reason: &str) {
+    sqlx::query("UPDATE uploads SET status = 'suspicious', suspicious_reason = ?1 WHERE id = ?2")
+        .bind(reason)
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to mark upload suspicious: {}", e))
+        .ok();
+}
+
+/// How drcv knows a file in the upload directory by name: a completed upload stored under
+/// it, or else a row `--watch-upload-dir` added for it.
+pub enum StoredFile {
+    Received,
+    External { id: i64, size: i64 },
+}
+
+pub async fn find_stored_file(pool: &SqlitePool, name: &str) -> Option<StoredFile> {
+    let rows = sqlx::query(
+        r#"SELECT id, size, status FROM uploads
+           WHERE COALESCE(stored_name, filename) = ?1 AND status IN ('complete', 'suspicious', 'external')"#)
+        .bind(name)
+        .fetch_all(pool).await
+        .map_err(|e| error!("Database error in find_stored_file: {}", e))
+        .ok()?;
+    if rows.iter().any(|row| row.get::<String, _>("status") != "external") {
+        return Some(StoredFile::Received);
+    }
+    rows.first().map(|row| StoredFile::External { id: row.get("id"), size: row.get("size") })
+}
+
+/// Records a file that appeared in the upload directory without going through drcv.
+pub async fn insert_external_file(pool: &SqlitePool, name: &str, size: i64) {
+    let now = utils::now();
+    let _ = sqlx::query(
+        r#"INSERT INTO uploads(filename, size, status, client_ip, started_at, updated_at, completed_at, stored_name)
+           VALUES(?1, ?2, 'external', 'local', ?3, ?3, ?3, ?1)"#)
+        .bind(name)
+        .bind(size)
+        .bind(&now)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to insert external file: {}", e));
+}
+
+pub async fn update_external_file(pool: &SqlitePool, id: i64, size: i64) {
+    let _ = sqlx::query("UPDATE uploads SET size = ?1, updated_at = ?2 WHERE id = ?3 AND status = 'external'")
+        .bind(size)
+        .bind(utils::now())
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to update external file: {}", e));
+}
+
+/// Forgets external rows by name, when the file is gone or an upload now owns the name.
+pub async fn delete_external_file(pool: &SqlitePool, name: &str) -> u64 {
+    sqlx::query("DELETE FROM uploads WHERE stored_name = ?1 AND status = 'external'")
+        .bind(name)
+        .execute(pool).await
+        .map(|r| r.rows_affected())
+        .map_err(|e| error!("Failed to delete external file: {}", e))
+        .unwrap_or(0)
+}
+
+pub async fn get_external_files(pool: &SqlitePool) -> Vec<String> {
+    sqlx::query("SELECT stored_name FROM uploads WHERE status = 'external'")
+        .fetch_all(pool).await
+        .map(|rows| rows.iter().map(|row| row.get("stored_name")).collect())
+        .unwrap_or_default()
+}
+
+/// All bytes are in, but the upload waits for `--validate-cmd` to pass the file.
+pub async fn mark_quarantined(pool: &SqlitePool, id: i64) {
+    sqlx::query("UPDATE uploads SET status = 'quarantined', updated_at = ?1, quarantine_reason = NULL WHERE id = ?2")
+        .bind(utils::now())
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to mark upload quarantined: {}", e))
+        .ok();
+}
+
+pub async fn set_hash_verdict(pool: &SqlitePool, id: i64, verdict: &str, detail: &str) {
+    sqlx::query("UPDATE uploads SET hash_verdict = ?1, hash_verdict_detail = ?2 WHERE id = ?3")
+        .bind(verdict)
+        .bind(detail)
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to record the hash lookup: {}", e))
+        .ok();
+}
+
+pub async fn set_sha256(pool: &SqlitePool, id: i64, sha256: &str) {
+    sqlx::query("UPDATE uploads SET sha256 = ?1 WHERE id = ?2")
+        .bind(sha256)
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to record the file's hash: {}", e))
+        .ok();
+}
+
+pub async fn set_quarantine_reason(pool: &SqlitePool, id: i64, reason: &str) {
+    sqlx::query("UPDATE uploads SET quarantine_reason = ?1, updated_at = ?2 WHERE id = ?3")
+        .bind(reason)
+        .bind(utils::now())
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to record the quarantine reason: {}", e))
+        .ok();
+}
+
+/// Records where `--route-script` sends the upload and how it tagged it.
+pub async fn set_route(pool: &SqlitePool, id: i64, dest_dir: Option<&str>, tags: &[String]) {
+    sqlx::query("UPDATE uploads SET dest_dir = ?1, tags = ?2 WHERE id = ?3")
+        .bind(dest_dir)
+        .bind((!tags.is_empty()).then(|| tags.join(",")))
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to record the upload's route: {}", e))
+        .ok();
+}
+
+pub async fn set_link(pool: &SqlitePool, id: i64, link_id: i64) {
+    sqlx::query("UPDATE uploads SET link_id = ?1 WHERE id = ?2")
+        .bind(link_id)
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to record the upload's link: {}", e))
+        .ok();
+}
+
+/// Appends a plugin's note to those already on the upload.
+pub async fn add_annotation(pool: &SqlitePool, id: i64, note: &str) {
+    sqlx::query("UPDATE uploads SET annotation = CASE WHEN annotation IS NULL THEN ?1 ELSE annotation || '; ' || ?1 END WHERE id = ?2")
+        .bind(note)
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to record the plugin note: {}", e))
+        .ok();
+}
+
+/// Quarantined uploads `--validate-cmd` hasn't given a verdict on, e.g. because the server
+/// stopped while it ran.
+pub async fn get_unvalidated_uploads(pool: &SqlitePool) -> Vec<UploadData> {
+    sqlx::query(&format!("SELECT {} FROM uploads WHERE status = 'quarantined' AND quarantine_reason IS NULL ORDER BY id", UPLOAD_COLUMNS))
+        .fetch_all(pool).await
+        .map(|rows| rows.iter().map(UploadData::from_row).collect())
+        .unwrap_or_else(|e| {
+            error!("Database error in get_unvalidated_uploads: {}", e);
+            Vec::new()
+        })
+}
+
+/// All bytes are in, but the upload waits for the sender to confirm its hash.
+pub async fn mark_received(pool: &SqlitePool, id: i64) {
+    sqlx::query("UPDATE uploads SET status = 'received', updated_at = ?1 WHERE id = ?2")
+        .bind(utils::now())
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to mark upload received: {}", e))
+        .ok();
+}
+
+/// The chunk count a v1 sender announced for an upload, recorded on its first chunk.
+pub async fn get_total_chunks(pool: &SqlitePool, id: i64) -> Option<u32> {
+    sqlx::query("SELECT total_chunks FROM uploads WHERE id = ?1")
+        .bind(id)
+        .fetch_optional(pool).await
+        .map_err(|e| error!("Database error in get_total_chunks: {}", e))
+        .ok()??
+        .try_get::<Option<i64>, _>("total_chunks").ok().flatten()
+        .map(|total| total as u32)
+}
+
+pub async fn set_total_chunks(pool: &SqlitePool, id: i64, total_chunks: u32) {
+    sqlx::query("UPDATE uploads SET total_chunks = ?1 WHERE id = ?2")
+        .bind(total_chunks)
+        .bind(id)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to set total chunks: {}", e))
+        .ok();
+}
+
+pub async fn mark_assembling(pool: &SqlitePool, id: i64, total_chunks: u32) {
+    sqlx::query(
+        r#"UPDATE uploads
+           SET status = 'assembling', total_chunks = ?1, updated_at = ?2
+           WHERE id = ?3"#)
+        .bind(total_chunks)
+        .bind(utils::now())
+        .bind(id)
+        .execute(pool).await.map_err(|e| {
+            error!("Failed to mark upload assembling: {}", e);
+            e
+        }).ok();
+}
+
+/// Gives up on an unfinished upload; returns false if it had already finished or been abandoned.
+pub async fn mark_abandoned(pool: &SqlitePool, id: i64) -> bool {
+    sqlx::query(
+        r#"UPDATE uploads
+           SET status = 'abandoned', updated_at = ?1
+           WHERE id = ?2 AND status NOT IN ('complete', 'suspicious', 'abandoned')"#)
+        .bind(utils::now())
+        .bind(id)
+        .execute(pool).await
+        .map(|r| r.rows_affected() > 0)
+        .unwrap_or_else(|e| {
+            error!("Failed to mark upload abandoned: {}", e);
+            false
+        })
+}
+
+/// Uploads disconnected, or left waiting for the finalize handshake, for longer than `timeout_seconds`.
+pub async fn get_expired_uploads(pool: &SqlitePool, timeout_seconds: i64) -> Vec<UploadData> {
+    let cutoff = (chrono::Utc::now() - chrono::Duration::seconds(timeout_seconds)).to_rfc3339();
+    sqlx::query(&format!("SELECT {} FROM uploads WHERE status IN ('disconnected', 'received') AND updated_at < ?1", UPLOAD_COLUMNS))
+        .bind(cutoff)
+        .fetch_all(pool).await
+        .map(|rows| rows.iter().map(UploadData::from_row).collect())
+        .unwrap_or_else(|e| {
+            error!("Database error in get_expired_uploads: {}", e);
+            Vec::new()
+        })
+}
+
+/// Uploads still waiting for bytes: started, in progress or disconnected.
+pub async fn get_incomplete_uploads(pool: &SqlitePool) -> Vec<UploadData> {
+    sqlx::query(&format!("SELECT {} FROM uploads WHERE status IN ('init', 'uploading', 'disconnected') ORDER BY id", UPLOAD_COLUMNS))
+        .fetch_all(pool).await
+        .map(|rows| rows.iter().map(UploadData::from_row).collect())
+        .unwrap_or_else(|e| {
+            error!("Database error in get_incomplete_uploads: {}", e);
+            Vec::new()
+        })
+}
+
+pub async fn get_assembling_uploads(pool: &SqlitePool) -> Vec<(i64, String, u32)> {
+    sqlx::query("SELECT id, filename, total_chunks FROM uploads WHERE status = 'assembling' ORDER BY id")
+        .fetch_all(pool).await
+        .map(|rows| rows.into_iter().map(|r| (
+            r.get::<i64, _>("id"),
+            r.get::<String, _>("filename"),
+            r.try_get::<i64, _>("total_chunks").unwrap_or(0) as u32,
+        )).collect())
+        .unwrap_or_else(|e| {
+            error!("Database error in get_assembling_uploads: {}", e);
+            Vec::new()
+        })
+}
+
+/// Marks the client connected; `transport` is given by requests that carry a chunk.
+pub async fn update_client_heartbeat(pool: &SqlitePool, client_ip: &str, user_agent: Option<&str>, instance_id: Option<&str>, transport: Option<&str>) {
     let now = utils::now();
     
     sqlx::query(
-        r#"INSERT INTO clients (client_ip, user_agent, first_seen, last_seen, status)
-           VALUES (?1, ?2, ?3, ?3, 'connected')
+        r#"INSERT INTO clients (client_ip, user_agent, first_seen, last_seen, status, instance_id, transport)
+           VALUES (?1, ?2, ?3, ?3, 'connected', ?4, ?5)
            ON CONFLICT(client_ip) DO UPDATE SET
            user_agent = COALESCE(?2, user_agent),
            last_seen = ?3,
-           status = 'connected'"#)
+           status = 'connected',
+           instance_id = COALESCE(?4, instance_id),
+           transport = COALESCE(?5, transport)"#)
         .bind(client_ip)
         .bind(user_agent)
         .bind(&now)
+        .bind(instance_id)
+        .bind(transport)
         .execute(pool).await.map_err(|e| {
             error!("Failed to update client heartbeat: {}", e);
             e
         }).ok();
 }
 
+pub async fn get_client_directive(pool: &SqlitePool, client_ip: &str) -> Option<String> {
+    sqlx::query("SELECT directive FROM clients WHERE client_ip = ?1")
+        .bind(client_ip)
+        .fetch_optional(pool).await
+        .ok()
+        .flatten()
+        .and_then(|r| r.try_get::<Option<String>, _>("directive").ok().flatten())
+}
+
+pub async fn set_client_directive(pool: &SqlitePool, client_ip: &str, directive: Option<&str>) -> bool {
+    match sqlx::query("UPDATE clients SET directive = ?1 WHERE client_ip = ?2")
+        .bind(directive)
+        .bind(client_ip)
+        .execute(pool).await {
+        Ok(result) => result.rows_affected() > 0,
+        Err(e) => {
+            error!("Failed to set client directive: {}", e);
+            false
+        }
+    }
+}
+
 pub async fn get_connected_clients(pool: &SqlitePool) -> Vec<serde_json::Value> {
     if let Ok(rows) = sqlx::query(
-        r#"SELECT client_ip, user_agent, first_seen, last_seen, status
+        r#"SELECT client_ip, user_agent, first_seen, last_seen, status, directive, instance_id, transport
            FROM clients 
            WHERE status = 'connected'
            ORDER BY last_seen DESC"#)
@@ -164,7 +1207,11 @@ pub async fn get_connected_clients(pool: &SqlitePool) -> Vec<serde_json::Value>
                 "user_agent": row.try_get::<String, _>("user_agent").ok(),
                 "first_seen": row.get::<String, _>("first_seen"),
                 "last_seen": row.get::<String, _>("last_seen"),
-                "status": row.get::<String, _>("status")
+                "status": row.get::<String, _>("status"),
+                "instance_id": row.try_get::<Option<String>, _>("instance_id").ok().flatten(),
+                "transport": row.try_get::<Option<String>, _>("transport").ok().flatten(),
+                "directive": row.try_get::<Option<String>, _>("directive").ok().flatten()
+                    .and_then(|d| serde_json::from_str::<serde_json::Value>(&d).ok())
             })
         }).collect()
     } else {
@@ -186,6 +1233,7 @@ pub async fn mark_stale_uploads_disconnected(pool: &SqlitePool, timeout_seconds:
             let filename: String = row.get("filename");
             let client_ip: String = row.get("client_ip");
             warn!("❌ Upload disconnected (heartbeat timeout): {} from {}", filename, client_ip);
+            events::publish(Event::UploadDisconnected { filename, client_ip });
         }
     }
     
@@ -214,3 +1262,514 @@ pub async fn mark_stale_clients_disconnected(pool: &SqlitePool, timeout_seconds:
             e
         }).ok();
 }
+
+pub async fn insert_event(pool: &SqlitePool, event: &Event) {
+    sqlx::query(
+        r#"INSERT INTO events (kind, client_ip, message, data, created_at)
+           VALUES (?1, ?2, ?3, ?4, ?5)"#)
+        .bind(event.name())
+        .bind(event.client_ip())
+        .bind(event.summary())
+        .bind(serde_json::to_string(event).unwrap_or_default())
+        .bind(utils::now())
+        .execute(pool).await.map_err(|e| {
+            error!("Failed to record event: {}", e);
+            e
+        }).ok();
+}
+
+/// One request about an upload, as recorded with `--trace-uploads`.
+#[derive(Serialize, Clone)]
+pub struct TraceEntry {
+    pub upload_id: i64,
+    pub method: String,
+    pub uri: String,
+    /// `Content-Length` of the request, if it had one
+    pub request_bytes: Option<i64>,
+    pub status: u16,
+    /// `code` of the error body, if the request failed
+    pub code: Option<String>,
+    pub chunk_index: Option<u32>,
+    /// Where the chunk starts, or for a resume query where the upload stood
+    pub byte_offset: Option<i64>,
+    /// Size of the chunk
+    pub bytes: Option<i64>,
+    pub started_at: String,
+    pub duration_ms: i64,
+}
+
+pub async fn insert_trace(pool: &SqlitePool, entry: &TraceEntry) {
+    sqlx::query(
+        r#"INSERT INTO upload_traces (upload_id, method, uri, request_bytes, status, code, chunk_index, byte_offset, bytes, started_at, duration_ms)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)"#)
+        .bind(entry.upload_id)
+        .bind(&entry.method)
+        .bind(&entry.uri)
+        .bind(entry.request_bytes)
+        .bind(entry.status)
+        .bind(&entry.code)
+        .bind(entry.chunk_index)
+        .bind(entry.byte_offset)
+        .bind(entry.bytes)
+        .bind(&entry.started_at)
+        .bind(entry.duration_ms)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to record trace: {}", e))
+        .ok();
+}
+
+/// The recorded requests of an upload, oldest first.
+pub async fn get_traces(pool: &SqlitePool, upload_id: i64) -> Vec<TraceEntry> {
+    sqlx::query("SELECT * FROM upload_traces WHERE upload_id = ?1 ORDER BY started_at, id")
+        .bind(upload_id)
+        .fetch_all(pool).await
+        .map_err(|e| error!("Database error in get_traces: {}", e))
+        .unwrap_or_default()
+        .iter()
+        .map(|r| TraceEntry {
+            upload_id: r.get("upload_id"),
+            method: r.get("method"),
+            uri: r.get("uri"),
+            request_bytes: r.get("request_bytes"),
+            status: r.get::<i64, _>("status") as u16,
+            code: r.get("code"),
+            chunk_index: r.get::<Option<i64>, _>("chunk_index").map(|i| i as u32),
+            byte_offset: r.get("byte_offset"),
+            bytes: r.get("bytes"),
+            started_at: r.get("started_at"),
+            duration_ms: r.get("duration_ms"),
+        })
+        .collect()
+}
+
+/// Distinct client addresses stored anywhere that aren't anonymized identities yet.
+pub async fn get_raw_client_ips(pool: &SqlitePool, anonymized_prefix: &str) -> Vec<String> {
+    sqlx::query(
+        r#"SELECT client_ip FROM uploads WHERE client_ip NOT LIKE ?1 || '%'
+           UNION SELECT client_ip FROM clients WHERE client_ip NOT LIKE ?1 || '%'
+           UNION SELECT client_ip FROM consents WHERE client_ip NOT LIKE ?1 || '%'
+           UNION SELECT client_ip FROM events WHERE client_ip IS NOT NULL AND client_ip NOT LIKE ?1 || '%'"#)
+        .bind(anonymized_prefix)
+        .fetch_all(pool).await
+        .map(|rows| rows.iter().map(|row| row.get("client_ip")).collect())
+        .unwrap_or_else(|e| {
+            error!("Database error in get_raw_client_ips: {}", e);
+            Vec::new()
+        })
+}
+
+/// Replaces a client address everywhere it's stored, including event messages.
+pub async fn rename_client_ip(pool: &SqlitePool, from: &str, to: &str) {
+    let rename = async {
+        let mut tx = pool.begin().await?;
+        for table in ["uploads", "consents"] {
+            sqlx::query(&format!("UPDATE {} SET client_ip = ?2 WHERE client_ip = ?1", table))
+                .bind(from)
+                .bind(to)
+                .execute(&mut *tx).await?;
+        }
+        // A client seen both before and after anonymization keeps the newer row
+        sqlx::query("UPDATE OR REPLACE clients SET client_ip = ?2 WHERE client_ip = ?1")
+            .bind(from)
+            .bind(to)
+            .execute(&mut *tx).await?;
+        sqlx::query(
+            r#"UPDATE events
+               SET client_ip = CASE WHEN client_ip = ?1 THEN ?2 ELSE client_ip END,
+                   message = REPLACE(message, ?1, ?2),
+                   data = REPLACE(data, '"' || ?1 || '"', '"' || ?2 || '"')
+               WHERE client_ip = ?1 OR instr(message, ?1) > 0"#)
+            .bind(from)
+            .bind(to)
+            .execute(&mut *tx).await?;
+        tx.commit().await
+    };
+    if let Err(e) = rename.await {
+        error!("Failed to rename client {}: {}", from, e);
+    }
+}
+
+/// Every upload of `client_ip`, in any state.
+pub async fn get_client_uploads(pool: &SqlitePool, client_ip: &str) -> Vec<UploadData> {
+    sqlx::query(&format!("SELECT {} FROM uploads WHERE client_ip = ?1 ORDER BY id", UPLOAD_COLUMNS))
+        .bind(client_ip)
+        .fetch_all(pool).await
+        .map(|rows| rows.iter().map(UploadData::from_row).collect())
+        .unwrap_or_else(|e| {
+            error!("Database error in get_client_uploads: {}", e);
+            Vec::new()
+        })
+}
+
+/// Rows kept about `client_ip` besides its uploads.
+#[derive(Serialize, Default)]
+pub struct ClientRecords {
+    pub events: i64,
+    pub consents: i64,
+    pub clients: i64,
+}
+
+/// Events about `client_ip`, including those naming only one of its uploads (e.g. completions).
+const CLIENT_EVENTS: &str = "client_ip = ?1 OR json_extract(data, '$.id') IN (SELECT id FROM uploads WHERE client_ip = ?1)";
+
+pub async fn count_client_records(pool: &SqlitePool, client_ip: &str) -> Result<ClientRecords, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        r#"SELECT (SELECT COUNT(*) FROM events WHERE {}) AS events,
+                  (SELECT COUNT(*) FROM consents WHERE client_ip = ?1) AS consents,
+                  (SELECT COUNT(*) FROM clients WHERE client_ip = ?1) AS clients"#, CLIENT_EVENTS))
+        .bind(client_ip)
+        .fetch_one(pool).await?;
+    Ok(ClientRecords { events: row.get("events"), consents: row.get("consents"), clients: row.get("clients") })
+}
+
+/// Deletes every row about `client_ip` in one transaction: uploads with their chunk
+/// records and traces, events, consents and the client entry.
+pub async fn purge_client(pool: &SqlitePool, client_ip: &str) -> Result<ClientRecords, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let events = sqlx::query(&format!("DELETE FROM events WHERE {}", CLIENT_EVENTS))
+        .bind(client_ip)
+        .execute(&mut *tx).await?
+        .rows_affected() as i64;
+    for table in ["upload_chunks", "upload_traces"] {
+        sqlx::query(&format!("DELETE FROM {} WHERE upload_id IN (SELECT id FROM uploads WHERE client_ip = ?1)", table))
+            .bind(client_ip)
+            .execute(&mut *tx).await?;
+    }
+    sqlx::query("DELETE FROM uploads WHERE client_ip = ?1")
+        .bind(client_ip)
+        .execute(&mut *tx).await?;
+    let mut deleted = ClientRecords { events, ..Default::default() };
+    for (table, count) in [("consents", &mut deleted.consents), ("clients", &mut deleted.clients)] {
+        *count = sqlx::query(&format!("DELETE FROM {} WHERE client_ip = ?1", table))
+            .bind(client_ip)
+            .execute(&mut *tx).await?
+            .rows_affected() as i64;
+    }
+    tx.commit().await?;
+    Ok(deleted)
+}
+
+/// Totals over all uploads, for the admin dashboard.
+#[derive(Serialize)]
+pub struct UploadStats {
+    pub uploads: i64,
+    pub in_progress: i64,
+    pub complete: i64,
+    pub disconnected: i64,
+    /// Bytes stored, in progress or complete
+    pub bytes: i64,
+}
+
+pub async fn get_upload_stats(pool: &SqlitePool) -> UploadStats {
+    sqlx::query(
+        r#"SELECT COUNT(*) AS uploads,
+                  COALESCE(SUM(status IN ('init', 'uploading', 'assembling', 'received', 'quarantined', 'moving')), 0) AS in_progress,
+                  COALESCE(SUM(status IN ('complete', 'suspicious')), 0) AS complete,
+                  COALESCE(SUM(status = 'disconnected'), 0) AS disconnected,
+                  COALESCE(SUM(CASE WHEN status != 'abandoned' THEN size ELSE 0 END), 0) AS bytes
+           FROM uploads"#)
+        .fetch_one(pool).await
+        .map(|row| UploadStats {
+            uploads: row.get("uploads"),
+            in_progress: row.get("in_progress"),
+            complete: row.get("complete"),
+            disconnected: row.get("disconnected"),
+            bytes: row.get("bytes"),
+        })
+        .unwrap_or_else(|e| {
+            error!("Database error in get_upload_stats: {}", e);
+            UploadStats { uploads: 0, in_progress: 0, complete: 0, disconnected: 0, bytes: 0 }
+        })
+}
+
+/// The most recently completed upload, by completion time.
+pub async fn get_last_completed_upload(pool: &SqlitePool) -> Option<UploadData> {
+    sqlx::query(&format!("SELECT {} FROM uploads WHERE status IN ('complete', 'suspicious') ORDER BY completed_at DESC, id DESC LIMIT 1", UPLOAD_COLUMNS))
+        .fetch_optional(pool).await
+        .map_err(|e| error!("Database error in get_last_completed_upload: {}", e))
+        .ok()?
+        .map(|row| UploadData::from_row(&row))
+}
+
+/// What a time series is computed from: journaled chunk sizes, or a count of recorded events.
+pub enum SeriesSource {
+    ChunkBytes,
+    Events(&'static str),
+}
+
+/// Sums a series into `step`-second buckets, aligned to the Unix epoch, of rows timestamped
+/// in `[from, to)`. Only buckets with rows are returned, as `(bucket start, value)`.
+pub async fn get_time_buckets(pool: &SqlitePool, source: &SeriesSource, from: &str, to: &str, step: i64) -> Vec<(i64, i64)> {
+    // Timestamps are RFC 3339 in UTC, so their first 19 characters are what strftime parses
+    let sql = match source {
+        SeriesSource::ChunkBytes =>
+            r#"SELECT CAST(strftime('%s', substr(received_at, 1, 19)) AS INTEGER) / ?3 * ?3 AS bucket, SUM(size) AS value
+               FROM upload_chunks WHERE received_at >= ?1 AND received_at < ?2
+               GROUP BY bucket ORDER BY bucket"#,
+        SeriesSource::Events(_) =>
+            r#"SELECT CAST(strftime('%s', substr(created_at, 1, 19)) AS INTEGER) / ?3 * ?3 AS bucket, COUNT(*) AS value
+               FROM events WHERE created_at >= ?1 AND created_at < ?2 AND kind = ?4
+               GROUP BY bucket ORDER BY bucket"#,
+    };
+    let mut query = sqlx::query(sql).bind(from).bind(to).bind(step);
+    if let SeriesSource::Events(kind) = source {
+        query = query.bind(*kind);
+    }
+    query.fetch_all(pool).await
+        .map_err(|e| error!("Database error in get_time_buckets: {}", e))
+        .unwrap_or_default()
+        .iter()
+        .map(|row| (row.get("bucket"), row.get("value")))
+        .collect()
+}
+
+/// Rows deleted by `prune_history`.
+#[derive(Default)]
+pub struct Pruned {
+    pub events: u64,
+    pub chunks: u64,
+    pub traces: u64,
+}
+
+/// Rows deleted per statement, so uploads writing meanwhile never wait long for the lock
+const PRUNE_BATCH: i64 = 5000;
+
+/// Deletes events older than `cutoff` and the chunk records and traces of uploads that were
+/// finished or abandoned before it. Unfinished uploads keep their chunk records, which resuming relies on.
+pub async fn prune_history(pool: &SqlitePool, cutoff: &str) -> Result<Pruned, sqlx::Error> {
+    let mut pruned = Pruned::default();
+    for (count, statement) in [
+        (&mut pruned.events, "DELETE FROM events WHERE id IN (SELECT id FROM events WHERE created_at < ?1 LIMIT ?2)"),
+        (&mut pruned.chunks, r#"DELETE FROM upload_chunks WHERE rowid IN (
+               SELECT c.rowid FROM upload_chunks c JOIN uploads u ON u.id = c.upload_id
+               WHERE u.status IN ('complete', 'suspicious', 'abandoned') AND u.updated_at < ?1 LIMIT ?2)"#),
+        (&mut pruned.traces, r#"DELETE FROM upload_traces WHERE id IN (
+               SELECT t.id FROM upload_traces t JOIN uploads u ON u.id = t.upload_id
+               WHERE u.status IN ('complete', 'suspicious', 'abandoned') AND u.updated_at < ?1 LIMIT ?2)"#),
+    ] {
+        loop {
+            let deleted = sqlx::query(statement)
+                .bind(cutoff)
+                .bind(PRUNE_BATCH)
+                .execute(pool).await?
+                .rows_affected();
+            *count += deleted;
+            if deleted < PRUNE_BATCH as u64 {
+                break;
+            }
+        }
+    }
+    Ok(pruned)
+}
+
+/// Gives the free pages of the database back to the filesystem. A no-op for databases created
+/// before incremental auto-vacuum was turned on, until `drcv db compact` rewrites them.
+pub async fn incremental_vacuum(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query("PRAGMA incremental_vacuum").execute(pool).await?;
+    Ok(())
+}
+
+pub async fn get_recent_events(pool: &SqlitePool, limit: i64) -> Vec<serde_json::Value> {
+    sqlx::query(
+        r#"SELECT id, kind, client_ip, message, created_at
+           FROM events ORDER BY id DESC LIMIT ?1"#)
+        .bind(limit)
+        .fetch_all(pool).await
+        .map(|rows| rows.into_iter().map(|row| serde_json::json!({
+            "id": row.get::<i64, _>("id"),
+            "kind": row.get::<String, _>("kind"),
+            "client_ip": row.try_get::<Option<String>, _>("client_ip").ok().flatten(),
+            "message": row.get::<String, _>("message"),
+            "created_at": row.get::<String, _>("created_at"),
+        })).collect())
+        .unwrap_or_else(|e| {
+            error!("Database error in get_recent_events: {}", e);
+            Vec::new()
+        })
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub key_hash: String,
+    /// Comma-separated, e.g. `read:data,read:stats`
+    pub scopes: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+impl ApiKey {
+    pub fn from_row(row: &SqliteRow) -> Self {
+        ApiKey {
+            id: row.get("id"),
+            name: row.get("name"),
+            key_hash: row.get("key_hash"),
+            scopes: row.get("scopes"),
+            created_at: row.get("created_at"),
+            last_used_at: row.try_get::<Option<String>, _>("last_used_at").ok().flatten(),
+        }
+    }
+}
+
+pub async fn insert_api_key(pool: &SqlitePool, name: &str, key_hash: &str, scopes: &str) -> Result<i64, sqlx::Error> {
+    sqlx::query("INSERT INTO api_keys(name, key_hash, scopes, created_at) VALUES(?1, ?2, ?3, ?4)")
+        .bind(name)
+        .bind(key_hash)
+        .bind(scopes)
+        .bind(utils::now())
+        .execute(pool).await
+        .map(|r| r.last_insert_rowid())
+}
+
+pub async fn get_api_keys(pool: &SqlitePool) -> Vec<ApiKey> {
+    sqlx::query("SELECT * FROM api_keys ORDER BY id")
+        .fetch_all(pool).await
+        .map(|rows| rows.iter().map(ApiKey::from_row).collect())
+        .unwrap_or_else(|e| {
+            error!("Database error in get_api_keys: {}", e);
+            Vec::new()
+        })
+}
+
+pub async fn find_api_key(pool: &SqlitePool, key_hash: &str) -> Option<ApiKey> {
+    sqlx::query("SELECT * FROM api_keys WHERE key_hash = ?1")
+        .bind(key_hash)
+        .fetch_optional(pool).await
+        .map_err(|e| error!("Database error in find_api_key: {}", e))
+        .ok()?
+        .map(|row| ApiKey::from_row(&row))
+}
+
+pub async fn touch_api_key(pool: &SqlitePool, id: i64) {
+    let _ = sqlx::query("UPDATE api_keys SET last_used_at = ?1 WHERE id = ?2")
+        .bind(utils::now())
+        .bind(id)
+        .execute(pool).await;
+}
+
+pub async fn delete_api_key(pool: &SqlitePool, name: &str) -> u64 {
+    sqlx::query("DELETE FROM api_keys WHERE name = ?1")
+        .bind(name)
+        .execute(pool).await
+        .map(|r| r.rows_affected())
+        .unwrap_or(0)
+}
+
+/// Puts a key exported elsewhere in place, replacing one of the same name.
+pub async fn restore_api_key(pool: &SqlitePool, key: &ApiKey) {
+    let _ = sqlx::query(
+        r#"INSERT INTO api_keys(name, key_hash, scopes, created_at, last_used_at) VALUES(?1, ?2, ?3, ?4, ?5)
+           ON CONFLICT(name) DO UPDATE SET key_hash = excluded.key_hash, scopes = excluded.scopes,
+               created_at = excluded.created_at, last_used_at = excluded.last_used_at"#)
+        .bind(&key.name)
+        .bind(&key.key_hash)
+        .bind(&key.scopes)
+        .bind(&key.created_at)
+        .bind(&key.last_used_at)
+        .execute(pool).await
+        .map_err(|e| error!("Failed to restore API key {}: {}", key.name, e));
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UploadLink {
+    pub id: i64,
+    pub token: String,
+    pub label: Option<String>,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub max_bytes: Option<i64>,
+    pub max_files: Option<i64>,
+    /// Comma-separated, lowercase and without dots, e.g. `pdf,docx`
+    pub allowed_extensions: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+impl UploadLink {
+    pub fn from_row(row: &SqliteRow) -> Self {
+        UploadLink {
+            id: row.get("id"),
+            token: row.get("token"),
+            label: row.try_get::<Option<String>, _>("label").ok().flatten(),
+            created_at: row.get("created_at"),
+            expires_at: row.try_get::<Option<String>, _>("expires_at").ok().flatten(),
+            max_bytes: row.try_get::<Option<i64>, _>("max_bytes").ok().flatten(),
+            max_files: row.try_get::<Option<i64>, _>("max_files").ok().flatten(),
+            allowed_extensions: row.try_get::<Option<String>, _>("allowed_extensions").ok().flatten(),
+            revoked_at: row.try_get::<Option<String>, _>("revoked_at").ok().flatten(),
+        }
+    }
+}
+
+pub async fn insert_link(pool: &SqlitePool, link: &UploadLink) -> Result<i64, sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO links(token, label, created_at, expires_at, max_bytes, max_files, allowed_extensions)
+           VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)"#)
+        .bind(&link.token)
+        .bind(&link.label)
+        .bind(&link.created_at)
+        .bind(&link.expires_at)
+        .bind(link.max_bytes)
+        .bind(link.max_files)
+        .bind(&link.allowed_extensions)
+        .execute(pool).await
+        .map(|r| r.last_insert_rowid())
+}
+
+pub async fn get_links(pool: &SqlitePool) -> Vec<UploadLink> {
+    sqlx::query("SELECT * FROM links ORDER BY id")
+        .fetch_all(pool).await
+        .map(|rows| rows.iter().map(UploadLink::from_row).collect())
+        .unwrap_or_else(|e| {
+            error!("Database error in get_links: {}", e);
+            Vec::new()
+        })
+}
+
+pub async fn find_link(pool: &SqlitePool, token: &str) -> Option<UploadLink> {
+    sqlx::query("SELECT * FROM links WHERE token = ?1")
+        .bind(token)
+        .fetch_optional(pool).await
+        .map_err(|e| error!("Database error in find_link: {}", e))
+        .ok()?
+        .map(|row| UploadLink::from_row(&row))
+}
+
+/// Stops a link from admitting new files; returns whether there was one to revoke.
+pub async fn revoke_link(pool: &SqlitePool, id: i64) -> bool {
+    sqlx::query("UPDATE links SET revoked_at = ?1 WHERE id = ?2 AND revoked_at IS NULL")
+        .bind(utils::now())
+        .bind(id)
+        .execute(pool).await
+        .map(|r| r.rows_affected() > 0)
+        .unwrap_or(false)
+}
+
+/// For an upload sent through a link with `max_bytes`: that limit and what's left of it for
+/// this upload, after the other uploads of the link.
+pub async fn link_bytes_left(pool: &SqlitePool, id: i64) -> Option<(i64, i64)> {
+    sqlx::query(
+        r#"SELECT l.max_bytes AS max_bytes, l.max_bytes - COALESCE((
+               SELECT SUM(MAX(COALESCE(o.declared_size, 0), o.size)) FROM uploads o
+               WHERE o.link_id = l.id AND o.id != u.id AND o.status != 'abandoned'), 0) AS bytes_left
+           FROM uploads u JOIN links l ON l.id = u.link_id
+           WHERE u.id = ?1 AND l.max_bytes IS NOT NULL"#)
+        .bind(id)
+        .fetch_optional(pool).await
+        .map_err(|e| error!("Database error in link_bytes_left: {}", e))
+        .ok()?
+        .map(|row| (row.get("max_bytes"), row.get("bytes_left")))
+}
+
+/// Files sent through a link and the bytes they take, counting the declared size of those
+/// still in progress; abandoned ones aside.
+pub async fn link_usage(pool: &SqlitePool, link_id: i64) -> (i64, i64) {
+    sqlx::query("SELECT COUNT(*) AS n, COALESCE(SUM(MAX(COALESCE(declared_size, 0), size)), 0) AS bytes FROM uploads WHERE link_id = ?1 AND status != 'abandoned'")
+        .bind(link_id)
+        .fetch_one(pool).await
+        .map(|row| (row.get("n"), row.get("bytes")))
+        .unwrap_or_else(|e| {
+            error!("Database error in link_usage: {}", e);
+            (0, 0)
+        })
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use once_cell::sync::Lazy;
+use tokio::fs::File;
+use log::{debug, warn};
+use crate::{config::AppConfig, registry};
+
+/// An upload written straight into an unnamed file in the upload directory (`O_TMPFILE`),
+/// which is linked under its final name on completion instead of renaming a `.part` file.
+/// The kernel drops the file when its last handle closes, so it's linked as the usual `.part`
+/// as soon as it might be needed by name: when the upload stops being live, on shutdown, and
+/// before a hash confirmation, `--validate-cmd` or a plugin looks at it.
+struct DirectFile {
+    /// Chunks of one upload are appended one at a time
+    file: Arc<tokio::sync::Mutex<File>>,
+    part_path: PathBuf,
+}
+
+static FILES: Lazy<Mutex<HashMap<i64, DirectFile>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// Set once the upload directory turned out not to support unnamed files
+static UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+/// The unnamed file of upload `id`, created with `--direct-write` when the upload has nothing
+/// on disk yet. `None` means the upload uses its `.part` file.
+pub fn file(config: &AppConfig, id: i64, part_path: &Path, received_bytes: i64) -> Option<Arc<tokio::sync::Mutex<File>>> {
+    let mut files = FILES.lock().unwrap();
+    if let Some(direct) = files.get(&id) {
+        return Some(Arc::clone(&direct.file));
+    }
+    if !config.direct_write || UNSUPPORTED.load(Ordering::Relaxed) || received_bytes > 0 || part_path.exists() {
+        return None;
+    }
+    match create(Path::new(&config.upload_dir)) {
+        Ok(file) => {
+            let file = Arc::new(tokio::sync::Mutex::new(File::from_std(file)));
+            files.insert(id, DirectFile { file: Arc::clone(&file), part_path: part_path.to_path_buf() });
+            debug!("Writing upload {} straight into the upload directory", id);
+            Some(file)
+        }
+        Err(e) => {
+            warn!("⚠️ --direct-write is unavailable in {}, using .part files: {}", config.upload_dir, e);
+            UNSUPPORTED.store(true, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+/// Links the finished file of upload `id` at `final_path`, replacing a file of that name like
+/// a rename would. `None` if the upload wasn't written directly.
+pub async fn persist(id: i64, final_path: &Path) -> Option<std::io::Result<()>> {
+    let direct = FILES.lock().unwrap().remove(&id)?;
+    let file = direct.file.lock().await;
+    let linked = match link(&file, final_path) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            link_replacing(&file, &direct.part_path).and_then(|()| std::fs::rename(&direct.part_path, final_path))
+        }
+        other => other,
+    };
+    Some(linked)
+}
+
+/// Gives the unnamed file of upload `id`, if any, its `.part` name, after which it's resumed,
+/// checked and completed like any other.
+pub async fn materialize(id: i64) -> std::io::Result<()> {
+    let Some(direct) = FILES.lock().unwrap().remove(&id) else { return Ok(()) };
+    let file = direct.file.lock().await;
+    link_replacing(&file, &direct.part_path)
+}
+
+/// Materializes the files of uploads that are no longer live, so they can be resumed later.
+pub async fn materialize_idle() {
+    let idle: Vec<i64> = FILES.lock().unwrap().keys().copied().filter(|id| !registry::is_tracked(*id)).collect();
+    materialize_each(idle).await;
+}
+
+/// Materializes every unnamed file, before shutting down.
+pub async fn materialize_all() {
+    let all: Vec<i64> = FILES.lock().unwrap().keys().copied().collect();
+    materialize_each(all).await;
+}
+
+async fn materialize_each(ids: Vec<i64>) {
+    for id in ids {
+        if let Err(e) = materialize(id).await {
+            warn!("⚠️ Failed to keep the partial data of upload {}: {}", id, e);
+        }
+    }
+}
+
+/// Forgets the unnamed file of upload `id`; its data is gone once no chunk is writing to it.
+pub fn discard(id: i64) {
+    FILES.lock().unwrap().remove(&id);
+}
+
+fn link_replacing(file: &File, path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    link(file, path)
+}
+
+#[cfg(target_os = "linux")]
+fn create(dir: &Path) -> std::io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new().write(true).mode(0o644).custom_flags(libc::O_TMPFILE).open(dir)
+}
+
+/// `linkat` through `/proc/self/fd`, which unlike `AT_EMPTY_PATH` needs no privileges.
+#[cfg(target_os = "linux")]
+fn link(file: &File, path: &Path) -> std::io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::{ffi::OsStrExt, io::AsRawFd};
+    let from = CString::new(format!("/proc/self/fd/{}", file.as_raw_fd()))?;
+    let to = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: both paths are valid NUL-terminated strings that outlive the call
+    let result = unsafe { libc::linkat(libc::AT_FDCWD, from.as_ptr(), libc::AT_FDCWD, to.as_ptr(), libc::AT_SYMLINK_FOLLOW) };
+    if result == 0 { Ok(()) } else { Err(std::io::Error::last_os_error()) }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn create(_dir: &Path) -> std::io::Result<std::fs::File> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "--direct-write needs Linux"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn link(_file: &File, _path: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "--direct-write needs Linux"))
+}
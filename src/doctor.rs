@@ -0,0 +1,204 @@
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Row};
+use crate::{config::AppConfig, db::DB_FILE};
+
+enum Outcome {
+    Pass(String),
+    Warn(String, String),
+    Fail(String, String),
+}
+
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn print(&mut self, check: &str, outcome: Outcome) {
+        match outcome {
+            Outcome::Pass(detail) => println!("✅ {:<13} {}", check, detail),
+            Outcome::Warn(detail, hint) => {
+                println!("⚠️  {:<13} {}", check, detail);
+                println!("   💡 {}", hint);
+            }
+            Outcome::Fail(detail, hint) => {
+                self.failures += 1;
+                println!("❌ {:<13} {}", check, detail);
+                println!("   💡 {}", hint);
+            }
+        }
+    }
+}
+
+/// `drcv doctor`: checks everything a normal start depends on, without starting anything.
+pub async fn run(config: &AppConfig) {
+    let mut report = Report { failures: 0 };
+
+    if uses_cloudflared(config) {
+        report.print("cloudflared", check_cloudflared());
+    }
+    report.print("DNS", check_dns(config).await);
+    report.print("Upload port", check_port("0.0.0.0", config.upload_port, "upload"));
+    report.print("Admin port", check_port("127.0.0.1", config.admin_port, "admin"));
+    report.print("Database", check_database().await);
+    report.print("Upload dir", check_write_access(&config.upload_dir));
+    report.print("Disk space", check_disk_space(config));
+
+    if report.failures > 0 {
+        println!("\n{} check(s) failed", report.failures);
+        std::process::exit(1);
+    }
+    println!("\nAll checks passed");
+}
+
+fn uses_cloudflared(config: &AppConfig) -> bool {
+    config.public_url.is_none() && config.tunnel_provider == "cloudflare"
+}
+
+pub fn cloudflared_installed() -> bool {
+    Command::new("cloudflared").arg("--version").output().is_ok_and(|o| o.status.success())
+}
+
+pub fn cloudflared_logged_in() -> bool {
+    dirs::home_dir().is_some_and(|home| home.join(".cloudflared").join("cert.pem").exists())
+}
+
+fn check_cloudflared() -> Outcome {
+    if !cloudflared_installed() {
+        return Outcome::Fail(
+            "not installed".to_string(),
+            "Install it from https://developers.cloudflare.com/cloudflare-one/connections/connect-networks/downloads/ (macOS: brew install cloudflared)".to_string(),
+        );
+    }
+    if !cloudflared_logged_in() {
+        return Outcome::Fail(
+            "installed, but not logged in (~/.cloudflared/cert.pem missing)".to_string(),
+            "Run `cloudflared tunnel login`".to_string(),
+        );
+    }
+    Outcome::Pass("installed and logged in".to_string())
+}
+
+/// Resolves the host senders (or drcv itself) must reach for the configured tunnel.
+async fn check_dns(config: &AppConfig) -> Outcome {
+    let (host, what) = if let Some(url) = &config.public_url {
+        match url::Url::parse(url).ok().and_then(|u| u.host_str().map(str::to_string)) {
+            Some(host) => (host, "share hostname"),
+            None => return Outcome::Fail(format!("cannot parse --public-url {}", url), "Use a full URL such as https://drop.example.com".to_string()),
+        }
+    } else {
+        match config.tunnel_provider.as_str() {
+            "ssh" => match &config.ssh_target {
+                Some(target) => (target.rsplit('@').next().unwrap_or(target).to_string(), "SSH server"),
+                None => return Outcome::Fail("--ssh-target is not set".to_string(), "Pass e.g. --ssh-target nokey@localhost.run".to_string()),
+            },
+            "bore" => match &config.relay_server {
+                Some(server) => (server.clone(), "relay server"),
+                None => return Outcome::Fail("--relay-server is not set".to_string(), "Pass the host running `bore server`".to_string()),
+            },
+            _ => match share_hash().await {
+                Some(hash) => (format!("{}.{}", hash, config.tunnel_domain), "share hostname"),
+                None => return Outcome::Pass("no share hostname assigned yet; one is created on first start".to_string()),
+            },
+        }
+    };
+
+    let resolved = tokio::net::lookup_host((host.as_str(), 443)).await.is_ok_and(|mut addrs| addrs.next().is_some());
+    if resolved {
+        Outcome::Pass(format!("{} {} resolves", what, host))
+    } else if what == "share hostname" && config.public_url.is_none() {
+        Outcome::Warn(
+            format!("{} {} does not resolve", what, host),
+            "The record is (re)created when drcv starts; if this persists, check the zone in the Cloudflare dashboard".to_string(),
+        )
+    } else {
+        Outcome::Fail(format!("{} {} does not resolve", what, host), "Check the hostname and your network/DNS settings".to_string())
+    }
+}
+
+/// Reads the persisted share hash without creating or migrating the database.
+pub async fn share_hash() -> Option<String> {
+    if !Path::new(DB_FILE).exists() {
+        return None;
+    }
+    let mut conn = SqliteConnectOptions::from_str(&format!("sqlite:{}", DB_FILE)).ok()?.read_only(true).connect().await.ok()?;
+    sqlx::query("SELECT v FROM kv WHERE k = 'cf_hash'")
+        .fetch_optional(&mut conn).await.ok()?
+        .and_then(|r| r.try_get::<String, _>("v").ok())
+}
+
+fn check_port(host: &str, port: u16, role: &str) -> Outcome {
+    match std::net::TcpListener::bind((host, port)) {
+        Ok(_) => Outcome::Pass(format!("{} is free", port)),
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => Outcome::Fail(
+            format!("{} is already in use", port),
+            format!("Stop the process holding it (e.g. `lsof -i :{}`) or pick another one with --{}-port", port, role),
+        ),
+        Err(e) => Outcome::Fail(format!("cannot listen on {}: {}", port, e), format!("Pick another one with --{}-port", role)),
+    }
+}
+
+async fn check_database() -> Outcome {
+    if !Path::new(DB_FILE).exists() {
+        return Outcome::Pass(format!("{} does not exist yet; it is created on first start", DB_FILE));
+    }
+    let conn = match SqliteConnectOptions::from_str(&format!("sqlite:{}", DB_FILE)) {
+        Ok(options) => options.read_only(true).connect().await,
+        Err(e) => Err(e),
+    };
+    let mut conn = match conn {
+        Ok(conn) => conn,
+        Err(e) => return Outcome::Fail(format!("cannot open {}: {}", DB_FILE, e), "Check the file's permissions".to_string()),
+    };
+    let rows = match sqlx::query("PRAGMA integrity_check").fetch_all(&mut conn).await {
+        Ok(rows) => rows,
+        Err(e) => return Outcome::Fail(format!("integrity check failed to run: {}", e), corrupt_hint()),
+    };
+    let problems: Vec<String> = rows.iter().filter_map(|r| r.try_get::<String, _>(0).ok()).filter(|s| s != "ok").collect();
+    if problems.is_empty() {
+        Outcome::Pass(format!("{} passed integrity_check", DB_FILE))
+    } else {
+        Outcome::Fail(format!("{} is corrupt: {}", DB_FILE, problems.join("; ")), corrupt_hint())
+    }
+}
+
+fn corrupt_hint() -> String {
+    format!("Stop drcv and move {} aside; a fresh one is created on start (upload history and the share hostname are lost)", DB_FILE)
+}
+
+fn check_write_access(dir: &str) -> Outcome {
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        return Outcome::Fail(format!("cannot create {}: {}", dir, e), "Choose another --upload-dir or fix the parent directory's permissions".to_string());
+    }
+    let probe = Path::new(dir).join(format!(".drcv-doctor-{}", std::process::id()));
+    match std::fs::write(&probe, b"ok") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            Outcome::Pass(format!("{} is writable", dir))
+        }
+        Err(e) => Outcome::Fail(format!("cannot write to {}: {}", dir, e), format!("Fix the permissions (e.g. `chown $USER {}`) or choose another --upload-dir", dir)),
+    }
+}
+
+fn check_disk_space(config: &AppConfig) -> Outcome {
+    let free = match fs2::available_space(&config.upload_dir) {
+        Ok(free) => free,
+        Err(e) => return Outcome::Warn(format!("cannot determine free space: {}", e), "Make sure --upload-dir exists".to_string()),
+    };
+    let gib = |n: u64| format!("{:.1} GiB", n as f64 / (1u64 << 30) as f64);
+    if free < config.min_disk_free {
+        Outcome::Fail(
+            format!("{} free, below --min-disk-free {}", gib(free), gib(config.min_disk_free)),
+            "Free up space or point --upload-dir at a larger disk; new uploads are rejected until then".to_string(),
+        )
+    } else if free < config.max_file_size {
+        Outcome::Warn(
+            format!("{} free, less than --max-file-size {}", gib(free), gib(config.max_file_size)),
+            "A maximum-size upload would fill the disk; lower --max-file-size or free up space".to_string(),
+        )
+    } else {
+        Outcome::Pass(format!("{} free", gib(free)))
+    }
+}
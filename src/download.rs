@@ -0,0 +1,64 @@
+use axum::{body::Body, extract::{Extension, Path, State}, http::{header, StatusCode}, response::{IntoResponse, Response}};
+use sqlx::SqlitePool;
+use std::path::PathBuf;
+use tokio::io::AsyncReadExt;
+use log::warn;
+use crate::{config::AppConfig, db, error::ApiError};
+
+/// Large reads keep the disk streaming; the socket write path copies once either way.
+const READ_SIZE: usize = 1024 * 1024;
+/// Reads kept ahead of the connection, so a slow disk seek doesn't stall a fast link
+const READ_AHEAD: usize = 4;
+
+/// `GET /uploads/:id/file`: the stored file of a completed upload, for pulling it back from
+/// another machine on the LAN (with `--admin-bind` and a `read:data` API key). hyper has no
+/// sendfile path, so the file is streamed in large reads from a task that stays a few reads
+/// ahead of the connection.
+pub async fn admin_download(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError> {
+    let upload = db::get_upload(&pool, id).await
+        .ok_or_else(|| ApiError::not_found("upload_not_found", format!("Unknown upload: {}", id)))?;
+    if !upload.is_stored() {
+        return Err(ApiError::conflict("upload_incomplete", format!("Upload {} is {}, not complete", id, upload.status)));
+    }
+    let stored_name = upload.stored_name.as_deref().unwrap_or(&upload.filename);
+    let path = PathBuf::from(&config.upload_dir).join(stored_name);
+    let mut file = tokio::fs::File::open(&path).await
+        .map_err(|e| ApiError::new(StatusCode::GONE, "file_gone", format!("{} is no longer in the upload directory: {}", stored_name, e)))?;
+    let len = file.metadata().await
+        .map_err(|e| ApiError::internal("storage_error", format!("Failed to read {}: {}", stored_name, e)))?
+        .len();
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<bytes::Bytes>>(READ_AHEAD);
+    tokio::spawn(async move {
+        loop {
+            let mut buf = bytes::BytesMut::with_capacity(READ_SIZE);
+            let read = match file.read_buf(&mut buf).await {
+                Ok(0) => return,
+                Ok(_) => Ok(buf.freeze()),
+                Err(e) => {
+                    warn!("⚠️ Failed to read {} for download: {}", path.display(), e);
+                    Err(e)
+                }
+            };
+            let failed = read.is_err();
+            if tx.send(read).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    let name = stored_name.rsplit('/').next().unwrap_or(stored_name);
+    let encoded: String = url::form_urlencoded::byte_serialize(name.as_bytes()).collect();
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_LENGTH, len.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename*=UTF-8''{}", encoded.replace('+', "%20"))),
+        ],
+        Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)),
+    ).into_response())
+}
@@ -0,0 +1,59 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use tokio::sync::{broadcast, Notify};
+use log::warn;
+use crate::{config::{AppConfig, EmitFormat}, events::{self, Event}, utils};
+
+static COMPLETIONS_EMITTED: AtomicUsize = AtomicUsize::new(0);
+static EMITTED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Prints every lifecycle event to stdout as one JSON object per line (`--emit json`).
+/// `upload_completed` lines also carry the file's SHA-256.
+pub fn spawn(config: &AppConfig) {
+    let Some(EmitFormat::Json) = config.emit else { return };
+    let mut rx = events::subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(Event::Test { .. }) => continue,
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("⚠️ Event stream skipped {} events", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            let mut line = serde_json::to_value(&event).unwrap_or(Value::Null);
+            line["ts"] = Value::String(utils::now());
+            if let Event::UploadCompleted { path, .. } = &event {
+                line["sha256"] = match utils::sha256_file(path).await {
+                    Ok(hash) => Value::String(hash),
+                    Err(e) => {
+                        warn!("⚠️ Failed to checksum {}: {}", path, e);
+                        Value::Null
+                    }
+                };
+            }
+            let mut stdout = std::io::stdout().lock();
+            let _ = writeln!(stdout, "{}", line);
+            let _ = stdout.flush();
+            if matches!(event, Event::UploadCompleted { .. }) {
+                COMPLETIONS_EMITTED.fetch_add(1, Ordering::Relaxed);
+                EMITTED.notify_waiters();
+            }
+        }
+    });
+}
+
+/// Waits until `count` `upload_completed` lines have been written, so shutdown doesn't cut one off.
+pub async fn wait_for_completions(count: usize) {
+    loop {
+        let emitted = EMITTED.notified();
+        if COMPLETIONS_EMITTED.load(Ordering::Relaxed) >= count {
+            return;
+        }
+        emitted.await;
+    }
+}
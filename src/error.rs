@@ -0,0 +1,121 @@
+use axum::{extract::Request, http::{header, StatusCode}, middleware::Next, response::{IntoResponse, Response}, Json};
+use serde::Serialize;
+
+/// Plain-text error bodies longer than this are replaced by the status text
+const MAX_REWRAPPED: usize = 64 * 1024;
+
+/// How every endpoint answers a request it can't serve:
+/// `{"code": "upload_not_found", "message": "...", "details": {...}, "retryable": false}`.
+/// `code` is stable for clients to branch on, `message` is meant for people, `details` carries
+/// what a client needs to act on the error (e.g. the chunk to send instead), and `retryable`
+/// says whether the same request may succeed later.
+#[derive(Serialize, Debug)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+    pub retryable: bool,
+    /// Sent as `Retry-After` too
+    #[serde(skip)]
+    pub retry_after: Option<u64>,
+}
+
+impl ApiError {
+    /// Server errors and timeouts are retryable, everything else isn't unless marked so.
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        let retryable = status.is_server_error() || status == StatusCode::REQUEST_TIMEOUT;
+        ApiError { status, code, message: message.into(), details: None, retryable, retry_after: None }
+    }
+
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    pub fn not_found(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, code, message)
+    }
+
+    pub fn conflict(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, code, message)
+    }
+
+    pub fn internal(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, code, message)
+    }
+
+    pub fn with_details(mut self, details: impl Serialize) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+
+    /// Marks the error as passing, to be retried after `seconds`.
+    pub fn retry_after(mut self, seconds: u64) -> Self {
+        self.retryable = true;
+        self.retry_after = Some(seconds);
+        self
+    }
+}
+
+/// The `code` of an error response, kept in its extensions for middleware such as `trace`
+#[derive(Clone, Copy)]
+pub struct ErrorCode(pub &'static str);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, Json(&self)).into_response();
+        if let Some(seconds) = self.retry_after {
+            response.headers_mut().insert(header::RETRY_AFTER, seconds.into());
+        }
+        response.extensions_mut().insert(ErrorCode(self.code));
+        response
+    }
+}
+
+/// Malformed multipart bodies are the client's to fix, including those the parser gives up on
+/// with a server error.
+impl From<axum_typed_multipart::TypedMultipartError> for ApiError {
+    fn from(rejection: axum_typed_multipart::TypedMultipartError) -> Self {
+        use axum_typed_multipart::TypedMultipartError;
+        let message = rejection.to_string();
+        match rejection {
+            TypedMultipartError::FieldTooLarge { .. } => ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "field_too_large", message),
+            _ if rejection.get_status().is_server_error() => ApiError::bad_request("malformed_multipart", message),
+            _ => ApiError::new(rejection.get_status(), "invalid_request", message),
+        }
+    }
+}
+
+/// Wraps the plain-text errors axum answers with on its own, for unknown routes, malformed
+/// JSON or query strings and bodies over the limit, in the same JSON body as everything else.
+pub async fn envelope(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    let is_json = response.headers().get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !(status.is_client_error() || status.is_server_error()) || is_json {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let message = match axum::body::to_bytes(body, MAX_REWRAPPED).await {
+        Ok(body) if !body.is_empty() => String::from_utf8_lossy(&body).into_owned(),
+        _ => status.canonical_reason().unwrap_or("Error").to_string(),
+    };
+    let code = match status {
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::PAYLOAD_TOO_LARGE => "body_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        s if s.is_client_error() => "invalid_request",
+        _ => "internal_error",
+    };
+    let mut response = ApiError::new(status, code, message).into_response();
+    // Keeps e.g. `Allow` on a 405
+    parts.headers.remove(header::CONTENT_TYPE);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    response.headers_mut().extend(parts.headers);
+    response
+}
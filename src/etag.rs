@@ -0,0 +1,34 @@
+use axum::{body::Body, extract::Request, http::{header, HeaderValue, StatusCode}, middleware::Next, response::{IntoResponse, Response}};
+use sha2::{Digest, Sha256};
+
+/// Tags successful responses with a hash of their body and answers a matching
+/// `If-None-Match` with `304`, so dashboards polling every second, and proxies in between,
+/// only transfer changes. `Cache-Control: no-cache` makes browsers revalidate each time.
+pub async fn conditional(request: Request, next: Next) -> Response {
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let etag = format!("\"{}\"", &format!("{:x}", Sha256::digest(&bytes))[..32]);
+    let Ok(etag) = HeaderValue::from_str(&etag) else { return Response::from_parts(parts, Body::from(bytes)) };
+    parts.headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+    if if_none_match.is_some_and(|value| matches(&value, &etag)) {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag), (header::CACHE_CONTROL, HeaderValue::from_static("no-cache"))]).into_response();
+    }
+    parts.headers.insert(header::ETAG, etag);
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// `If-None-Match` holds `*` or a list of tags, possibly weak (`W/"..."`).
+fn matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let Ok(value) = if_none_match.to_str() else { return false };
+    let etag = etag.to_str().unwrap_or_default();
+    value.split(',').map(str::trim).any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
@@ -0,0 +1,110 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tokio::sync::broadcast;
+use log::warn;
+use crate::{db, intel::HashVerdict};
+
+/// Lifecycle events published on the in-process bus and fanned out to notifiers.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    UploadStarted { id: i64, filename: String, client_ip: String },
+    /// `hash_verdict` is set when the file's hash was looked up (`--virustotal-api-key`, ...)
+    UploadCompleted { id: i64, filename: String, size: i64, path: String, hash_verdict: Option<HashVerdict> },
+    UploadDisconnected { filename: String, client_ip: String },
+    UploadAbandoned { id: i64, filename: String, client_ip: String, reason: String },
+    UploadQuarantined { id: i64, filename: String, client_ip: String, reason: String },
+    UploadSuspicious { id: i64, filename: String, client_ip: String, reason: String },
+    ResumeRequested { id: i64, filename: String, client_ip: String, resume_url: Option<String> },
+    TunnelUp { hostname: String },
+    TunnelDown { hostname: String },
+    TunnelConnected { detail: String },
+    TunnelReconnecting { detail: String },
+    TunnelError { message: String },
+    PressureHigh { reason: String },
+    PressureCleared,
+    WindowClosed { in_progress: usize },
+    Test { message: String },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::UploadStarted { .. } => "upload_started",
+            Event::UploadCompleted { .. } => "upload_completed",
+            Event::UploadDisconnected { .. } => "upload_disconnected",
+            Event::UploadAbandoned { .. } => "upload_abandoned",
+            Event::UploadQuarantined { .. } => "upload_quarantined",
+            Event::UploadSuspicious { .. } => "upload_suspicious",
+            Event::ResumeRequested { .. } => "resume_requested",
+            Event::TunnelUp { .. } => "tunnel_up",
+            Event::TunnelDown { .. } => "tunnel_down",
+            Event::TunnelConnected { .. } => "tunnel_connected",
+            Event::TunnelReconnecting { .. } => "tunnel_reconnecting",
+            Event::TunnelError { .. } => "tunnel_error",
+            Event::PressureHigh { .. } => "pressure_high",
+            Event::PressureCleared => "pressure_cleared",
+            Event::WindowClosed { .. } => "window_closed",
+            Event::Test { .. } => "test",
+        }
+    }
+
+    pub fn client_ip(&self) -> Option<&str> {
+        match self {
+            Event::UploadStarted { client_ip, .. } | Event::UploadDisconnected { client_ip, .. } | Event::UploadAbandoned { client_ip, .. } | Event::UploadQuarantined { client_ip, .. } | Event::UploadSuspicious { client_ip, .. } | Event::ResumeRequested { client_ip, .. } => Some(client_ip),
+            _ => None,
+        }
+    }
+
+    /// One-line human readable message for chat/email notifiers.
+    pub fn summary(&self) -> String {
+        match self {
+            Event::UploadStarted { filename, client_ip, .. } => format!("▶️ Upload started: {} from {}", filename, client_ip),
+            Event::UploadCompleted { filename, size, hash_verdict: None, .. } => format!("✅ Upload completed: {} ({} bytes)", filename, size),
+            Event::UploadCompleted { filename, size, hash_verdict: Some(verdict), .. } => {
+                format!("✅ Upload completed: {} ({} bytes), hash lookup: {} ({})", filename, size, verdict.verdict.as_str(), verdict.detail)
+            }
+            Event::UploadDisconnected { filename, client_ip } => format!("❌ Upload disconnected: {} from {}", filename, client_ip),
+            Event::UploadAbandoned { filename, client_ip, reason, .. } => format!("🗑️ Upload abandoned ({}): {} from {}", reason, filename, client_ip),
+            Event::UploadQuarantined { filename, client_ip, reason, .. } => format!("🛡️ Upload quarantined ({}): {} from {}", reason, filename, client_ip),
+            Event::UploadSuspicious { filename, client_ip, reason, .. } => format!("🏷️ Suspicious upload ({}): {} from {}", reason, filename, client_ip),
+            Event::ResumeRequested { filename, client_ip, .. } => format!("📨 Asked {} to resume {}", client_ip, filename),
+            Event::TunnelUp { hostname } => format!("🔗 Tunnel up: https://{}", hostname),
+            Event::TunnelDown { hostname } => format!("🔌 Tunnel down: {}", hostname),
+            Event::TunnelConnected { detail } => format!("🔗 Tunnel connection registered: {}", detail),
+            Event::TunnelReconnecting { detail } => format!("🔄 Tunnel reconnecting: {}", detail),
+            Event::TunnelError { message } => format!("⚠️ Tunnel error: {}", message),
+            Event::PressureHigh { reason } => format!("🚧 Rejecting new uploads: {}", reason),
+            Event::PressureCleared => "✅ Resource pressure cleared, accepting new uploads".to_string(),
+            Event::WindowClosed { in_progress } => format!("⏰ Receive window closed, {} upload(s) still in progress", in_progress),
+            Event::Test { message } => format!("🧪 {}", message),
+        }
+    }
+}
+
+static BUS: Lazy<broadcast::Sender<Event>> = Lazy::new(|| broadcast::channel(256).0);
+
+pub fn publish(event: Event) {
+    // No subscribers is fine; events are best-effort
+    let _ = BUS.send(event);
+}
+
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    BUS.subscribe()
+}
+
+/// Persists every bus event into the `events` table for the admin history.
+pub fn spawn_recorder(pool: SqlitePool) {
+    let mut rx = subscribe();
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(Event::Test { .. }) => {}
+                Ok(event) => db::insert_event(&pool, &event).await,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => warn!("⚠️ Event recorder skipped {} events", skipped),
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
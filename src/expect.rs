@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use once_cell::sync::Lazy;
+use tokio::sync::Notify;
+use log::{info, warn};
+use crate::{config::AppConfig, emit, events::{self, Event}, utils};
+
+static UNMET: AtomicBool = AtomicBool::new(false);
+static DONE: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Resolves once `--expect-files` uploads have completed.
+pub async fn done() {
+    DONE.notified().await;
+}
+
+/// Process exit status: non-zero if drcv stops before the expected files arrived.
+pub fn exit_code() -> i32 {
+    if UNMET.load(Ordering::Relaxed) { 1 } else { 0 }
+}
+
+/// Counts completed uploads for `--expect-files` and prints `sha256sum`-style lines for them
+/// on stdout, so a pipeline can `drcv --expect-files 1 | ...`.
+pub fn spawn(config: &AppConfig) {
+    let Some(expected) = config.expect_files else { return };
+    UNMET.store(true, Ordering::Relaxed);
+    info!("📥 Waiting for {} upload(s), then exiting", expected);
+
+    let emitting = config.emit.is_some();
+    let mut rx = events::subscribe();
+    tokio::spawn(async move {
+        let mut received = 0;
+        while received < expected {
+            let path = match rx.recv().await {
+                Ok(Event::UploadCompleted { path, .. }) => path,
+                Ok(_) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("⚠️ Missed {} events while counting expected uploads", skipped);
+                    continue;
+                }
+                Err(_) => return,
+            };
+            received += 1;
+            // With --emit json the event stream on stdout already carries path and hash
+            if !emitting {
+                let checksum = utils::sha256_file(&path).await.unwrap_or_else(|e| {
+                    warn!("⚠️ Failed to checksum {}: {}", path, e);
+                    "-".repeat(64)
+                });
+                println!("{}  {}", checksum, path);
+            }
+            info!("📥 Received {} of {} expected upload(s)", received, expected);
+        }
+        if emitting {
+            emit::wait_for_completions(expected).await;
+        }
+        UNMET.store(false, Ordering::Relaxed);
+        DONE.notify_one();
+    });
+}
@@ -0,0 +1,148 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use axum::Json;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::sync::oneshot;
+use crate::config::AppConfig;
+
+/// `--max-concurrent-chunks`, 0 = unlimited
+static LIMIT: AtomicUsize = AtomicUsize::new(0);
+static STATE: Lazy<Mutex<State>> = Lazy::new(|| Mutex::new(State::default()));
+
+#[derive(Default)]
+struct State {
+    busy: usize,
+    /// Clients with queued chunks, in the order they get the next free slot
+    turns: VecDeque<String>,
+    clients: HashMap<String, ClientSlots>,
+}
+
+#[derive(Default)]
+struct ClientSlots {
+    active: usize,
+    queued: VecDeque<oneshot::Sender<Slot>>,
+    chunks: u64,
+}
+
+impl State {
+    fn grant(&mut self, client_ip: &str) -> Slot {
+        let client = self.clients.entry(client_ip.to_string()).or_default();
+        client.active += 1;
+        client.chunks += 1;
+        self.busy += 1;
+        Slot { client_ip: Some(client_ip.to_string()) }
+    }
+
+    fn revoke(&mut self, client_ip: &str) {
+        if let Some(client) = self.clients.get_mut(client_ip) {
+            client.active -= 1;
+        }
+        self.busy -= 1;
+    }
+
+    /// Fills free slots from the queue, one chunk per client in turn, so a sender with many
+    /// parallel connections waits its turn like everyone else.
+    fn hand_on(&mut self) {
+        let limit = LIMIT.load(Ordering::Relaxed);
+        while limit == 0 || self.busy < limit {
+            let Some(client_ip) = self.turns.pop_front() else { return };
+            let Some(waiter) = self.clients.get_mut(&client_ip).and_then(|c| c.queued.pop_front()) else { continue };
+            if self.clients.get(&client_ip).is_some_and(|c| !c.queued.is_empty()) {
+                self.turns.push_back(client_ip.clone());
+            }
+            let slot = self.grant(&client_ip);
+            // The request went away while queued
+            if let Err(mut slot) = waiter.send(slot) {
+                slot.client_ip = None;
+                self.revoke(&client_ip);
+            }
+        }
+    }
+}
+
+/// A chunk processing slot; handed to the next client in turn when dropped.
+pub struct Slot {
+    client_ip: Option<String>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let Some(client_ip) = self.client_ip.take() else { return };
+        let mut state = STATE.lock().unwrap();
+        state.revoke(&client_ip);
+        state.hand_on();
+    }
+}
+
+pub fn init(config: &AppConfig) {
+    LIMIT.store(config.max_concurrent_chunks, Ordering::Relaxed);
+}
+
+/// Waits for a slot to process one chunk of `client_ip`. Without `--max-concurrent-chunks`
+/// this returns at once and only counts.
+pub async fn acquire(client_ip: &str) -> Slot {
+    let waiting = {
+        let mut state = STATE.lock().unwrap();
+        let limit = LIMIT.load(Ordering::Relaxed);
+        if state.turns.is_empty() && (limit == 0 || state.busy < limit) {
+            return state.grant(client_ip);
+        }
+        let (tx, rx) = oneshot::channel();
+        let client = state.clients.entry(client_ip.to_string()).or_default();
+        client.queued.push_back(tx);
+        if client.queued.len() == 1 {
+            state.turns.push_back(client_ip.to_string());
+        }
+        rx
+    };
+    // Queued senders are only ever dropped after sending
+    waiting.await.expect("queued chunk slot is always handed over")
+}
+
+#[derive(Serialize)]
+pub struct ClientShare {
+    pub client_ip: String,
+    /// Chunks being processed now
+    pub active: usize,
+    /// Chunks waiting for a slot
+    pub queued: usize,
+    /// Chunks processed since drcv started
+    pub chunks: u64,
+    /// Fraction of the busy slots this client holds
+    pub share: f64,
+}
+
+#[derive(Serialize)]
+pub struct Fairness {
+    pub limit: Option<usize>,
+    pub busy: usize,
+    pub clients: Vec<ClientShare>,
+}
+
+pub fn snapshot() -> Fairness {
+    let state = STATE.lock().unwrap();
+    let mut clients: Vec<ClientShare> = state.clients.iter()
+        .map(|(client_ip, c)| ClientShare {
+            client_ip: client_ip.clone(),
+            active: c.active,
+            queued: c.queued.len(),
+            chunks: c.chunks,
+            share: if state.busy == 0 { 0.0 } else { c.active as f64 / state.busy as f64 },
+        })
+        .collect();
+    clients.sort_by(|a, b| (b.active + b.queued).cmp(&(a.active + a.queued)).then(b.chunks.cmp(&a.chunks)));
+    let limit = LIMIT.load(Ordering::Relaxed);
+    Fairness { limit: (limit > 0).then_some(limit), busy: state.busy, clients }
+}
+
+/// Chunks waiting for a slot, across all clients.
+pub fn queued() -> usize {
+    STATE.lock().unwrap().clients.values().map(|c| c.queued.len()).sum()
+}
+
+/// `GET /stats/clients`: how the chunk slots are shared between senders right now.
+pub async fn admin_client_shares() -> Json<Fairness> {
+    Json(snapshot())
+}
@@ -0,0 +1,255 @@
+use axum::{extract::{State, ConnectInfo, Extension}, response::{IntoResponse, Response}, http::{HeaderMap, StatusCode}, Json};
+use sqlx::SqlitePool;
+use std::{net::SocketAddr, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+use log::{info, warn};
+use crate::{checksums, coalescer, db, direct, config::AppConfig, error::ApiError, events::{self, Event}, handles, intel, limits, metadata, mover, naming, net::extract_client_ip, registry, sessions, sniff, trace, utils, validate};
+
+#[derive(Deserialize)]
+pub struct FinalizeRequest {
+    pub upload_id: Option<i64>,
+    pub filename: String,
+    pub sha256: String,
+}
+
+#[derive(Serialize)]
+pub struct FinalizeResponse {
+    pub upload_id: i64,
+    pub complete: bool,
+    pub size: i64,
+    pub declared_size: Option<i64>,
+    pub sha256: String,
+    /// Set when the hashes differ
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_sha256: Option<String>,
+}
+
+/// Where a completed upload goes: its own name, or with `--rename-template` the rendered
+/// name, numbered so it never replaces an earlier file; in the subdirectory `--route-script`
+/// picked, if any.
+async fn final_path(pool: &SqlitePool, config: &AppConfig, id: i64, filename: &str) -> PathBuf {
+    let upload = db::get_upload(pool, id).await;
+    let dir = match upload.as_ref().and_then(|upload| upload.dest_dir.as_deref()) {
+        Some(dest_dir) => PathBuf::from(&config.upload_dir).join(dest_dir),
+        None => PathBuf::from(&config.upload_dir),
+    };
+    let Some(template) = &config.rename_template else { return dir.join(naming::on_disk(filename).as_ref()) };
+    match upload {
+        Some(upload) => naming::unique_path(&dir, &naming::render(template, &upload)).await,
+        None => naming::unique_path(&dir, &naming::on_disk(filename)).await,
+    }
+}
+
+/// Completes a fully received upload: stores it right away, or with `--validate-cmd`
+/// quarantines it in its session directory until the command passes it.
+pub async fn complete(pool: &SqlitePool, config: &AppConfig, id: i64, filename: &str) -> std::io::Result<PathBuf> {
+    if config.validate_cmd.is_none() {
+        return store(pool, config, id, filename).await;
+    }
+    handles::discard(id);
+    direct::materialize(id).await?;
+    let tmp_path = sessions::part_path(config.scratch_dir(), id, filename).await?;
+    coalescer::flush(pool).await;
+    db::mark_quarantined(pool, id).await;
+    registry::finish(id);
+    info!("🛡️ Received all of {}, holding it until --validate-cmd passes it", filename);
+    validate::spawn(pool.clone(), config.clone(), id);
+    Ok(tmp_path)
+}
+
+/// Moves a fully received `.part` file into the upload directory, or links a `--direct-write`
+/// one there, and marks the upload complete. With `--spool-dir` the file is moved in the
+/// background instead, and the upload is `moving` until it's in place.
+pub async fn store(pool: &SqlitePool, config: &AppConfig, id: i64, filename: &str) -> std::io::Result<PathBuf> {
+    // No handle may outlive the rename, including one returned by an overlapping retry
+    handles::discard(id);
+    let tmp_path = sessions::part_path(config.scratch_dir(), id, filename).await?;
+    let final_path = final_path(pool, config, id, filename).await;
+    #[cfg(feature = "plugins")]
+    let final_path = {
+        // Plugins may read the file by its .part name
+        direct::materialize(id).await?;
+        coalescer::flush(pool).await;
+        let Some(upload) = db::get_upload(pool, id).await else { return Ok(tmp_path) };
+        match crate::plugins::on_upload_complete(pool, &upload, final_path).await {
+            Ok(final_path) => final_path,
+            Err(reason) => {
+                reject(pool, upload, reason).await;
+                return Ok(tmp_path);
+            }
+        }
+    };
+    if config.spool_dir.is_some() {
+        coalescer::flush(pool).await;
+        db::mark_moving(pool, id, &stored_name(config, &final_path, filename)).await;
+        registry::finish(id);
+        info!("🚚 Received all of {}, moving it to {:?}", filename, final_path);
+        mover::spawn(pool.clone(), config.clone(), id, filename.to_string(), tmp_path, final_path.clone());
+        return Ok(final_path);
+    }
+    // The name is stored as given; only the calls below need the long form on Windows
+    let disk_path = naming::long_path(&final_path);
+    if let Some(dir) = disk_path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    match direct::persist(id, &disk_path).await {
+        Some(linked) => linked?,
+        None => tokio::fs::rename(&tmp_path, &disk_path).await?,
+    }
+    stored(pool, config, id, filename, &final_path).await;
+    Ok(final_path)
+}
+
+/// Relative to the upload directory, so it includes a --route-script subdirectory
+fn stored_name(config: &AppConfig, final_path: &Path, filename: &str) -> String {
+    final_path.strip_prefix(&config.upload_dir)
+        .map(|name| name.to_string_lossy().replace('\\', "/"))
+        .unwrap_or_else(|_| filename.to_string())
+}
+
+/// Marks an upload complete once its file is in place at `final_path`.
+pub async fn stored(pool: &SqlitePool, config: &AppConfig, id: i64, filename: &str, final_path: &Path) {
+    sessions::remove(config, id).await;
+    if config.preserve_metadata {
+        if let Some(upload) = db::get_upload(pool, id).await {
+            metadata::apply(upload, &naming::long_path(final_path)).await;
+        }
+    }
+    let stored_name = stored_name(config, final_path, filename);
+    if stored_name == filename {
+        info!("✅ Completed upload: {:?}", final_path);
+    } else {
+        info!("✅ Completed upload: {} stored as {:?}", filename, final_path);
+    }
+    coalescer::flush(pool).await;
+    db::mark_complete(pool, id, &stored_name).await;
+    if config.check_file_types {
+        flag_mismatch(pool, id, filename, final_path).await;
+    }
+    registry::finish(id);
+    checksums::spawn_write(config, final_path.to_path_buf());
+    let size = tokio::fs::metadata(final_path).await.map(|m| m.len() as i64).unwrap_or(0);
+    intel::publish_completed(pool, config, id, filename.to_string(), size, final_path.to_path_buf());
+}
+
+/// `--check-file-types`: marks the upload `suspicious` if its content contradicts its extension.
+async fn flag_mismatch(pool: &SqlitePool, id: i64, filename: &str, final_path: &Path) {
+    let Some(reason) = sniff::check(filename, &naming::long_path(final_path)).await else { return };
+    db::mark_suspicious(pool, id, &reason).await;
+    let client_ip = db::get_upload(pool, id).await.map(|upload| upload.client_ip).unwrap_or_default();
+    warn!("🏷️ Suspicious upload {} from {}: {}", filename, client_ip, reason);
+    events::publish(Event::UploadSuspicious { id, filename: filename.to_string(), client_ip, reason });
+}
+
+/// An `upload_complete` plugin refused the file: like one `--validate-cmd` rejected, it stays
+/// quarantined in its session directory with the reason.
+#[cfg(feature = "plugins")]
+async fn reject(pool: &SqlitePool, upload: db::UploadData, reason: String) {
+    db::mark_quarantined(pool, upload.id).await;
+    db::set_quarantine_reason(pool, upload.id, &reason).await;
+    registry::finish(upload.id);
+    warn!("🧩 Quarantined {} from {}: {}", upload.filename, upload.client_ip, reason);
+    events::publish(Event::UploadQuarantined { id: upload.id, filename: upload.filename, client_ip: upload.client_ip, reason });
+}
+
+/// With `--require-finalize`, the last chunk leaves the upload `received` until the sender
+/// confirms the whole-file hash.
+pub async fn await_confirmation(pool: &SqlitePool, id: i64, filename: &str) {
+    handles::discard(id);
+    if let Err(e) = direct::materialize(id).await {
+        warn!("⚠️ Failed to keep the received data of {}: {}", filename, e);
+    }
+    coalescer::flush(pool).await;
+    db::mark_received(pool, id).await;
+    registry::finish(id);
+    info!("📨 Received all of {}, waiting for the sender to confirm its hash", filename);
+}
+
+/// Checks a fully received upload against the SHA-256 its sender sent with the last chunk,
+/// before it's stored, and records the hash. On a mismatch the upload is discarded, so the
+/// sender starts over.
+pub async fn verify(pool: &SqlitePool, config: &AppConfig, id: i64, filename: &str, expected: &str) -> Result<(), ApiError> {
+    let storage_error = |e: std::io::Error| ApiError::internal("storage_error", format!("Failed to hash {}: {}", filename, e));
+    handles::discard(id);
+    direct::materialize(id).await.map_err(storage_error)?;
+    let path = sessions::part_path(config.scratch_dir(), id, filename).await.map_err(storage_error)?;
+    let sha256 = utils::sha256_file(&path.display().to_string()).await.map_err(storage_error)?;
+    let expected = expected.trim().to_ascii_lowercase();
+    if sha256 == expected {
+        db::set_sha256(pool, id, &sha256).await;
+        return Ok(());
+    }
+
+    coalescer::flush(pool).await;
+    let Some(upload) = db::get_upload(pool, id).await else {
+        return Err(ApiError::not_found("upload_not_found", format!("No upload of {}", filename)));
+    };
+    let size = tokio::fs::metadata(&path).await.map(|m| m.len() as i64).unwrap_or(0);
+    warn!("❌ Hash mismatch for {} from {}: sender has {}, received {} ({} bytes)", filename, upload.client_ip, expected, sha256, size);
+    sessions::abandon(pool, config, &upload, "hash mismatch").await;
+    let details = FinalizeResponse { upload_id: id, complete: false, size, declared_size: upload.declared_size, sha256, expected_sha256: Some(expected) };
+    Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "hash_mismatch", format!("{} arrived corrupted ({} bytes received)", filename, size)).with_details(details))
+}
+
+/// `POST /upload/finalize`: compares the sender's SHA-256 of the whole file with the server's.
+/// A `received` upload is completed on a match and discarded on a mismatch, so the sender
+/// starts over; a `complete` one is only checked.
+pub async fn handle_finalize(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(config): Extension<AppConfig>,
+    headers: HeaderMap,
+    Json(request): Json<FinalizeRequest>,
+) -> Result<Response, ApiError> {
+    limits::filename(&request.filename)?;
+    let client_ip = extract_client_ip(&headers, &addr);
+    let upload = match request.upload_id {
+        Some(id) => db::get_upload(&pool, id).await,
+        None => db::find_latest_upload(&pool, &request.filename, &client_ip).await,
+    };
+    let upload = match upload {
+        Some(upload) if upload.client_ip == client_ip && upload.filename == request.filename => upload,
+        _ => return Err(ApiError::not_found("upload_not_found", format!("No upload of {}", request.filename))),
+    };
+    trace::note(upload.id, None, None, None);
+
+    let path = match upload.status.as_str() {
+        "received" | "quarantined" => sessions::part_path(config.scratch_dir(), upload.id, &upload.filename)
+            .await
+            .map_err(|e| ApiError::internal("storage_error", format!("Failed to open session directory: {}", e)))?,
+        "complete" | "suspicious" => PathBuf::from(&config.upload_dir).join(upload.stored_name.as_deref().unwrap_or(&upload.filename)),
+        status => return Err(ApiError::conflict("upload_incomplete", format!("Upload of {} is not fully received ({}, {} bytes)", upload.filename, status, upload.size))),
+    };
+    let size = tokio::fs::metadata(&path).await.map(|m| m.len() as i64).unwrap_or(0);
+    let sha256 = utils::sha256_file(&path.display().to_string())
+        .await
+        .map_err(|e| ApiError::internal("storage_error", format!("Failed to hash {}: {}", upload.filename, e)))?;
+
+    let expected = request.sha256.trim().to_ascii_lowercase();
+    let mut response = FinalizeResponse {
+        upload_id: upload.id,
+        complete: upload.is_stored(),
+        size,
+        declared_size: upload.declared_size,
+        sha256,
+        expected_sha256: None,
+    };
+    if response.sha256 != expected {
+        warn!("❌ Hash mismatch for {} from {}: sender has {}, received {} ({} bytes)", upload.filename, client_ip, expected, response.sha256, size);
+        if upload.status == "received" {
+            sessions::abandon(&pool, &config, &upload, "hash mismatch").await;
+        }
+        response.expected_sha256 = Some(expected);
+        let message = format!("{} arrived corrupted ({} bytes received)", upload.filename, size);
+        return Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "hash_mismatch", message).with_details(response));
+    }
+
+    db::set_sha256(&pool, upload.id, &response.sha256).await;
+    if upload.status == "received" {
+        complete(&pool, &config, upload.id, &upload.filename)
+            .await
+            .map_err(|e| ApiError::internal("storage_error", format!("Failed to finalize file: {}", e)))?;
+        response.complete = db::get_upload(&pool, upload.id).await.is_some_and(|upload| upload.is_stored());
+    }
+    Ok(Json(response).into_response())
+}
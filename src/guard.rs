@@ -0,0 +1,97 @@
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use log::error;
+use crate::{config::AppConfig, utils};
+
+const DB_LOCK: &str = "drcv.db.lock";
+const UPLOAD_DIR_LOCK: &str = ".drcv.lock";
+
+#[derive(Serialize, Deserialize)]
+struct Holder {
+    pid: u32,
+    upload_port: u16,
+    admin_port: u16,
+    upload_dir: String,
+    started_at: String,
+}
+
+/// Exclusive OS locks on the database and upload directory, held for the life of the process
+/// and released automatically when it exits, even on a crash.
+pub struct InstanceGuard {
+    _locks: Vec<File>,
+}
+
+/// Refuses to start when another drcv already serves the same database or upload directory.
+/// Instances that share a database on purpose (`--instance-id`) are not guarded.
+pub fn acquire(config: &AppConfig) -> InstanceGuard {
+    if config.instance_id.is_some() {
+        return InstanceGuard { _locks: Vec::new() };
+    }
+    let holder = Holder {
+        pid: std::process::id(),
+        upload_port: config.upload_port,
+        admin_port: config.admin_port,
+        upload_dir: config.upload_dir.clone(),
+        started_at: utils::now(),
+    };
+    let _ = std::fs::create_dir_all(&config.upload_dir);
+
+    let locks = [PathBuf::from(DB_LOCK), Path::new(&config.upload_dir).join(UPLOAD_DIR_LOCK)]
+        .iter()
+        .map(|path| lock(path, &holder))
+        .collect();
+    InstanceGuard { _locks: locks }
+}
+
+fn lock(path: &Path, holder: &Holder) -> File {
+    let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)
+        .unwrap_or_else(|e| {
+            error!("❌ Failed to open lock file {}: {}", path.display(), e);
+            std::process::exit(1);
+        });
+
+    if file.try_lock_exclusive().is_err() {
+        let mut contents = String::new();
+        let _ = file.read_to_string(&mut contents);
+        error!("❌ Another drcv is already using {}", path.display());
+        match serde_json::from_str::<Holder>(&contents) {
+            Ok(other) => {
+                error!("   PID {} started at {}", other.pid, other.started_at);
+                error!("   Upload: http://127.0.0.1:{} • Admin: http://127.0.0.1:{} • Upload dir: {}", other.upload_port, other.admin_port, other.upload_dir);
+            }
+            Err(_) => error!("   (no details recorded in the lock file)"),
+        }
+        error!("💡 Stop it first, or run from another directory with a different --upload-dir");
+        std::process::exit(1);
+    }
+
+    let _ = file.set_len(0);
+    let _ = file.rewind();
+    let _ = file.write_all(serde_json::to_string(holder).unwrap_or_default().as_bytes());
+    let _ = file.flush();
+    file
+}
+
+/// Fails fast on busy ports, before a tunnel is brought up for a server that can't start.
+pub fn check_ports(config: &AppConfig) {
+    if let Err(e) = std::net::TcpListener::bind(("0.0.0.0", config.upload_port)) {
+        exit_on_bind_error(config.upload_port, "Upload", e);
+    }
+    if let Err(e) = std::net::TcpListener::bind((config.admin_bind, config.admin_port)) {
+        exit_on_bind_error(config.admin_port, "Admin", e);
+    }
+}
+
+/// Explains a listener that failed to bind and exits.
+pub fn exit_on_bind_error(port: u16, role: &str, e: std::io::Error) -> ! {
+    if e.kind() == std::io::ErrorKind::AddrInUse {
+        error!("❌ {} port {} is already in use", role, port);
+        error!("💡 Pick another one with --{}-port, or find the process holding it (e.g. `lsof -i :{}`)", role.to_lowercase(), port);
+    } else {
+        error!("❌ Failed to listen on {} port {}: {}", role.to_lowercase(), port, e);
+    }
+    std::process::exit(1);
+}
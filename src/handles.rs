@@ -0,0 +1,65 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use dashmap::DashMap;
+use std::time::{Duration, Instant};
+use tokio::fs::File;
+use tokio::sync::OwnedMutexGuard;
+use log::debug;
+
+struct PooledHandle {
+    path: PathBuf,
+    file: File,
+    last_used: Instant,
+}
+
+/// Open `.part` handles kept between chunk requests, keyed by upload id. A handle is checked
+/// out for the duration of one write, so it's never shared between concurrent requests.
+static POOL: Lazy<Mutex<HashMap<i64, PooledHandle>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// One lock per upload taking chunks, held from placing a chunk until it's journaled.
+static WRITERS: Lazy<DashMap<i64, Arc<tokio::sync::Mutex<()>>>> = Lazy::new(DashMap::new);
+
+/// Waits until no other request is placing a chunk of upload `id`.
+pub async fn lock(id: i64) -> OwnedMutexGuard<()> {
+    let writer = Arc::clone(&WRITERS.entry(id).or_default());
+    writer.lock_owned().await
+}
+
+/// Returns the pooled handle for `id`, opening `path` if none is pooled. Writes seek first, as
+/// chunks may land anywhere in the file.
+pub async fn checkout(id: i64, path: &Path) -> std::io::Result<File> {
+    let pooled = POOL.lock().unwrap().remove(&id);
+    match pooled {
+        Some(handle) if handle.path == path => Ok(handle.file),
+        _ => tokio::fs::OpenOptions::new().create(true).truncate(false).write(true).open(path).await,
+    }
+}
+
+/// Puts a handle back after a successful write, closing the least recently used one when full.
+pub fn checkin(id: i64, path: &Path, file: File, capacity: usize) {
+    let mut pool = POOL.lock().unwrap();
+    if pool.len() >= capacity {
+        let oldest = pool.iter().min_by_key(|(_, h)| h.last_used).map(|(id, _)| *id);
+        if let Some(oldest) = oldest {
+            pool.remove(&oldest);
+        }
+    }
+    pool.insert(id, PooledHandle { path: path.to_path_buf(), file, last_used: Instant::now() });
+}
+
+/// Drops any pooled handle for `id`, e.g. before the `.part` file is renamed or removed.
+pub fn discard(id: i64) {
+    POOL.lock().unwrap().remove(&id);
+    WRITERS.remove(&id);
+}
+
+pub fn close_idle(max_idle: Duration) {
+    let mut pool = POOL.lock().unwrap();
+    let before = pool.len();
+    pool.retain(|_, h| h.last_used.elapsed() < max_idle);
+    if pool.len() < before {
+        debug!("Closed {} idle upload file handles", before - pool.len());
+    }
+}
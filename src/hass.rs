@@ -0,0 +1,104 @@
+use axum::{extract::{State, Extension}, Json};
+use rumqttc::{AsyncClient, QoS};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use log::{info, warn};
+use crate::{config::AppConfig, db, events::{self, Event}, notify::mqtt, registry};
+
+/// How often the MQTT state is republished, so disk free stays current between uploads.
+const STATE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What Home Assistant sensors read, flat so each one picks a field with `value_json.<name>`.
+#[derive(Serialize)]
+pub struct HassState {
+    pub active_uploads: usize,
+    pub last_file: Option<String>,
+    pub last_file_size: Option<i64>,
+    pub last_file_at: Option<String>,
+    pub disk_free_bytes: Option<u64>,
+}
+
+pub async fn state(pool: &SqlitePool, config: &AppConfig) -> HassState {
+    let last = db::get_last_completed_upload(pool).await;
+    HassState {
+        active_uploads: registry::active_count(),
+        last_file_size: last.as_ref().map(|upload| upload.size),
+        last_file_at: last.as_ref().and_then(|upload| upload.completed_at.clone()),
+        last_file: last.map(|upload| upload.stored_name.unwrap_or(upload.filename)),
+        disk_free_bytes: fs2::available_space(&config.upload_dir).ok(),
+    }
+}
+
+/// `GET /integrations/hass`: the same state for Home Assistant's RESTful sensor.
+pub async fn handle_state(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+) -> Json<HassState> {
+    Json(state(&pool, &config).await)
+}
+
+/// `--mqtt-hass-discovery`: announces drcv's sensors to Home Assistant and keeps their state
+/// topic up to date, on every upload event and every `STATE_INTERVAL`.
+pub fn spawn(pool: SqlitePool, config: &AppConfig) {
+    let (true, Some(url)) = (config.mqtt_hass_discovery, &config.mqtt_url) else { return };
+    // A connection of its own, as the notifier's doesn't retain
+    let client = match mqtt::connect_client(url, "-hass") {
+        Ok(client) => client,
+        Err(e) => return warn!("⚠️ Home Assistant discovery disabled: {}", e),
+    };
+    let config = config.clone();
+    let mut rx = events::subscribe();
+    tokio::spawn(async move {
+        let prefix = config.mqtt_topic_prefix.trim_end_matches('/').to_string();
+        let state_topic = format!("{}/state", prefix);
+        announce(&client, &prefix, &state_topic).await;
+        info!("🏠 Announced drcv sensors to Home Assistant");
+
+        let mut tick = tokio::time::interval(STATE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = tick.tick() => {}
+                event = rx.recv() => match event {
+                    Ok(Event::UploadStarted { .. } | Event::UploadCompleted { .. } | Event::UploadDisconnected { .. } | Event::UploadAbandoned { .. }) => {}
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            }
+            let payload = serde_json::to_vec(&state(&pool, &config).await).unwrap_or_default();
+            if let Err(e) = client.publish(&state_topic, QoS::AtLeastOnce, true, payload).await {
+                warn!("⚠️ Failed to publish the Home Assistant state: {}", e);
+            }
+        }
+    });
+}
+
+/// Publishes retained discovery configs under `homeassistant/sensor/<node>/`, one per sensor.
+/// The node id comes from the topic prefix, so drcv instances with their own prefix show up
+/// as separate devices.
+async fn announce(client: &AsyncClient, prefix: &str, state_topic: &str) {
+    let node = prefix.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+    let device = json!({
+        "identifiers": [node],
+        "name": prefix,
+        "manufacturer": "drcv",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+    let sensors = [
+        ("active_uploads", json!({ "name": "Active uploads", "icon": "mdi:upload", "state_class": "measurement" })),
+        ("last_file", json!({ "name": "Last received file", "icon": "mdi:file-download", "json_attributes_topic": state_topic })),
+        ("disk_free_bytes", json!({ "name": "Disk free", "device_class": "data_size", "unit_of_measurement": "B", "state_class": "measurement" })),
+    ];
+    for (field, mut sensor) in sensors {
+        sensor["unique_id"] = json!(format!("{}_{}", node, field));
+        sensor["state_topic"] = json!(state_topic);
+        sensor["value_template"] = json!(format!("{{{{ value_json.{} }}}}", field));
+        sensor["device"] = device.clone();
+        let topic = format!("homeassistant/sensor/{}/{}/config", node, field);
+        if let Err(e) = client.publish(topic, QoS::AtLeastOnce, true, sensor.to_string()).await {
+            warn!("⚠️ Failed to publish the Home Assistant discovery config: {}", e);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! `GET /healthz` on both ports, for container liveness and readiness probes: 200 while drcv
+//! serves and its database answers, 503 once it is shutting down so no new uploads are routed
+//! to it.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use axum::{extract::State, http::StatusCode, Json};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tokio::sync::Notify;
+use crate::error::ApiError;
+
+static DRAINING: AtomicBool = AtomicBool::new(false);
+static SERVERS_STOPPED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Fails the health check from now on; called when shutdown begins.
+pub fn start_draining() {
+    DRAINING.store(true, Ordering::Relaxed);
+}
+
+/// Called once both servers have finished the requests they were serving.
+pub fn servers_stopped() {
+    SERVERS_STOPPED.notify_one();
+}
+
+/// Resolves after [`servers_stopped`], even if that came first.
+pub async fn stopped() {
+    SERVERS_STOPPED.notified().await;
+}
+
+/// `GET /healthz`; needs no API key on the admin port.
+pub async fn handle_healthz(State(pool): State<SqlitePool>) -> Result<Json<Value>, ApiError> {
+    if DRAINING.load(Ordering::Relaxed) {
+        return Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "draining", "drcv is shutting down"));
+    }
+    let query = sqlx::query("SELECT 1").execute(&pool);
+    match tokio::time::timeout(Duration::from_secs(2), query).await {
+        Ok(Ok(_)) => Ok(Json(json!({ "status": "ok" }))),
+        Ok(Err(e)) => Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "database_unavailable", format!("The database doesn't answer: {}", e))),
+        Err(_) => Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "database_unavailable", "The database didn't answer within 2s")),
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::{aead::{Aead, KeyInit}, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Row};
+use crate::{config::{AppConfig, IdentityArgs, DEFAULT_CONFIG_FILE}, db::{self, DB_FILE}, guard, utils};
+
+const FORMAT: &str = "drcv-identity";
+const PBKDF2_ROUNDS: u32 = 600_000;
+/// Rounds a bundle may ask for; far more would keep import-identity busy for hours
+const ROUNDS_RANGE: std::ops::RangeInclusive<u32> = 100_000..=10_000_000;
+/// Read instead of prompting, for scripts; never taken from the command line, where `ps` shows it
+const PASSPHRASE_ENV: &str = "DRCV_PASSPHRASE";
+
+/// What makes a drcv installation recognisable from outside: the kv table (share hash,
+/// identity salt), the API keys dashboards use and the config file it was started with.
+#[derive(Serialize, Deserialize)]
+struct Identity {
+    created_at: String,
+    drcv_version: String,
+    kv: BTreeMap<String, String>,
+    #[serde(default)]
+    api_keys: Vec<db::ApiKey>,
+    settings: Option<String>,
+}
+
+/// The file on disk; only `ciphertext` carries the identity.
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    format: String,
+    kdf: String,
+    rounds: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("❌ {}", message);
+    std::process::exit(1);
+}
+
+fn passphrase(confirm: bool) -> String {
+    if let Some(passphrase) = std::env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty()) {
+        return passphrase;
+    }
+    let passphrase = rpassword::prompt_password("Passphrase: ").unwrap_or_else(|e| fail(format!("Failed to read the passphrase: {}", e)));
+    if passphrase.is_empty() {
+        fail("The passphrase must not be empty".to_string());
+    }
+    if confirm && rpassword::prompt_password("Repeat passphrase: ").ok().as_ref() != Some(&passphrase) {
+        fail("The passphrases don't match".to_string());
+    }
+    passphrase
+}
+
+fn cipher(passphrase: &str, salt: &[u8], rounds: u32) -> XChaCha20Poly1305 {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(passphrase.as_bytes(), salt, rounds, &mut key);
+    XChaCha20Poly1305::new(&key.into())
+}
+
+fn seal(identity: &Identity, passphrase: &str) -> Bundle {
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let plaintext = serde_json::to_vec(identity).unwrap_or_default();
+    let ciphertext = cipher(passphrase, &salt, PBKDF2_ROUNDS)
+        .encrypt(XNonce::from_slice(&nonce), plaintext.as_slice())
+        .unwrap_or_else(|e| fail(format!("Failed to encrypt the bundle: {}", e)));
+    Bundle {
+        format: FORMAT.to_string(),
+        kdf: "pbkdf2-sha256".to_string(),
+        rounds: PBKDF2_ROUNDS,
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    }
+}
+
+fn open(bundle: &Bundle, passphrase: &str) -> Identity {
+    if bundle.format != FORMAT {
+        fail(format!("Not a drcv identity bundle (format {})", bundle.format));
+    }
+    let decode = |field: &str| BASE64.decode(field).unwrap_or_else(|e| fail(format!("Corrupt bundle: {}", e)));
+    if !ROUNDS_RANGE.contains(&bundle.rounds) {
+        fail(format!("Corrupt bundle: {} rounds is outside {}..={}", bundle.rounds, ROUNDS_RANGE.start(), ROUNDS_RANGE.end()));
+    }
+    let nonce = decode(&bundle.nonce);
+    if nonce.len() != 24 {
+        fail("Corrupt bundle: bad nonce".to_string());
+    }
+    let plaintext = cipher(passphrase, &decode(&bundle.salt), bundle.rounds)
+        .decrypt(XNonce::from_slice(&nonce), decode(&bundle.ciphertext).as_slice())
+        .unwrap_or_else(|_| fail("Wrong passphrase, or the bundle was modified".to_string()));
+    serde_json::from_slice(&plaintext).unwrap_or_else(|e| fail(format!("Corrupt bundle: {}", e)))
+}
+
+/// The config file drcv runs with, as `load_args` picks it.
+fn config_file(explicit: Option<&str>) -> Option<String> {
+    explicit.map(str::to_string).or_else(|| Path::new(DEFAULT_CONFIG_FILE).exists().then(|| DEFAULT_CONFIG_FILE.to_string()))
+}
+
+/// `drcv export-identity <FILE>`: writes the share hash, identity salt, API keys and config
+/// file to an encrypted bundle, for moving drcv to another machine under the same hostname.
+pub async fn export(args: &IdentityArgs, explicit_config: Option<&str>) {
+    if !Path::new(DB_FILE).exists() {
+        fail(format!("No {} in the current directory", DB_FILE));
+    }
+    if Path::new(&args.path).exists() && !args.force {
+        fail(format!("{} already exists; pass --force to overwrite it", args.path));
+    }
+    let mut conn = SqliteConnectOptions::from_str(&format!("sqlite:{}", DB_FILE))
+        .unwrap_or_else(|e| fail(format!("Failed to open {}: {}", DB_FILE, e)))
+        .read_only(true)
+        .connect().await
+        .unwrap_or_else(|e| fail(format!("Failed to open {}: {}", DB_FILE, e)));
+    let kv: BTreeMap<String, String> = sqlx::query("SELECT k, v FROM kv ORDER BY k")
+        .fetch_all(&mut conn).await
+        .unwrap_or_else(|e| fail(format!("Failed to read the kv table: {}", e)))
+        .iter()
+        .map(|row| (row.get("k"), row.get("v")))
+        .collect();
+    // Databases from before API keys have no table for them
+    let api_keys: Vec<db::ApiKey> = sqlx::query("SELECT * FROM api_keys ORDER BY id")
+        .fetch_all(&mut conn).await
+        .map(|rows| rows.iter().map(db::ApiKey::from_row).collect())
+        .unwrap_or_default();
+    let settings = config_file(explicit_config).map(|path| {
+        std::fs::read_to_string(&path).unwrap_or_else(|e| fail(format!("Failed to read {}: {}", path, e)))
+    });
+
+    let identity = Identity {
+        created_at: utils::now(),
+        drcv_version: env!("CARGO_PKG_VERSION").to_string(),
+        kv,
+        api_keys,
+        settings,
+    };
+    let bundle = seal(&identity, &passphrase(true));
+    let json = serde_json::to_string_pretty(&bundle).unwrap_or_default();
+    std::fs::write(&args.path, json).unwrap_or_else(|e| fail(format!("Failed to write {}: {}", args.path, e)));
+
+    println!("✅ Exported {} kv entries, {} API keys{} to {}", identity.kv.len(), identity.api_keys.len(), if identity.settings.is_some() { " and the config file" } else { "" }, args.path);
+    if let Some(hash) = identity.kv.get("cf_hash") {
+        println!("🔗 Importing it elsewhere keeps the share hostname {}", hash);
+    }
+}
+
+/// `drcv import-identity <FILE>`: adopts a bundle from `export-identity`. Entries that
+/// differ from this installation's are only replaced with `--force`.
+pub async fn import(args: &IdentityArgs, config: &AppConfig, explicit_config: Option<&str>) {
+    let text = std::fs::read_to_string(&args.path).unwrap_or_else(|e| fail(format!("Failed to read {}: {}", args.path, e)));
+    let bundle: Bundle = serde_json::from_str(&text).unwrap_or_else(|e| fail(format!("Not a drcv identity bundle: {}", e)));
+    let identity = open(&bundle, &passphrase(false));
+
+    // Exits if a drcv is serving from here
+    let _guard = guard::acquire(config);
+    let pool = db::init_pool(config).await.unwrap_or_else(|e| fail(format!("Failed to open {}: {}", DB_FILE, e)));
+
+    let mut conflicts = Vec::new();
+    for (key, value) in &identity.kv {
+        if db::kv_get(&pool, key).await.is_some_and(|current| &current != value) {
+            conflicts.push(key.clone());
+        }
+    }
+    let current_keys = db::get_api_keys(&pool).await;
+    for key in &identity.api_keys {
+        if current_keys.iter().any(|k| k.name == key.name && k.key_hash != key.key_hash) {
+            conflicts.push(format!("API key {}", key.name));
+        }
+    }
+    let settings_path = config_file(explicit_config).unwrap_or_else(|| DEFAULT_CONFIG_FILE.to_string());
+    let settings_differ = identity.settings.as_ref().is_some_and(|settings| {
+        std::fs::read_to_string(&settings_path).is_ok_and(|current| &current != settings)
+    });
+    if settings_differ {
+        conflicts.push(settings_path.clone());
+    }
+    if !conflicts.is_empty() && !args.force {
+        fail(format!("This installation already has a different {}; pass --force to replace it", conflicts.join(", ")));
+    }
+
+    for (key, value) in &identity.kv {
+        db::kv_set(&pool, key, value).await;
+    }
+    for key in &identity.api_keys {
+        db::restore_api_key(&pool, key).await;
+    }
+    if let Some(settings) = &identity.settings {
+        if settings_differ {
+            let backup = format!("{}.bak", settings_path);
+            std::fs::rename(&settings_path, &backup).unwrap_or_else(|e| fail(format!("Failed to move {} aside: {}", settings_path, e)));
+            println!("   Kept the previous config file as {}", backup);
+        }
+        std::fs::write(&settings_path, settings).unwrap_or_else(|e| fail(format!("Failed to write {}: {}", settings_path, e)));
+    }
+
+    println!("✅ Imported {} kv entries, {} API keys{} exported {}", identity.kv.len(), identity.api_keys.len(), if identity.settings.is_some() { format!(" and {}", settings_path) } else { String::new() }, identity.created_at);
+    if let Some(hash) = identity.kv.get("cf_hash") {
+        println!("🔗 The share URL stays https://{}.{}", hash, config.tunnel_domain);
+    }
+}
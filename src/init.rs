@@ -0,0 +1,139 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process::Command;
+use crate::{config::InitArgs, doctor};
+
+fn ask(question: &str, default: &str) -> String {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    let _ = io::stdout().flush();
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line).unwrap_or(0) == 0 {
+        println!();
+        std::process::exit(1);
+    }
+    let answer = line.trim();
+    if answer.is_empty() { default.to_string() } else { answer.to_string() }
+}
+
+fn confirm(question: &str, default: bool) -> bool {
+    let answer = ask(&format!("{} (y/n)", question), if default { "y" } else { "n" });
+    answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes")
+}
+
+fn choose(question: &str, options: &[&str]) -> String {
+    loop {
+        let answer = ask(&format!("{} ({})", question, options.join("/")), options[0]).to_lowercase();
+        if options.contains(&answer.as_str()) {
+            return answer;
+        }
+        println!("  Please answer one of: {}", options.join(", "));
+    }
+}
+
+fn list(question: &str) -> Vec<toml::Value> {
+    ask(question, "")
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| toml::Value::String(s.to_string()))
+        .collect()
+}
+
+/// `drcv init`: asks the handful of questions a first-time recipient needs and writes a config file.
+pub fn run(init: &InitArgs) {
+    println!("📦 drcv setup — press Enter to accept the [default]\n");
+    if Path::new(&init.output).exists() && !confirm(&format!("{} already exists. Overwrite?", init.output), false) {
+        return;
+    }
+
+    let mut table = toml::Table::new();
+    let mut set = |key: &str, value: toml::Value| { table.insert(key.to_string(), value); };
+
+    set("upload-dir", ask("Where should received files be saved?", "./uploads").into());
+    set("max-file-size", ask("Largest file you want to accept (e.g. 10GiB)", "100GiB").into());
+
+    println!("\nHow will senders reach you?");
+    println!("  cloudflare — {{hash}}.drcv.app through Cloudflare Tunnel (needs cloudflared)");
+    println!("  ssh        — reverse SSH tunnel, e.g. localhost.run");
+    println!("  bore       — your own bore relay server");
+    println!("  proxy      — you already run a reverse proxy in front of this machine");
+    let provider = choose("Tunnel", &["cloudflare", "ssh", "bore", "proxy"]);
+    match provider.as_str() {
+        "cloudflare" => check_cloudflared(),
+        "ssh" => {
+            set("tunnel-provider", "ssh".into());
+            set("ssh-target", ask("SSH target", "nokey@localhost.run").into());
+        }
+        "bore" => {
+            set("tunnel-provider", "bore".into());
+            set("relay-server", ask("Relay server host", "").into());
+            let secret = ask("Relay secret (leave empty for none)", "");
+            if !secret.is_empty() {
+                set("relay-secret", secret.into());
+            }
+        }
+        _ => {
+            set("public-url", ask("Public URL of your proxy (e.g. https://drop.example.com)", "").into());
+        }
+    }
+
+    println!("\nWho may upload?");
+    let access = if provider == "cloudflare" {
+        choose("Restrict senders", &["anyone", "emails", "networks"])
+    } else {
+        choose("Restrict senders", &["anyone", "networks"])
+    };
+    match access.as_str() {
+        "emails" => {
+            println!("  (Cloudflare Access login; needs CLOUDFLARE_API_TOKEN when drcv runs)");
+            set("cf-access-email", list("Allowed emails, comma separated").into());
+            let domains = list("Allowed email domains, comma separated (optional)");
+            if !domains.is_empty() {
+                set("cf-access-domain", domains.into());
+            }
+        }
+        "networks" => set("allow-cidr", list("Allowed networks, comma separated (e.g. 203.0.113.0/24)").into()),
+        _ => {}
+    }
+
+    println!("\nNotifications (slack://, tgram://, mailto://, json://, mqtt:// — see README)");
+    let notify = list("Notification URLs, comma separated (optional)");
+    if !notify.is_empty() {
+        set("notify-url", notify.into());
+    }
+
+    let text = toml::to_string(&table).unwrap_or_default();
+    match std::fs::write(&init.output, format!("# Generated by `drcv init`; command line options override these\n{}", text)) {
+        Ok(()) => {
+            println!("\n✅ Wrote {}", init.output);
+            if init.output == crate::config::DEFAULT_CONFIG_FILE {
+                println!("   Run `drcv` in this directory to start.");
+            } else {
+                println!("   Start with `drcv --config {}`.", init.output);
+            }
+        }
+        Err(e) => {
+            eprintln!("❌ Failed to write {}: {}", init.output, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn check_cloudflared() {
+    if !doctor::cloudflared_installed() {
+        println!("  ⚠️  cloudflared is not installed. See https://developers.cloudflare.com/cloudflare-one/connections/connect-networks/downloads/");
+        if cfg!(target_os = "macos") {
+            println!("     brew install cloudflared");
+        }
+        return;
+    }
+    if doctor::cloudflared_logged_in() {
+        println!("  ✅ cloudflared is installed and logged in");
+    } else if confirm("  cloudflared is not logged in yet. Run `cloudflared tunnel login` now?", true) {
+        let _ = Command::new("cloudflared").args(["tunnel", "login"]).status();
+    }
+}
@@ -0,0 +1,140 @@
+//! Hash lookups for completed files: with `--virustotal-api-key` or `--malwarebazaar-api-key`,
+//! the file's SHA-256, and nothing of its content, is looked up with those services. The
+//! verdict is stored on the upload and sent along with its `upload_completed` notification,
+//! which waits for it.
+use std::path::PathBuf;
+use std::time::Duration;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::SqlitePool;
+use log::warn;
+use crate::{config::AppConfig, db, events::{self, Event}, utils};
+
+const VIRUSTOTAL: &str = "https://www.virustotal.com/api/v3/files";
+const MALWAREBAZAAR: &str = "https://mb-api.abuse.ch/api/v1/";
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// From least to most alarming, so the worst of several answers is the largest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// No service could be asked
+    Error,
+    /// No service knows the hash
+    Unknown,
+    Clean,
+    Suspicious,
+    Malicious,
+}
+
+impl Verdict {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Verdict::Error => "error",
+            Verdict::Unknown => "unknown",
+            Verdict::Clean => "clean",
+            Verdict::Suspicious => "suspicious",
+            Verdict::Malicious => "malicious",
+        }
+    }
+}
+
+/// What the services said about a file, e.g. `malicious` with
+/// `VirusTotal: 12 of 70 engines flag it; MalwareBazaar: AgentTesla`.
+#[derive(Clone, Debug, Serialize)]
+pub struct HashVerdict {
+    pub verdict: Verdict,
+    pub sha256: String,
+    pub detail: String,
+}
+
+pub fn enabled(config: &AppConfig) -> bool {
+    config.virustotal_api_key.is_some() || config.malwarebazaar_api_key.is_some()
+}
+
+/// Publishes `upload_completed` for a stored file, after looking up its hash when a service
+/// is configured.
+pub fn publish_completed(pool: &SqlitePool, config: &AppConfig, id: i64, filename: String, size: i64, path: PathBuf) {
+    if !enabled(config) {
+        return events::publish(Event::UploadCompleted { id, filename, size, path: path.display().to_string(), hash_verdict: None });
+    }
+    let (pool, config) = (pool.clone(), config.clone());
+    tokio::spawn(async move {
+        let path = path.display().to_string();
+        let hash_verdict = match utils::sha256_file(&path).await {
+            Ok(sha256) => Some(look_up(&config, sha256).await),
+            Err(e) => {
+                warn!("⚠️ Failed to hash {} for a lookup: {}", filename, e);
+                None
+            }
+        };
+        if let Some(verdict) = &hash_verdict {
+            db::set_hash_verdict(&pool, id, verdict.verdict.as_str(), &verdict.detail).await;
+            if verdict.verdict >= Verdict::Suspicious {
+                warn!("☣️ {} is {}: {}", filename, verdict.verdict.as_str(), verdict.detail);
+            }
+        }
+        events::publish(Event::UploadCompleted { id, filename, size, path, hash_verdict });
+    });
+}
+
+async fn look_up(config: &AppConfig, sha256: String) -> HashVerdict {
+    let client = reqwest::Client::builder().timeout(TIMEOUT).build().unwrap_or_default();
+    let mut answers = Vec::new();
+    if let Some(key) = &config.virustotal_api_key {
+        answers.push(("VirusTotal", virustotal(&client, key, &sha256).await));
+    }
+    if let Some(key) = &config.malwarebazaar_api_key {
+        answers.push(("MalwareBazaar", malwarebazaar(&client, key, &sha256).await));
+    }
+    let verdict = answers.iter().map(|(_, answer)| answer.as_ref().map_or(Verdict::Error, |(verdict, _)| *verdict)).max().unwrap_or(Verdict::Error);
+    let detail = answers.into_iter()
+        .map(|(service, answer)| match answer {
+            Ok((_, detail)) => format!("{}: {}", service, detail),
+            Err(e) => format!("{}: {}", service, e),
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+    HashVerdict { verdict, sha256, detail }
+}
+
+async fn virustotal(client: &reqwest::Client, key: &str, sha256: &str) -> Result<(Verdict, String), String> {
+    let response = client.get(format!("{}/{}", VIRUSTOTAL, sha256))
+        .header("x-apikey", key)
+        .send().await
+        .map_err(|e| e.to_string())?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok((Verdict::Unknown, "not seen before".to_string()));
+    }
+    let report: Value = response.error_for_status().map_err(|e| e.to_string())?
+        .json().await.map_err(|e| e.to_string())?;
+    let stats = &report["data"]["attributes"]["last_analysis_stats"];
+    let count = |field: &str| stats[field].as_u64().unwrap_or(0);
+    let engines = ["malicious", "suspicious", "undetected", "harmless"].iter().map(|field| count(field)).sum::<u64>();
+    Ok(match (count("malicious"), count("suspicious")) {
+        (0, 0) => (Verdict::Clean, format!("no engine of {} flags it", engines)),
+        (0, suspicious) => (Verdict::Suspicious, format!("{} of {} engines find it suspicious", suspicious, engines)),
+        (malicious, _) => (Verdict::Malicious, format!("{} of {} engines flag it", malicious, engines)),
+    })
+}
+
+async fn malwarebazaar(client: &reqwest::Client, key: &str, sha256: &str) -> Result<(Verdict, String), String> {
+    let answer: Value = client.post(MALWAREBAZAAR)
+        .header("Auth-Key", key)
+        .form(&[("query", "get_info"), ("hash", sha256)])
+        .send().await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json().await
+        .map_err(|e| e.to_string())?;
+    // MalwareBazaar only holds malware samples, so knowing the hash is the verdict
+    match answer["query_status"].as_str() {
+        Some("ok") => {
+            let signature = answer["data"][0]["signature"].as_str().unwrap_or("unnamed family");
+            Ok((Verdict::Malicious, format!("known sample of {}", signature)))
+        }
+        Some("hash_not_found") => Ok((Verdict::Unknown, "not a known sample".to_string())),
+        Some(status) => Err(status.to_string()),
+        None => Err("unexpected answer".to_string()),
+    }
+}
@@ -0,0 +1,82 @@
+//! drcv as a library: the binary in `main.rs` wires these modules together, and integration
+//! tests start the same upload app through [`testing::TestServer`].
+
+pub mod db;
+pub mod upload;
+pub mod assembly;
+pub mod admin;
+pub mod download;
+pub mod basic;
+pub mod tunnels;
+pub mod utils;
+pub mod config;
+pub mod apps;
+pub mod events;
+pub mod notify;
+pub mod net;
+pub mod compression;
+pub mod etag;
+pub mod bench;
+pub mod pressure;
+pub mod error;
+pub mod fairness;
+pub mod backoff;
+pub mod handles;
+pub mod direct;
+pub mod coalescer;
+pub mod registry;
+pub mod guard;
+pub mod sessions;
+pub mod watcher;
+pub mod finalize;
+pub mod naming;
+pub mod checksums;
+pub mod window;
+pub mod limits;
+pub mod expect;
+pub mod emit;
+pub mod protocol;
+pub mod policy;
+pub mod routing;
+pub mod consent;
+pub mod purge;
+pub mod status;
+pub mod anonymize;
+pub mod init;
+pub mod doctor;
+pub mod backup;
+pub mod maintenance;
+pub mod metadata;
+pub mod mover;
+pub mod identity;
+pub mod apikeys;
+pub mod hass;
+pub mod timeseries;
+pub mod resume_report;
+pub mod search;
+pub mod signing;
+pub mod validate;
+pub mod service;
+pub mod share;
+pub mod page;
+pub mod console;
+pub mod tui;
+pub mod chaos;
+pub mod trace;
+pub mod reload;
+pub mod privileges;
+pub mod sandbox;
+pub mod health;
+pub mod update;
+pub mod crash;
+pub mod intel;
+pub mod sniff;
+pub mod progress;
+pub mod chunkmap;
+pub mod bandwidth;
+pub mod links;
+#[cfg(feature = "plugins")]
+pub mod plugins;
+#[cfg(feature = "tray")]
+pub mod tray;
+pub mod testing;
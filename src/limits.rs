@@ -0,0 +1,98 @@
+use axum::{extract::Request, http::StatusCode, middleware::Next, response::{IntoResponse, Response}};
+use crate::{error::ApiError, upload::{ChunkUploadRequest, ChunkUploadRequestV2}};
+
+/// Longest filename accepted, in bytes; most filesystems stop at 255
+pub const MAX_FILENAME_BYTES: usize = 255;
+/// At one byte per chunk that is already a 1 MiB file; real chunks are megabytes
+pub const MAX_TOTAL_CHUNKS: u32 = 1 << 20;
+/// Longest sender name or email address accepted, in bytes
+const MAX_SENDER_BYTES: usize = 256;
+/// Request headers on the upload port, in total and by count; browsers send a few KiB at most
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+const MAX_HEADERS: usize = 64;
+
+fn invalid(code: &'static str, message: String) -> ApiError {
+    ApiError::bad_request(code, message)
+}
+
+/// A name the sender gave a file: not empty, at most `MAX_FILENAME_BYTES` and without control
+/// characters. Path separators are fine here; `naming::clean_name` strips them before storing.
+pub fn filename(name: &str) -> Result<(), ApiError> {
+    if name.trim().is_empty() {
+        return Err(invalid("invalid_filename", "filename is empty".to_string()));
+    }
+    if name.len() > MAX_FILENAME_BYTES {
+        return Err(invalid("invalid_filename", format!("filename is {} bytes long, the limit is {}", name.len(), MAX_FILENAME_BYTES)));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(invalid("invalid_filename", "filename contains control characters".to_string()));
+    }
+    Ok(())
+}
+
+/// The name and email address a sender gave, if any.
+pub fn sender(sender_name: Option<&str>, sender_email: Option<&str>) -> Result<(), ApiError> {
+    for (field, value) in [("sender_name", sender_name), ("sender_email", sender_email)] {
+        let Some(value) = value else { continue };
+        if value.len() > MAX_SENDER_BYTES || value.chars().any(char::is_control) {
+            return Err(invalid("invalid_sender", format!("{} must be at most {} bytes without control characters", field, MAX_SENDER_BYTES)));
+        }
+    }
+    Ok(())
+}
+
+/// A hash field, if given, of 64 hex digits.
+fn sha256(field: &str, code: &'static str, hash: Option<&str>) -> Result<(), ApiError> {
+    match hash.map(str::trim) {
+        Some(hash) if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Err(invalid(code, format!("{} must be 64 hex digits", field)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A v1 chunk: `total_chunks` between 1 and `MAX_TOTAL_CHUNKS` and no more than the file has
+/// bytes, `chunk_index` below it, and a `sha256` of 64 hex digits.
+pub fn chunk_v1(upload_data: &ChunkUploadRequest) -> Result<(), ApiError> {
+    filename(&upload_data.filename)?;
+    sender(upload_data.sender_name.as_deref(), upload_data.sender_email.as_deref())?;
+    let total_chunks = upload_data.total_chunks;
+    let most = upload_data.file_size.map_or(MAX_TOTAL_CHUNKS, |size| size.clamp(1, MAX_TOTAL_CHUNKS as u64) as u32);
+    if total_chunks == 0 || total_chunks > most {
+        return Err(invalid("invalid_total_chunks", format!("total_chunks must be between 1 and {}, got {}", most, total_chunks)));
+    }
+    if upload_data.chunk_index >= total_chunks {
+        return Err(invalid("invalid_chunk_index", format!("chunk_index {} is out of range for {} chunks", upload_data.chunk_index, total_chunks)));
+    }
+    sha256("sha256", "invalid_sha256", upload_data.sha256.as_deref())
+}
+
+/// A v2 chunk: an offset within the declared size, a positive `upload_id`, and a
+/// `chunk_sha256` and `sha256` of 64 hex digits.
+pub fn chunk_v2(upload_data: &ChunkUploadRequestV2) -> Result<(), ApiError> {
+    filename(&upload_data.filename)?;
+    sender(upload_data.sender_name.as_deref(), upload_data.sender_email.as_deref())?;
+    if upload_data.upload_id.is_some_and(|id| id <= 0) {
+        return Err(invalid("invalid_upload_id", "upload_id must be positive".to_string()));
+    }
+    if upload_data.offset > upload_data.size {
+        return Err(invalid("invalid_offset", format!("offset {} is past the declared size of {}", upload_data.offset, upload_data.size)));
+    }
+    sha256("chunk_sha256", "invalid_chunk_sha256", upload_data.chunk_sha256.as_deref())?;
+    sha256("sha256", "invalid_sha256", upload_data.sha256.as_deref())
+}
+
+/// Refuses requests to the upload port with more than `MAX_HEADERS` headers or
+/// `MAX_HEADER_BYTES` of them, before any handler reads them.
+pub async fn check_headers(request: Request, next: Next) -> Response {
+    let headers = request.headers();
+    let bytes: usize = headers.iter().map(|(name, value)| name.as_str().len() + value.len()).sum();
+    if headers.len() > MAX_HEADERS || bytes > MAX_HEADER_BYTES {
+        return ApiError::new(
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            "headers_too_large",
+            format!("{} headers of {} bytes; the limit is {} headers of {} bytes", headers.len(), bytes, MAX_HEADERS, MAX_HEADER_BYTES),
+        ).into_response();
+    }
+    next.run(request).await
+}
@@ -0,0 +1,198 @@
+//! Shareable upload links: `/u/<token>` opens the upload page, and every file sent from it
+//! is attributed to the link and held to the link's own expiry, byte and file limits and
+//! allowed extensions, on top of the drop's policy. Links are minted and revoked from the
+//! admin app.
+use std::{path::Path, sync::Arc};
+use axum::{
+    extract::{Extension, Path as UrlPath, State},
+    http::{HeaderMap, StatusCode},
+    response::Html,
+    Json,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::sync::RwLock;
+use log::info;
+use crate::{apps::admin::TunnelInfo, config::AppConfig, db::{self, UploadLink}, error::ApiError, policy::Violation, utils};
+
+/// Sent by the upload page opened from a link with every chunk.
+pub const HEADER: &str = "x-drcv-link";
+
+const TOKEN_LENGTH: usize = 20;
+
+/// The link a request was sent through, if it names one. A token that isn't known or was
+/// revoked fails the request, uploads in progress included.
+pub async fn from_headers(pool: &SqlitePool, headers: &HeaderMap) -> Result<Option<UploadLink>, ApiError> {
+    let Some(token) = headers.get(HEADER).and_then(|v| v.to_str().ok()).map(str::trim).filter(|t| !t.is_empty()) else {
+        return Ok(None);
+    };
+    match db::find_link(pool, token).await {
+        Some(link) if link.revoked_at.is_none() => Ok(Some(link)),
+        _ => Err(ApiError::not_found("link_not_found", "This upload link doesn't exist or was revoked")),
+    }
+}
+
+fn is_expired(link: &UploadLink) -> bool {
+    link.expires_at.as_deref()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .is_some_and(|t| t <= chrono::Utc::now())
+}
+
+/// Checks a new file of `size` bytes (as declared) against the link it's sent through.
+pub async fn check(pool: &SqlitePool, link: &UploadLink, filename: &str, size: Option<u64>) -> Result<(), ApiError> {
+    if is_expired(link) {
+        return Err(ApiError::new(StatusCode::GONE, "link_expired", "This upload link has expired"));
+    }
+    if let Some(allowed) = &link.allowed_extensions {
+        let extension = Path::new(filename)
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        if !allowed.split(',').any(|e| e == extension) {
+            let allowed: Vec<String> = allowed.split(',').map(|e| format!(".{}", e)).collect();
+            return Err(Violation::new("link_extension", format!("{} isn't accepted by this link; allowed file types: {}", filename, allowed.join(", "))).into());
+        }
+    }
+    let (files, bytes) = db::link_usage(pool, link.id).await;
+    if let Some(max_files) = link.max_files {
+        if files >= max_files {
+            return Err(Violation::new("link_max_files", format!("This link accepts at most {} files and is full", max_files)).into());
+        }
+    }
+    if let Some(max_bytes) = link.max_bytes {
+        if bytes.saturating_add(size.unwrap_or(0) as i64) > max_bytes {
+            return Err(Violation::new("link_max_bytes", format!("This link accepts at most {} bytes in total, {} of them are left", max_bytes, (max_bytes - bytes).max(0))).into());
+        }
+    }
+    Ok(())
+}
+
+/// Checks a chunk that would bring upload `id` to `held` bytes, so a sender can't get past a
+/// link's `max_bytes` by declaring a smaller file, or none, than it sends.
+pub async fn check_chunk(pool: &SqlitePool, id: i64, held: i64) -> Result<(), ApiError> {
+    match db::link_bytes_left(pool, id).await {
+        Some((max_bytes, left)) if held > left => {
+            Err(Violation::new("link_max_bytes", format!("This link accepts at most {} bytes in total, {} of them are left for this file", max_bytes, left.max(0))).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `GET /u/:token` on the upload app: the upload page, for a link that still takes files.
+pub async fn handle_link_page(State(pool): State<SqlitePool>, UrlPath(token): UrlPath<String>) -> Result<Html<&'static str>, ApiError> {
+    let link = db::find_link(&pool, &token).await
+        .filter(|link| link.revoked_at.is_none())
+        .ok_or_else(|| ApiError::not_found("link_not_found", "This upload link doesn't exist or was revoked"))?;
+    if is_expired(&link) {
+        return Err(ApiError::new(StatusCode::GONE, "link_expired", "This upload link has expired"));
+    }
+    Ok(Html(include_str!("static/index.html")))
+}
+
+/// A link as the admin app shows it.
+#[derive(Serialize)]
+pub struct LinkStatus {
+    #[serde(flatten)]
+    pub link: UploadLink,
+    /// Path on the upload app, e.g. `/u/Xy3...`
+    pub path: String,
+    /// Full URL to share, once the tunnel or `--public-url` gives one
+    pub url: Option<String>,
+    pub expired: bool,
+    pub files: i64,
+    pub bytes: i64,
+}
+
+async fn status(pool: &SqlitePool, link: UploadLink, base_url: Option<&str>) -> LinkStatus {
+    let (files, bytes) = db::link_usage(pool, link.id).await;
+    let path = format!("/u/{}", link.token);
+    LinkStatus {
+        url: base_url.map(|url| format!("{}{}", url.trim_end_matches('/'), path)),
+        path,
+        expired: is_expired(&link),
+        files,
+        bytes,
+        link,
+    }
+}
+
+async fn base_url(config: &AppConfig, tunnel_info: &RwLock<TunnelInfo>) -> Option<String> {
+    tunnel_info.read().await.url.clone().or(config.public_url.clone())
+}
+
+/// `GET /links`
+pub async fn admin_links(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(tunnel_info): Extension<Arc<RwLock<TunnelInfo>>>,
+) -> Json<Vec<LinkStatus>> {
+    let base_url = base_url(&config, &tunnel_info).await;
+    let mut links = Vec::new();
+    for link in db::get_links(&pool).await {
+        links.push(status(&pool, link, base_url.as_deref()).await);
+    }
+    Json(links)
+}
+
+#[derive(Deserialize)]
+pub struct NewLink {
+    pub label: Option<String>,
+    /// How long the link takes new files, e.g. `7d`; forever when unset
+    pub expires_in: Option<String>,
+    /// Total size of the files sent through it, e.g. `10GiB`
+    pub max_bytes: Option<String>,
+    pub max_files: Option<u32>,
+    /// e.g. `["pdf", ".docx"]`; any when empty
+    #[serde(default)]
+    pub allowed_extensions: Vec<String>,
+}
+
+/// `POST /links`: mints a link with a fresh random token.
+pub async fn admin_create_link(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(tunnel_info): Extension<Arc<RwLock<TunnelInfo>>>,
+    Json(request): Json<NewLink>,
+) -> Result<(StatusCode, Json<LinkStatus>), ApiError> {
+    let blank = |s: &Option<String>| s.as_deref().map(str::trim).filter(|s| !s.is_empty()).map(str::to_string);
+    let expires_at = blank(&request.expires_in)
+        .map(|d| humantime::parse_duration(&d).map_err(|_| ApiError::bad_request("invalid_link", format!("expires_in {:?} is not a duration like 7d", d))))
+        .transpose()?
+        // Too far off to represent is as good as never
+        .and_then(|d| chrono::Duration::from_std(d).ok().and_then(|d| chrono::Utc::now().checked_add_signed(d)))
+        .map(|t| t.to_rfc3339());
+    let max_bytes = blank(&request.max_bytes)
+        .map(|s| byte_unit::Byte::parse_str(&s, true).map(|b| b.as_u64().min(i64::MAX as u64) as i64)
+            .map_err(|_| ApiError::bad_request("invalid_link", format!("max_bytes {:?} is not a size like 10GiB", s))))
+        .transpose()?;
+    let extensions: Vec<String> = request.allowed_extensions.iter()
+        .map(|e| e.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect();
+    let mut link = UploadLink {
+        id: 0,
+        token: rand::thread_rng().sample_iter(&Alphanumeric).take(TOKEN_LENGTH).map(char::from).collect(),
+        label: blank(&request.label),
+        created_at: utils::now(),
+        expires_at,
+        max_bytes,
+        max_files: request.max_files.map(i64::from),
+        allowed_extensions: (!extensions.is_empty()).then(|| extensions.join(",")),
+        revoked_at: None,
+    };
+    link.id = db::insert_link(&pool, &link).await
+        .map_err(|e| ApiError::internal("database_error", format!("Failed to create the link: {}", e)))?;
+    info!("🔗 Upload link {} created{}", link.id, link.label.as_deref().map(|l| format!(" ({})", l)).unwrap_or_default());
+    let base_url = base_url(&config, &tunnel_info).await;
+    Ok((StatusCode::CREATED, Json(status(&pool, link, base_url.as_deref()).await)))
+}
+
+/// `DELETE /links/:id`: revokes a link. Files already sent through it stay attributed to it.
+pub async fn admin_revoke_link(State(pool): State<SqlitePool>, UrlPath(id): UrlPath<i64>) -> Result<StatusCode, ApiError> {
+    if !db::revoke_link(&pool, id).await {
+        return Err(ApiError::not_found("link_not_found", format!("No active link {}", id)));
+    }
+    info!("🔗 Upload link {} revoked", id);
+    Ok(StatusCode::NO_CONTENT)
+}
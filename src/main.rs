@@ -1,126 +1,273 @@
-mod db;
-mod upload;
-mod admin;
-mod tunnels;
-mod utils;
-mod config;
-mod apps;
-
 use sqlx::SqlitePool;
+use std::io::IsTerminal;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use clap::Parser;
 use log::{info, warn, error};
-use config::Args;
-use tunnels::{TunnelConfig, create_tunnel_provider};
-use apps::{admin::TunnelInfo, upload::create_app as create_upload_app, admin::create_app as create_admin_app};
+use drcv::{anonymize, apikeys, backup, bandwidth, bench, chaos, coalescer, config, console, crash, db, direct, doctor, emit, events, expect, fairness, guard, hass, health, identity, init, maintenance, mover, naming, net, notify, page, pressure, privileges, reload, resume_report, sandbox, service, sessions, share, signing, tui, update, validate, watcher, window};
+#[cfg(feature = "plugins")]
+use drcv::plugins;
+#[cfg(feature = "tray")]
+use drcv::tray;
+use config::{Command, LogFormat};
+use drcv::tunnels::{AccessOptions, DnsOptions, TunnelConfig, TunnelSupervisor, create_tunnel_provider};
+use drcv::apps::{admin::TunnelInfo, upload::create_app as create_upload_app, admin::create_app as create_admin_app};
 
-#[tokio::main]
-async fn main() {
-    let args = Args::parse();
+fn main() {
+    let mut args = config::load_args();
+    if args.tui && !std::io::stdout().is_terminal() {
+        eprintln!("--tui needs a terminal; logging as usual");
+        args.tui = false;
+    }
     
     // Initialize logger with appropriate level
     let log_level = if args.verbose { "debug" } else { "info" };
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level)).init();
+    let mut logger = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level));
+    // A Windows service has no console to log to
+    if cfg!(windows) && args.service.is_some() {
+        if let Ok(file) = std::fs::OpenOptions::new().create(true).append(true).open("drcv.log") {
+            logger.target(env_logger::Target::Pipe(Box::new(file)));
+        }
+    } else if args.tui && args.command.is_none() {
+        logger.target(env_logger::Target::Pipe(tui::log_writer()));
+    }
+    let json_logs = args.log_format.unwrap_or(if args.container { LogFormat::Json } else { LogFormat::Text }) == LogFormat::Json;
+    if json_logs {
+        logger.format(|buf, record| {
+            use std::io::Write;
+            let line = serde_json::json!({
+                "time": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+    crash::install_logger(logger.build());
+    if args.command.is_none() {
+        crash::install_hook();
+    }
+    
+    #[cfg(windows)]
+    if args.service.is_some() {
+        return service::dispatch(args);
+    }
+    // Before the runtime starts, so its threads are confined too
+    if args.sandbox && args.command.is_none() {
+        sandbox::enter(&args.to_config());
+    }
+    #[cfg(feature = "tray")]
+    if args.tray && args.command.is_none() {
+        std::thread::spawn(move || runtime().block_on(run(args)));
+        tray::run_event_loop();
+    }
+    runtime().block_on(run(args));
+}
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Runtime::new().unwrap_or_else(|e| {
+        error!("Failed to start the async runtime: {}", e);
+        std::process::exit(1);
+    })
+}
+
+async fn run(args: config::Args) {
+    match &args.command {
+        Some(Command::Bench(bench_args)) => return bench::run(bench_args).await,
+        Some(Command::Init(init_args)) => return init::run(init_args),
+        Some(Command::Doctor) => return doctor::run(&args.to_config()).await,
+        Some(Command::Service(service_args)) => return service::run(service_args),
+        Some(Command::Backup(backup_args)) => return backup::backup(backup_args, &args.to_config()).await,
+        Some(Command::Restore(restore_args)) => return backup::restore(restore_args, &args.to_config()).await,
+        Some(Command::ExportIdentity(identity_args)) => return identity::export(identity_args, args.config.as_deref()).await,
+        Some(Command::ImportIdentity(identity_args)) => return identity::import(identity_args, &args.to_config(), args.config.as_deref()).await,
+        Some(Command::ApiKey(api_key_args)) => return apikeys::run(api_key_args, &args.to_config()).await,
+        Some(Command::ResumeReport(report_args)) => return resume_report::run(report_args, &args.to_config()).await,
+        Some(Command::Db(db_args)) => return maintenance::run(db_args, &args.to_config()).await,
+        Some(Command::SelfUpdate(update_args)) => return update::run(update_args).await,
+        None => {}
+    }
     
     let config = args.to_config();
+    reload::init(&config);
     if args.verbose {
         args.print_config_info(&config);
     }
     
-    let pool = initialize_database().await;
-    let tunnel_info = Arc::new(RwLock::new(TunnelInfo { hostname: None }));  
-    let tunnel_runner = setup_tunnel(&pool, &config, &tunnel_info).await;
-    let shutdown_tx = start_background_tasks(&pool, &config, tunnel_runner);
+    let _instance_guard = guard::acquire(&config);
+    guard::check_ports(&config);
+    crash::announce();
+    #[cfg(feature = "plugins")]
+    plugins::load(&args.plugins);
+    let pool = initialize_database(&config).await;
+    apikeys::check_container_access(&pool, &config).await;
+    anonymize::init(&pool, &config).await;
+    signing::init(&pool, &config).await;
+    page::init(&pool).await;
+    bandwidth::init(&pool).await;
+    events::spawn_recorder(pool.clone());
+    emit::spawn(&config);
+    let notifiers = start_notifiers(&config);
+    pressure::spawn_monitor(config.clone());
+    fairness::init(&config);
+    chaos::init(&config);
+    update::spawn_check(&config);
+    naming::init(&config);
+    net::init(&config);
+    coalescer::spawn(pool.clone(), config.db_flush_interval);
+    maintenance::spawn(pool.clone(), &config);
+    watcher::spawn(pool.clone(), &config);
+    hass::spawn(pool.clone(), &config);
+    validate::spawn_pending(&pool, &config).await;
+    mover::resume_pending(&pool, &config).await;
+    let tunnel_info = Arc::new(RwLock::new(TunnelInfo { hostname: None, url: None, stats: None }));
+    let tunnel = setup_tunnel(&pool, &config, &tunnel_info).await;
+    #[cfg(feature = "tray")]
+    if args.tray {
+        tray::spawn_status_updater(Arc::clone(&tunnel_info));
+    }
+    // Services and tray apps are usually started without a terminal
+    let interactive = args.service.is_none();
+    #[cfg(feature = "tray")]
+    let interactive = interactive && !args.tray;
+    // The dashboard reads the keyboard itself; a container's stdin is usually closed
+    let interactive = interactive && !args.tui && !config.container;
+    let shutdown_tx = start_background_tasks(&pool, &config, &tunnel_info, &tunnel, interactive);
+    window::spawn(config.clone(), Arc::clone(&tunnel));
+    expect::spawn(&config);
     let upload_task = create_upload_app(&pool, &config, &shutdown_tx).await;
-    let admin_task = create_admin_app(&pool, &config, &tunnel_info, &shutdown_tx).await;
+    let admin_task = create_admin_app(&pool, &config, &tunnel_info, &tunnel, &notifiers, &shutdown_tx).await;
+    privileges::drop_to(&config);
     
     info!("DRCV is ready");
+    if args.tui {
+        tui::spawn(pool.clone(), config.clone(), Arc::clone(&tunnel_info));
+    }
 
-    let tunnel_info_read = tunnel_info.read().await;
-    if let Some(hostname) = &tunnel_info_read.hostname {
-        info!("  • Share: https://{}", hostname);
+    // Not held past this point: stopping or rotating the tunnel needs the write lock
+    if let Some(url) = tunnel_info.read().await.url.clone() {
+        info!("  • Share: {}", url);
+        let short = share::short_link(&pool, &config, &url).await;
+        if let Some(short) = &short {
+            info!("  • Short: {}", short);
+        }
+        if config.copy_url {
+            share::copy(short.unwrap_or(url));
+        }
     }
     info!("  • Admin: http://127.0.0.1:{}", config.admin_port);
     info!("  • Upload dir: {}", config.upload_dir);
+    if interactive {
+        info!("  • Type h and Enter for commands, q to quit");
+    }
+    if let Some(key_id) = signing::key_id() {
+        info!("  • Signing key: {}", key_id);
+    }
     
     let _ = tokio::join!(upload_task, admin_task);
+    health::servers_stopped();
+    // The servers stop as soon as shutdown begins; the shutdown task ends the process after the
+    // grace period. Returning instead would drop the runtime, which blocks on the stdin reader.
+    std::future::pending::<()>().await;
 }
 
-async fn initialize_database() -> SqlitePool {
-    db::init_pool().await.unwrap_or_else(|e| {
+async fn initialize_database(config: &config::AppConfig) -> SqlitePool {
+    db::init_pool(config).await.unwrap_or_else(|e| {
         error!("Failed to initialize database: {}", e);
         std::process::exit(1);
     })
 }
 
-async fn setup_tunnel(pool: &SqlitePool, config: &config::AppConfig, tunnel_info: &Arc<RwLock<TunnelInfo>>) -> Option<Box<dyn tunnels::TunnelRunner>> {
-    let provider = match create_tunnel_provider(&config.tunnel_provider) {
+fn start_notifiers(config: &config::AppConfig) -> notify::Notifiers {
+    match notify::create_notifiers(config) {
+        Ok(registry) => {
+            let notifiers = notify::Notifiers::new(registry);
+            notify::spawn_dispatcher(notifiers.clone());
+            notifiers.follow_reloads();
+            notifiers
+        }
+        Err(e) => {
+            error!("⚠️  Failed to configure notifiers: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn setup_tunnel(pool: &SqlitePool, config: &config::AppConfig, tunnel_info: &Arc<RwLock<TunnelInfo>>) -> Arc<TunnelSupervisor> {
+    let provider = match create_tunnel_provider(config) {
         Ok(p) => p,
         Err(e) => {
             error!("⚠️  Failed to create tunnel provider: {}", e);
-            error!("💡 Available providers: cloudflare");
+            error!("💡 Available providers: cloudflare, ssh, bore");
             std::process::exit(1);
         }
     };
     
     let cfg = TunnelConfig { 
         hostname_root: config.tunnel_domain.clone(), 
-        local_port: config.upload_port 
+        local_port: config.upload_port,
+        dns: DnsOptions {
+            cleanup_on_shutdown: config.cf_dns_cleanup,
+        },
+        access: (!config.cf_access_emails.is_empty() || !config.cf_access_domains.is_empty()).then(|| AccessOptions {
+            emails: config.cf_access_emails.clone(),
+            email_domains: config.cf_access_domains.clone(),
+        }),
     };
     
-    match provider.ensure(pool, &cfg).await {
-        Ok(manager) => {
-            let hostname = manager.hostname().to_string();
-            {
-                let mut info = tunnel_info.write().await;
-                info.hostname = Some(hostname);
-            }
-            
-            match manager.run().await {
-                Ok(runner) => Some(runner),
-                Err(e) => { 
-                    warn!("⚠️  Failed to run tunnel: {}", e); 
-                    None 
-                }
-            }
-        }
-        Err(_) => None
+    let supervisor = Arc::new(TunnelSupervisor::new(provider, pool.clone(), cfg, Arc::clone(tunnel_info)));
+    if let Err(e) = supervisor.start().await {
+        warn!("⚠️  Failed to run tunnel: {}", e);
     }
+    supervisor
 }
 
-fn start_background_tasks(pool: &SqlitePool, config: &config::AppConfig, tunnel_runner: Option<Box<dyn tunnels::TunnelRunner>>) -> tokio::sync::broadcast::Sender<()> {
+fn start_background_tasks(pool: &SqlitePool, config: &config::AppConfig, tunnel_info: &Arc<RwLock<TunnelInfo>>, tunnel: &Arc<TunnelSupervisor>, interactive: bool) -> tokio::sync::broadcast::Sender<()> {
     use tokio::sync::broadcast;
     let (shutdown_tx, _) = broadcast::channel::<()>(1);
     let shutdown_tx_clone = shutdown_tx.clone();
     
     let config_shutdown = config.shutdown_grace_period;
+    let fast_drain = config.container;
+    // Commands typed into the terminal; none for services and tray apps
+    let console = interactive.then(|| console::Console {
+        pool: pool.clone(),
+        config: config.clone(),
+        tunnel_info: Arc::clone(tunnel_info),
+        tunnel: Arc::clone(tunnel),
+    });
+    let tunnel = Arc::clone(tunnel);
+    let pool_shutdown = pool.clone();
     tokio::spawn(async move {
-        wait_for_shutdown_signal().await;
+        wait_for_shutdown_signal(console).await;
+        health::start_draining();
+        tui::stop();
         info!("Shutting down…");
-        if let Some(runner) = tunnel_runner { let _ = runner.shutdown().await; }
+        tunnel.stop().await;
+        direct::materialize_all().await;
+        coalescer::flush(&pool_shutdown).await;
         let _ = shutdown_tx_clone.send(());
-        tokio::time::sleep(config_shutdown).await;
+        // In a container, exit as soon as the requests in flight are answered
+        let drained = async {
+            if fast_drain { health::stopped().await } else { std::future::pending().await }
+        };
+        tokio::select! { _ = tokio::time::sleep(config_shutdown) => {}, _ = drained => {} }
+        coalescer::flush(&pool_shutdown).await;
         info!("Shutting down. Bye!");
-        std::process::exit(0);
+        service::report_stopped();
+        std::process::exit(expect::exit_code());
     });
     
-    let pool_clone = pool.clone();
-    let config_clone = config.clone();
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(config_clone.cleanup_interval);
-        loop {
-            interval.tick().await;
-            db::mark_stale_uploads_disconnected(&pool_clone, config_clone.upload_stale_timeout).await;
-            db::mark_stale_clients_disconnected(&pool_clone, config_clone.client_stale_timeout).await;
-        }
-    });
+    sessions::spawn_cleanup(pool.clone(), config);
+    reload::spawn_signal_handler();
     
     shutdown_tx
 }
 
-async fn wait_for_shutdown_signal() {
-    use tokio::io::{AsyncBufReadExt, BufReader};
+/// Ctrl-C, `q` or EOF on the console when run from a terminal; a stop request from the service
+/// manager, the tray menu, `q` in the dashboard, a closed `--open-for` window or the last
+/// `--expect-files` upload either way.
+async fn wait_for_shutdown_signal(console: Option<console::Console>) {
     use tokio::signal;
 
     let ctrl_c = async {
@@ -128,19 +275,19 @@ async fn wait_for_shutdown_signal() {
     };
 
     let stdin_quit = async {
-        let mut reader = BufReader::new(tokio::io::stdin());
-        let mut line = String::new();
-        loop {
-            line.clear();
-            match reader.read_line(&mut line).await {
-                Ok(0) => break, // EOF
-                Ok(_) => {
-                    if line.trim().eq_ignore_ascii_case("q") { break; }
-                }
-                Err(_e) => break,
-            }
+        match &console {
+            Some(console) => console.run().await,
+            // A service's stdin is closed from the start
+            None => std::future::pending().await,
         }
     };
 
-    tokio::select! { _ = ctrl_c => {}, _ = stdin_quit => {} }
+    let tray_quit = async {
+        #[cfg(feature = "tray")]
+        tray::quit_requested().await;
+        #[cfg(not(feature = "tray"))]
+        std::future::pending::<()>().await;
+    };
+
+    tokio::select! { _ = ctrl_c => {}, _ = stdin_quit => {}, _ = service::stop_requested() => {}, _ = tray_quit => {}, _ = window::exit_requested() => {}, _ = expect::done() => {}, _ = tui::quit_requested() => {} }
 }
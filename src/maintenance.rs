@@ -0,0 +1,79 @@
+use std::path::Path;
+use sqlx::SqlitePool;
+use log::{error, info};
+use crate::{config::{AppConfig, DbAction, DbArgs}, db::{self, DB_FILE}, guard};
+
+fn fail(message: String) -> ! {
+    eprintln!("❌ {}", message);
+    std::process::exit(1);
+}
+
+/// Prunes history older than `--history-retention` and gives the freed pages back, once an hour,
+/// so a long-lived instance's database stays about as large as its retention window.
+pub fn spawn(pool: SqlitePool, config: &AppConfig) {
+    if config.history_retention.is_zero() {
+        return;
+    }
+    let config = config.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.maintenance_interval);
+        loop {
+            interval.tick().await;
+            // Instances sharing a database take turns like the other background jobs
+            if let Some(instance_id) = &config.instance_id {
+                if !db::acquire_lease(&pool, "maintenance", instance_id, config.instance_stale_timeout).await {
+                    continue;
+                }
+            }
+            if let Err(e) = run_once(&pool, &config).await {
+                error!("Database maintenance failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn run_once(pool: &SqlitePool, config: &AppConfig) -> Result<(), sqlx::Error> {
+    let pruned = prune(pool, config).await?;
+    if pruned.events + pruned.chunks + pruned.traces > 0 {
+        info!("🧹 Pruned {} events, {} chunk records and {} trace entries past --history-retention", pruned.events, pruned.chunks, pruned.traces);
+    }
+    db::incremental_vacuum(pool).await
+}
+
+async fn prune(pool: &SqlitePool, config: &AppConfig) -> Result<db::Pruned, sqlx::Error> {
+    if config.history_retention.is_zero() {
+        return Ok(db::Pruned::default());
+    }
+    let retention = chrono::Duration::from_std(config.history_retention).unwrap_or(chrono::Duration::MAX);
+    let cutoff = chrono::Utc::now().checked_sub_signed(retention).unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC);
+    db::prune_history(pool, &cutoff.to_rfc3339()).await
+}
+
+/// `drcv db ...`
+pub async fn run(args: &DbArgs, config: &AppConfig) {
+    match args.action {
+        DbAction::Compact => compact(config).await,
+    }
+}
+
+/// `drcv db compact`: prunes like the hourly task, then rewrites drcv.db with VACUUM. That also
+/// turns on incremental auto-vacuum for databases created before drcv used it.
+async fn compact(config: &AppConfig) {
+    if !Path::new(DB_FILE).exists() {
+        fail(format!("No {} in the current directory", DB_FILE));
+    }
+    // VACUUM needs the database to itself
+    let _guard = guard::acquire(config);
+    let before = file_size();
+    let pool = db::init_pool(config).await.unwrap_or_else(|e| fail(format!("Failed to open {}: {}", DB_FILE, e)));
+    let pruned = prune(&pool, config).await.unwrap_or_else(|e| fail(format!("Failed to prune history: {}", e)));
+    sqlx::query("VACUUM").execute(&pool).await.unwrap_or_else(|e| fail(format!("Failed to compact {}: {}", DB_FILE, e)));
+    pool.close().await;
+    let after = file_size();
+    println!("🧹 Pruned {} events, {} chunk records and {} trace entries", pruned.events, pruned.chunks, pruned.traces);
+    println!("✅ {}: {:.1} MiB → {:.1} MiB", DB_FILE, before as f64 / 1048576.0, after as f64 / 1048576.0);
+}
+
+fn file_size() -> u64 {
+    std::fs::metadata(DB_FILE).map(|m| m.len()).unwrap_or(0)
+}
@@ -0,0 +1,54 @@
+use std::path::Path;
+use log::warn;
+use crate::db::UploadData;
+
+/// With `--preserve-metadata`, gives a stored file the modification time it had on the sender's
+/// side, and on Linux and macOS extended attributes saying where it came from: the freedesktop
+/// `user.xdg.origin.url`, plus `user.drcv.sender`, `user.drcv.sender_email` and
+/// `user.drcv.client_ip`. Failures are logged; the file is stored either way.
+pub async fn apply(upload: UploadData, path: &Path) {
+    let path = path.to_path_buf();
+    let _ = tokio::task::spawn_blocking(move || {
+        if let Some(modified) = upload.last_modified.as_deref().and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok()) {
+            let set = std::fs::File::options().write(true).open(&path)
+                .and_then(|file| file.set_modified(modified.into()));
+            if let Err(e) = set {
+                warn!("⚠️ Failed to keep the modification time of {}: {}", path.display(), e);
+            }
+        }
+        let attributes = [
+            ("user.xdg.origin.url", upload.origin.as_deref()),
+            ("user.drcv.sender", upload.sender_name.as_deref()),
+            ("user.drcv.sender_email", upload.sender_email.as_deref()),
+            ("user.drcv.client_ip", Some(upload.client_ip.as_str())),
+        ];
+        for (name, value) in attributes {
+            let Some(value) = value else { continue };
+            if let Err(e) = set_xattr(&path, name, value) {
+                // Typically a filesystem without user attributes, e.g. tmpfs or FAT
+                warn!("⚠️ Failed to set {} on {}: {}", name, path.display(), e);
+                break;
+            }
+        }
+    }).await;
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn set_xattr(path: &Path, name: &str, value: &str) -> std::io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = CString::new(name)?;
+    // SAFETY: both strings are NUL-terminated and outlive the call, and value's length is given
+    #[cfg(target_os = "linux")]
+    let result = unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value.as_ptr().cast(), value.len(), 0) };
+    // SAFETY: as above
+    #[cfg(target_os = "macos")]
+    let result = unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value.as_ptr().cast(), value.len(), 0, 0) };
+    if result == 0 { Ok(()) } else { Err(std::io::Error::last_os_error()) }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn set_xattr(_path: &Path, _name: &str, _value: &str) -> std::io::Result<()> {
+    Ok(())
+}
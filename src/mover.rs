@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use axum::Json;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use log::{info, warn};
+use crate::{config::AppConfig, db, finalize, naming, sessions};
+
+/// Large copies keep a NAS or USB disk streaming
+const COPY_BUFFER: usize = 8 * 1024 * 1024;
+
+/// A completed upload on its way from `--spool-dir` to the upload directory.
+#[derive(Serialize, Clone)]
+pub struct Move {
+    pub id: i64,
+    pub filename: String,
+    pub to: String,
+    pub size: u64,
+    /// Bytes copied so far; a move within one filesystem is a rename and copies nothing
+    pub copied: u64,
+    /// Why the last attempt failed; another follows after `move_retry_interval`
+    pub error: Option<String>,
+}
+
+static MOVES: Lazy<Mutex<HashMap<i64, Move>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Moves the file of upload `id` from `from` to `final_path` in the background, retrying
+/// every `move_retry_interval` while the destination is unavailable, then marks it complete.
+pub fn spawn(pool: SqlitePool, config: AppConfig, id: i64, filename: String, from: PathBuf, final_path: PathBuf) {
+    {
+        let mut moves = MOVES.lock().unwrap();
+        if moves.contains_key(&id) {
+            return;
+        }
+        let size = std::fs::metadata(&from).map(|m| m.len()).unwrap_or(0);
+        moves.insert(id, Move { id, filename: filename.clone(), to: final_path.display().to_string(), size, copied: 0, error: None });
+    }
+    tokio::spawn(async move {
+        let final_path = loop {
+            match move_file(id, &config, &from, &final_path).await {
+                Ok(final_path) => break final_path,
+                Err(e) => {
+                    warn!("⚠️ Failed to move {} to {}, retrying in {:?}: {}", filename, final_path.display(), config.move_retry_interval, e);
+                    if let Some(progress) = MOVES.lock().unwrap().get_mut(&id) {
+                        progress.error = Some(e.to_string());
+                        progress.copied = 0;
+                    }
+                    tokio::time::sleep(config.move_retry_interval).await;
+                }
+            }
+        };
+        finalize::stored(&pool, &config, id, &filename, &final_path).await;
+        MOVES.lock().unwrap().remove(&id);
+    });
+}
+
+/// Picks up moves that were under way when drcv stopped; the file is still in `--spool-dir`.
+pub async fn resume_pending(pool: &SqlitePool, config: &AppConfig) {
+    for upload in db::get_moving_uploads(pool).await {
+        let Some(stored_name) = &upload.stored_name else { continue };
+        let from = match sessions::part_path(config.scratch_dir(), upload.id, &upload.filename).await {
+            Ok(from) => from,
+            Err(e) => {
+                warn!("⚠️ Can't resume moving {}: {}", upload.filename, e);
+                continue;
+            }
+        };
+        let final_path = PathBuf::from(&config.upload_dir).join(stored_name);
+        // A crash between the rename and recording it leaves nothing to move
+        if !from.exists() && final_path.exists() {
+            finalize::stored(pool, config, upload.id, &upload.filename, &final_path).await;
+            continue;
+        }
+        info!("🚚 Resuming the move of {} to {}", upload.filename, final_path.display());
+        spawn(pool.clone(), config.clone(), upload.id, upload.filename, from, final_path);
+    }
+}
+
+/// Renames `from` to `to`, or across filesystems copies it next to `to` under a hidden name and
+/// renames it into place, so the upload directory never shows a partial file. Returns where the
+/// file ended up, numbered again if `--rename-template` names were taken meanwhile.
+async fn move_file(id: i64, config: &AppConfig, from: &Path, to: &Path) -> std::io::Result<PathBuf> {
+    let to = match to.parent() {
+        Some(dir) if config.rename_template.is_some() && tokio::fs::try_exists(to).await.unwrap_or(false) => {
+            let name = to.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            naming::unique_path(dir, &name).await
+        }
+        _ => to.to_path_buf(),
+    };
+    let disk_path = naming::long_path(&to);
+    if let Some(dir) = disk_path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    match tokio::fs::rename(from, &disk_path).await {
+        Ok(()) => return Ok(to),
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {}
+        Err(e) => return Err(e),
+    }
+
+    let name = disk_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let partial = disk_path.with_file_name(format!(".{}.drcv-moving", name));
+    let copied = copy(id, from, &partial).await;
+    if let Err(e) = copied {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&partial, &disk_path).await?;
+    tokio::fs::remove_file(from).await?;
+    Ok(to)
+}
+
+async fn copy(id: i64, from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut source = tokio::fs::File::open(from).await?;
+    let mut target = tokio::fs::File::create(to).await?;
+    let mut buf = vec![0u8; COPY_BUFFER];
+    let mut copied = 0u64;
+    loop {
+        let n = source.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        target.write_all(&buf[..n]).await?;
+        copied += n as u64;
+        if let Some(progress) = MOVES.lock().unwrap().get_mut(&id) {
+            progress.copied = copied;
+        }
+    }
+    // The spooled copy is deleted next, so this one must be on disk first
+    target.sync_all().await
+}
+
+/// `GET /uploads/moving`: completed uploads still being moved from `--spool-dir`.
+pub async fn admin_moving() -> Json<Vec<Move>> {
+    let mut moves: Vec<Move> = MOVES.lock().unwrap().values().cloned().collect();
+    moves.sort_by_key(|m| m.id);
+    Json(moves)
+}
@@ -0,0 +1,137 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::{config::AppConfig, db::UploadData};
+
+/// Characters Windows doesn't allow in file names, besides control characters
+const WINDOWS_RESERVED_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+/// Device names Windows won't create a file under, with any extension (`con.txt` too)
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+/// Windows allows 255 UTF-16 units per name; this leaves room for `-N` numbering and `.part`
+const MAX_NAME_UNITS: usize = 240;
+
+/// Whether stored names follow Windows' rules
+static PORTABLE: AtomicBool = AtomicBool::new(cfg!(windows));
+
+/// Applies `--portable-names`; on Windows the rules always apply.
+pub fn init(config: &AppConfig) {
+    PORTABLE.store(config.portable_names, Ordering::Relaxed);
+}
+
+/// The name a sender's file is stored under. With Windows' rules, characters it rejects become
+/// `_`, trailing dots and spaces are dropped, device names like `CON` get a `_` prefix and long
+/// names are shortened, keeping the extension. Elsewhere the name is used as sent.
+pub fn on_disk(name: &str) -> Cow<'_, str> {
+    if !PORTABLE.load(Ordering::Relaxed) {
+        return Cow::Borrowed(name);
+    }
+    let mut safe: String = name.chars()
+        .map(|c| if WINDOWS_RESERVED_CHARS.contains(&c) || c.is_control() { '_' } else { c })
+        .collect();
+    safe.truncate(safe.trim_end_matches(['.', ' ']).len());
+    if safe.is_empty() {
+        return Cow::Owned("_".to_string());
+    }
+    let device = safe.split('.').next().unwrap_or("").trim_end();
+    if WINDOWS_RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(device)) {
+        safe.insert(0, '_');
+    }
+    if safe.encode_utf16().count() > MAX_NAME_UNITS {
+        let ext = Path::new(&safe).extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .filter(|e| e.encode_utf16().count() < 32)
+            .unwrap_or_default();
+        let mut units = ext.encode_utf16().count();
+        let stem: String = safe[..safe.len() - ext.len()].chars()
+            .take_while(|c| {
+                units += c.len_utf16();
+                units <= MAX_NAME_UNITS
+            })
+            .collect();
+        safe = format!("{}{}", stem.trim_end_matches(['.', ' ']), ext);
+    }
+    if safe == name { Cow::Borrowed(name) } else { Cow::Owned(safe) }
+}
+
+/// `path` in the `\\?\` form Windows needs for paths beyond 260 characters. Unchanged when
+/// short enough, and everywhere else.
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> PathBuf {
+    // Some calls, like creating a directory, already fail past 248
+    if path.as_os_str().len() < 248 || path.as_os_str().to_string_lossy().starts_with(r"\\?\") {
+        return path.to_path_buf();
+    }
+    // Resolves `..` and turns `/` into `\`, neither of which the `\\?\` form allows
+    let Ok(absolute) = std::path::absolute(path) else { return path.to_path_buf() };
+    let absolute = absolute.to_string_lossy().into_owned();
+    match absolute.strip_prefix(r"\\") {
+        Some(share) => PathBuf::from(format!(r"\\?\UNC\{}", share)),
+        None => PathBuf::from(format!(r"\\?\{}", absolute)),
+    }
+}
+
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// Keeps a placeholder value from introducing path separators or control characters.
+fn clean(value: &str) -> String {
+    value.chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c })
+        .collect()
+}
+
+/// Renders `--rename-template` for a completed upload, e.g. `{date}-{sender}-{orig}` gives
+/// `2024-05-01-alice-report.pdf`. Placeholders: `{date}`, `{time}`, `{sender}` (the sender's
+/// name, or their IP without one), `{ip}`, `{id}`, `{orig}`, `{stem}` and `{ext}`.
+pub fn render(template: &str, upload: &UploadData) -> String {
+    let now = chrono::Local::now();
+    let orig = Path::new(&upload.filename);
+    let stem = orig.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = orig.extension().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let sender = upload.sender_name.as_deref().unwrap_or(&upload.client_ip);
+
+    let rendered = template
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{time}", &now.format("%H%M%S").to_string())
+        .replace("{sender}", &clean(sender))
+        .replace("{ip}", &clean(&upload.client_ip))
+        .replace("{id}", &upload.id.to_string())
+        .replace("{orig}", &clean(&upload.filename))
+        .replace("{stem}", &clean(&stem))
+        .replace("{ext}", &clean(&ext));
+    clean_name(&rendered, &upload.filename)
+}
+
+/// `name` made safe to store a file under, or `fallback` when nothing usable is left of it.
+pub fn clean_name(name: &str, fallback: &str) -> String {
+    let name = clean(name);
+    let name = match name.trim() {
+        "" | "." | ".." => clean(fallback),
+        _ => name,
+    };
+    on_disk(&name).into_owned()
+}
+
+/// First of `name`, `stem-1.ext`, `stem-2.ext`, ... not taken in `dir`.
+pub async fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    if !tokio::fs::try_exists(&candidate).await.unwrap_or(false) {
+        return candidate;
+    }
+    let path = Path::new(name);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| name.to_string());
+    let ext = path.extension().map(|s| format!(".{}", s.to_string_lossy())).unwrap_or_default();
+    for n in 1.. {
+        let candidate = dir.join(format!("{}-{}{}", stem, n, ext));
+        if !tokio::fs::try_exists(&candidate).await.unwrap_or(false) {
+            return candidate;
+        }
+    }
+    unreachable!()
+}
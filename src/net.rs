@@ -0,0 +1,168 @@
+use axum::{extract::{ConnectInfo, Request, Extension}, http::{HeaderMap, StatusCode}, middleware::Next, response::{IntoResponse, Response}};
+use ipnet::IpNet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::RwLock;
+use tokio::net::TcpListener;
+use log::warn;
+use crate::{anonymize, config::AppConfig, error::ApiError};
+
+/// Tunnel providers whose local client hands every public request to the upload port from
+/// loopback, without a header naming the sender.
+const LOOPBACK_TUNNELS: &[&str] = &["ssh", "bore"];
+
+/// Which peers may name the client in a header instead of being taken as the client.
+struct ProxyTrust {
+    /// Loopback peers are the local cloudflared, which sets `CF-Connecting-IP` itself
+    cloudflared: bool,
+    /// `--trusted-proxy`
+    proxies: Vec<IpNet>,
+}
+
+static TRUST: RwLock<ProxyTrust> = RwLock::new(ProxyTrust { cloudflared: false, proxies: Vec::new() });
+
+/// Applies the tunnel provider and `--trusted-proxy` to how clients are identified.
+pub fn init(config: &AppConfig) {
+    *TRUST.write().unwrap() = ProxyTrust {
+        cloudflared: config.public_url.is_none() && config.tunnel_provider.eq_ignore_ascii_case("cloudflare"),
+        proxies: config.trusted_proxies.clone(),
+    };
+}
+
+/// The configured tunnel provider, if it is one of [`LOOPBACK_TUNNELS`].
+fn loopback_tunnel(config: &AppConfig) -> Option<&'static str> {
+    if config.public_url.is_some() {
+        return None;
+    }
+    LOOPBACK_TUNNELS.iter().copied().find(|provider| config.tunnel_provider.eq_ignore_ascii_case(provider))
+}
+
+/// Refuses settings that can't work behind a loopback tunnel: every sender arrives from
+/// localhost there, so address ACLs can't tell them apart, and a trusted proxy covering
+/// localhost would take any sender's word for its address.
+pub fn check_proxy_trust(config: &AppConfig) -> Result<(), String> {
+    let Some(provider) = loopback_tunnel(config) else { return Ok(()) };
+    if !config.allow_cidrs.is_empty() || !config.deny_cidrs.is_empty() {
+        return Err(format!("--allow-cidr and --deny-cidr can't be used with the {} tunnel provider: it forwards every sender from localhost, so their addresses are unknown", provider));
+    }
+    let localhost = [IpAddr::V4(Ipv4Addr::LOCALHOST), IpAddr::V6(Ipv6Addr::LOCALHOST)];
+    if let Some(net) = config.trusted_proxies.iter().find(|net| localhost.iter().any(|ip| net.contains(ip))) {
+        return Err(format!("--trusted-proxy {} can't be used with the {} tunnel provider: it covers localhost, where the tunnel forwards every sender from", net, provider));
+    }
+    Ok(())
+}
+
+/// Folds IPv4-mapped IPv6 addresses (`::ffff:1.2.3.4`, seen on dual-stack sockets) back to IPv4
+/// so one client is always tracked under the same address.
+pub fn normalize_ip(ip: IpAddr) -> IpAddr {
+    ip.to_canonical()
+}
+
+/// Parses an address from a proxy header: `1.2.3.4`, `1.2.3.4:5678`, `2001:db8::1` or `[2001:db8::1]:5678`.
+pub fn parse_header_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    if let Ok(ip) = value.parse::<IpAddr>() {
+        return Some(normalize_ip(ip));
+    }
+    value.parse::<SocketAddr>().ok().map(|addr| normalize_ip(addr.ip()))
+}
+
+/// The client's identity as stored and shown: its address, or a salted hash of it with
+/// `--anonymize-ips`.
+pub fn extract_client_ip(headers: &HeaderMap, addr: &SocketAddr) -> String {
+    anonymize::identity(extract_raw_ip(headers, addr))
+}
+
+/// The client's actual address, for decisions made within the request such as ACLs.
+pub fn extract_raw_ip(headers: &HeaderMap, addr: &SocketAddr) -> String {
+    let peer_ip = normalize_ip(addr.ip());
+    let trust = TRUST.read().unwrap();
+    let header_ip = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).and_then(parse_header_ip);
+
+    // cloudflared replaces whatever CF-Connecting-IP the sender sent
+    if trust.cloudflared && peer_ip.is_loopback() {
+        if let Some(ip) = header_ip("cf-connecting-ip") {
+            return ip.to_string();
+        }
+    }
+
+    let trusted = |ip: &IpAddr| trust.proxies.iter().any(|net| net.contains(ip));
+    if trusted(&peer_ip) {
+        // Each proxy appends the address it heard from, so the sender is the right-most hop
+        // that isn't a trusted proxy; anything left of it is the sender's own say-so
+        let mut client = None;
+        let hops = headers.get_all("x-forwarded-for").iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect::<Vec<_>>();
+        for hop in hops.into_iter().rev() {
+            let Some(ip) = parse_header_ip(hop) else { break };
+            client = Some(ip);
+            if !trusted(&ip) {
+                break;
+            }
+        }
+        if let Some(ip) = client.or_else(|| header_ip("x-real-ip")) {
+            return ip.to_string();
+        }
+    }
+
+    peer_ip.to_string()
+}
+
+pub fn parse_cidr(value: &str) -> Result<IpNet, String> {
+    let value = value.trim();
+    // A bare address is a single-host network
+    value.parse::<IpNet>()
+        .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("Invalid CIDR: {}", value))
+}
+
+pub fn is_allowed(ip: IpAddr, allow: &[IpNet], deny: &[IpNet]) -> bool {
+    let ip = normalize_ip(ip);
+    if deny.iter().any(|net| net.contains(&ip)) {
+        return false;
+    }
+    allow.is_empty() || allow.iter().any(|net| net.contains(&ip))
+}
+
+/// Rejects requests from clients outside `--allow-cidr` or inside `--deny-cidr`.
+pub async fn enforce_acl(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(config): Extension<AppConfig>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if config.allow_cidrs.is_empty() && config.deny_cidrs.is_empty() {
+        return next.run(request).await;
+    }
+    let client_ip = extract_raw_ip(request.headers(), &addr);
+    match client_ip.parse::<IpAddr>() {
+        Ok(ip) if is_allowed(ip, &config.allow_cidrs, &config.deny_cidrs) => next.run(request).await,
+        _ => ApiError::new(StatusCode::FORBIDDEN, "access_denied", "Access denied").into_response(),
+    }
+}
+
+/// Listens on both IPv4 and IPv6 (`[::]` with v6-only off), falling back to IPv4 when IPv6 is unavailable.
+pub fn bind_dual_stack(port: u16) -> std::io::Result<TcpListener> {
+    use socket2::{Domain, Socket, Type};
+
+    let v6 = (|| {
+        let socket = Socket::new(Domain::IPV6, Type::STREAM, None)?;
+        socket.set_only_v6(false)?;
+        socket.set_reuse_address(true)?;
+        socket.bind(&SocketAddr::from(([0u16; 8], port)).into())?;
+        socket.listen(1024)?;
+        socket.set_nonblocking(true)?;
+        TcpListener::from_std(socket.into())
+    })();
+
+    match v6 {
+        Ok(listener) => Ok(listener),
+        Err(e) => {
+            warn!("⚠️  IPv6 unavailable ({}), listening on IPv4 only", e);
+            let socket = std::net::TcpListener::bind(("0.0.0.0", port))?;
+            socket.set_nonblocking(true)?;
+            TcpListener::from_std(socket)
+        }
+    }
+}
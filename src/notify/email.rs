@@ -0,0 +1,100 @@
+use super::{template::{self, Template}, Notifier, NotifyError};
+use crate::events::Event;
+use async_trait::async_trait;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor, message::Mailbox, transport::smtp::authentication::Credentials};
+
+pub struct EmailNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    subject: Option<Template>,
+    body: Option<Template>,
+}
+
+impl EmailNotifier {
+    /// Parses `mailto[s]://user:pass@host[:port]?to=a@x,b@y&from=c@z&smtp=server`.
+    /// Without `to`, mail goes to `user@host`; `mailtos` uses implicit TLS instead of STARTTLS.
+    /// `subject=` is an inline template for the subject and `template=` a file for the body.
+    pub fn from_url(url: &str) -> Result<Self, NotifyError> {
+        let url = url::Url::parse(url)
+            .map_err(|e| NotifyError::ConfigError(format!("invalid mailto URL: {}", e)))?;
+        let host = url.host_str()
+            .ok_or_else(|| NotifyError::ConfigError("mailto:// needs a host".to_string()))?;
+        let query: std::collections::HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+        let user = url.username();
+        let default_address = if user.contains('@') { user.to_string() } else { format!("{}@{}", user, host) };
+        let parse_mailbox = |s: &str| s.trim().parse::<Mailbox>()
+            .map_err(|e| NotifyError::ConfigError(format!("invalid address {}: {}", s, e)));
+
+        let from = parse_mailbox(query.get("from").map(String::as_str).unwrap_or(&default_address))?;
+        let to = query.get("to").map(String::as_str).unwrap_or(&default_address)
+            .split(',')
+            .map(parse_mailbox)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let smtp_host = query.get("smtp").map(String::as_str).unwrap_or(host);
+        let builder = if url.scheme() == "mailtos" {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(smtp_host)
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(smtp_host)
+        }.map_err(|e| NotifyError::ConfigError(format!("invalid SMTP host: {}", e)))?;
+
+        let mut builder = builder;
+        if let Some(port) = url.port() {
+            builder = builder.port(port);
+        }
+        if !user.is_empty() {
+            let password = url.password().unwrap_or("");
+            builder = builder.credentials(Credentials::new(user.to_string(), password.to_string()));
+        }
+
+        let subject = query.get("subject").map(|s| Template::parse("subject".to_string(), s.clone())).transpose()?;
+        Ok(Self { transport: builder.build(), from, to, subject, body: template::from_options(&query)? })
+    }
+
+    /// Sends a message to an arbitrary recipient instead of the configured ones, e.g. a sender.
+    pub async fn send_to(&self, to: &str, subject: &str, body: &str) -> Result<(), NotifyError> {
+        let to = to.trim().parse::<Mailbox>()
+            .map_err(|e| NotifyError::DeliveryError(format!("invalid address {}: {}", to, e)))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .body(body.to_string())
+            .map_err(|e| NotifyError::DeliveryError(e.to_string()))?;
+        self.transport.send(message).await
+            .map(|_| ())
+            .map_err(|e| NotifyError::DeliveryError(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    async fn notify(&self, event: &Event) -> Result<(), NotifyError> {
+        let subject = match &self.subject {
+            Some(subject) => subject.render(event)?,
+            None => format!("[drcv] {}", event.summary()),
+        };
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(subject.trim());
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let body = match &self.body {
+            Some(body) => body.render(event)?,
+            None => format!("{}\n\n{}\n", event.summary(), serde_json::to_string_pretty(event).unwrap_or_default()),
+        };
+        let message = message.body(body)
+            .map_err(|e| NotifyError::DeliveryError(e.to_string()))?;
+
+        self.transport.send(message).await
+            .map(|_| ())
+            .map_err(|e| NotifyError::DeliveryError(e.to_string()))
+    }
+}
@@ -0,0 +1,283 @@
+use async_trait::async_trait;
+use serde::Serialize;
+use std::{fmt, sync::{Arc, Mutex}, time::Duration};
+use log::{error, info, warn, debug};
+use tokio::sync::{broadcast::error::RecvError, watch};
+use crate::{config::AppConfig, events::{self, Event}, reload, utils};
+
+#[derive(Debug)]
+pub enum NotifyError {
+    ConfigError(String),
+    DeliveryError(String),
+}
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotifyError::ConfigError(msg) => write!(f, "Configuration error: {}", msg),
+            NotifyError::DeliveryError(msg) => write!(f, "Delivery error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for NotifyError {}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &str;
+    async fn notify(&self, event: &Event) -> Result<(), NotifyError>;
+}
+
+pub mod mqtt;
+pub mod slack;
+pub mod telegram;
+pub mod email;
+pub mod webhook;
+pub mod template;
+
+pub use mqtt::MqttNotifier;
+pub use slack::SlackNotifier;
+pub use telegram::TelegramNotifier;
+pub use email::EmailNotifier;
+pub use webhook::WebhookNotifier;
+
+/// Builds a notifier from an Apprise-style URL such as `slack://`, `tgram://` or `mailto://`.
+pub fn create_notifier(url: &str, config: &AppConfig) -> Result<Box<dyn Notifier>, NotifyError> {
+    let (scheme, rest) = url.split_once("://")
+        .ok_or_else(|| NotifyError::ConfigError(format!("Notification URL needs a scheme: {}", url)))?;
+    match scheme.to_lowercase().as_str() {
+        "slack" => Ok(Box::new(SlackNotifier::from_url(rest)?)),
+        "tgram" => Ok(Box::new(TelegramNotifier::from_url(rest)?)),
+        "mailto" | "mailtos" => Ok(Box::new(EmailNotifier::from_url(url)?)),
+        "json" => Ok(Box::new(WebhookNotifier::from_url(false, rest)?)),
+        "jsons" => Ok(Box::new(WebhookNotifier::from_url(true, rest)?)),
+        "mqtt" => Ok(Box::new(MqttNotifier::connect(url, &config.mqtt_topic_prefix)?)),
+        _ => Err(NotifyError::ConfigError(format!("Unknown notification scheme: {}", scheme)))
+    }
+}
+
+/// How long one notifier may take before its delivery counts as failed.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct NotifierEntry {
+    notifier: Arc<dyn Notifier>,
+    /// As configured, to keep the notifier (and its connection) across reloads
+    url: String,
+    target: String,
+    stats: Mutex<NotifierStats>,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct NotifierStats {
+    pub delivered: u64,
+    pub errors: u64,
+    pub last_delivery_at: Option<String>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct NotifierStatus {
+    /// Position among the configured targets, which tells apart several of the same kind
+    pub index: usize,
+    pub name: String,
+    pub target: String,
+    #[serde(flatten)]
+    pub stats: NotifierStats,
+}
+
+#[derive(Serialize)]
+pub struct DeliveryResult {
+    pub index: usize,
+    pub name: String,
+    pub target: String,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+/// Configured notifiers with per-channel delivery bookkeeping.
+#[derive(Default)]
+pub struct NotifierRegistry {
+    entries: Vec<NotifierEntry>,
+    /// SMTP settings of the first `mailto://` target, reused to mail senders directly
+    mailer: Option<EmailNotifier>,
+}
+
+impl NotifierRegistry {
+    /// Adds the notifier for `url`, taken over with its stats from `previous` if it has one.
+    fn add(&mut self, url: &str, previous: Option<&NotifierRegistry>, create: impl FnOnce() -> Result<Box<dyn Notifier>, NotifyError>) -> Result<(), NotifyError> {
+        let kept = previous.and_then(|p| p.entries.iter().find(|e| e.url == url));
+        let (notifier, stats) = match kept {
+            Some(entry) => (Arc::clone(&entry.notifier), entry.stats.lock().unwrap().clone()),
+            None => (Arc::from(create()?), NotifierStats::default()),
+        };
+        self.entries.push(NotifierEntry { notifier, url: url.to_string(), target: redact_url(url), stats: Mutex::new(stats) });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn mailer(&self) -> Option<&EmailNotifier> {
+        self.mailer.as_ref()
+    }
+
+    pub fn statuses(&self) -> Vec<NotifierStatus> {
+        self.entries.iter().enumerate().map(|(index, e)| NotifierStatus {
+            index,
+            name: e.notifier.name().to_string(),
+            target: e.target.clone(),
+            stats: e.stats.lock().unwrap().clone(),
+        }).collect()
+    }
+
+    /// Delivers to every notifier, or only the one at index `only`, at once and records the
+    /// outcomes, so a slow target holds up neither the others nor the next event for long.
+    pub async fn deliver(&self, event: &Event, only: Option<usize>) -> Vec<DeliveryResult> {
+        let mut deliveries = tokio::task::JoinSet::new();
+        for (i, entry) in self.entries.iter().enumerate().filter(|&(i, _)| only.is_none_or(|n| i == n)) {
+            let (notifier, event) = (Arc::clone(&entry.notifier), event.clone());
+            deliveries.spawn(async move {
+                let result = tokio::time::timeout(DELIVERY_TIMEOUT, notifier.notify(&event)).await
+                    .unwrap_or_else(|_| Err(NotifyError::DeliveryError(format!("No answer within {}s", DELIVERY_TIMEOUT.as_secs()))));
+                (i, result)
+            });
+        }
+        let mut outcomes = Vec::new();
+        while let Some(joined) = deliveries.join_next().await {
+            match joined {
+                Ok(outcome) => outcomes.push(outcome),
+                Err(e) => error!("Notifier task failed: {}", e),
+            }
+        }
+        outcomes.sort_by_key(|&(i, _)| i);
+
+        let mut results = Vec::new();
+        for (i, result) in outcomes {
+            let entry = &self.entries[i];
+            let now = utils::now();
+            let mut stats = entry.stats.lock().unwrap();
+            match &result {
+                Ok(()) => {
+                    debug!("Delivered {} via {}", event.name(), entry.notifier.name());
+                    stats.delivered += 1;
+                    stats.last_delivery_at = Some(now);
+                }
+                Err(e) => {
+                    warn!("⚠️ Notifier {} failed: {}", entry.notifier.name(), e);
+                    stats.errors += 1;
+                    stats.last_error = Some(e.to_string());
+                    stats.last_error_at = Some(now);
+                }
+            }
+            results.push(DeliveryResult {
+                index: i,
+                name: entry.notifier.name().to_string(),
+                target: entry.target.clone(),
+                ok: result.is_ok(),
+                error: result.err().map(|e| e.to_string()),
+            });
+        }
+        results
+    }
+}
+
+/// Hides credentials so targets can be listed in the admin API.
+fn redact_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else { return "***".to_string() };
+    match scheme {
+        "slack" | "tgram" => {
+            let tail: String = rest.chars().rev().take(4).collect::<Vec<_>>().into_iter().rev().collect();
+            format!("{}://…{}", scheme, tail)
+        }
+        _ => match url::Url::parse(url) {
+            Ok(mut parsed) => {
+                if parsed.password().is_some() {
+                    let _ = parsed.set_password(Some("***"));
+                }
+                parsed.to_string()
+            }
+            Err(_) => format!("{}://***", scheme),
+        },
+    }
+}
+
+pub fn create_notifiers(config: &AppConfig) -> Result<NotifierRegistry, NotifyError> {
+    build(config, None)
+}
+
+/// Notifiers for `config`, reusing those of `previous` whose URL is unchanged, so an MQTT
+/// connection isn't opened twice.
+fn build(config: &AppConfig, previous: Option<&NotifierRegistry>) -> Result<NotifierRegistry, NotifyError> {
+    let mut registry = NotifierRegistry::default();
+    if let Some(url) = &config.mqtt_url {
+        registry.add(url, previous, || Ok(Box::new(MqttNotifier::connect(url, &config.mqtt_topic_prefix)?)))?;
+    }
+    for url in &config.notify_urls {
+        registry.add(url, previous, || create_notifier(url, config))?;
+        if registry.mailer.is_none() && (url.starts_with("mailto://") || url.starts_with("mailtos://")) {
+            registry.mailer = Some(EmailNotifier::from_url(url)?);
+        }
+    }
+    Ok(registry)
+}
+
+/// The notifiers in use, replaced when a reload changes `--notify-url`.
+#[derive(Clone)]
+pub struct Notifiers(Arc<watch::Sender<Arc<NotifierRegistry>>>);
+
+impl Notifiers {
+    pub fn new(registry: NotifierRegistry) -> Self {
+        Notifiers(Arc::new(watch::Sender::new(Arc::new(registry))))
+    }
+
+    pub fn current(&self) -> Arc<NotifierRegistry> {
+        Arc::clone(&self.0.borrow())
+    }
+
+    /// Rebuilds the notifiers whenever a reload changes their URLs; on an error the previous
+    /// ones stay.
+    pub fn follow_reloads(&self) {
+        let Some(mut configs) = reload::subscribe() else { return };
+        let notifiers = self.clone();
+        tokio::spawn(async move {
+            let mut urls = configs.borrow().notify_urls.clone();
+            while configs.changed().await.is_ok() {
+                let config = configs.borrow_and_update().clone();
+                if config.notify_urls == urls {
+                    continue;
+                }
+                urls = config.notify_urls.clone();
+                match build(&config, Some(&notifiers.current())) {
+                    Ok(registry) => {
+                        info!("🔔 Notifiers reloaded: {} configured", registry.entries.len());
+                        notifiers.0.send_replace(Arc::new(registry));
+                    }
+                    Err(e) => error!("⚠️ Keeping the previous notifiers: {}", e),
+                }
+            }
+        });
+    }
+}
+
+/// Forwards every bus event to the current notifiers until the bus closes.
+pub fn spawn_dispatcher(notifiers: Notifiers) {
+    let mut rx = events::subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("⚠️ Notifier dispatcher skipped {} events", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let registry = notifiers.current();
+            if !registry.is_empty() {
+                registry.deliver(&event, None).await;
+            }
+        }
+    });
+}
@@ -0,0 +1,63 @@
+use super::{Notifier, NotifyError};
+use crate::events::Event;
+use async_trait::async_trait;
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+use std::time::Duration;
+use log::warn;
+
+pub struct MqttNotifier {
+    client: AsyncClient,
+    topic_prefix: String,
+}
+
+/// Connects to `mqtt://[user:pass@]host[:port]` and drives the event loop in the background.
+/// `role` tells this process's connections apart, as brokers drop a duplicate client id.
+pub fn connect_client(url: &str, role: &str) -> Result<AsyncClient, NotifyError> {
+    let url = url::Url::parse(url)
+        .map_err(|e| NotifyError::ConfigError(format!("invalid MQTT URL: {}", e)))?;
+    if url.scheme() != "mqtt" {
+        return Err(NotifyError::ConfigError(format!("unsupported MQTT scheme: {}", url.scheme())));
+    }
+    let host = url.host_str()
+        .ok_or_else(|| NotifyError::ConfigError("MQTT URL has no host".to_string()))?;
+
+    let client_id = format!("drcv-{}{}", std::process::id(), role);
+    let mut options = MqttOptions::new(client_id, host, url.port().unwrap_or(1883));
+    options.set_keep_alive(Duration::from_secs(30));
+    if !url.username().is_empty() {
+        options.set_credentials(url.username(), url.password().unwrap_or(""));
+    }
+
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = eventloop.poll().await {
+                warn!("⚠️ MQTT connection error: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    });
+    Ok(client)
+}
+
+impl MqttNotifier {
+    pub fn connect(url: &str, topic_prefix: &str) -> Result<Self, NotifyError> {
+        let client = connect_client(url, "")?;
+        Ok(Self { client, topic_prefix: topic_prefix.trim_end_matches('/').to_string() })
+    }
+}
+
+#[async_trait]
+impl Notifier for MqttNotifier {
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    async fn notify(&self, event: &Event) -> Result<(), NotifyError> {
+        let topic = format!("{}/{}", self.topic_prefix, event.name());
+        let payload = serde_json::to_vec(event)
+            .map_err(|e| NotifyError::DeliveryError(e.to_string()))?;
+        self.client.publish(topic, QoS::AtLeastOnce, false, payload).await
+            .map_err(|e| NotifyError::DeliveryError(e.to_string()))
+    }
+}
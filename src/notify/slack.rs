@@ -0,0 +1,49 @@
+use super::{template::{self, Template}, Notifier, NotifyError};
+use crate::events::Event;
+use async_trait::async_trait;
+
+pub struct SlackNotifier {
+    client: reqwest::Client,
+    webhook_url: String,
+    channel: Option<String>,
+    template: Option<Template>,
+}
+
+impl SlackNotifier {
+    /// Parses `slack://TokenA/TokenB/TokenC[/#channel][?template=message.j2]` (the incoming webhook path).
+    pub fn from_url(rest: &str) -> Result<Self, NotifyError> {
+        let (rest, options) = template::take_options(rest, &["template"]);
+        let parts: Vec<&str> = rest.split('/').filter(|p| !p.is_empty()).collect();
+        if parts.len() < 3 {
+            return Err(NotifyError::ConfigError("slack:// needs TokenA/TokenB/TokenC".to_string()));
+        }
+        Ok(Self {
+            client: reqwest::Client::new(),
+            webhook_url: format!("https://hooks.slack.com/services/{}/{}/{}", parts[0], parts[1], parts[2]),
+            channel: parts.get(3).map(|c| c.to_string()),
+            template: template::from_options(&options)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    fn name(&self) -> &str {
+        "slack"
+    }
+
+    async fn notify(&self, event: &Event) -> Result<(), NotifyError> {
+        let text = match &self.template {
+            Some(template) => template.render(event)?,
+            None => event.summary(),
+        };
+        let mut body = serde_json::json!({ "text": text });
+        if let Some(channel) = &self.channel {
+            body["channel"] = serde_json::Value::String(channel.clone());
+        }
+        self.client.post(&self.webhook_url).json(&body).send().await
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| NotifyError::DeliveryError(e.to_string()))
+    }
+}
@@ -0,0 +1,49 @@
+use super::{template::{self, Template}, Notifier, NotifyError};
+use crate::events::Event;
+use async_trait::async_trait;
+
+pub struct TelegramNotifier {
+    client: reqwest::Client,
+    bot_token: String,
+    chat_ids: Vec<String>,
+    template: Option<Template>,
+}
+
+impl TelegramNotifier {
+    /// Parses `tgram://bottoken/ChatID1[/ChatID2...][?template=message.j2]`.
+    pub fn from_url(rest: &str) -> Result<Self, NotifyError> {
+        let (rest, options) = template::take_options(rest, &["template"]);
+        let mut parts = rest.split('/').filter(|p| !p.is_empty());
+        let bot_token = parts.next()
+            .ok_or_else(|| NotifyError::ConfigError("tgram:// needs a bot token".to_string()))?
+            .to_string();
+        let chat_ids: Vec<String> = parts.map(|p| p.to_string()).collect();
+        if chat_ids.is_empty() {
+            return Err(NotifyError::ConfigError("tgram:// needs at least one chat id".to_string()));
+        }
+        Ok(Self { client: reqwest::Client::new(), bot_token, chat_ids, template: template::from_options(&options)? })
+    }
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &str {
+        "telegram"
+    }
+
+    async fn notify(&self, event: &Event) -> Result<(), NotifyError> {
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token);
+        let text = match &self.template {
+            Some(template) => template.render(event)?,
+            None => event.summary(),
+        };
+        for chat_id in &self.chat_ids {
+            self.client.post(&url)
+                .json(&serde_json::json!({ "chat_id": chat_id, "text": text }))
+                .send().await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| NotifyError::DeliveryError(format!("chat {}: {}", chat_id, e)))?;
+        }
+        Ok(())
+    }
+}
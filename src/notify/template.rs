@@ -0,0 +1,60 @@
+use super::NotifyError;
+use crate::events::Event;
+use minijinja::{context, Environment, Value};
+use std::{collections::HashMap, path::Path};
+
+/// A notification payload or message rendered from a user's minijinja template, with the
+/// event's fields, `event` (its name) and `summary` (the default one-line message) in scope.
+pub struct Template {
+    env: Environment<'static>,
+    name: String,
+}
+
+impl Template {
+    /// Reads a `template=` file. Its extension picks auto-escaping the way minijinja does, so
+    /// values in a `.json` template come out as JSON strings.
+    pub fn load(path: &str) -> Result<Self, NotifyError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| NotifyError::ConfigError(format!("can't read template {}: {}", path, e)))?;
+        let name = Path::new(path).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| path.to_string());
+        Self::parse(name, source)
+    }
+
+    /// A template given inline, such as a `subject=`.
+    pub fn parse(name: String, source: String) -> Result<Self, NotifyError> {
+        let mut env = Environment::new();
+        env.add_template_owned(name.clone(), source)
+            .map_err(|e| NotifyError::ConfigError(format!("invalid template {}: {}", name, e)))?;
+        Ok(Self { env, name })
+    }
+
+    pub fn render(&self, event: &Event) -> Result<String, NotifyError> {
+        let template = self.env.get_template(&self.name)
+            .map_err(|e| NotifyError::DeliveryError(e.to_string()))?;
+        template.render(context! { summary => event.summary(), ..Value::from_serialize(event) })
+            .map_err(|e| NotifyError::DeliveryError(format!("template {}: {}", self.name, e)))
+    }
+}
+
+/// Splits drcv's own options (such as `template`) off the query of a notification URL, so the
+/// rest still reaches a webhook target untouched.
+pub fn take_options(rest: &str, names: &[&str]) -> (String, HashMap<String, String>) {
+    let Some((base, query)) = rest.split_once('?') else { return (rest.to_string(), HashMap::new()) };
+    let mut options = HashMap::new();
+    let mut kept = url::form_urlencoded::Serializer::new(String::new());
+    for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        if names.contains(&key.as_ref()) {
+            options.insert(key.into_owned(), value.into_owned());
+        } else {
+            kept.append_pair(&key, &value);
+        }
+    }
+    let kept = kept.finish();
+    let rest = if kept.is_empty() { base.to_string() } else { format!("{}?{}", base, kept) };
+    (rest, options)
+}
+
+/// The `template=` file among the options, if any.
+pub fn from_options(options: &HashMap<String, String>) -> Result<Option<Template>, NotifyError> {
+    options.get("template").map(|path| Template::load(path)).transpose()
+}
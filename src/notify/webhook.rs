@@ -0,0 +1,48 @@
+use super::{template::{self, Template}, Notifier, NotifyError};
+use crate::events::Event;
+use async_trait::async_trait;
+
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+    template: Option<Template>,
+    content_type: String,
+}
+
+impl WebhookNotifier {
+    /// Parses `json://host/path` (HTTP) or `jsons://host/path` (HTTPS); the event is POSTed as JSON,
+    /// or with `?template=payload.json` as rendered (`content_type=` if it isn't JSON).
+    pub fn from_url(secure: bool, rest: &str) -> Result<Self, NotifyError> {
+        let (rest, options) = template::take_options(rest, &["template", "content_type"]);
+        if rest.is_empty() {
+            return Err(NotifyError::ConfigError("json:// needs a host".to_string()));
+        }
+        let scheme = if secure { "https" } else { "http" };
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url: format!("{}://{}", scheme, rest),
+            template: template::from_options(&options)?,
+            content_type: options.get("content_type").cloned().unwrap_or_else(|| "application/json".to_string()),
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    async fn notify(&self, event: &Event) -> Result<(), NotifyError> {
+        let request = match &self.template {
+            Some(template) => self.client.post(&self.url)
+                .header(reqwest::header::CONTENT_TYPE, &self.content_type)
+                .body(template.render(event)?),
+            None => self.client.post(&self.url).json(event),
+        };
+        request.send().await
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| NotifyError::DeliveryError(e.to_string()))
+    }
+}
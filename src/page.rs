@@ -0,0 +1,82 @@
+use axum::{extract::State, Json};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::sync::watch;
+use log::info;
+use crate::{db, error::ApiError};
+
+const MAX_TITLE_LEN: usize = 80;
+const MAX_MESSAGE_LEN: usize = 1000;
+
+/// What the admin can change on upload pages that are already open.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PageSettings {
+    /// Heading and window title instead of "drcv uploader"
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Hex colour of the heading and upload button, e.g. `#3b82f6`
+    #[serde(default)]
+    pub accent: Option<String>,
+    /// Banner above the upload form, e.g. "We're closing in 10 minutes"
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+impl PageSettings {
+    /// Blank fields are unset; the accent must be a plain `#rgb` or `#rrggbb` colour, as the
+    /// page puts it into a style.
+    fn validated(self) -> Result<Self, String> {
+        let field = |value: Option<String>, name: &str, max: usize| -> Result<Option<String>, String> {
+            let value = value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+            match value {
+                Some(v) if v.chars().count() > max => Err(format!("{} is longer than {} characters", name, max)),
+                other => Ok(other),
+            }
+        };
+        let accent = field(self.accent, "accent", 7)?;
+        if let Some(accent) = &accent {
+            let hex = accent.strip_prefix('#').unwrap_or("");
+            if !matches!(hex.len(), 3 | 6) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("accent {:?} is not a colour like #3b82f6", accent));
+            }
+        }
+        Ok(Self {
+            title: field(self.title, "title", MAX_TITLE_LEN)?,
+            accent,
+            message: field(self.message, "message", MAX_MESSAGE_LEN)?,
+        })
+    }
+}
+
+static SETTINGS: Lazy<watch::Sender<PageSettings>> = Lazy::new(|| watch::channel(PageSettings::default()).0);
+
+/// Restores the settings last saved from the admin app.
+pub async fn init(pool: &SqlitePool) {
+    if let Some(settings) = db::kv_get(pool, "page_settings").await.and_then(|json| serde_json::from_str(&json).ok()) {
+        SETTINGS.send_replace(settings);
+    }
+}
+
+/// The current settings, then each change; `GET /notifications` forwards them as `page` messages.
+pub fn subscribe() -> watch::Receiver<PageSettings> {
+    SETTINGS.subscribe()
+}
+
+/// `GET /page`
+pub async fn admin_page() -> Json<PageSettings> {
+    Json(SETTINGS.borrow().clone())
+}
+
+/// `PUT /page`: replaces the upload page settings and pushes them to every open upload page
+/// right away. They're kept in the database across restarts.
+pub async fn admin_update_page(
+    State(pool): State<SqlitePool>,
+    Json(settings): Json<PageSettings>,
+) -> Result<Json<PageSettings>, ApiError> {
+    let settings = settings.validated().map_err(|e| ApiError::bad_request("invalid_page_settings", e))?;
+    db::kv_set(&pool, "page_settings", &serde_json::to_string(&settings).unwrap()).await;
+    SETTINGS.send_replace(settings.clone());
+    info!("🪧 Upload page settings changed{}", settings.message.as_deref().map(|m| format!(": {}", m)).unwrap_or_default());
+    Ok(Json(settings))
+}
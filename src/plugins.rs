@@ -0,0 +1,232 @@
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use wasmtime::{Caller, Config, Engine, InstancePre, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+use log::{error, info, warn};
+use crate::{db::{self, UploadData}, naming, policy::Violation};
+
+/// Instructions one hook call may run, so a plugin stuck in a loop can't hold up uploads.
+const FUEL: u64 = 1_000_000_000;
+/// Memory one plugin instance may grow to.
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Exports a plugin module needs besides at least one hook.
+const REQUIRED_EXPORTS: [&str; 2] = ["memory", "drcv_alloc"];
+const HOOKS: [&str; 2] = ["upload_init", "upload_complete"];
+
+struct Plugin {
+    name: String,
+    instance: InstancePre<Host>,
+}
+
+struct Host {
+    plugin: String,
+    limits: StoreLimits,
+}
+
+static PLUGINS: OnceCell<Vec<Plugin>> = OnceCell::new();
+
+/// What a hook reads as JSON; `stored_name` and `size` are only known at `upload_complete`.
+#[derive(Serialize)]
+struct HookInput {
+    hook: &'static str,
+    upload_id: Option<i64>,
+    filename: String,
+    stored_name: Option<String>,
+    size: Option<i64>,
+    client_ip: String,
+    sender_name: Option<String>,
+    sender_email: Option<String>,
+}
+
+/// What a hook may answer; no answer, or an empty one, lets the upload through.
+#[derive(Deserialize, Default)]
+struct HookOutput {
+    #[serde(default)]
+    reject: Option<String>,
+    #[serde(default)]
+    rename: Option<String>,
+    #[serde(default)]
+    note: Option<String>,
+}
+
+#[derive(Default)]
+struct Outcome {
+    rename: Option<String>,
+    notes: Vec<String>,
+}
+
+/// `drcv.log(ptr, len)`: lets a plugin write a line to drcv's log.
+fn host_log(mut caller: Caller<'_, Host>, ptr: i32, len: i32) {
+    let Some(memory) = caller.get_export("memory").and_then(|export| export.into_memory()) else { return };
+    let (start, len) = (ptr as u32 as usize, len as u32 as usize);
+    let message = memory.data(&caller)
+        .get(start..start.saturating_add(len))
+        .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+        .unwrap_or_default();
+    info!("🧩 {}: {}", caller.data().plugin, message);
+}
+
+/// A trap's own message, without the wasm backtrace wrapped around it.
+fn describe(e: wasmtime::Error) -> String {
+    e.root_cause().to_string()
+}
+
+fn fail(message: String) -> ! {
+    error!("{}", message);
+    std::process::exit(1);
+}
+
+/// Compiles the `--plugin` modules at startup. One that doesn't load stops drcv rather than
+/// letting it serve without the checks the plugin adds.
+pub fn load(paths: &[String]) {
+    if paths.is_empty() {
+        return;
+    }
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config).unwrap_or_else(|e| fail(format!("Failed to start the plugin runtime: {}", e)));
+    let mut linker = Linker::new(&engine);
+    linker.func_wrap("drcv", "log", host_log)
+        .unwrap_or_else(|e| fail(format!("Failed to set up the plugin host API: {}", e)));
+
+    let plugins: Vec<Plugin> = paths.iter().map(|path| {
+        let module = Module::from_file(&engine, path).unwrap_or_else(|e| fail(format!("Failed to load plugin {}: {}", path, e)));
+        if let Some(missing) = REQUIRED_EXPORTS.iter().find(|name| module.get_export(name).is_none()) {
+            fail(format!("Plugin {} doesn't export `{}`", path, missing));
+        }
+        if !HOOKS.iter().any(|hook| module.get_export(hook).is_some()) {
+            fail(format!("Plugin {} exports none of the hooks {}", path, HOOKS.join(", ")));
+        }
+        let instance = linker.instantiate_pre(&module).unwrap_or_else(|e| fail(format!("Failed to link plugin {}: {}", path, e)));
+        let name = Path::new(path).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| path.clone());
+        Plugin { name, instance }
+    }).collect();
+    let names: Vec<&str> = plugins.iter().map(|p| p.name.as_str()).collect();
+    info!("🧩 Loaded plugins: {}", names.join(", "));
+    let _ = PLUGINS.set(plugins);
+}
+
+fn loaded() -> &'static [Plugin] {
+    PLUGINS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// Calls `hook` in a fresh instance of the plugin, so no state carries over between uploads.
+fn call(plugin: &Plugin, hook: &str, input: &[u8]) -> Result<Option<HookOutput>, String> {
+    let module = plugin.instance.module();
+    if module.get_export(hook).is_none() {
+        return Ok(None);
+    }
+    let host = Host { plugin: plugin.name.clone(), limits: StoreLimitsBuilder::new().memory_size(MEMORY_LIMIT).build() };
+    let mut store = Store::new(module.engine(), host);
+    store.limiter(|host| &mut host.limits);
+    store.set_fuel(FUEL).map_err(describe)?;
+
+    let instance = plugin.instance.instantiate(&mut store).map_err(describe)?;
+    let memory = instance.get_memory(&mut store, "memory").ok_or("`memory` isn't a memory")?;
+    let alloc = instance.get_typed_func::<i32, i32>(&mut store, "drcv_alloc").map_err(describe)?;
+    let hook = instance.get_typed_func::<(i32, i32), i64>(&mut store, hook).map_err(describe)?;
+
+    let len = input.len() as i32;
+    let ptr = alloc.call(&mut store, len).map_err(describe)?;
+    memory.write(&mut store, ptr as u32 as usize, input).map_err(|e| e.to_string())?;
+    let packed = hook.call(&mut store, (ptr, len)).map_err(describe)? as u64;
+    if packed == 0 {
+        return Ok(None);
+    }
+    let (start, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+    let output = memory.data(&store)
+        .get(start..start.saturating_add(len))
+        .ok_or("answered with a range outside its memory")?;
+    serde_json::from_slice(output).map(Some).map_err(|e| format!("answered with invalid JSON: {}", e))
+}
+
+/// Runs `hook` of every plugin in `--plugin` order, each seeing the name the ones before
+/// settled on. The first rejection ends the run; a plugin that fails counts as one.
+fn run_all(mut input: HookInput) -> Result<Outcome, String> {
+    let mut outcome = Outcome::default();
+    for plugin in loaded() {
+        let bytes = serde_json::to_vec(&input).unwrap_or_default();
+        let output = match call(plugin, input.hook, &bytes) {
+            Ok(Some(output)) => output,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("🧩 Plugin {} failed on {} of {}: {}", plugin.name, input.hook, input.filename, e);
+                return Err(format!("plugin {} failed", plugin.name));
+            }
+        };
+        if let Some(reason) = output.reject {
+            let reason = if reason.trim().is_empty() { format!("rejected by plugin {}", plugin.name) } else { reason };
+            info!("🧩 Plugin {} rejected {} from {}: {}", plugin.name, input.filename, input.client_ip, reason);
+            return Err(reason);
+        }
+        if let Some(note) = output.note.filter(|note| !note.trim().is_empty()) {
+            outcome.notes.push(note);
+        }
+        if let Some(name) = output.rename.filter(|name| !name.trim().is_empty()) {
+            if input.stored_name.is_none() {
+                warn!("🧩 Plugin {} can't rename {} before it's complete", plugin.name, input.filename);
+                continue;
+            }
+            let name = naming::clean_name(&name, &input.filename);
+            input.stored_name = Some(name.clone());
+            outcome.rename = Some(name);
+        }
+    }
+    Ok(outcome)
+}
+
+async fn run(input: HookInput) -> Result<Outcome, String> {
+    tokio::task::spawn_blocking(move || run_all(input))
+        .await
+        .unwrap_or_else(|e| Err(format!("plugin runtime failed: {}", e)))
+}
+
+/// `upload_init`: a new upload is about to start. The plugins may refuse it, or leave notes to
+/// record once it has an id.
+pub async fn on_upload_init(filename: &str, sender_name: Option<&str>, sender_email: Option<&str>, client_ip: &str) -> Result<Option<String>, Violation> {
+    if loaded().is_empty() {
+        return Ok(None);
+    }
+    let input = HookInput {
+        hook: "upload_init",
+        upload_id: None,
+        filename: filename.to_string(),
+        stored_name: None,
+        size: None,
+        client_ip: client_ip.to_string(),
+        sender_name: sender_name.map(str::to_string),
+        sender_email: sender_email.map(str::to_string),
+    };
+    match run(input).await {
+        Ok(outcome) => Ok((!outcome.notes.is_empty()).then(|| outcome.notes.join("; "))),
+        Err(reason) => Err(Violation::new("plugin", reason)),
+    }
+}
+
+/// `upload_complete`: all of the upload is in and about to be stored at `path`. Returns where
+/// to store it instead if a plugin renamed it, or why a plugin rejected it.
+pub async fn on_upload_complete(pool: &SqlitePool, upload: &UploadData, path: PathBuf) -> Result<PathBuf, String> {
+    if loaded().is_empty() {
+        return Ok(path);
+    }
+    let input = HookInput {
+        hook: "upload_complete",
+        upload_id: Some(upload.id),
+        filename: upload.filename.clone(),
+        stored_name: Some(path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| upload.filename.clone())),
+        size: Some(upload.size),
+        client_ip: upload.client_ip.clone(),
+        sender_name: upload.sender_name.clone(),
+        sender_email: upload.sender_email.clone(),
+    };
+    let outcome = run(input).await?;
+    if !outcome.notes.is_empty() {
+        db::add_annotation(pool, upload.id, &outcome.notes.join("; ")).await;
+    }
+    match (outcome.rename, path.parent()) {
+        (Some(name), Some(dir)) => Ok(naming::unique_path(dir, &name).await),
+        _ => Ok(path),
+    }
+}
@@ -0,0 +1,109 @@
+use axum::{http::StatusCode, response::{IntoResponse, Response}};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::path::Path;
+use crate::{config::AppConfig, db, error::ApiError};
+
+/// Rules this drop imposes on new uploads, as advertised by `GET /capabilities` so the
+/// page can ask for a name or warn about a file before sending it.
+#[derive(Serialize)]
+pub struct Policy {
+    pub require_sender_name: bool,
+    pub allowed_extensions: Vec<String>,
+    pub max_files: Option<usize>,
+    pub max_files_per_client: Option<usize>,
+    pub max_files_per_client_per_day: Option<usize>,
+    pub filename_pattern: Option<String>,
+    /// Version of the terms to accept via `POST /consent`, if any
+    pub terms_version: Option<String>,
+}
+
+pub fn describe(config: &AppConfig) -> Policy {
+    Policy {
+        require_sender_name: config.require_sender_name,
+        allowed_extensions: config.allowed_extensions.clone(),
+        max_files: config.max_files,
+        max_files_per_client: config.max_files_per_client,
+        max_files_per_client_per_day: config.max_files_per_client_per_day,
+        filename_pattern: config.filename_pattern.as_ref().map(|p| p.as_str().to_string()),
+        terms_version: config.terms.as_ref().map(|t| t.version.clone()),
+    }
+}
+
+/// Why a new upload was refused; `rule` names the policy so the page can point at the
+/// field to fix, `message` is shown as is.
+pub struct Violation {
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl Violation {
+    pub fn new(rule: &'static str, message: String) -> Self {
+        Violation { rule, message }
+    }
+}
+
+/// A `policy_violation` error with the rule in `details.rule`.
+impl From<Violation> for ApiError {
+    fn from(violation: Violation) -> Self {
+        ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "policy_violation", violation.message)
+            .with_details(serde_json::json!({ "rule": violation.rule }))
+    }
+}
+
+impl IntoResponse for Violation {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// Checks a file the sender is about to start. Uploads already in progress were checked
+/// on their first chunk and aren't held to rules added since.
+pub async fn check(pool: &SqlitePool, config: &AppConfig, filename: &str, sender_name: Option<&str>, client_ip: &str) -> Result<(), Violation> {
+    if let Some(terms) = &config.terms {
+        if db::find_consent(pool, client_ip, &terms.version).await.is_none() {
+            return Err(Violation::new("terms", "Please accept the terms of use before uploading".to_string()));
+        }
+    }
+
+    if config.require_sender_name && sender_name.is_none_or(|name| name.trim().is_empty()) {
+        return Err(Violation::new("sender_name", "Please enter your name before uploading".to_string()));
+    }
+
+    if !config.allowed_extensions.is_empty() {
+        let extension = Path::new(filename)
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        if !config.allowed_extensions.contains(&extension) {
+            let allowed: Vec<String> = config.allowed_extensions.iter().map(|e| format!(".{}", e)).collect();
+            return Err(Violation::new("extension", format!("{} isn't accepted here; allowed file types: {}", filename, allowed.join(", "))));
+        }
+    }
+
+    if let Some(pattern) = &config.filename_pattern {
+        if !pattern.is_match(filename) {
+            return Err(Violation::new("filename_pattern", format!("{} doesn't match the required naming pattern {}", filename, pattern.as_str())));
+        }
+    }
+
+    if let Some(max_files) = config.max_files {
+        if db::count_kept_uploads(pool).await >= max_files as i64 {
+            return Err(Violation::new("max_files", format!("This drop accepts at most {} files and is full", max_files)));
+        }
+    }
+
+    if let Some(max_files) = config.max_files_per_client {
+        if db::count_client_uploads(pool, client_ip, None).await >= max_files as i64 {
+            return Err(Violation::new("max_files_per_client", format!("This drop accepts at most {} files from each sender", max_files)));
+        }
+    }
+
+    if let Some(max_files) = config.max_files_per_client_per_day {
+        let since = (chrono::Utc::now() - chrono::Duration::days(1)).to_rfc3339();
+        if db::count_client_uploads(pool, client_ip, Some(&since)).await >= max_files as i64 {
+            return Err(Violation::new("max_files_per_client_per_day", format!("This drop accepts at most {} files a day from each sender; please try again later", max_files)));
+        }
+    }
+    Ok(())
+}
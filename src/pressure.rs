@@ -0,0 +1,78 @@
+use once_cell::sync::Lazy;
+use std::sync::RwLock;
+use log::{info, warn};
+use crate::{config::AppConfig, events::{self, Event}, reload};
+
+/// Why new uploads are currently being turned away, if they are.
+static PRESSURE: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
+
+pub fn current() -> Option<String> {
+    PRESSURE.read().unwrap().clone()
+}
+
+/// Samples memory, file handles and disk space; while any threshold is exceeded new uploads are
+/// rejected (in-flight ones keep going) and the transition is published as an event. The
+/// thresholds follow config reloads.
+pub fn spawn_monitor(config: AppConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.pressure_check_interval);
+        loop {
+            interval.tick().await;
+            let reason = check(&reload::current().unwrap_or_else(|| config.clone()));
+            let previous = std::mem::replace(&mut *PRESSURE.write().unwrap(), reason.clone());
+            match (previous, reason) {
+                (None, Some(reason)) => {
+                    warn!("🚧 Rejecting new uploads: {}", reason);
+                    events::publish(Event::PressureHigh { reason });
+                }
+                (Some(_), None) => {
+                    info!("✅ Resource pressure cleared, accepting new uploads");
+                    events::publish(Event::PressureCleared);
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+fn check(config: &AppConfig) -> Option<String> {
+    if config.max_rss > 0 {
+        if let Some(rss) = resident_memory() {
+            if rss > config.max_rss {
+                return Some(format!("memory usage {} bytes exceeds {} bytes", rss, config.max_rss));
+            }
+        }
+    }
+    if config.max_open_files > 0 {
+        if let Some(open) = open_files() {
+            if open > config.max_open_files {
+                return Some(format!("{} open files exceed the limit of {}", open, config.max_open_files));
+            }
+        }
+    }
+    if config.min_disk_free > 0 {
+        // Files wait on the --spool-dir disk while they're moved, so both need the space
+        for dir in [Some(config.upload_dir.as_str()), config.spool_dir.as_deref()].into_iter().flatten() {
+            let _ = std::fs::create_dir_all(dir);
+            if let Ok(free) = fs2::available_space(dir) {
+                if free < config.min_disk_free {
+                    return Some(format!("only {} bytes free in {}", free, dir));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Resident set size in bytes (Linux only).
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+fn open_files() -> Option<u64> {
+    let dir = if cfg!(target_os = "linux") { "/proc/self/fd" } else { "/dev/fd" };
+    std::fs::read_dir(dir).ok().map(|entries| entries.count() as u64)
+}
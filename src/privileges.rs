@@ -0,0 +1,113 @@
+//! `--user` / `--group`: started as root to bind ports below 1024, drcv switches to an
+//! unprivileged account once its listeners are open. The upload and spool directories and the
+//! database are handed to that account first, so it can keep writing to them.
+use log::error;
+use crate::config::AppConfig;
+
+fn fail(message: String) -> ! {
+    error!("❌ {}", message);
+    std::process::exit(1);
+}
+
+#[cfg(not(unix))]
+pub fn drop_to(config: &AppConfig) {
+    if config.user.is_some() {
+        fail("--user is only supported on Unix; run drcv as a service account instead".to_string());
+    }
+}
+
+#[cfg(unix)]
+pub fn drop_to(config: &AppConfig) {
+    use std::path::Path;
+    use log::{info, warn};
+    use crate::db;
+
+    let Some(user) = &config.user else { return };
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        fail(format!("--user {} needs drcv to be started as root", user));
+    }
+    let (uid, primary_gid) = lookup_user(user).unwrap_or_else(|| fail(format!("Unknown user: {}", user)));
+    let gid = match &config.group {
+        Some(group) => lookup_group(group).unwrap_or_else(|| fail(format!("Unknown group: {}", group))),
+        None => primary_gid,
+    };
+
+    let dirs = [Some(config.upload_dir.as_str()), config.spool_dir.as_deref(), config.chunk_spool_dir.as_deref(), Some(crate::crash::CRASH_DIR)];
+    for dir in dirs.into_iter().flatten() {
+        if let Err(e) = chown_all(Path::new(dir), uid, gid) {
+            fail(format!("Failed to hand {} to {}: {}", dir, user, e));
+        }
+    }
+    for suffix in ["", "-journal", "-wal", "-shm", ".lock"] {
+        let file = format!("{}{}", db::DB_FILE, suffix);
+        match std::os::unix::fs::chown(&file, Some(uid), Some(gid)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => fail(format!("Failed to hand {} to {}: {}", file, user, e)),
+            _ => {}
+        }
+    }
+
+    // SAFETY: plain system calls on integer ids; glibc and musl apply them to every thread
+    let dropped = unsafe {
+        libc::setgroups(1, &gid) == 0 && libc::setgid(gid) == 0 && libc::setuid(uid) == 0
+    };
+    if !dropped {
+        fail(format!("Failed to switch to user {}: {}", user, std::io::Error::last_os_error()));
+    }
+    // SAFETY: as above
+    if uid != 0 && unsafe { libc::setuid(0) } == 0 {
+        fail(format!("Switched to user {} but could become root again", user));
+    }
+    info!("👤 Running as {} (uid {}, gid {})", user, uid, gid);
+
+    // SQLite creates its journal next to the database
+    // SAFETY: the path is NUL-terminated
+    if unsafe { libc::access(c".".as_ptr(), libc::W_OK) } != 0 {
+        warn!("⚠️ {} can't write to the working directory, where SQLite keeps its journal; database writes will fail", user);
+    }
+}
+
+/// `name` or a numeric uid, with the user's primary group.
+#[cfg(unix)]
+fn lookup_user(name: &str) -> Option<(libc::uid_t, libc::gid_t)> {
+    let name = std::ffi::CString::new(name).ok()?;
+    // SAFETY: name is NUL-terminated; the returned entry is read before any other getpw* call
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    if !entry.is_null() {
+        // SAFETY: checked for null above
+        return Some(unsafe { ((*entry).pw_uid, (*entry).pw_gid) });
+    }
+    let uid: libc::uid_t = name.to_str().ok()?.parse().ok()?;
+    // SAFETY: as above
+    let entry = unsafe { libc::getpwuid(uid) };
+    // A uid without an account gets a group of the same number
+    Some(if entry.is_null() { (uid, uid) } else { unsafe { (uid, (*entry).pw_gid) } })
+}
+
+#[cfg(unix)]
+fn lookup_group(name: &str) -> Option<libc::gid_t> {
+    let name = std::ffi::CString::new(name).ok()?;
+    // SAFETY: name is NUL-terminated; the returned entry is read before any other getgr* call
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    if entry.is_null() {
+        return name.to_str().ok()?.parse().ok();
+    }
+    // SAFETY: checked for null above
+    Some(unsafe { (*entry).gr_gid })
+}
+
+/// Changes the owner of `path` and everything below it, without following symlinks.
+#[cfg(unix)]
+fn chown_all(path: &std::path::Path, uid: libc::uid_t, gid: libc::gid_t) -> std::io::Result<()> {
+    std::fs::create_dir_all(path)?;
+    std::os::unix::fs::lchown(path, Some(uid), Some(gid))?;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            chown_all(&entry.path(), uid, gid)?;
+        } else {
+            std::os::unix::fs::lchown(entry.path(), Some(uid), Some(gid))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,37 @@
+//! `GET /progress`: one progress figure for everything being received, small and cheap enough
+//! to poll every second from a status page or a streaming overlay (e.g. an OBS browser source).
+use axum::{http::header, response::IntoResponse, Json};
+use serde::Serialize;
+use crate::registry;
+
+#[derive(Serialize)]
+pub struct Progress {
+    /// Uploads being received by this instance
+    pub active_uploads: usize,
+    /// Received of declared bytes, in percent, over the active uploads that declared a size;
+    /// `null` when there are none
+    pub percent: Option<f64>,
+    pub received_bytes: i64,
+    pub total_bytes: i64,
+    pub speed_bps: f64,
+    /// At the current combined speed
+    pub eta_seconds: Option<u64>,
+}
+
+pub fn current() -> Progress {
+    let (received_bytes, total_bytes) = registry::declared_progress();
+    let (speed_bps, _) = registry::throughput();
+    let remaining = (total_bytes - received_bytes).max(0) as f64;
+    Progress {
+        active_uploads: registry::active_count(),
+        percent: (total_bytes > 0).then(|| (received_bytes as f64 * 1000.0 / total_bytes as f64).floor() / 10.0),
+        received_bytes,
+        total_bytes,
+        speed_bps,
+        eta_seconds: (total_bytes > 0 && speed_bps > 0.0).then(|| (remaining / speed_bps).ceil() as u64),
+    }
+}
+
+pub async fn handle_progress() -> impl IntoResponse {
+    ([(header::CACHE_CONTROL, "no-store")], Json(current()))
+}
@@ -0,0 +1,91 @@
+use axum::{extract::Extension, http::{HeaderMap, StatusCode}, response::IntoResponse, Json};
+use serde::Serialize;
+use crate::{config::AppConfig, error::ApiError, policy::{self, Policy}, upload};
+
+/// Request header carrying the chunk protocol version; echoed on responses.
+pub const HEADER: &str = "x-drcv-protocol";
+
+/// Chunk upload protocol versions.
+/// - v1: multipart `filename`, `chunk_index`, `total_chunks`, `chunk`; the response is the upload id.
+/// - v2: multipart `filename`, `size`, `offset`, optional `upload_id` and `chunk_sha256`, `chunk`;
+///   the server checks the offset and hash and answers with a JSON acknowledgement.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Protocol {
+    V1,
+    V2,
+}
+
+impl Protocol {
+    pub fn number(self) -> u32 {
+        match self {
+            Protocol::V1 => 1,
+            Protocol::V2 => 2,
+        }
+    }
+}
+
+/// Versions this server speaks; v2 appends by offset, which the shared chunk spool doesn't support.
+pub fn supported(config: &AppConfig) -> Vec<u32> {
+    if config.chunk_spool_dir.is_some() { vec![1] } else { vec![1, 2] }
+}
+
+/// Version a chunk request asks for. Requests without the header, e.g. from upload pages
+/// cached before versioning existed, are v1.
+pub fn requested(headers: &HeaderMap, config: &AppConfig) -> Result<Protocol, ApiError> {
+    let Some(value) = headers.get(HEADER) else { return Ok(Protocol::V1) };
+    let version = value.to_str().ok().and_then(|v| v.trim().parse::<u32>().ok());
+    match version {
+        Some(1) => Ok(Protocol::V1),
+        Some(2) if supported(config).contains(&2) => Ok(Protocol::V2),
+        _ => Err(ApiError::bad_request("unsupported_protocol", format!("Unsupported protocol version {:?}; supported: {:?}", value, supported(config)))),
+    }
+}
+
+#[derive(Serialize)]
+pub struct Capabilities {
+    pub protocol: u32,
+    pub protocols: Vec<u32>,
+    pub chunk_size: u64,
+    /// Chunks of one file that may be sent at once
+    pub parallel_chunks: u32,
+    pub max_file_size: u64,
+    pub content_encodings: Vec<&'static str>,
+    /// Whether uploads only complete after `POST /upload/finalize`
+    pub finalize_required: bool,
+    /// Rules new uploads must pass
+    pub policy: Policy,
+    /// Whether `GET /status` is served
+    pub status_page: bool,
+    /// Whether the tus endpoints at `/files` accept uploads
+    pub tus: bool,
+}
+
+/// `GET /capabilities`: the client lists the versions it speaks in `X-Drcv-Protocol`
+/// (e.g. `1, 2`) and gets the highest one both sides support back, in the header and body.
+pub async fn handle_capabilities(
+    Extension(config): Extension<AppConfig>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let protocols = supported(&config);
+    let offered: Vec<u32> = match headers.get(HEADER).and_then(|v| v.to_str().ok()) {
+        Some(list) => list.split(',').filter_map(|v| v.trim().parse().ok()).collect(),
+        None => vec![1],
+    };
+    let protocol = offered.into_iter()
+        .filter(|v| protocols.contains(v))
+        .max()
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_ACCEPTABLE, "unsupported_protocol", format!("No common protocol version; supported: {:?}", protocols)))?;
+
+    Ok(([(HEADER, protocol.to_string())], Json(Capabilities {
+        protocol,
+        protocols,
+        chunk_size: config.chunk_size,
+        parallel_chunks: config.parallel_chunks,
+        max_file_size: config.max_file_size,
+        content_encodings: vec!["gzip", "zstd"],
+        finalize_required: config.require_finalize,
+        policy: policy::describe(&config),
+        status_page: config.status_page,
+        tus: upload::tus_available(&config),
+    })))
+}
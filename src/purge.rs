@@ -0,0 +1,105 @@
+use axum::{extract::{Path, Query, State, Extension}, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::path::PathBuf;
+use log::{info, warn};
+use crate::{checksums, coalescer, config::AppConfig, db::{self, ClientRecords}, error::ApiError, registry, sessions};
+
+#[derive(Deserialize)]
+pub struct PurgeQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize)]
+pub struct PurgedUpload {
+    pub id: i64,
+    pub filename: String,
+    pub status: String,
+    pub size: i64,
+}
+
+#[derive(Serialize)]
+pub struct PurgeReport {
+    pub client_ip: String,
+    pub dry_run: bool,
+    pub uploads: Vec<PurgedUpload>,
+    /// Completed files and scratch directories on disk
+    pub files: Vec<String>,
+    pub bytes: u64,
+    #[serde(flatten)]
+    pub records: ClientRecords,
+}
+
+/// Files on disk belonging to an upload: the stored file once complete, else its scratch directory.
+async fn upload_files(config: &AppConfig, upload: &db::UploadData) -> Vec<(PathBuf, u64)> {
+    let mut files = Vec::new();
+    if upload.is_stored() {
+        let path = PathBuf::from(&config.upload_dir).join(upload.stored_name.as_deref().unwrap_or(&upload.filename));
+        let sidecar = checksums::sidecar_path(&path);
+        if let Ok(meta) = tokio::fs::metadata(&path).await {
+            files.push((path, meta.len()));
+        }
+        if let Ok(meta) = tokio::fs::metadata(&sidecar).await {
+            files.push((sidecar, meta.len()));
+        }
+    }
+    let dir = sessions::dir(config.scratch_dir(), upload.id);
+    if tokio::fs::try_exists(&dir).await.unwrap_or(false) {
+        let size = sessions::part_path(config.scratch_dir(), upload.id, &upload.filename).await
+            .ok()
+            .and_then(|part| std::fs::metadata(part).ok())
+            .map(|m| m.len())
+            .unwrap_or(0);
+        files.push((dir, size));
+    }
+    files
+}
+
+/// `DELETE /clients/:ip/data`: removes everything drcv keeps about a sender, for data
+/// deletion requests: uploads with their files and partials, events, consents and the
+/// client entry. With `?dry_run=true` only reports what would be removed.
+pub async fn admin_purge_client(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+    Path(client_ip): Path<String>,
+    Query(query): Query<PurgeQuery>,
+) -> Result<Json<PurgeReport>, ApiError> {
+    coalescer::flush(&pool).await;
+    let uploads = db::get_client_uploads(&pool, &client_ip).await;
+    let mut files = Vec::new();
+    for upload in &uploads {
+        files.extend(upload_files(&config, upload).await);
+    }
+    let db_error = |e: sqlx::Error| ApiError::internal("database_error", format!("Database error: {}", e));
+
+    let records = if query.dry_run {
+        db::count_client_records(&pool, &client_ip).await.map_err(db_error)?
+    } else {
+        for upload in &uploads {
+            registry::finish(upload.id);
+            sessions::remove(&config, upload.id).await;
+        }
+        for (path, _) in &files {
+            if path.is_file() {
+                if let Err(e) = tokio::fs::remove_file(path).await {
+                    warn!("⚠️ Failed to remove {}: {}", path.display(), e);
+                }
+            }
+        }
+        let records = db::purge_client(&pool, &client_ip).await.map_err(db_error)?;
+        info!("🧹 Purged a client's data: {} uploads, {} files, {} events, {} consents", uploads.len(), files.len(), records.events, records.consents);
+        records
+    };
+
+    Ok(Json(PurgeReport {
+        client_ip,
+        dry_run: query.dry_run,
+        bytes: files.iter().map(|(_, size)| size).sum(),
+        files: files.into_iter().map(|(path, _)| path.display().to_string()).collect(),
+        uploads: uploads.into_iter()
+            .map(|u| PurgedUpload { id: u.id, filename: u.filename, status: u.status, size: u.size })
+            .collect(),
+        records,
+    }))
+}
@@ -0,0 +1,122 @@
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use sqlx::SqlitePool;
+use std::time::{Duration, Instant};
+use crate::{coalescer, db::{self, UploadData}, utils};
+
+struct LiveUpload {
+    data: UploadData,
+    last_activity: Instant,
+    last_progress: Instant,
+    speed_bps: f64,
+}
+
+/// Live state of uploads receiving chunks in this process. SQLite only gets checkpoints
+/// (through the coalescer) and state transitions; admin reads prefer this.
+static ACTIVE: Lazy<DashMap<i64, LiveUpload>> = Lazy::new(DashMap::new);
+
+/// Starts tracking `id` from its persisted state, if not tracked already.
+pub async fn track(pool: &SqlitePool, id: i64) {
+    if ACTIVE.contains_key(&id) {
+        return;
+    }
+    coalescer::flush(pool).await;
+    if let Some(data) = db::get_upload(pool, id).await {
+        let now = Instant::now();
+        ACTIVE.entry(id).or_insert(LiveUpload { data, last_activity: now, last_progress: now, speed_bps: 0.0 });
+    }
+}
+
+pub async fn record_progress(pool: &SqlitePool, id: i64, delta_size: i64, wire_bytes: i64) {
+    if let Some(mut live) = ACTIVE.get_mut(&id) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(live.last_progress).as_secs_f64().max(0.001);
+        let instant_bps = delta_size.max(0) as f64 / elapsed;
+        live.speed_bps = if live.speed_bps == 0.0 { instant_bps } else { live.speed_bps * 0.7 + instant_bps * 0.3 };
+        live.last_progress = now;
+        live.last_activity = now;
+        live.data.size += delta_size;
+        live.data.wire_bytes += wire_bytes;
+        live.data.updated_at = utils::now();
+        if live.data.status != "assembling" {
+            live.data.status = "uploading".to_string();
+        }
+    }
+    coalescer::record_progress(pool, id, delta_size, wire_bytes).await;
+}
+
+pub async fn record_heartbeat(pool: &SqlitePool, id: i64, client_ip: &str) -> bool {
+    let live = match ACTIVE.get_mut(&id) {
+        Some(mut live) if live.data.client_ip == client_ip => {
+            live.last_activity = Instant::now();
+            live.data.updated_at = utils::now();
+            live.data.status == "uploading"
+        }
+        _ => false,
+    };
+    coalescer::record_heartbeat(pool, id, client_ip, live).await
+}
+
+/// Stops tracking `id`; from here on the database row is authoritative.
+pub fn finish(id: i64) {
+    ACTIVE.remove(&id);
+}
+
+/// Bytes received so far for an upload tracked here.
+pub fn size(id: i64) -> Option<i64> {
+    ACTIVE.get(&id).map(|live| live.data.size)
+}
+
+/// True if `id` is live here, i.e. its state in SQLite may lag behind.
+pub fn is_tracked(id: i64) -> bool {
+    ACTIVE.contains_key(&id)
+}
+
+/// Uploads currently tracked here, i.e. started and neither finished nor gone stale.
+pub fn active_count() -> usize {
+    ACTIVE.len()
+}
+
+/// Combined receive rate of the uploads tracked here, in bytes per second, and how soon the
+/// first of those with a declared size should finish at its current speed.
+pub fn throughput() -> (f64, Option<Duration>) {
+    let mut speed_bps = 0.0;
+    let mut soonest: Option<f64> = None;
+    for live in ACTIVE.iter() {
+        speed_bps += live.speed_bps;
+        let Some(declared) = live.data.declared_size else { continue };
+        if live.speed_bps > 0.0 {
+            let secs = (declared - live.data.size).max(0) as f64 / live.speed_bps;
+            soonest = Some(soonest.map_or(secs, |s| s.min(secs)));
+        }
+    }
+    (speed_bps, soonest.map(Duration::from_secs_f64))
+}
+
+/// Bytes received and bytes declared, summed over the uploads tracked here that declared a size.
+pub fn declared_progress() -> (i64, i64) {
+    ACTIVE.iter()
+        .filter_map(|live| live.data.declared_size.map(|declared| (live.data.size.min(declared), declared)))
+        .fold((0, 0), |(received, total), (size, declared)| (received + size, total + declared))
+}
+
+pub fn evict_idle(max_idle: Duration) {
+    ACTIVE.retain(|_, live| live.last_activity.elapsed() < max_idle);
+}
+
+fn snapshot(live: &LiveUpload) -> UploadData {
+    UploadData { speed_bps: Some(live.speed_bps), ..live.data.clone() }
+}
+
+/// Replaces a row read from the database with the live state, if the upload is active here.
+pub fn overlay(data: UploadData) -> UploadData {
+    ACTIVE.get(&data.id).map(|live| snapshot(&live)).unwrap_or(data)
+}
+
+/// Active uploads updated after `since` (an RFC 3339 timestamp).
+pub fn changed_since(since: &str) -> Vec<UploadData> {
+    ACTIVE.iter()
+        .filter(|live| live.data.updated_at.as_str() > since)
+        .map(|live| snapshot(&live))
+        .collect()
+}
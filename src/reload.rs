@@ -0,0 +1,98 @@
+//! Reloading settings without a restart, on SIGHUP or `POST /reload` on the admin port: the
+//! command line is parsed again on top of the config file as it is now, and the result is
+//! published on a watch channel. Every request picks up the latest config, so uploads in
+//! progress carry on and their next chunk is checked against the new limits.
+use axum::{extract::Request, middleware::Next, response::Response, Json};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use tokio::sync::watch;
+use log::{error, info, warn};
+use crate::{config::{self, AppConfig}, error::ApiError, fairness, naming, net};
+
+static CONFIG: OnceCell<watch::Sender<AppConfig>> = OnceCell::new();
+
+#[derive(Serialize)]
+pub struct Reloaded {
+    /// Changed settings that only take effect on a restart, and were left as they were
+    pub restart_required: Vec<String>,
+}
+
+pub fn init(config: &AppConfig) {
+    let _ = CONFIG.set(watch::Sender::new(config.clone()));
+}
+
+/// Follows reloads; `None` when drcv wasn't started with [`init`], as in tests.
+pub fn subscribe() -> Option<watch::Receiver<AppConfig>> {
+    CONFIG.get().map(watch::Sender::subscribe)
+}
+
+pub fn current() -> Option<AppConfig> {
+    CONFIG.get().map(|config| config.borrow().clone())
+}
+
+/// Middleware for both apps: hands handlers the latest config in place of the one the app was
+/// created with.
+pub async fn latest(mut request: Request, next: Next) -> Response {
+    if let Some(config) = current() {
+        request.extensions_mut().insert(config);
+    }
+    next.run(request).await
+}
+
+/// Parses the config again and publishes it. Settings bound at startup, such as ports, the
+/// upload directory, the tunnel and the database, keep their values and are listed instead.
+pub fn reload() -> Result<Reloaded, String> {
+    let sender = CONFIG.get().ok_or("drcv isn't serving")?;
+    let mut fresh = config::reload_args()?.try_to_config()?;
+    let current = sender.borrow().clone();
+
+    let mut restart_required = Vec::new();
+    macro_rules! keep {
+        ($($field:ident),* $(,)?) => {$(
+            if fresh.$field != current.$field {
+                restart_required.push(stringify!($field).replace('_', "-"));
+                fresh.$field = current.$field.clone();
+            }
+        )*};
+    }
+    keep!(
+        upload_port, admin_port, admin_bind, container, user, group, sandbox, upload_dir, spool_dir, chunk_spool_dir, chunk_size,
+        tunnel_domain, tunnel_provider, public_url, copy_url, shortener,
+        ssh_target, ssh_remote_port, relay_server, relay_secret, relay_port,
+        cf_dns_cleanup, cf_access_emails, cf_access_domains,
+        instance_id, instance_url, mqtt_url, mqtt_topic_prefix, mqtt_hass_discovery,
+        direct_write, anonymize_ips, sign_responses, watch_upload_dir,
+        open_for, exit_when_closed, expect_files, emit,
+        db_flush_interval, db_pool_size, db_synchronous, db_cache_size, db_mmap_size, history_retention,
+        chaos, chaos_seed, chaos_rate, update_check,
+    );
+
+    fairness::init(&fresh);
+    naming::init(&fresh);
+    net::init(&fresh);
+    sender.send_replace(fresh);
+    info!("🔄 Config reloaded");
+    if !restart_required.is_empty() {
+        warn!("⚠️ Restart drcv to apply: {}", restart_required.join(", "));
+    }
+    Ok(Reloaded { restart_required })
+}
+
+/// Reloads on SIGHUP, where there is one.
+pub fn spawn_signal_handler() {
+    #[cfg(unix)]
+    tokio::spawn(async {
+        use tokio::signal::unix::{signal, SignalKind};
+        let Ok(mut hangups) = signal(SignalKind::hangup()) else { return };
+        while hangups.recv().await.is_some() {
+            if let Err(e) = reload() {
+                error!("⚠️ Config not reloaded: {}", e);
+            }
+        }
+    });
+}
+
+/// `POST /reload`
+pub async fn admin_reload() -> Result<Json<Reloaded>, ApiError> {
+    reload().map(Json).map_err(|e| ApiError::bad_request("invalid_config", e))
+}
@@ -0,0 +1,126 @@
+use axum::{extract::{State, Extension}, Json};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use crate::{chunkmap, config::{AppConfig, ResumeReportArgs}, db, naming, sessions};
+
+/// Bytes `start..end` of a file; an open `end` runs to the end of a file of unknown size.
+#[derive(Serialize)]
+pub struct ByteRange {
+    pub start: i64,
+    pub end: Option<i64>,
+}
+
+/// An unfinished upload, what of it is on disk and what's still missing.
+#[derive(Serialize)]
+pub struct MissingReport {
+    pub id: i64,
+    pub filename: String,
+    pub status: String,
+    pub client_ip: String,
+    pub updated_at: String,
+    pub declared_size: Option<i64>,
+    pub received_bytes: i64,
+    pub missing: Vec<ByteRange>,
+    /// The partial in the upload's scratch directory, if there is one
+    pub part_file: Option<String>,
+}
+
+/// The ranges of a file not covered by its journaled chunks. Bytes a version that didn't
+/// journal chunks wrote before the first one, and the recorded size of an upload with no
+/// chunks at all (e.g. spooled), are read from the start of the file as `reconcile` does.
+fn missing_ranges(chunks: &[(u32, i64, i64)], recorded_size: i64, declared_size: Option<i64>) -> (i64, Vec<ByteRange>) {
+    let mut covered: Vec<(i64, i64)> = match chunkmap::legacy_prefix(chunks, recorded_size) {
+        0 => Vec::new(),
+        prefix => vec![(0, prefix)],
+    };
+    // Chunks come ordered by offset
+    for &(_, offset, size) in chunks {
+        match covered.last_mut() {
+            Some((_, end)) if offset <= *end => *end = (*end).max(offset + size),
+            _ => covered.push((offset, offset + size)),
+        }
+    }
+
+    let mut missing = Vec::new();
+    let mut at = 0;
+    for &(start, end) in &covered {
+        if start > at {
+            missing.push(ByteRange { start: at, end: Some(start) });
+        }
+        at = end;
+    }
+    match declared_size {
+        Some(size) if size > at => missing.push(ByteRange { start: at, end: Some(size) }),
+        Some(_) => {}
+        None => missing.push(ByteRange { start: at, end: None }),
+    }
+    (covered.iter().map(|(start, end)| end - start).sum(), missing)
+}
+
+/// Missing byte ranges of every upload that's neither finished nor abandoned, from the
+/// chunk journal kept for each one.
+pub async fn report(pool: &SqlitePool, config: &AppConfig) -> Vec<MissingReport> {
+    let mut reports = Vec::new();
+    for upload in db::get_incomplete_uploads(pool).await {
+        let chunks = db::get_upload_chunks(pool, upload.id).await;
+        let (received_bytes, missing) = missing_ranges(&chunks, upload.size, upload.declared_size);
+        let part = sessions::dir(config.scratch_dir(), upload.id).join(format!("{}.part", naming::on_disk(&upload.filename)));
+        reports.push(MissingReport {
+            id: upload.id,
+            part_file: part.is_file().then(|| part.display().to_string()),
+            filename: upload.filename,
+            status: upload.status,
+            client_ip: upload.client_ip,
+            updated_at: upload.updated_at,
+            declared_size: upload.declared_size,
+            received_bytes,
+            missing,
+        });
+    }
+    reports
+}
+
+/// `GET /uploads/missing`: the resume report for the admin page.
+pub async fn admin_missing(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+) -> Json<Vec<MissingReport>> {
+    Json(report(&pool, &config).await)
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("❌ {}", message);
+    std::process::exit(1);
+}
+
+/// `drcv resume-report`: lists exactly which byte ranges each unfinished upload is missing,
+/// for splicing in data that arrived some other way.
+pub async fn run(args: &ResumeReportArgs, config: &AppConfig) {
+    naming::init(config);
+    let pool = db::init_pool(config).await.unwrap_or_else(|e| fail(format!("Failed to open {}: {}", db::DB_FILE, e)));
+    let reports = report(&pool, config).await;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&reports).unwrap_or_default());
+        return;
+    }
+    if reports.is_empty() {
+        println!("✅ No unfinished uploads");
+        return;
+    }
+    for report in &reports {
+        let of = report.declared_size.map(|size| format!(" of {}", size)).unwrap_or_default();
+        println!("🧩 #{} {} from {} ({}, last seen {})", report.id, report.filename, report.client_ip, report.status, report.updated_at);
+        println!("   received {}{} bytes", report.received_bytes, of);
+        for range in &report.missing {
+            match range.end {
+                Some(end) => println!("   missing {}..{} ({} bytes)", range.start, end, end - range.start),
+                None => println!("   missing {}.. (size unknown)", range.start),
+            }
+        }
+        match &report.part_file {
+            Some(path) => println!("   partial: {}", path),
+            None => println!("   no partial on disk"),
+        }
+    }
+    println!("💡 Ranges are half-open byte offsets; write each one into a copy of the partial at the same offset, e.g. with dd conv=notrunc");
+}
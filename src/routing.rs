@@ -0,0 +1,113 @@
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use std::{path::Path, sync::Arc};
+use log::{info, warn};
+use crate::{naming, policy::Violation};
+
+/// Operations one evaluation may run, so a script stuck in a loop can't hold up uploads.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A compiled `--route-script`, evaluated on the first chunk of every new upload.
+#[derive(Clone)]
+pub struct RouteScript {
+    engine: Arc<Engine>,
+    ast: AST,
+}
+
+/// Where the script sends an upload and the tags it gave it.
+#[derive(Default)]
+pub struct Route {
+    /// Subdirectory of the upload directory, already made safe
+    pub dir: Option<String>,
+    /// Without commas, so they can be stored as a list
+    pub tags: Vec<String>,
+}
+
+/// What the script is told about the upload.
+pub struct Upload<'a> {
+    pub filename: &'a str,
+    pub sender_name: Option<&'a str>,
+    pub sender_email: Option<&'a str>,
+    pub client_ip: &'a str,
+    pub size: Option<u64>,
+}
+
+fn optional(value: Option<&str>) -> Dynamic {
+    value.map(|v| Dynamic::from(v.to_string())).unwrap_or(Dynamic::UNIT)
+}
+
+/// Keeps a script's directory inside the upload directory: no absolute paths, no `..`, no
+/// hidden components (the session directories live in `.sessions`).
+fn clean_dir(dir: &str) -> Option<String> {
+    let parts: Vec<String> = dir.split(['/', '\\'])
+        .map(str::trim)
+        .filter(|part| !part.is_empty() && !part.starts_with('.'))
+        .map(|part| naming::clean_name(part, "_"))
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+impl RouteScript {
+    pub fn load(path: &str) -> Result<RouteScript, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| info!("📜 {}", text));
+        let ast = engine.compile_file(path.into())
+            .map_err(|e| format!("Failed to load route script {}: {}", path, e))?;
+        Ok(RouteScript { engine: Arc::new(engine), ast })
+    }
+
+    /// Runs the script for a new upload. It returns nothing (or `true`) to accept the upload as
+    /// is, `false` to refuse it, or a map with any of `allow`, `reason`, `dir` and `tags`.
+    pub fn route(&self, upload: &Upload) -> Result<Route, Violation> {
+        let now = chrono::Local::now();
+        let extension = Path::new(upload.filename)
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        let mut scope = Scope::new();
+        scope.push_constant("filename", upload.filename.to_string());
+        scope.push_constant("ext", extension);
+        scope.push_constant_dynamic("sender", optional(upload.sender_name));
+        scope.push_constant_dynamic("sender_email", optional(upload.sender_email));
+        scope.push_constant("client_ip", upload.client_ip.to_string());
+        scope.push_constant_dynamic("size", upload.size.map(|s| Dynamic::from(s as i64)).unwrap_or(Dynamic::UNIT));
+        scope.push_constant("date", now.format("%Y-%m-%d").to_string());
+        scope.push_constant("hour", now.format("%H").to_string().parse::<i64>().unwrap_or(0));
+        scope.push_constant("weekday", now.format("%a").to_string());
+
+        let refused = |reason: String| Err(Violation::new("route_script", reason));
+        let result = match self.engine.eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast) {
+            Ok(result) => result,
+            Err(e) => {
+                warn!("📜 Route script failed on {} from {}: {}", upload.filename, upload.client_ip, e);
+                return refused("This drop can't take the file right now (routing failed)".to_string());
+            }
+        };
+        if result.is_unit() {
+            return Ok(Route::default());
+        }
+        if let Ok(allow) = result.as_bool() {
+            return if allow { Ok(Route::default()) } else { refused(format!("{} isn't accepted here", upload.filename)) };
+        }
+        let Some(map) = result.clone().try_cast::<Map>() else {
+            warn!("📜 Route script returned a {} for {}; expected (), a bool or a map", result.type_name(), upload.filename);
+            return refused("This drop can't take the file right now (routing failed)".to_string());
+        };
+
+        let text = |key: &str| map.get(key).and_then(|v| v.clone().into_string().ok()).filter(|s| !s.trim().is_empty());
+        if map.get("allow").and_then(|v| v.as_bool().ok()) == Some(false) {
+            return refused(text("reason").unwrap_or_else(|| format!("{} isn't accepted here", upload.filename)));
+        }
+        let tags = match map.get("tags") {
+            Some(tags) if tags.is_array() => tags.clone().into_array().unwrap_or_default().into_iter()
+                .filter_map(|tag| tag.into_string().ok())
+                .collect(),
+            Some(tag) => tag.clone().into_string().ok().into_iter().collect(),
+            None => Vec::new(),
+        };
+        Ok(Route {
+            dir: text("dir").as_deref().and_then(clean_dir),
+            tags: tags.into_iter().map(|t| t.replace(',', " ").trim().to_string()).filter(|t| !t.is_empty()).collect(),
+        })
+    }
+}
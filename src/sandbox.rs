@@ -0,0 +1,195 @@
+//! `--sandbox`: confines where drcv can write, using Landlock on Linux. Once serving, creating,
+//! changing, renaming or deleting files is only possible in the upload and spool directories
+//! and `crashes/`, the working directory for the database and its journal, and `~/.cloudflared`
+//! for the Cloudflare tunnel's config. Reads are not restricted.
+//!
+//! A Landlock rule covers everything beneath its directory, and SQLite creates and deletes its
+//! journal next to the database, so the working directory can't be narrowed to `drcv.db`. drcv
+//! refuses to sandbox itself unless that directory holds nothing but its own files.
+//!
+//! Landlock confines the thread that enables it and every thread and process started from it,
+//! so this runs on the main thread before the async runtime starts, and tunnel clients and
+//! `--validate-cmd` commands are confined too.
+use log::error;
+use crate::config::AppConfig;
+
+fn fail(message: String) -> ! {
+    error!("❌ {}", message);
+    std::process::exit(1);
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn enter(config: &AppConfig) {
+    if config.sandbox {
+        fail("--sandbox is only supported on Linux".to_string());
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn enter(config: &AppConfig) {
+    use std::path::PathBuf;
+    use log::info;
+
+    if !config.sandbox {
+        return;
+    }
+    let abi = landlock::abi();
+    if abi < 1 {
+        fail(format!("--sandbox needs Landlock, which this kernel doesn't offer: {}", std::io::Error::last_os_error()));
+    }
+    // ABI 1 refuses every move between directories, which finishing an upload relies on
+    if abi < 2 {
+        fail("--sandbox needs Linux 5.19 or newer (Landlock ABI 2)".to_string());
+    }
+    let handled = landlock::WRITE | if abi >= 3 { landlock::TRUNCATE } else { 0 };
+
+    let mut dirs = vec![PathBuf::from(&config.upload_dir), PathBuf::from(crate::crash::CRASH_DIR)];
+    dirs.extend(config.spool_dir.iter().chain(&config.chunk_spool_dir).map(PathBuf::from));
+    if config.tunnel_provider == "cloudflare" && config.public_url.is_none() {
+        dirs.extend(dirs::home_dir().map(|home| home.join(".cloudflared")));
+    }
+
+    if let Some(name) = foreign_entry(&dirs) {
+        fail(format!("--sandbox leaves the working directory writable for {}, so it must hold nothing but drcv's files, but it has {}. \
+            Start drcv from a directory of its own, with any drcv.toml elsewhere (--config)", crate::db::DB_FILE, name));
+    }
+
+    let ruleset = landlock::Ruleset::new(handled).unwrap_or_else(|e| fail(format!("Failed to set up the sandbox: {}", e)));
+    for dir in &dirs {
+        let added = std::fs::create_dir_all(dir).and_then(|_| ruleset.allow(dir, handled));
+        if let Err(e) = added {
+            fail(format!("Failed to allow writes to {} in the sandbox: {}", dir.display(), e));
+        }
+    }
+    // The database, its journal and lock file
+    let files = landlock::MAKE_REG | landlock::REMOVE_FILE | landlock::WRITE_FILE | landlock::TRUNCATE;
+    if let Err(e) = ruleset.allow(".", files & handled) {
+        fail(format!("Failed to allow database writes in the sandbox: {}", e));
+    }
+    // Child processes get /dev/null as stdin and stdout; there may be no terminal to allow
+    for device in ["/dev/null", "/dev/tty"] {
+        let _ = ruleset.allow(device, (landlock::WRITE_FILE | landlock::TRUNCATE) & handled);
+    }
+    if let Err(e) = ruleset.restrict_self() {
+        fail(format!("Failed to enter the sandbox: {}", e));
+    }
+    let shown: Vec<String> = dirs.iter().map(|dir| dir.display().to_string()).collect();
+    let cwd = std::env::current_dir().map(|dir| dir.display().to_string()).unwrap_or_else(|_| ".".to_string());
+    info!("🔒 Sandboxed (Landlock ABI {}): file writes limited to {} and {} (the database's directory)", abi, shown.join(", "), cwd);
+}
+
+/// Something in the working directory that isn't the database, its journal or lock, or one of
+/// drcv's own `dirs`.
+#[cfg(target_os = "linux")]
+fn foreign_entry(dirs: &[std::path::PathBuf]) -> Option<String> {
+    let own: Vec<_> = dirs.iter().filter_map(|dir| std::fs::canonicalize(dir).ok()).collect();
+    let entries = match std::fs::read_dir(".") {
+        Ok(entries) => entries,
+        Err(e) => fail(format!("Failed to list the working directory for the sandbox: {}", e)),
+    };
+    entries.flatten()
+        .find(|entry| {
+            let name = entry.file_name();
+            let is_database = name.to_str().is_some_and(|name| name.starts_with(crate::db::DB_FILE));
+            !is_database && !std::fs::canonicalize(entry.path()).is_ok_and(|path| own.contains(&path))
+        })
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+}
+
+/// The parts of the Landlock API drcv uses; libc has the syscall numbers but not the structs.
+#[cfg(target_os = "linux")]
+mod landlock {
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    pub const WRITE_FILE: u64 = 1 << 1;
+    const REMOVE_DIR: u64 = 1 << 4;
+    pub const REMOVE_FILE: u64 = 1 << 5;
+    const MAKE_CHAR: u64 = 1 << 6;
+    const MAKE_DIR: u64 = 1 << 7;
+    pub const MAKE_REG: u64 = 1 << 8;
+    const MAKE_SOCK: u64 = 1 << 9;
+    const MAKE_FIFO: u64 = 1 << 10;
+    const MAKE_BLOCK: u64 = 1 << 11;
+    const MAKE_SYM: u64 = 1 << 12;
+    /// Moving and linking files between directories (ABI 2)
+    const REFER: u64 = 1 << 13;
+    /// ABI 3
+    pub const TRUNCATE: u64 = 1 << 14;
+    /// Every access that changes the file system, as of ABI 2
+    pub const WRITE: u64 = WRITE_FILE | REMOVE_DIR | REMOVE_FILE | MAKE_CHAR | MAKE_DIR | MAKE_REG
+        | MAKE_SOCK | MAKE_FIFO | MAKE_BLOCK | MAKE_SYM | REFER;
+    /// Accesses that apply to a file rather than a directory's entries
+    const FILE_ACCESS: u64 = WRITE_FILE | TRUNCATE | 1 << 0 | 1 << 2;
+
+    const CREATE_RULESET_VERSION: u32 = 1 << 0;
+    const RULE_PATH_BENEATH: u32 = 1;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    /// The highest Landlock ABI the kernel supports, or below 1 without Landlock.
+    pub fn abi() -> i64 {
+        // SAFETY: asking for the version takes no attribute
+        unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, std::ptr::null::<RulesetAttr>(), 0usize, CREATE_RULESET_VERSION) }
+    }
+
+    pub struct Ruleset(OwnedFd);
+
+    impl Ruleset {
+        pub fn new(handled: u64) -> io::Result<Self> {
+            let attr = RulesetAttr { handled_access_fs: handled };
+            // SAFETY: attr is a valid landlock_ruleset_attr of the size passed
+            let fd = unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, &attr, std::mem::size_of::<RulesetAttr>(), 0u32) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: the kernel just returned this descriptor to us
+            Ok(Ruleset(unsafe { OwnedFd::from_raw_fd(fd as i32) }))
+        }
+
+        /// Allows `access` beneath `path`, or to `path` itself if it isn't a directory.
+        pub fn allow(&self, path: impl AsRef<Path>, access: u64) -> io::Result<()> {
+            let path = path.as_ref();
+            let access = if path.is_dir() { access } else { access & FILE_ACCESS };
+            let name = std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(io::Error::other)?;
+            // SAFETY: name is NUL-terminated
+            let fd = unsafe { libc::open(name.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: as above
+            let parent = unsafe { OwnedFd::from_raw_fd(fd) };
+            let attr = PathBeneathAttr { allowed_access: access, parent_fd: parent.as_raw_fd() };
+            // SAFETY: attr is a valid landlock_path_beneath_attr and both descriptors are open
+            let added = unsafe { libc::syscall(libc::SYS_landlock_add_rule, self.0.as_raw_fd(), RULE_PATH_BENEATH, &attr, 0u32) };
+            if added < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        /// Confines the calling thread, and every thread and process it starts from now on.
+        pub fn restrict_self(self) -> io::Result<()> {
+            // SAFETY: plain system calls; no_new_privs is required to restrict without CAP_SYS_ADMIN
+            let restricted = unsafe {
+                libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0
+                    && libc::syscall(libc::SYS_landlock_restrict_self, self.0.as_raw_fd(), 0u32) == 0
+            };
+            if !restricted {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+}
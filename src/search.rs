@@ -0,0 +1,52 @@
+use axum::{extract::{Extension, Query, State}, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use crate::{config::AppConfig, db::{self, UploadData}, error::ApiError};
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub page: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub upload: UploadData,
+    /// Where the query matched, with the matching words in `[...]`
+    pub snippet: String,
+}
+
+/// Turns what an operator typed into an FTS5 query. Plain words match as word prefixes, so
+/// `quart rep` finds `Quarterly_Report.pdf`; a query with quotes or FTS5 operators
+/// (`"annual report"`, `tags:invoice`, `NOT draft`) is passed through as written.
+fn fts_query(q: &str) -> String {
+    let advanced = q.contains(['"', '*', ':', '(', ')'])
+        || q.split_whitespace().any(|w| matches!(w, "AND" | "OR" | "NOT" | "NEAR"));
+    if advanced {
+        return q.to_string();
+    }
+    q.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| format!("\"{}\"*", w))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `GET /search?q=`: completed uploads whose filename, stored name, tags, sender name or
+/// plugin notes match, best match first.
+pub async fn admin_search(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+    Query(params): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchHit>>, ApiError> {
+    let query = fts_query(params.q.trim());
+    if query.is_empty() {
+        return Ok(Json(Vec::new()));
+    }
+    let offset = ((params.page.unwrap_or(1).max(1) - 1) * config.default_page_size as usize) as i64;
+    let hits = db::search_uploads(&pool, &query, config.default_page_size, offset).await
+        // Almost always a malformed query, e.g. an unbalanced quote
+        .map_err(|e| ApiError::bad_request("invalid_query", format!("Invalid search {:?}: {}", params.q, e)))?;
+    Ok(Json(hits.into_iter().map(|(upload, snippet)| SearchHit { upload, snippet }).collect()))
+}
@@ -0,0 +1,83 @@
+use std::path::{Path, PathBuf};
+use super::command;
+
+const LABEL: &str = "app.drcv";
+
+fn plist_path() -> Result<PathBuf, String> {
+    dirs::home_dir()
+        .map(|home| home.join("Library").join("LaunchAgents").join(format!("{}.plist", LABEL)))
+        .ok_or_else(|| "cannot determine the home directory".to_string())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+pub fn install(dir: &Path, exe: &Path) -> Result<(), String> {
+    let path = plist_path()?;
+    let dir = escape(&dir.to_string_lossy());
+    // launchd agents get a bare PATH; keep the one cloudflared (e.g. from Homebrew) was found on
+    let search_path = escape(&std::env::var("PATH").unwrap_or_default());
+    let plist = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>Label</key>
+  <string>{label}</string>
+  <key>ProgramArguments</key>
+  <array>
+    <string>{exe}</string>
+    <string>--service</string>
+    <string>{dir}</string>
+  </array>
+  <key>EnvironmentVariables</key>
+  <dict>
+    <key>PATH</key>
+    <string>{search_path}</string>
+  </dict>
+  <key>RunAtLoad</key>
+  <true/>
+  <key>KeepAlive</key>
+  <dict>
+    <key>SuccessfulExit</key>
+    <false/>
+  </dict>
+  <key>StandardOutPath</key>
+  <string>{dir}/drcv.log</string>
+  <key>StandardErrorPath</key>
+  <string>{dir}/drcv.log</string>
+</dict>
+</plist>
+"#,
+        label = LABEL,
+        exe = escape(&exe.to_string_lossy()),
+        dir = dir,
+        search_path = search_path,
+    );
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("cannot create {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(&path, plist).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    command("launchctl", &["load", "-w", &path.to_string_lossy()])?;
+    println!("💡 Logs are written to drcv.log in the service directory");
+    Ok(())
+}
+
+pub fn uninstall() -> Result<(), String> {
+    let path = plist_path()?;
+    if !path.exists() {
+        return Err(format!("no service installed ({} not found)", path.display()));
+    }
+    command("launchctl", &["unload", "-w", &path.to_string_lossy()])?;
+    std::fs::remove_file(&path).map_err(|e| format!("cannot remove {}: {}", path.display(), e))
+}
+
+pub fn start() -> Result<(), String> {
+    command("launchctl", &["start", LABEL])
+}
+
+/// A clean exit after SIGTERM is a successful exit, so KeepAlive doesn't restart it.
+pub fn stop() -> Result<(), String> {
+    command("launchctl", &["stop", LABEL])
+}
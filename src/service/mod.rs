@@ -0,0 +1,87 @@
+#[cfg(target_os = "macos")]
+mod launchd;
+#[cfg(target_os = "linux")]
+mod systemd;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "macos")]
+use launchd as platform;
+#[cfg(target_os = "linux")]
+use systemd as platform;
+#[cfg(windows)]
+use windows as platform;
+#[cfg(windows)]
+pub use windows::dispatch;
+
+use std::path::Path;
+use crate::config::{ServiceAction, ServiceArgs, DEFAULT_CONFIG_FILE};
+
+/// `drcv service ...`: manages the OS service that runs `drcv --service <dir>`.
+pub fn run(args: &ServiceArgs) {
+    let result = match args.action {
+        ServiceAction::Install => install(),
+        ServiceAction::Uninstall => platform::uninstall().map(|()| println!("✅ Service removed")),
+        ServiceAction::Start => platform::start().map(|()| println!("✅ Service started")),
+        ServiceAction::Stop => platform::stop().map(|()| println!("✅ Service stopped")),
+    };
+    if let Err(e) = result {
+        eprintln!("❌ {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn install() -> Result<(), String> {
+    let dir = std::env::current_dir().map_err(|e| format!("cannot determine the current directory: {}", e))?;
+    let exe = std::env::current_exe().map_err(|e| format!("cannot locate the drcv executable: {}", e))?;
+    if !Path::new(DEFAULT_CONFIG_FILE).exists() {
+        println!("⚠️  No {} here; the service will run with default options. Run `drcv init` to create one.", DEFAULT_CONFIG_FILE);
+    }
+    platform::install(&dir, &exe)?;
+    println!("✅ Service installed; it serves from {}", dir.display());
+    println!("   Options are read from {} there; restart the service after editing it.", DEFAULT_CONFIG_FILE);
+    Ok(())
+}
+
+/// Resolves when the service manager asks drcv to stop (SIGTERM on Unix).
+#[cfg(unix)]
+pub async fn stop_requested() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => { terminate.recv().await; }
+        Err(_) => std::future::pending().await,
+    }
+}
+
+#[cfg(windows)]
+pub use windows::{report_stopped, stop_requested};
+
+/// Tells the service manager the process is about to exit; only Windows needs this.
+#[cfg(not(windows))]
+pub fn report_stopped() {}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
+mod platform {
+    use std::path::Path;
+
+    const UNSUPPORTED: &str = "drcv service is only supported on macOS, Linux and Windows";
+
+    pub fn install(_dir: &Path, _exe: &Path) -> Result<(), String> { Err(UNSUPPORTED.to_string()) }
+    pub fn uninstall() -> Result<(), String> { Err(UNSUPPORTED.to_string()) }
+    pub fn start() -> Result<(), String> { Err(UNSUPPORTED.to_string()) }
+    pub fn stop() -> Result<(), String> { Err(UNSUPPORTED.to_string()) }
+}
+
+/// Runs a service manager command, turning a non-zero exit into its stderr.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn command(program: &str, args: &[&str]) -> Result<(), String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!("`{} {}` failed: {}", program, args.join(" "), String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+use super::command;
+
+const UNIT: &str = "drcv.service";
+
+fn unit_path() -> Result<PathBuf, String> {
+    dirs::config_dir()
+        .map(|dir| dir.join("systemd").join("user").join(UNIT))
+        .ok_or_else(|| "cannot determine the user config directory".to_string())
+}
+
+/// Quotes a word for an `ExecStart=` line; `%` would otherwise start a unit specifier.
+fn quote(word: &str) -> String {
+    format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\"").replace('%', "%%"))
+}
+
+pub fn install(dir: &Path, exe: &Path) -> Result<(), String> {
+    let path = unit_path()?;
+    let search_path = std::env::var("PATH").unwrap_or_default();
+    let unit = format!(
+        "[Unit]\n\
+         Description=drcv resumable file receiver\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         ExecStart={} --service {}\n\
+         Environment={}\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        quote(&exe.to_string_lossy()),
+        quote(&dir.to_string_lossy()),
+        // cloudflared/ssh/bore are looked up on the PATH drcv was installed with
+        quote(&format!("PATH={}", search_path)),
+    );
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("cannot create {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(&path, unit).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    command("systemctl", &["--user", "daemon-reload"])?;
+    command("systemctl", &["--user", "enable", "--now", UNIT])?;
+    println!("💡 To keep it running while you are logged out (e.g. on a NAS): loginctl enable-linger $USER");
+    Ok(())
+}
+
+pub fn uninstall() -> Result<(), String> {
+    let path = unit_path()?;
+    if !path.exists() {
+        return Err(format!("no service installed ({} not found)", path.display()));
+    }
+    command("systemctl", &["--user", "disable", "--now", UNIT])?;
+    std::fs::remove_file(&path).map_err(|e| format!("cannot remove {}: {}", path.display(), e))?;
+    command("systemctl", &["--user", "daemon-reload"])
+}
+
+pub fn start() -> Result<(), String> {
+    command("systemctl", &["--user", "start", UNIT])
+}
+
+pub fn stop() -> Result<(), String> {
+    command("systemctl", &["--user", "stop", UNIT])
+}
@@ -0,0 +1,135 @@
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use once_cell::sync::Lazy;
+use tokio::sync::Notify;
+use windows_service::{
+    define_windows_service,
+    service::{ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode, ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType},
+    service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle},
+    service_dispatcher,
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+use log::error;
+use crate::config::Args;
+
+const SERVICE_NAME: &str = "drcv";
+
+static PENDING_ARGS: Mutex<Option<Args>> = Mutex::new(None);
+static STATUS: OnceLock<ServiceStatusHandle> = OnceLock::new();
+static STOP: Lazy<Notify> = Lazy::new(Notify::new);
+
+fn manager(access: ServiceManagerAccess) -> Result<ServiceManager, String> {
+    ServiceManager::local_computer(None::<&str>, access)
+        .map_err(|e| format!("cannot open the service manager (run from an Administrator prompt): {}", e))
+}
+
+pub fn install(dir: &Path, exe: &Path) -> Result<(), String> {
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from("drcv file receiver"),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: exe.to_path_buf(),
+        launch_arguments: vec![OsString::from("--service"), dir.as_os_str().to_os_string()],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager(ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE)?
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG | ServiceAccess::START)
+        .map_err(|e| format!("cannot create the service: {}", e))?;
+    let _ = service.set_description("Receives resumable uploads shared through a tunnel");
+    service.start(&[] as &[&OsStr]).map_err(|e| format!("service installed but failed to start: {}", e))?;
+    println!("💡 Logs are written to drcv.log in the service directory");
+    Ok(())
+}
+
+pub fn uninstall() -> Result<(), String> {
+    let service = manager(ServiceManagerAccess::CONNECT)?
+        .open_service(SERVICE_NAME, ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE)
+        .map_err(|e| format!("no service installed: {}", e))?;
+    if service.query_status().is_ok_and(|s| s.current_state != ServiceState::Stopped) {
+        let _ = service.stop();
+    }
+    service.delete().map_err(|e| format!("cannot remove the service: {}", e))
+}
+
+pub fn start() -> Result<(), String> {
+    manager(ServiceManagerAccess::CONNECT)?
+        .open_service(SERVICE_NAME, ServiceAccess::START)
+        .and_then(|service| service.start(&[] as &[&OsStr]))
+        .map_err(|e| format!("cannot start the service: {}", e))
+}
+
+pub fn stop() -> Result<(), String> {
+    manager(ServiceManagerAccess::CONNECT)?
+        .open_service(SERVICE_NAME, ServiceAccess::STOP)
+        .and_then(|service| service.stop())
+        .map(|_| ())
+        .map_err(|e| format!("cannot stop the service: {}", e))
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Hands the process to the service control manager, which calls back into `service_main`
+/// on its own thread; blocks until the service has stopped.
+pub fn dispatch(args: Args) {
+    *PENDING_ARGS.lock().unwrap() = Some(args);
+    if let Err(e) = service_dispatcher::start(SERVICE_NAME, ffi_service_main) {
+        error!("❌ Failed to start as a Windows service: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    let handler = |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            STOP.notify_one();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    };
+    let handle = match service_control_handler::register(SERVICE_NAME, handler) {
+        Ok(handle) => handle,
+        Err(e) => {
+            error!("❌ Failed to register the service control handler: {}", e);
+            return;
+        }
+    };
+    let _ = STATUS.set(handle);
+    set_state(ServiceState::Running);
+
+    let Some(args) = PENDING_ARGS.lock().unwrap().take() else { return };
+    crate::runtime().block_on(crate::run(args));
+}
+
+fn set_state(state: ServiceState) {
+    let Some(handle) = STATUS.get() else { return };
+    let controls_accepted = if state == ServiceState::Running {
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+    } else {
+        ServiceControlAccept::empty()
+    };
+    let _ = handle.set_service_status(ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code: ServiceExitCode::Win32(0),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    });
+}
+
+/// Resolves when the service control manager sends Stop or Shutdown.
+pub async fn stop_requested() {
+    STOP.notified().await;
+}
+
+pub fn report_stopped() {
+    set_state(ServiceState::Stopped);
+}
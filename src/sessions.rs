@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use sqlx::SqlitePool;
+use log::{info, warn};
+use crate::{assembly, chunkmap, coalescer, db, direct, handles, naming, registry, config::AppConfig, events::{self, Event}};
+
+/// Per-upload scratch directories live here, out of sight of the finished files.
+const SESSIONS_DIR: &str = ".sessions";
+
+pub fn dir(upload_dir: &str, id: i64) -> PathBuf {
+    PathBuf::from(upload_dir).join(SESSIONS_DIR).join(id.to_string())
+}
+
+/// Path of the `.part` file inside the upload's scratch directory, creating the directory.
+/// A partial left in the upload directory itself by an older version is moved in, so it still resumes.
+pub async fn part_path(upload_dir: &str, id: i64, filename: &str) -> std::io::Result<PathBuf> {
+    let dir = dir(upload_dir, id);
+    tokio::fs::create_dir_all(&dir).await?;
+    let path = naming::long_path(&dir.join(format!("{}.part", naming::on_disk(filename))));
+    let legacy = PathBuf::from(upload_dir).join(format!("{}.part", filename));
+    if tokio::fs::metadata(&path).await.is_err() && tokio::fs::metadata(&legacy).await.is_ok() {
+        tokio::fs::rename(&legacy, &path).await?;
+    }
+    Ok(path)
+}
+
+/// What a sender needs to resume an upload.
+pub struct ResumeState {
+    /// End of the unbroken run of bytes from the start of the file; chunks received beyond a
+    /// gap are kept, and skipped when sent again
+    pub received_bytes: i64,
+    /// Index the next chunk gets; unknown for spooled uploads and partials written by older versions
+    pub next_chunk_index: Option<u32>,
+}
+
+/// Resume state of an unfinished upload. One that isn't live in this process (e.g. after a
+/// restart) is first reconciled with its scratch data on disk.
+pub async fn resume_state(pool: &SqlitePool, config: &AppConfig, upload: &db::UploadData) -> ResumeState {
+    if registry::is_tracked(upload.id) {
+        let chunks = db::get_upload_chunks(pool, upload.id).await;
+        let (received_bytes, next_chunk_index) = chunkmap::contiguous(&chunks, registry::size(upload.id).unwrap_or(upload.size));
+        return ResumeState { received_bytes, next_chunk_index };
+    }
+    reconcile(pool, config, upload).await
+}
+
+/// Brings the recorded size of an upload in line with what's on disk. Chunks that don't end
+/// within the `.part` file, torn by a crash, are forgotten and the file is cut back to the
+/// last chunk recorded whole, so anything written but not yet recorded is sent again instead
+/// of being kept twice.
+async fn reconcile(pool: &SqlitePool, config: &AppConfig, upload: &db::UploadData) -> ResumeState {
+    let id = upload.id;
+    if let Some(spool_dir) = &config.chunk_spool_dir {
+        let received_bytes = assembly::spooled_bytes(spool_dir, id).await;
+        if received_bytes != upload.size {
+            db::set_size(pool, id, received_bytes).await;
+        }
+        return ResumeState { received_bytes, next_chunk_index: None };
+    }
+
+    let Ok(path) = part_path(config.scratch_dir(), id, &upload.filename).await else {
+        return ResumeState { received_bytes: upload.size, next_chunk_index: None };
+    };
+    let on_disk = tokio::fs::metadata(&path).await.map(|m| m.len() as i64).unwrap_or(0);
+    let mut chunks = db::get_upload_chunks(pool, id).await;
+
+    // Bytes before the first recorded chunk may have been written by a version that didn't record chunks
+    let prefix = chunkmap::legacy_prefix(&chunks, upload.size).min(on_disk);
+    let kept = chunks.iter().take_while(|&&(_, offset, size)| offset + size <= on_disk).count();
+    if kept < chunks.len() {
+        db::forget_chunks_from(pool, id, chunks[kept].1).await;
+        chunks.truncate(kept);
+    }
+    let whole = chunks.last().map_or(prefix, |&(_, offset, size)| offset + size).max(prefix);
+    let received: i64 = prefix + chunks.iter().map(|&(_, _, size)| size).sum::<i64>();
+    if on_disk > whole {
+        handles::discard(id);
+        let truncated = match tokio::fs::OpenOptions::new().write(true).open(&path).await {
+            Ok(file) => file.set_len(whole as u64).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = truncated {
+            warn!("⚠️ Failed to truncate {}: {}", path.display(), e);
+            db::set_size(pool, id, on_disk).await;
+            return ResumeState { received_bytes: on_disk, next_chunk_index: None };
+        }
+    }
+    let (received_bytes, next_chunk_index) = chunkmap::contiguous(&chunks, received);
+    if received != upload.size || on_disk != whole {
+        info!("🩹 Reconciled {}: resuming from {} bytes ({} on disk, {} recorded)", upload.filename, received_bytes, on_disk, upload.size);
+        db::set_size(pool, id, received).await;
+    }
+    ResumeState { received_bytes, next_chunk_index }
+}
+
+/// Chunks of an unfinished upload still to be sent, as inclusive index ranges, with the
+/// number of chunks the sender last said the file has. Unknown before it has said, and for
+/// partials written by older versions, whose chunks aren't all journaled.
+pub async fn missing_chunks(pool: &SqlitePool, config: &AppConfig, upload: &db::UploadData) -> Option<(u32, Vec<(u32, u32)>)> {
+    let total_chunks = db::get_total_chunks(pool, upload.id).await?;
+    let present: HashSet<u32> = match &config.chunk_spool_dir {
+        Some(spool_dir) => assembly::spooled_chunks(spool_dir, upload.id).await,
+        None => {
+            let chunks = db::get_upload_chunks(pool, upload.id).await;
+            if chunkmap::legacy_prefix(&chunks, registry::size(upload.id).unwrap_or(upload.size)) > 0 {
+                return None;
+            }
+            chunks.iter().map(|&(index, _, _)| index).collect()
+        }
+    };
+    Some((total_chunks, chunkmap::missing_ranges(total_chunks, |index| present.contains(&index))))
+}
+
+/// Leading bytes of a file a sender hashes to prove it's resending the same content.
+pub const LEAD_BLOCK_SIZE: u64 = 1024 * 1024;
+
+/// Takes over a disconnected partial of the same file from another client identity, e.g. a
+/// sender who switched devices or browsers, if the SHA-256 of its first `LEAD_BLOCK_SIZE`
+/// bytes matches `lead_sha256`.
+pub async fn recycle(pool: &SqlitePool, config: &AppConfig, filename: &str, size: u64, lead_sha256: &str, client_ip: &str) -> Option<db::UploadData> {
+    let lead_len = size.min(LEAD_BLOCK_SIZE);
+    for upload in db::find_recyclable_uploads(pool, filename, size, client_ip).await {
+        if registry::is_tracked(upload.id) || (upload.size as u64) < lead_len {
+            continue;
+        }
+        let Ok(path) = part_path(config.scratch_dir(), upload.id, filename).await else { continue };
+        match lead_hash(&path, lead_len).await {
+            Ok(hash) if hash.eq_ignore_ascii_case(lead_sha256) => {}
+            Ok(_) => continue,
+            Err(e) => {
+                warn!("⚠️ Failed to read {}: {}", path.display(), e);
+                continue;
+            }
+        }
+        if db::reassign_upload(pool, upload.id, client_ip).await {
+            info!("♻️ Recycled partial {} ({} bytes) from {} for {}", filename, upload.size, upload.client_ip, client_ip);
+            return db::get_upload(pool, upload.id).await;
+        }
+    }
+    None
+}
+
+async fn lead_hash(path: &Path, len: u64) -> std::io::Result<String> {
+    use sha2::{Digest, Sha256};
+    use tokio::io::AsyncReadExt;
+    let mut lead = Vec::with_capacity(len as usize);
+    tokio::fs::File::open(path).await?.take(len).read_to_end(&mut lead).await?;
+    Ok(format!("{:x}", Sha256::digest(&lead)))
+}
+
+/// Removes the scratch state of an upload: pooled handle, unnamed or `.part` file and spooled chunks.
+pub async fn remove(config: &AppConfig, id: i64) {
+    handles::discard(id);
+    direct::discard(id);
+    remove_dir(&dir(config.scratch_dir(), id)).await;
+    if let Some(spool_dir) = &config.chunk_spool_dir {
+        remove_dir(&assembly::chunk_dir(spool_dir, id)).await;
+    }
+}
+
+async fn remove_dir(dir: &Path) {
+    match tokio::fs::remove_dir_all(dir).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("⚠️ Failed to remove {}: {}", dir.display(), e),
+    }
+}
+
+/// Abandons an unfinished upload and deletes its partial data; false if it was already finished.
+pub async fn abandon(pool: &SqlitePool, config: &AppConfig, upload: &db::UploadData, reason: &str) -> bool {
+    if !db::mark_abandoned(pool, upload.id).await {
+        return false;
+    }
+    registry::finish(upload.id);
+    remove(config, upload.id).await;
+    info!("🗑️ Abandoned upload ({}): {} from {}", reason, upload.filename, upload.client_ip);
+    events::publish(Event::UploadAbandoned {
+        id: upload.id,
+        filename: upload.filename.clone(),
+        client_ip: upload.client_ip.clone(),
+        reason: reason.to_string(),
+    });
+    true
+}
+
+/// Every `cleanup_interval`: closes idle `.part` handles, marks uploads and clients that went
+/// quiet as disconnected, expires old sessions and assembles spooled uploads whose chunks are in.
+pub fn spawn_cleanup(pool: SqlitePool, config: &AppConfig) {
+    let config = config.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.cleanup_interval);
+        loop {
+            interval.tick().await;
+            handles::close_idle(config.part_file_idle_timeout);
+            if let Some(instance_id) = &config.instance_id {
+                db::touch_instance(&pool, instance_id, config.instance_url.as_deref()).await;
+                // Only the lease holder runs the shared background jobs
+                if !db::acquire_lease(&pool, "background", instance_id, config.instance_stale_timeout).await {
+                    continue;
+                }
+            }
+            coalescer::flush(&pool).await;
+            db::mark_stale_uploads_disconnected(&pool, config.upload_stale_timeout).await;
+            registry::evict_idle(std::time::Duration::from_secs(config.upload_stale_timeout as u64));
+            direct::materialize_idle().await;
+            expire(&pool, &config).await;
+            db::mark_stale_clients_disconnected(&pool, config.client_stale_timeout).await;
+            assembly::assemble_spooled_uploads(&pool, &config).await;
+        }
+    });
+}
+
+/// Abandons uploads disconnected for longer than `--abandon-after`, then sweeps scratch
+/// directories whose upload no longer needs them (e.g. left behind by a crash).
+pub async fn expire(pool: &SqlitePool, config: &AppConfig) {
+    if !config.abandon_after.is_zero() {
+        for upload in db::get_expired_uploads(pool, config.abandon_after.as_secs() as i64).await {
+            abandon(pool, config, &upload, "expired").await;
+        }
+    }
+
+    let Ok(mut entries) = tokio::fs::read_dir(PathBuf::from(config.scratch_dir()).join(SESSIONS_DIR)).await else { return };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Some(id) = entry.file_name().to_str().and_then(|name| name.parse::<i64>().ok()) else { continue };
+        let finished = match db::get_upload(pool, id).await {
+            Some(upload) => upload.is_stored() || upload.status == "abandoned",
+            None => true,
+        };
+        if finished {
+            remove_dir(&entry.path()).await;
+        }
+    }
+}
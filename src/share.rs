@@ -0,0 +1,66 @@
+use std::sync::{mpsc, Mutex};
+use once_cell::sync::Lazy;
+use sqlx::SqlitePool;
+use log::{info, warn};
+use crate::{config::AppConfig, db};
+
+static CLIPBOARD: Lazy<Mutex<Option<mpsc::Sender<String>>>> = Lazy::new(|| Mutex::new(None));
+
+/// The compact form of a share URL to read out, from `--shortener` (cached per URL, so a
+/// rotated hostname gets a fresh one). `None` without a shortener or if it failed.
+pub async fn short_link(pool: &SqlitePool, config: &AppConfig, url: &str) -> Option<String> {
+    let url = url.trim_end_matches('/');
+    let shortener = config.shortener.as_ref()?;
+    let key = format!("short_url:{}", url);
+    if let Some(cached) = db::kv_get(pool, &key).await {
+        return Some(cached);
+    }
+    match shorten(shortener, url).await {
+        Ok(short) => {
+            db::kv_set(pool, &key, &short).await;
+            Some(short)
+        }
+        Err(e) => {
+            warn!("⚠️ Failed to shorten {}: {}", url, e);
+            None
+        }
+    }
+}
+
+/// Calls a shortener API that answers a GET with the short URL as plain text, e.g.
+/// `https://is.gd/create.php?format=simple&url={url}`.
+async fn shorten(shortener: &str, url: &str) -> Result<String, String> {
+    let encoded: String = url::form_urlencoded::byte_serialize(url.as_bytes()).collect();
+    let response = reqwest::get(shortener.replace("{url}", &encoded)).await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let short = response.text().await.map_err(|e| e.to_string())?.trim().to_string();
+    if !short.starts_with("http://") && !short.starts_with("https://") {
+        return Err(format!("unexpected answer {:?}", short.chars().take(80).collect::<String>()));
+    }
+    Ok(short)
+}
+
+/// Puts text on the system clipboard. A single thread owns the clipboard for as long as drcv
+/// runs, since on X11 and Wayland the copied text is served from this process.
+pub fn copy(text: String) {
+    let mut sender = CLIPBOARD.lock().unwrap();
+    let sender = sender.get_or_insert_with(|| {
+        let (tx, rx) = mpsc::channel::<String>();
+        std::thread::spawn(move || {
+            let mut clipboard = match arboard::Clipboard::new() {
+                Ok(clipboard) => clipboard,
+                Err(e) => return warn!("⚠️ Clipboard unavailable: {}", e),
+            };
+            for text in rx {
+                match clipboard.set_text(text.clone()) {
+                    Ok(()) => info!("📋 Copied {} to the clipboard", text),
+                    Err(e) => warn!("⚠️ Failed to copy the share link: {}", e),
+                }
+            }
+        });
+        tx
+    });
+    let _ = sender.send(text);
+}
+
@@ -0,0 +1,99 @@
+use axum::{body::Body, extract::{Request, Extension}, http::{header, HeaderValue}, middleware::Next, response::{IntoResponse, Response}, Json};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ed25519_dalek::{Signer, SigningKey};
+use once_cell::sync::OnceCell;
+use rand::RngCore;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use log::{error, info};
+use crate::{config::AppConfig, db, error::ApiError};
+
+pub const HEADER: &str = "x-drcv-signature";
+
+static KEY: OnceCell<SigningKey> = OnceCell::new();
+
+/// Turns on `--sign-responses` with the server's Ed25519 key, created on first use and kept
+/// in the database, so it survives restarts and moves with `export-identity`.
+pub async fn init(pool: &SqlitePool, config: &AppConfig) {
+    if !config.sign_responses {
+        return;
+    }
+    let stored = db::kv_get(pool, "signing_key").await
+        .and_then(|encoded| BASE64.decode(encoded).ok())
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok());
+    let secret = match stored {
+        Some(secret) => secret,
+        None => {
+            let mut secret = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut secret);
+            db::kv_set(pool, "signing_key", &BASE64.encode(secret)).await;
+            info!("🔏 Created a response signing key");
+            secret
+        }
+    };
+    let _ = KEY.set(SigningKey::from_bytes(&secret));
+}
+
+/// First 16 hex digits of the SHA-256 of the public key, for comparing it out of band.
+pub fn key_id() -> Option<String> {
+    use sha2::{Digest, Sha256};
+    let key = KEY.get()?;
+    Some(format!("{:x}", Sha256::digest(key.verifying_key().as_bytes()))[..16].to_string())
+}
+
+/// Signs successful responses: `X-Drcv-Signature: keyid=<id>;t=<unix>;sig=<base64>`, an
+/// Ed25519 signature of `<t>\n<host>\n<body>`. The host is the one the sender addressed, so
+/// a lookalike hostname relaying to this server is caught as well as a server without the key.
+pub async fn sign(request: Request, next: Next) -> Response {
+    let Some(key) = KEY.get() else { return next.run(request).await };
+    let host = request.headers().get(header::HOST).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        return ApiError::internal("signing_failed", "Failed to read the response to sign").into_response();
+    };
+    let timestamp = chrono::Utc::now().timestamp();
+    let mut message = format!("{}\n{}\n", timestamp, host).into_bytes();
+    message.extend_from_slice(&bytes);
+    let signature = BASE64.encode(key.sign(&message).to_bytes());
+    let value = format!("keyid={};t={};sig={}", key_id().unwrap_or_default(), timestamp, signature);
+    match HeaderValue::from_str(&value) {
+        Ok(value) => { parts.headers.insert(HEADER, value); }
+        Err(e) => error!("Failed to sign a response: {}", e),
+    }
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+#[derive(Serialize)]
+pub struct WellKnown {
+    pub server: &'static str,
+    pub version: &'static str,
+    pub algorithm: &'static str,
+    pub key_id: String,
+    pub public_key: String,
+    pub header: &'static str,
+    pub signed_message: &'static str,
+}
+
+/// `GET /.well-known/drcv.json`: the public key senders check signatures against; `404`
+/// without `--sign-responses`.
+pub async fn handle_well_known(
+    Extension(config): Extension<AppConfig>,
+) -> Result<Json<WellKnown>, ApiError> {
+    let (true, Some(key)) = (config.sign_responses, KEY.get()) else {
+        return Err(ApiError::not_found("signing_disabled", "This drop doesn't sign its responses"));
+    };
+    Ok(Json(WellKnown {
+        server: "drcv",
+        version: env!("CARGO_PKG_VERSION"),
+        algorithm: "ed25519",
+        key_id: key_id().unwrap_or_default(),
+        public_key: BASE64.encode(key.verifying_key().as_bytes()),
+        header: "X-Drcv-Signature",
+        signed_message: "<t>\n<host>\n<body>",
+    }))
+}
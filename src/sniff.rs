@@ -0,0 +1,100 @@
+//! `--check-file-types`: compares the first bytes of each completed file with its extension.
+//! An upload whose content is of a different known type than its extension claims, such as a
+//! `.pdf` that is a Windows executable, is stored as usual but marked `suspicious`, logged
+//! and announced with an `upload_suspicious` event. Files of a type drcv doesn't know, or
+//! without an extension, are never flagged.
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+/// Enough for every signature below, including a PE header behind a long DOS stub.
+const HEAD: usize = 4096;
+
+struct Kind {
+    name: &'static str,
+    /// Extensions a file of this kind may have; a kind without `matches` only claims them
+    extensions: &'static [&'static str],
+    matches: Option<fn(&[u8]) -> bool>,
+}
+
+const KINDS: &[Kind] = &[
+    Kind { name: "a Windows executable", extensions: &["exe", "dll", "sys", "scr", "cpl", "ocx", "drv", "efi", "mui", "com"], matches: Some(is_pe) },
+    Kind { name: "a Linux executable (ELF)", extensions: &["so", "elf", "bin", "o", "ko", "out", "run", "axf", "prx"], matches: Some(|b| b.starts_with(b"\x7fELF")) },
+    Kind {
+        name: "a macOS executable (Mach-O)",
+        extensions: &["dylib", "bundle", "o"],
+        matches: Some(|b| [b"\xfe\xed\xfa\xce", b"\xfe\xed\xfa\xcf", b"\xce\xfa\xed\xfe", b"\xcf\xfa\xed\xfe"].iter().any(|m| b.starts_with(*m))),
+    },
+    Kind { name: "a script", extensions: &["sh", "bash", "zsh", "ksh", "fish", "command", "py", "pl", "rb", "php", "js", "mjs", "run"], matches: Some(|b| b.starts_with(b"#!")) },
+    Kind { name: "a Windows shortcut", extensions: &["lnk"], matches: Some(|b| b.starts_with(b"\x4c\x00\x00\x00\x01\x14\x02\x00")) },
+    Kind { name: "a PDF document", extensions: &["pdf", "ai"], matches: Some(|b| b.starts_with(b"%PDF-")) },
+    Kind { name: "a PNG image", extensions: &["png", "apng"], matches: Some(|b| b.starts_with(b"\x89PNG\r\n\x1a\n")) },
+    Kind { name: "a JPEG image", extensions: &["jpg", "jpeg", "jpe", "jfif"], matches: Some(|b| b.starts_with(b"\xff\xd8\xff")) },
+    Kind { name: "a GIF image", extensions: &["gif"], matches: Some(|b| b.starts_with(b"GIF87a") || b.starts_with(b"GIF89a")) },
+    Kind { name: "a WebP image", extensions: &["webp"], matches: Some(|b| riff(b, b"WEBP")) },
+    Kind { name: "a WAV file", extensions: &["wav"], matches: Some(|b| riff(b, b"WAVE")) },
+    Kind { name: "an AVI video", extensions: &["avi"], matches: Some(|b| riff(b, b"AVI ")) },
+    Kind { name: "a TIFF image", extensions: &["tif", "tiff", "dng", "nef", "cr2", "arw", "orf", "rw2", "pef"], matches: Some(|b| b.starts_with(b"II*\x00") || b.starts_with(b"MM\x00*")) },
+    Kind {
+        name: "an MP4/QuickTime file",
+        extensions: &["mp4", "m4v", "m4a", "m4b", "mov", "qt", "3gp", "3g2", "heic", "heif", "avif", "f4v"],
+        matches: Some(|b| b.get(4..8) == Some(b"ftyp")),
+    },
+    Kind { name: "a Matroska/WebM video", extensions: &["mkv", "mka", "mks", "mk3d", "webm"], matches: Some(|b| b.starts_with(b"\x1a\x45\xdf\xa3")) },
+    Kind { name: "an MP3 file", extensions: &["mp3", "aac"], matches: Some(|b| b.starts_with(b"ID3")) },
+    Kind { name: "an Ogg file", extensions: &["ogg", "oga", "ogv", "opus", "spx"], matches: Some(|b| b.starts_with(b"OggS")) },
+    Kind { name: "a FLAC file", extensions: &["flac"], matches: Some(|b| b.starts_with(b"fLaC")) },
+    Kind {
+        name: "a ZIP archive",
+        extensions: &["zip", "docx", "xlsx", "pptx", "docm", "xlsm", "pptm", "odt", "ods", "odp", "odg", "epub", "jar", "war", "apk", "aab", "ipa",
+            "xpi", "crx", "vsix", "whl", "nupkg", "kmz", "3mf", "appx", "msix", "xps", "oxps", "cbz", "sketch", "pages", "numbers", "key", "usdz"],
+        matches: Some(|b| b.starts_with(b"PK\x03\x04") || b.starts_with(b"PK\x05\x06")),
+    },
+    Kind { name: "a gzip archive", extensions: &["gz", "tgz", "svgz"], matches: Some(|b| b.starts_with(b"\x1f\x8b")) },
+    Kind { name: "a 7-Zip archive", extensions: &["7z"], matches: Some(|b| b.starts_with(b"7z\xbc\xaf\x27\x1c")) },
+    Kind { name: "a RAR archive", extensions: &["rar", "cbr"], matches: Some(|b| b.starts_with(b"Rar!\x1a\x07")) },
+    Kind { name: "an xz archive", extensions: &["xz", "txz"], matches: Some(|b| b.starts_with(b"\xfd7zXZ\x00")) },
+    Kind { name: "a bzip2 archive", extensions: &["bz2", "tbz", "tbz2"], matches: Some(|b| b.starts_with(b"BZh")) },
+    Kind { name: "a Zstandard archive", extensions: &["zst", "tzst"], matches: Some(|b| b.starts_with(b"\x28\xb5\x2f\xfd")) },
+    Kind {
+        name: "an Office 97-2003/OLE document",
+        extensions: &["doc", "dot", "xls", "xlt", "ppt", "pot", "pps", "msi", "msp", "msg", "pub", "vsd", "mpp", "hwp"],
+        matches: Some(|b| b.starts_with(b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1")),
+    },
+    Kind { name: "an SQLite database", extensions: &["sqlite", "sqlite3", "db", "db3"], matches: Some(|b| b.starts_with(b"SQLite format 3\x00")) },
+    // Plain text has no signature, but its extensions promise none of the kinds above
+    Kind { name: "text", extensions: &["txt", "csv", "tsv", "md", "json", "xml", "html", "htm", "svg", "log", "ini", "yaml", "yml", "rtf"], matches: None },
+];
+
+/// A DOS header whose `e_lfanew` points at a PE signature; "MZ" alone is too likely in text.
+fn is_pe(b: &[u8]) -> bool {
+    if !b.starts_with(b"MZ") {
+        return false;
+    }
+    let Some(offset) = b.get(0x3c..0x40).map(|o| u32::from_le_bytes([o[0], o[1], o[2], o[3]]) as usize) else { return false };
+    b.get(offset..offset + 4) == Some(b"PE\x00\x00")
+}
+
+fn riff(b: &[u8], form: &[u8]) -> bool {
+    b.starts_with(b"RIFF") && b.get(8..12) == Some(form)
+}
+
+/// Why the content of a file named `filename` contradicts its extension, if it does, e.g.
+/// `content is a Windows executable, not a .pdf`.
+pub fn mismatch(filename: &str, head: &[u8]) -> Option<String> {
+    let extension = Path::new(filename).extension()?.to_str()?.to_lowercase();
+    let content = KINDS.iter().find(|kind| kind.matches.is_some_and(|matches| matches(head)))?;
+    if content.extensions.contains(&extension.as_str()) {
+        return None;
+    }
+    // An extension drcv doesn't know makes no claim to contradict
+    KINDS.iter().any(|kind| kind.extensions.contains(&extension.as_str()))
+        .then(|| format!("content is {}, not a .{}", content.name, extension))
+}
+
+/// [`mismatch`] for the file stored at `path`.
+pub async fn check(filename: &str, path: &Path) -> Option<String> {
+    let mut head = Vec::with_capacity(HEAD);
+    let file = tokio::fs::File::open(path).await.ok()?;
+    file.take(HEAD as u64).read_to_end(&mut head).await.ok()?;
+    mismatch(filename, &head)
+}
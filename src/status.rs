@@ -0,0 +1,111 @@
+use axum::{extract::{State, ConnectInfo, Extension}, http::HeaderMap, response::Html, Json};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::net::SocketAddr;
+use crate::{config::AppConfig, db, error::ApiError, net::extract_client_ip, registry};
+
+/// One of the viewer's uploads, without anything identifying the sender.
+#[derive(Serialize)]
+pub struct StatusEntry {
+    pub filename: String,
+    pub status: String,
+    pub size: i64,
+    pub declared_size: Option<i64>,
+    pub speed_bps: Option<f64>,
+    pub started_at: String,
+    pub updated_at: String,
+    pub completed_at: Option<String>,
+}
+
+impl From<db::UploadData> for StatusEntry {
+    fn from(upload: db::UploadData) -> Self {
+        StatusEntry {
+            filename: upload.filename,
+            status: sender_status(upload.status),
+            size: upload.size,
+            declared_size: upload.declared_size,
+            speed_bps: upload.speed_bps,
+            started_at: upload.started_at,
+            updated_at: upload.updated_at,
+            completed_at: upload.completed_at,
+        }
+    }
+}
+
+/// Senders aren't told `--check-file-types` flagged their file.
+fn sender_status(status: String) -> String {
+    if status == "suspicious" { "complete".to_string() } else { status }
+}
+
+fn enabled(config: &AppConfig) -> Result<(), ApiError> {
+    if config.status_page { Ok(()) } else { Err(ApiError::not_found("no_status_page", "No status page on this drop")) }
+}
+
+/// `GET /status`: a read-only page where senders can check on their uploads after closing
+/// the upload tab; `404` without `--status-page`.
+pub async fn handle_status_page(Extension(config): Extension<AppConfig>) -> Result<Html<&'static str>, ApiError> {
+    enabled(&config)?;
+    Ok(Html(include_str!("static/status.html")))
+}
+
+/// `GET /status/data`: the caller's own uploads, matched by client identity, with live
+/// progress for the ones in flight.
+pub async fn handle_status_data(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(config): Extension<AppConfig>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<StatusEntry>>, ApiError> {
+    enabled(&config)?;
+    let client_ip = extract_client_ip(&headers, &addr);
+    let uploads = db::get_client_uploads(&pool, &client_ip).await
+        .into_iter()
+        .rev()
+        .filter(|upload| upload.status != "abandoned")
+        .map(|upload| registry::overlay(upload).into())
+        .collect();
+    Ok(Json(uploads))
+}
+
+/// How many past uploads `GET /my/uploads` returns.
+const MY_UPLOADS_LIMIT: usize = 50;
+
+#[derive(Serialize)]
+pub struct MyUpload {
+    pub upload_id: i64,
+    pub filename: String,
+    pub status: String,
+    pub size: i64,
+    pub declared_size: Option<i64>,
+    pub started_at: String,
+    pub completed_at: Option<String>,
+    /// Page link that asks to pick the file again, for disconnected uploads
+    pub resume_url: Option<String>,
+}
+
+/// `GET /my/uploads`: the caller's past uploads, newest first, so the upload page can list
+/// them and offer resuming after the browser lost its own history.
+pub async fn handle_my_uploads(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Json<Vec<MyUpload>> {
+    let client_ip = extract_client_ip(&headers, &addr);
+    let uploads = db::get_client_uploads(&pool, &client_ip).await
+        .into_iter()
+        .rev()
+        .take(MY_UPLOADS_LIMIT)
+        .map(|upload| MyUpload {
+            resume_url: (upload.status == "disconnected").then(|| format!("/?resume={}",
+                url::form_urlencoded::byte_serialize(upload.filename.as_bytes()).collect::<String>())),
+            upload_id: upload.id,
+            filename: upload.filename,
+            status: sender_status(upload.status),
+            size: upload.size,
+            declared_size: upload.declared_size,
+            started_at: upload.started_at,
+            completed_at: upload.completed_at,
+        })
+        .collect();
+    Json(uploads)
+}
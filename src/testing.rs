@@ -0,0 +1,105 @@
+//! An in-process drcv for integration tests: the upload app on an ephemeral loopback port, with
+//! its own upload directory under the system temp dir and an in-memory database.
+//!
+//! ```no_run
+//! # async fn example() {
+//! let server = drcv::testing::TestServer::start().await;
+//! let response = reqwest::get(server.url("/capabilities")).await.unwrap();
+//! assert!(response.status().is_success());
+//! # }
+//! ```
+//!
+//! Upload state such as the live upload registry is process-wide, so servers in one test
+//! binary run one at a time: `start` waits until the previous server was dropped.
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use clap::Parser;
+use once_cell::sync::Lazy;
+use sqlx::SqlitePool;
+use tokio::sync::{broadcast, Mutex, OwnedMutexGuard};
+use crate::{apps, bandwidth, config::{AppConfig, Args}, db, events, fairness, naming, net, page, registry, sessions};
+
+static SERIAL: Lazy<Arc<Mutex<()>>> = Lazy::new(|| Arc::new(Mutex::new(())));
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+pub struct TestServer {
+    pub pool: SqlitePool,
+    pub config: AppConfig,
+    /// Where the upload app listens, e.g. `http://127.0.0.1:39211`
+    pub base_url: String,
+    dir: PathBuf,
+    shutdown: broadcast::Sender<()>,
+    _serial: OwnedMutexGuard<()>,
+}
+
+impl TestServer {
+    /// A server with default settings.
+    pub async fn start() -> Self {
+        Self::start_with(&[], |_| {}).await
+    }
+
+    /// A server started with extra command-line `args` (e.g. `["--strict-chunks"]`), and with
+    /// `adjust` applied to the resulting config for settings that have no flag, such as
+    /// `cleanup_interval`.
+    pub async fn start_with(args: &[&str], adjust: impl FnOnce(&mut AppConfig)) -> Self {
+        let serial = Arc::clone(&SERIAL).lock_owned().await;
+        registry::evict_idle(Duration::ZERO);
+
+        let dir = std::env::temp_dir().join(format!("drcv-test-{}-{}", std::process::id(), NEXT_DIR.fetch_add(1, Ordering::Relaxed)));
+        let upload_dir = dir.join("uploads");
+        std::fs::create_dir_all(&upload_dir).expect("create the test upload directory");
+        let upload_dir = upload_dir.to_string_lossy().into_owned();
+        let mut argv = vec!["drcv", "--upload-dir", upload_dir.as_str()];
+        argv.extend_from_slice(args);
+        let mut config = Args::parse_from(argv).to_config();
+        // Written through, so a request's effects are in the database when it returns
+        config.db_flush_interval = Duration::ZERO;
+        adjust(&mut config);
+
+        let pool = db::init_pool_at("sqlite::memory:", &config).await.expect("open the in-memory database");
+        page::init(&pool).await;
+        bandwidth::init(&pool).await;
+        events::spawn_recorder(pool.clone());
+        fairness::init(&config);
+        naming::init(&config);
+        net::init(&config);
+        sessions::spawn_cleanup(pool.clone(), &config);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("bind a loopback port");
+        let base_url = format!("http://{}", listener.local_addr().expect("local address"));
+        let service = apps::upload::create_router(&pool, &config).into_make_service_with_connect_info::<SocketAddr>();
+        let (shutdown, mut shutdown_rx) = broadcast::channel::<()>(1);
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, service)
+                .with_graceful_shutdown(async move { let _ = shutdown_rx.recv().await; })
+                .await;
+        });
+
+        TestServer { pool, config, base_url, dir, shutdown, _serial: serial }
+    }
+
+    /// `path` on the upload app, e.g. `url("/upload")`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Where a completed upload named `name` is stored.
+    pub fn stored_file(&self, name: &str) -> PathBuf {
+        Path::new(&self.config.upload_dir).join(name)
+    }
+
+    /// The upload's row as the admin app would show it.
+    pub async fn upload(&self, id: i64) -> Option<db::UploadData> {
+        db::get_upload(&self.pool, id).await.map(registry::overlay)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.shutdown.send(());
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
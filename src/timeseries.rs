@@ -0,0 +1,83 @@
+use axum::{extract::{Query, State}, Json};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use crate::{db::{self, SeriesSource}, error::ApiError};
+
+/// The series `GET /metrics/timeseries` can chart, and what each is computed from.
+const METRICS: [(&str, SeriesSource); 5] = [
+    ("bytes_received", SeriesSource::ChunkBytes),
+    ("uploads_started", SeriesSource::Events("upload_started")),
+    ("uploads_completed", SeriesSource::Events("upload_completed")),
+    ("uploads_disconnected", SeriesSource::Events("upload_disconnected")),
+    ("uploads_abandoned", SeriesSource::Events("upload_abandoned")),
+];
+
+/// Keeps a careless `step` from producing a response Grafana can't draw anyway.
+const MAX_POINTS: i64 = 11_000;
+
+#[derive(Deserialize)]
+pub struct TimeseriesQuery {
+    metric: String,
+    step: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+/// A series in the shape Grafana's JSON datasources read: `[value, unix milliseconds]` pairs.
+#[derive(Serialize)]
+pub struct Series {
+    target: String,
+    datapoints: Vec<(i64, i64)>,
+}
+
+/// RFC 3339, or Unix milliseconds as Grafana's `${__from}` and `${__to}` expand to.
+fn parse_time(value: &str) -> Result<DateTime<Utc>, ApiError> {
+    let parsed = match value.parse::<i64>() {
+        Ok(millis) => DateTime::from_timestamp_millis(millis),
+        Err(_) => DateTime::parse_from_rfc3339(value).ok().map(|time| time.with_timezone(&Utc)),
+    };
+    parsed.ok_or_else(|| ApiError::bad_request("invalid_time", format!("Invalid time: {}", value)))
+}
+
+/// `GET /metrics/timeseries?metric=<name>&step=1m[&from=&to=]`: a metric summed per `step`
+/// from the chunk journal and event history, the last 24 hours unless `from`/`to` say
+/// otherwise. Buckets without activity are zero, so charts don't interpolate across gaps.
+pub async fn handle_timeseries(
+    State(pool): State<SqlitePool>,
+    Query(params): Query<TimeseriesQuery>,
+) -> Result<Json<Vec<Series>>, ApiError> {
+    let Some((_, source)) = METRICS.iter().find(|(name, _)| *name == params.metric) else {
+        let names: Vec<&str> = METRICS.iter().map(|(name, _)| *name).collect();
+        return Err(ApiError::bad_request("unknown_metric", format!("Unknown metric {}; expected one of {}", params.metric, names.join(", "))));
+    };
+    let step = params.step.as_deref().unwrap_or("1m");
+    let step = humantime::parse_duration(step)
+        .ok()
+        .map(|step| step.as_secs() as i64)
+        .filter(|&secs| secs > 0)
+        .ok_or_else(|| ApiError::bad_request("invalid_step", format!("Invalid step: {}; use e.g. 30s, 1m or 1h", step)))?;
+
+    let to = params.to.as_deref().map(parse_time).transpose()?.unwrap_or_else(Utc::now);
+    let from = params.from.as_deref().map(parse_time).transpose()?.unwrap_or(to - Duration::hours(24));
+    // Whole buckets only, the first one holding `from`
+    let start = from.timestamp().div_euclid(step) * step;
+    let end = to.timestamp();
+    if end <= start {
+        return Err(ApiError::bad_request("invalid_range", "from must be before to"));
+    }
+    if (end - start) / step > MAX_POINTS {
+        return Err(ApiError::bad_request("too_many_points", format!("More than {} points; use a larger step", MAX_POINTS)));
+    }
+
+    let bound = |secs: i64| DateTime::from_timestamp(secs, 0).unwrap_or_default().to_rfc3339();
+    let buckets = db::get_time_buckets(&pool, source, &bound(start), &bound(end), step).await;
+    let mut buckets = buckets.into_iter().peekable();
+    let datapoints = (start..end).step_by(step as usize)
+        .map(|bucket| {
+            let value = buckets.next_if(|&(at, _)| at == bucket).map_or(0, |(_, value)| value);
+            (value, bucket * 1000)
+        })
+        .collect();
+    Ok(Json(vec![Series { target: params.metric, datapoints }]))
+}
@@ -0,0 +1,128 @@
+//! `--trace-uploads`: a record of every request made about an upload, with its timing, the
+//! chunk it carried and how it was answered, for debugging reports like "my upload stalled at
+//! 97%". `GET /uploads/:id/trace` on the admin port returns it as a HAR file.
+use std::cell::Cell;
+use std::time::Instant;
+use axum::{extract::{Extension, Path, Request, State}, http::header, middleware::Next, response::{IntoResponse, Response}, Json};
+use chrono::{SecondsFormat, Utc};
+use serde_json::json;
+use sqlx::SqlitePool;
+use crate::{config::AppConfig, db::{self, TraceEntry}, error::{ApiError, ErrorCode}};
+
+/// What a handler learned about the request while serving it
+#[derive(Clone, Copy, Default)]
+struct Note {
+    upload_id: Option<i64>,
+    chunk_index: Option<u32>,
+    byte_offset: Option<i64>,
+    bytes: Option<i64>,
+}
+
+tokio::task_local! {
+    static NOTE: Cell<Note>;
+}
+
+/// Ties the request being served to upload `upload_id`; a no-op unless it is traced.
+pub fn note(upload_id: i64, chunk_index: Option<u32>, byte_offset: Option<u64>, bytes: Option<usize>) {
+    let _ = NOTE.try_with(|cell| cell.set(Note {
+        upload_id: Some(upload_id),
+        chunk_index,
+        byte_offset: byte_offset.map(|o| o as i64),
+        bytes: bytes.map(|b| b as i64),
+    }));
+}
+
+/// Middleware for the upload routes: with `--trace-uploads`, records each request its handler
+/// tied to an upload with [`note`]. Requests refused before that, e.g. as malformed, aren't.
+pub async fn record(State(pool): State<SqlitePool>, Extension(config): Extension<AppConfig>, request: Request, next: Next) -> Response {
+    if !config.trace_uploads {
+        return next.run(request).await;
+    }
+    let started_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let uri = request.uri().path_and_query().map_or_else(|| request.uri().path().to_string(), |p| p.to_string());
+    let request_bytes = request.headers().get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<i64>().ok());
+
+    let (response, note) = NOTE.scope(Cell::new(Note::default()), async {
+        let response = next.run(request).await;
+        (response, NOTE.with(Cell::get))
+    }).await;
+
+    if let Some(upload_id) = note.upload_id {
+        let entry = TraceEntry {
+            upload_id,
+            method,
+            uri,
+            request_bytes,
+            status: response.status().as_u16(),
+            code: response.extensions().get::<ErrorCode>().map(|code| code.0.to_string()),
+            chunk_index: note.chunk_index,
+            byte_offset: note.byte_offset,
+            bytes: note.bytes,
+            started_at,
+            duration_ms: started.elapsed().as_millis() as i64,
+        };
+        tokio::spawn(async move { db::insert_trace(&pool, &entry).await });
+    }
+    response
+}
+
+/// `GET /uploads/:id/trace`: the upload's recorded requests as a HAR 1.2 log. Fields HAR has
+/// no place for are kept in `_`-prefixed custom fields, e.g. `_chunkIndex` and `_errorCode`.
+pub async fn admin_trace(
+    State(pool): State<SqlitePool>,
+    Extension(config): Extension<AppConfig>,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError> {
+    let upload = db::get_upload(&pool, id).await
+        .ok_or_else(|| ApiError::not_found("upload_not_found", format!("Unknown upload: {}", id)))?;
+    let traces = db::get_traces(&pool, id).await;
+    let base = config.public_url.as_deref().unwrap_or("").trim_end_matches('/');
+    let entries: Vec<serde_json::Value> = traces.iter().map(|t| json!({
+        "startedDateTime": t.started_at,
+        "time": t.duration_ms,
+        "request": {
+            "method": t.method,
+            "url": format!("{}{}", base, t.uri),
+            "httpVersion": "HTTP/1.1",
+            "cookies": [],
+            "headers": [],
+            "queryString": [],
+            "headersSize": -1,
+            "bodySize": t.request_bytes.unwrap_or(-1),
+        },
+        "response": {
+            "status": t.status,
+            "statusText": axum::http::StatusCode::from_u16(t.status).ok().and_then(|s| s.canonical_reason()).unwrap_or(""),
+            "httpVersion": "HTTP/1.1",
+            "cookies": [],
+            "headers": [],
+            "content": { "size": -1, "mimeType": "" },
+            "redirectURL": "",
+            "headersSize": -1,
+            "bodySize": -1,
+        },
+        "cache": {},
+        "timings": { "send": 0, "wait": t.duration_ms, "receive": 0 },
+        "_chunkIndex": t.chunk_index,
+        "_byteOffset": t.byte_offset,
+        "_chunkBytes": t.bytes,
+        "_errorCode": t.code,
+    })).collect();
+    let mut har = json!({
+        "log": {
+            "version": "1.2",
+            "creator": { "name": "drcv", "version": env!("CARGO_PKG_VERSION") },
+            "entries": entries,
+            "_upload": upload,
+        }
+    });
+    if !config.trace_uploads {
+        har["log"]["comment"] = json!("--trace-uploads is off, so requests are not being recorded");
+    }
+    let disposition = format!("attachment; filename=\"drcv-upload-{}.har\"", id);
+    Ok(([(header::CONTENT_DISPOSITION, disposition)], Json(har)).into_response())
+}
@@ -0,0 +1,143 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use once_cell::sync::Lazy;
+use tao::event::{Event, StartCause};
+use tao::event_loop::{ControlFlow, EventLoopBuilder};
+use tokio::sync::{Notify, RwLock};
+use tray_icon::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tray_icon::{Icon, TrayIcon, TrayIconBuilder};
+use log::{error, warn};
+use crate::{apps::admin::TunnelInfo, registry};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Default, PartialEq)]
+struct Status {
+    share_url: Option<String>,
+    active_uploads: usize,
+}
+
+static STATUS: Lazy<Mutex<Status>> = Lazy::new(Default::default);
+static QUIT: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Copies what the tray shows out of the async side once a second.
+pub fn spawn_status_updater(tunnel_info: Arc<RwLock<TunnelInfo>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+        loop {
+            interval.tick().await;
+            let share_url = tunnel_info.read().await.url.clone();
+            *STATUS.lock().unwrap() = Status { share_url, active_uploads: registry::active_count() };
+        }
+    });
+}
+
+/// Resolves when Quit is picked from the tray menu.
+pub async fn quit_requested() {
+    QUIT.notified().await;
+}
+
+struct Tray {
+    _icon: TrayIcon,
+    share: MenuItem,
+    copy: MenuItem,
+    uploads: MenuItem,
+    quit: MenuItem,
+    shown: Option<Status>,
+}
+
+impl Tray {
+    fn build() -> Result<Self, String> {
+        let share = MenuItem::new("Waiting for the share link…", false, None);
+        let copy = MenuItem::new("Copy share link", false, None);
+        let uploads = MenuItem::new("No uploads in progress", false, None);
+        let quit = MenuItem::new("Quit drcv", true, None);
+        let menu = Menu::new();
+        menu.append_items(&[&share, &copy, &PredefinedMenuItem::separator(), &uploads, &PredefinedMenuItem::separator(), &quit])
+            .map_err(|e| e.to_string())?;
+        let icon = TrayIconBuilder::new()
+            .with_menu(Box::new(menu))
+            .with_tooltip("drcv")
+            .with_icon(icon())
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Tray { _icon: icon, share, copy, uploads, quit, shown: None })
+    }
+
+    fn refresh(&mut self) {
+        let status = STATUS.lock().unwrap().clone();
+        if self.shown.as_ref() == Some(&status) {
+            return;
+        }
+        match &status.share_url {
+            Some(url) => self.share.set_text(url),
+            None => self.share.set_text("Waiting for the share link…"),
+        }
+        self.copy.set_enabled(status.share_url.is_some());
+        self.uploads.set_text(match status.active_uploads {
+            0 => "No uploads in progress".to_string(),
+            1 => "1 upload in progress".to_string(),
+            n => format!("{} uploads in progress", n),
+        });
+        self.shown = Some(status);
+    }
+}
+
+/// Runs the tray on the main thread, as macOS requires; never returns. The server runs on
+/// another thread and ends the process when it shuts down.
+pub fn run_event_loop() -> ! {
+    let event_loop = EventLoopBuilder::new().build();
+    let mut tray: Option<Tray> = None;
+    // Kept alive so the copied link outlives the menu click on X11
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| warn!("⚠️ Clipboard unavailable: {}", e)).ok();
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::WaitUntil(Instant::now() + REFRESH_INTERVAL);
+
+        // The icon can only be created once the platform event loop is running
+        if let Event::NewEvents(StartCause::Init) = event {
+            match Tray::build() {
+                Ok(built) => tray = Some(built),
+                Err(e) => error!("❌ Failed to create the tray icon: {}", e),
+            }
+        }
+        let Some(tray) = tray.as_mut() else { return };
+        tray.refresh();
+
+        while let Ok(click) = MenuEvent::receiver().try_recv() {
+            if click.id == *tray.copy.id() {
+                let url = STATUS.lock().unwrap().share_url.clone();
+                if let (Some(url), Some(clipboard)) = (url, clipboard.as_mut()) {
+                    if let Err(e) = clipboard.set_text(url) {
+                        warn!("⚠️ Failed to copy the share link: {}", e);
+                    }
+                }
+            } else if click.id == *tray.quit.id() {
+                tray.quit.set_enabled(false);
+                QUIT.notify_one();
+            }
+        }
+    })
+}
+
+/// A green disc with a white downward arrow, drawn at startup so no image asset is needed.
+fn icon() -> Icon {
+    const SIZE: u32 = 32;
+    let mut rgba = Vec::with_capacity((SIZE * SIZE * 4) as usize);
+    let center = (SIZE as f32 - 1.0) / 2.0;
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let (dx, dy) = (x as f32 - center, y as f32 - center);
+            let inside = dx * dx + dy * dy <= center * center;
+            let shaft = dx.abs() <= 2.0 && (7..=18).contains(&y);
+            let head = (17..=24).contains(&y) && dx.abs() <= (24 - y) as f32;
+            let pixel = match (inside, shaft || head) {
+                (true, true) => [0xff, 0xff, 0xff, 0xff],
+                (true, false) => [0x22, 0xc5, 0x5e, 0xff],
+                _ => [0, 0, 0, 0],
+            };
+            rgba.extend_from_slice(&pixel);
+        }
+    }
+    Icon::from_rgba(rgba, SIZE, SIZE).expect("valid icon dimensions")
+}
@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use byte_unit::{Byte, UnitType};
+use once_cell::sync::Lazy;
+use ratatui::{
+    crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+use sqlx::SqlitePool;
+use tokio::sync::{Notify, RwLock};
+use crate::{admin, apps::admin::TunnelInfo, config::AppConfig, db::{self, UploadData}, events, registry};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// Log lines kept for the log pane.
+const LOG_LINES: usize = 500;
+
+/// What the dashboard shows, refreshed on the async side.
+#[derive(Default)]
+struct View {
+    share_url: Option<String>,
+    hostname: Option<String>,
+    admin_port: u16,
+    uploads: Vec<UploadData>,
+    clients: Vec<serde_json::Value>,
+    stats: Option<db::UploadStats>,
+    active: usize,
+}
+
+static VIEW: Lazy<Mutex<View>> = Lazy::new(Default::default);
+static LOG: Lazy<Mutex<VecDeque<String>>> = Lazy::new(Default::default);
+/// The terminal while the dashboard owns it; taken back by `stop` before drcv exits.
+static TERMINAL: Lazy<Mutex<Option<DefaultTerminal>>> = Lazy::new(Default::default);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static QUIT: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Collects log lines for the log pane while the dashboard is up, and passes them to stderr
+/// before and after, so startup errors and the goodbye still reach the terminal.
+struct LogWriter {
+    pending: Vec<u8>,
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !RUNNING.load(Ordering::Relaxed) {
+            return std::io::stderr().write(buf);
+        }
+        self.pending.extend_from_slice(buf);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let mut log = LOG.lock().unwrap();
+            log.push_back(String::from_utf8_lossy(&line).trim_end().to_string());
+            while log.len() > LOG_LINES {
+                log.pop_front();
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The logger target to use with `--tui`.
+pub fn log_writer() -> Box<dyn Write + Send> {
+    Box::new(LogWriter { pending: Vec::new() })
+}
+
+/// Resolves when `q` or Ctrl-C is pressed in the dashboard.
+pub async fn quit_requested() {
+    QUIT.notified().await;
+}
+
+/// Takes over the terminal and keeps the dashboard current: on every bus event, the same one
+/// the admin SSE stream follows, and once a second for speeds.
+pub fn spawn(pool: SqlitePool, config: AppConfig, tunnel_info: Arc<RwLock<TunnelInfo>>) {
+    *TERMINAL.lock().unwrap() = Some(ratatui::init());
+    RUNNING.store(true, Ordering::Relaxed);
+
+    tokio::spawn(async move {
+        let mut bus = events::subscribe();
+        let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                // Lagging only means several events arrived at once; one refresh covers them
+                event = bus.recv() => if matches!(event, Err(tokio::sync::broadcast::error::RecvError::Closed)) { break },
+            }
+            let (share_url, hostname) = {
+                let info = tunnel_info.read().await;
+                (info.url.clone().or(config.public_url.clone()), info.hostname.clone())
+            };
+            let view = View {
+                share_url,
+                hostname,
+                admin_port: config.admin_port,
+                uploads: admin::list_uploads(&pool, &config, 1, "").await,
+                clients: db::get_connected_clients(&pool).await,
+                stats: Some(db::get_upload_stats(&pool).await),
+                active: registry::active_count(),
+            };
+            *VIEW.lock().unwrap() = view;
+        }
+    });
+
+    std::thread::spawn(|| {
+        while RUNNING.load(Ordering::Relaxed) {
+            if let Some(terminal) = TERMINAL.lock().unwrap().as_mut() {
+                let _ = terminal.draw(draw);
+            }
+            // Also paces the redraws
+            if !event::poll(Duration::from_millis(250)).unwrap_or(false) {
+                continue;
+            }
+            if let Ok(TermEvent::Key(key)) = event::read() {
+                let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.kind == KeyEventKind::Press && (ctrl_c || key.code == KeyCode::Char('q')) {
+                    QUIT.notify_one();
+                }
+            }
+        }
+    });
+}
+
+/// Gives the terminal back; the shutdown log lines print below the last frame.
+pub fn stop() {
+    RUNNING.store(false, Ordering::Relaxed);
+    if TERMINAL.lock().unwrap().take().is_some() {
+        ratatui::restore();
+    }
+}
+
+fn bytes(n: i64) -> String {
+    format!("{:.1}", Byte::from_u64(n.max(0) as u64).get_appropriate_unit(UnitType::Binary))
+}
+
+fn status_color(status: &str) -> Color {
+    match status {
+        "complete" => Color::Green,
+        "uploading" | "assembling" | "received" | "init" => Color::Yellow,
+        "quarantined" | "suspicious" | "abandoned" => Color::Red,
+        _ => Color::DarkGray,
+    }
+}
+
+fn draw(frame: &mut Frame) {
+    let view = VIEW.lock().unwrap();
+    let [header, body, logs] = Layout::vertical([Constraint::Length(3), Constraint::Min(8), Constraint::Length(12)]).areas(frame.area());
+    let [uploads, clients] = Layout::horizontal([Constraint::Percentage(70), Constraint::Percentage(30)]).areas(body);
+
+    let tunnel = match (&view.share_url, &view.hostname) {
+        (Some(url), _) => Span::styled(url.clone(), Style::new().fg(Color::Green)),
+        (None, Some(hostname)) => Span::styled(format!("{} (connecting…)", hostname), Style::new().fg(Color::Yellow)),
+        (None, None) => Span::styled("no share link yet", Style::new().fg(Color::DarkGray)),
+    };
+    let totals = view.stats.as_ref().map(|s| format!("{} uploads, {} complete, {} stored", s.uploads, s.complete, bytes(s.bytes))).unwrap_or_default();
+    let status = Line::from(vec![
+        Span::raw("Share: "), tunnel,
+        Span::raw(format!("  │  {} in progress  │  {}  │  Admin: http://127.0.0.1:{}", view.active, totals, view.admin_port)),
+    ]);
+    frame.render_widget(Paragraph::new(status).block(Block::default().borders(Borders::ALL).title(" drcv — q to quit ")), header);
+
+    let rows = view.uploads.iter().map(|upload| {
+        let size = match upload.declared_size {
+            Some(declared) if declared > 0 => format!("{} / {} ({}%)", bytes(upload.size), bytes(declared), upload.size * 100 / declared),
+            _ => bytes(upload.size),
+        };
+        let speed = upload.speed_bps.filter(|bps| *bps > 0.0).map(|bps| format!("{}/s", bytes(bps as i64))).unwrap_or_default();
+        Row::new(vec![
+            Cell::from(upload.filename.clone()),
+            Cell::from(upload.status.clone()).style(Style::new().fg(status_color(&upload.status))),
+            Cell::from(size),
+            Cell::from(speed),
+            Cell::from(upload.client_ip.clone()),
+        ])
+    });
+    let table = Table::new(rows, [Constraint::Fill(3), Constraint::Length(12), Constraint::Length(26), Constraint::Length(12), Constraint::Fill(1)])
+        .header(Row::new(vec!["File", "Status", "Size", "Speed", "Client"]).style(Style::new().add_modifier(Modifier::BOLD)))
+        .block(Block::default().borders(Borders::ALL).title(" Uploads "));
+    frame.render_widget(table, uploads);
+
+    let client_lines: Vec<Line> = view.clients.iter().map(|client| {
+        let ip = client["client_ip"].as_str().unwrap_or_default();
+        let directive = client["directive"]["action"].as_str().map(|action| format!(" [{}]", action)).unwrap_or_default();
+        Line::from(format!("{}{}", ip, directive))
+    }).collect();
+    frame.render_widget(Paragraph::new(client_lines).block(Block::default().borders(Borders::ALL).title(format!(" Clients ({}) ", view.clients.len()))), clients);
+    drop(view);
+
+    // Only as many of the newest lines as fit inside the borders
+    let shown = logs.height.saturating_sub(2) as usize;
+    let log = LOG.lock().unwrap();
+    let lines: Vec<Line> = log.iter().skip(log.len().saturating_sub(shown)).map(|line| Line::from(line.clone())).collect();
+    frame.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" Log ")), logs);
+}
@@ -0,0 +1,90 @@
+use super::{process, TunnelProvider, TunnelManager, TunnelRunner, TunnelConfig, TunnelError};
+use async_trait::async_trait;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+use sqlx::SqlitePool;
+use log::{debug, info};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Self-hosted relay through `bore local`, for users who run their own `bore server`. The relay
+/// passes raw TCP, so every sender reaches the upload port from localhost and unnamed.
+pub struct BoreTunnelProvider {
+    pub server: String,
+    pub secret: Option<String>,
+    pub remote_port: Option<u16>,
+}
+
+#[async_trait]
+impl TunnelProvider for BoreTunnelProvider {
+    async fn ensure(&self, _pool: &SqlitePool, config: &TunnelConfig) -> Result<Box<dyn TunnelManager>, TunnelError> {
+        let mut cmd = Command::new("bore");
+        cmd.arg("local").arg(config.local_port.to_string()).arg("--to").arg(&self.server);
+        if let Some(port) = self.remote_port {
+            cmd.arg("--port").arg(port.to_string());
+        }
+        if let Some(secret) = &self.secret {
+            // Passed through the environment so it doesn't show up in `ps`
+            cmd.env("BORE_SECRET", secret);
+        }
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| TunnelError::NotInstalled(format!("failed to start bore (cargo install bore-cli): {}", e)))?;
+
+        let mut rx = process::output_lines(&mut child);
+        let listening = tokio::time::timeout(CONNECT_TIMEOUT, async {
+            let mut last = String::new();
+            while let Some(line) = rx.recv().await {
+                if let Some((_, endpoint)) = line.split_once("listening at ") {
+                    return Ok(endpoint.trim().to_string());
+                }
+                last = line;
+            }
+            Err(last)
+        }).await;
+
+        let hostname = match listening {
+            Ok(Ok(endpoint)) => endpoint,
+            Ok(Err(last)) if last.contains("secret") || last.contains("auth") => return Err(TunnelError::AuthError(last)),
+            Ok(Err(last)) => return Err(TunnelError::NetworkError(format!("bore exited: {}", last))),
+            Err(_) => return Err(TunnelError::NetworkError(format!("no response from relay {}", self.server))),
+        };
+        tokio::spawn(async move {
+            while let Some(line) = rx.recv().await {
+                debug!("bore: {}", line);
+            }
+        });
+
+        Ok(Box::new(BoreTunnelManager { hostname, child: Mutex::new(Some(child)) }))
+    }
+}
+
+struct BoreTunnelManager {
+    hostname: String,
+    child: Mutex<Option<Child>>,
+}
+
+#[async_trait]
+impl TunnelManager for BoreTunnelManager {
+    fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    // bore forwards raw TCP, so the relay endpoint serves plain HTTP
+    fn public_url(&self) -> String {
+        format!("http://{}", self.hostname)
+    }
+
+    async fn run(&self) -> Result<Box<dyn TunnelRunner>, TunnelError> {
+        let child = self.child.lock().await.take()
+            .ok_or_else(|| TunnelError::ConfigError("bore tunnel already running".to_string()))?;
+        info!("🔗 Relay tunnel established: {}", self.public_url());
+        Ok(process::supervise(child, "bore", self.hostname.clone()))
+    }
+}
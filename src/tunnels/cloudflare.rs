@@ -1,12 +1,14 @@
-use crate::{db, utils};
-use super::{TunnelProvider, TunnelManager, TunnelRunner, TunnelConfig, TunnelError};
+use crate::{chaos, db, events::{self, Event}, utils};
+use super::{TunnelProvider, TunnelManager, TunnelRunner, TunnelConfig, TunnelError, TunnelStats, TunnelStatsSource};
+use super::cloudflare_api::{AccessChange, CloudflareApi, DnsRecord};
 use async_trait::async_trait;
 use std::path::{PathBuf};
+use std::sync::Arc;
 use std::process::Stdio;
 use tokio::process::Command;
 use rand::{distributions::Alphanumeric, Rng};
 use sqlx::SqlitePool;
-use log::error;
+use log::{error, info, warn};
 
 pub struct CloudflareTunnelProvider;
 
@@ -33,16 +35,130 @@ impl TunnelProvider for CloudflareTunnelProvider {
         }
         let uuid = uuid.ok_or_else(|| TunnelError::ConfigError("Failed to obtain tunnel UUID".to_string()))?;
 
-        route_dns(&tunnel_name, &hostname).await?;
+        let dns_record = match CloudflareApi::from_env() {
+            Some(api) => {
+                let zone_id = api.zone_id(&config.hostname_root).await?;
+                let record = api.upsert_tunnel_cname(&zone_id, &hostname, &uuid).await?;
+                info!("🌐 DNS record for {} managed via Cloudflare API", hostname);
+                if let Some(access) = &config.access {
+                    let account_id = api.account_id(&zone_id).await?;
+                    match api.ensure_access_app(&account_id, &hostname, &access.emails, &access.email_domains).await? {
+                        AccessChange::Created => info!("🔐 Cloudflare Access application created for {}", hostname),
+                        AccessChange::PolicyUpdated => info!("🔐 Cloudflare Access policy for {} updated to the allowed senders", hostname),
+                        AccessChange::Unchanged => info!("🔐 Cloudflare Access application already protects {}", hostname),
+                    }
+                }
+                config.dns.cleanup_on_shutdown.then(|| Arc::new((api, record)))
+            }
+            None => {
+                if config.access.is_some() {
+                    return Err(TunnelError::ConfigError("Cloudflare Access bootstrap requires CLOUDFLARE_API_TOKEN".to_string()));
+                }
+                route_dns(&tunnel_name, &hostname).await?;
+                None
+            }
+        };
         let config_path = write_config(&uuid, &hostname, config.local_port).await?;
+        let metrics_addr = free_local_addr()?;
 
-        Ok(Box::new(CloudflareTunnelManager { hostname, config_path }))
+        Ok(Box::new(CloudflareTunnelManager { hostname, config_path, dns_record, metrics_addr }))
+    }
+
+    async fn rotate(&self, pool: &SqlitePool, config: &TunnelConfig) -> Result<Box<dyn TunnelManager>, TunnelError> {
+        let old_hash = db::kv_get(pool, "cf_hash").await;
+        db::kv_set(pool, "cf_hash", &rand_hash(6)).await;
+
+        let manager = match self.ensure(pool, config).await {
+            Ok(manager) => manager,
+            Err(e) => {
+                if let Some(old_hash) = &old_hash {
+                    db::kv_set(pool, "cf_hash", old_hash).await;
+                }
+                return Err(e);
+            }
+        };
+
+        if let Some(old_hash) = old_hash {
+            let old_hostname = format!("{}.{}", old_hash, config.hostname_root);
+            if let Some(api) = CloudflareApi::from_env() {
+                match api.zone_id(&config.hostname_root).await {
+                    Ok(zone_id) => { let _ = api.delete_cname(&zone_id, &old_hostname).await; }
+                    Err(e) => warn!("⚠️ Could not remove DNS record for {}: {}", old_hostname, e),
+                }
+            }
+            if let Err(e) = delete_tunnel(&format!("drcv-{}", old_hash)).await {
+                warn!("⚠️ Could not delete old tunnel for {}: {}", old_hostname, e);
+            }
+            let _ = tokio::fs::remove_file(config_path_for(&old_hostname)?).await;
+            info!("🗑️ Retired share hostname {}", old_hostname);
+        }
+        Ok(manager)
     }
 }
 
 struct CloudflareTunnelManager {
     hostname: String,
     config_path: PathBuf,
+    dns_record: Option<Arc<(CloudflareApi, DnsRecord)>>,
+    metrics_addr: String,
+}
+
+fn free_local_addr() -> Result<String, TunnelError> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")
+        .map_err(|e| TunnelError::ConfigError(format!("cannot reserve metrics port: {}", e)))?;
+    let addr = listener.local_addr()
+        .map_err(|e| TunnelError::ConfigError(e.to_string()))?;
+    Ok(addr.to_string())
+}
+
+/// Reads cloudflared's Prometheus endpoint (`--metrics`).
+struct CloudflaredMetrics {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[async_trait]
+impl TunnelStatsSource for CloudflaredMetrics {
+    async fn stats(&self) -> Result<TunnelStats, TunnelError> {
+        let body = self.client.get(&self.url).send().await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| TunnelError::NetworkError(format!("cloudflared metrics unavailable: {}", e)))?
+            .text().await
+            .map_err(|e| TunnelError::NetworkError(e.to_string()))?;
+        Ok(parse_metrics(&body))
+    }
+}
+
+fn parse_metrics(body: &str) -> TunnelStats {
+    let mut stats = TunnelStats::default();
+    for line in body.lines().filter(|l| !l.starts_with('#')) {
+        let Some((key, value)) = line.rsplit_once(' ') else { continue };
+        let value = value.parse::<f64>().unwrap_or(0.0) as u64;
+        let (name, labels) = key.split_once('{').unwrap_or((key, ""));
+        match name {
+            "cloudflared_tunnel_total_requests" => stats.total_requests += value,
+            "cloudflared_tunnel_request_errors" => stats.request_errors += value,
+            "cloudflared_tunnel_ha_connections" => stats.active_connections += value,
+            "cloudflared_tunnel_concurrent_requests_per_tunnel" => stats.concurrent_requests += value,
+            "cloudflared_tunnel_server_locations" if value > 0 => {
+                if let Some(location) = label_value(labels, "edge_location") {
+                    if !stats.edge_locations.contains(&location) {
+                        stats.edge_locations.push(location);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    stats
+}
+
+fn label_value(labels: &str, name: &str) -> Option<String> {
+    labels.trim_end_matches('}')
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| k.trim() == name)
+        .map(|(_, v)| v.trim_matches('"').to_string())
 }
 
 #[async_trait]
@@ -51,10 +167,19 @@ impl TunnelManager for CloudflareTunnelManager {
         &self.hostname
     }
 
+    fn stats_source(&self) -> Option<Arc<dyn TunnelStatsSource>> {
+        Some(Arc::new(CloudflaredMetrics {
+            client: reqwest::Client::new(),
+            url: format!("http://{}/metrics", self.metrics_addr),
+        }))
+    }
+
     async fn run(&self) -> Result<Box<dyn TunnelRunner>, TunnelError> {
         let cfg = &self.config_path;
         let mut child = Command::new("cloudflared")
-            .args(["--loglevel", "error", "--transport-loglevel", "error", "tunnel", "--config"]) 
+            .args(["--loglevel", "info", "--transport-loglevel", "warn", "tunnel", "--metrics"])
+            .arg(&self.metrics_addr)
+            .arg("--config")
             .arg(cfg)
             .arg("run")
             .stdout(Stdio::null())
@@ -62,26 +187,61 @@ impl TunnelManager for CloudflareTunnelManager {
             .spawn()
             .map_err(|e| TunnelError::NetworkError(format!("failed to start cloudflared: {}", e)))?;
 
-        let mut child_stderr = child.stderr.take();
-        tokio::spawn(async move {
-            if let Some(mut err) = child_stderr.take() {
-                let _ = tokio::io::copy(&mut err, &mut tokio::io::stderr()).await;
-            }
-        });
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(capture_logs(stderr));
+        }
 
-        Ok(Box::new(CloudflareTunnelRunner { child }))
+        let child = Arc::new(tokio::sync::Mutex::new(child));
+        if chaos::enabled() {
+            let child = Arc::clone(&child);
+            tokio::spawn(async move {
+                chaos::tunnel_kill().await;
+                if child.lock().await.start_kill().is_ok() {
+                    events::publish(Event::TunnelError { message: "cloudflared killed by --chaos".to_string() });
+                }
+            });
+        }
+        Ok(Box::new(CloudflareTunnelRunner { child, dns_record: self.dns_record.clone() }))
     }
 }
 
 struct CloudflareTunnelRunner {
-    child: tokio::process::Child,
+    child: Arc<tokio::sync::Mutex<tokio::process::Child>>,
+    dns_record: Option<Arc<(CloudflareApi, DnsRecord)>>,
 }
 
 #[async_trait]
 impl TunnelRunner for CloudflareTunnelRunner {
     async fn shutdown(mut self: Box<Self>) -> Result<(), TunnelError> {
-        self.child.kill().await
-            .map_err(|e| TunnelError::NetworkError(format!("failed to stop cloudflared: {}", e)))
+        self.child.lock().await.kill().await
+            .map_err(|e| TunnelError::NetworkError(format!("failed to stop cloudflared: {}", e)))?;
+        if let Some(record) = &self.dns_record {
+            let (api, record) = record.as_ref();
+            match api.delete_dns_record(record).await {
+                Ok(()) => info!("🧹 Removed DNS record"),
+                Err(e) => warn!("⚠️ Failed to remove DNS record: {}", e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turns cloudflared log lines into tunnel events; only errors still reach the terminal.
+async fn capture_logs(stderr: tokio::process::ChildStderr) {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let detail = line.split_once(" INF ").or_else(|| line.split_once(" WRN ")).or_else(|| line.split_once(" ERR "))
+            .map(|(_, rest)| rest.trim().to_string())
+            .unwrap_or_else(|| line.trim().to_string());
+        if line.contains(" ERR ") {
+            eprintln!("{}", line);
+            events::publish(Event::TunnelError { message: detail });
+        } else if line.contains("Registered tunnel connection") {
+            events::publish(Event::TunnelConnected { detail });
+        } else if line.contains("Unregistered tunnel connection") || line.contains("Retrying connection") || line.contains("Connection terminated") {
+            events::publish(Event::TunnelReconnecting { detail });
+        }
     }
 }
 
@@ -164,6 +324,19 @@ async fn create_tunnel(name: &str) -> Result<(), TunnelError> {
     Ok(())
 }
 
+async fn delete_tunnel(name: &str) -> Result<(), TunnelError> {
+    let out = Command::new("cloudflared")
+        .args(["tunnel", "delete", "-f", name])
+        .output()
+        .await
+        .map_err(|e| TunnelError::NetworkError(format!("failed to exec cloudflared tunnel delete: {}", e)))?;
+
+    if !out.status.success() {
+        return Err(TunnelError::ConfigError(format!("delete tunnel failed: {}", utils::bytes_to_string(&out.stderr))));
+    }
+    Ok(())
+}
+
 async fn get_tunnel_uuid(name: &str) -> Result<Option<String>, TunnelError> {
     let out = Command::new("cloudflared")
         .args(["tunnel", "list"])
@@ -226,10 +399,14 @@ async fn route_dns(name: &str, hostname: &str) -> Result<(), TunnelError> {
     Ok(())
 }
 
-async fn write_config(uuid: &str, hostname: &str, port: u16) -> Result<PathBuf, TunnelError> {
+fn config_path_for(hostname: &str) -> Result<PathBuf, TunnelError> {
     let home = dirs::home_dir().ok_or_else(|| TunnelError::ConfigError("cannot resolve home directory".to_string()))?;
-    let cfg_dir = home.join(".cloudflared");
-    let cfg_path = cfg_dir.join(format!("config-{}.yml", hostname));
+    Ok(home.join(".cloudflared").join(format!("config-{}.yml", hostname)))
+}
+
+async fn write_config(uuid: &str, hostname: &str, port: u16) -> Result<PathBuf, TunnelError> {
+    let cfg_path = config_path_for(hostname)?;
+    let cfg_dir = cfg_path.parent().map(PathBuf::from).unwrap_or_default();
     let creds = cfg_dir.join(format!("{}.json", uuid));
     let content = format!(
         "tunnel: {uuid}\ncredentials-file: {creds}\n\ningress:\n  - hostname: {host}\n    service: http://localhost:{port}\n  - service: http_status:404\n",
@@ -0,0 +1,196 @@
+use super::TunnelError;
+use serde::Deserialize;
+use serde_json::json;
+
+const API_BASE: &str = "https://api.cloudflare.com/client/v4";
+
+/// Minimal Cloudflare v4 API client, enabled by `CLOUDFLARE_API_TOKEN`.
+pub struct CloudflareApi {
+    client: reqwest::Client,
+    base: String,
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(default)]
+    errors: Vec<ApiMessage>,
+    result: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct ApiMessage {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct IdOnly {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Zone {
+    account: IdOnly,
+}
+
+#[derive(Deserialize)]
+struct AccessApp {
+    id: String,
+    #[serde(default)]
+    domain: String,
+}
+
+#[derive(Deserialize)]
+struct AccessPolicy {
+    id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    include: serde_json::Value,
+}
+
+/// Name of the allow policy drcv manages on its Access application.
+const ACCESS_POLICY_NAME: &str = "drcv allowed senders";
+
+/// What [`CloudflareApi::ensure_access_app`] had to change.
+#[derive(Debug, PartialEq)]
+pub enum AccessChange {
+    /// Application and allow policy created
+    Created,
+    /// The allow policy was missing or allowed other senders, and now matches
+    PolicyUpdated,
+    Unchanged,
+}
+
+pub struct DnsRecord {
+    pub zone_id: String,
+    pub record_id: String,
+}
+
+impl CloudflareApi {
+    pub fn from_env() -> Option<Self> {
+        let token = std::env::var("CLOUDFLARE_API_TOKEN").ok().filter(|t| !t.is_empty())?;
+        Some(Self::with_base(API_BASE, &token))
+    }
+
+    /// A client for the API at `base`, e.g. a stand-in in tests.
+    pub fn with_base(base: &str, token: &str) -> Self {
+        Self { client: reqwest::Client::new(), base: base.trim_end_matches('/').to_string(), token: token.to_string() }
+    }
+
+    async fn call<T: for<'de> Deserialize<'de>>(&self, method: reqwest::Method, path: &str, body: Option<serde_json::Value>) -> Result<T, TunnelError> {
+        let mut request = self.client.request(method, format!("{}{}", self.base, path))
+            .bearer_auth(&self.token);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response: ApiResponse<T> = request.send().await
+            .map_err(|e| TunnelError::NetworkError(format!("Cloudflare API request failed: {}", e)))?
+            .json().await
+            .map_err(|e| TunnelError::NetworkError(format!("Cloudflare API response invalid: {}", e)))?;
+
+        if !response.success {
+            let errors: Vec<String> = response.errors.into_iter().map(|e| e.message).collect();
+            if errors.iter().any(|e| e.to_lowercase().contains("authentication")) {
+                return Err(TunnelError::AuthError(errors.join("; ")));
+            }
+            return Err(TunnelError::ConfigError(format!("Cloudflare API error: {}", errors.join("; "))));
+        }
+        response.result.ok_or_else(|| TunnelError::ConfigError("Cloudflare API returned no result".to_string()))
+    }
+
+    pub async fn zone_id(&self, zone_name: &str) -> Result<String, TunnelError> {
+        if let Ok(id) = std::env::var("CLOUDFLARE_ZONE_ID") {
+            return Ok(id);
+        }
+        let zones: Vec<IdOnly> = self.call(reqwest::Method::GET, &format!("/zones?name={}", zone_name), None).await?;
+        zones.into_iter().next()
+            .map(|z| z.id)
+            .ok_or_else(|| TunnelError::ConfigError(format!("Zone {} not found for this API token", zone_name)))
+    }
+
+    /// Creates or updates the CNAME pointing `hostname` at the tunnel. `<uuid>.cfargotunnel.com`
+    /// only resolves behind Cloudflare's proxy, so the record is always proxied, which in turn
+    /// only takes the automatic TTL.
+    pub async fn upsert_tunnel_cname(&self, zone_id: &str, hostname: &str, tunnel_uuid: &str) -> Result<DnsRecord, TunnelError> {
+        let body = json!({
+            "type": "CNAME",
+            "name": hostname,
+            "content": format!("{}.cfargotunnel.com", tunnel_uuid),
+            "proxied": true,
+            "ttl": 1,
+            "comment": "managed by drcv",
+        });
+
+        let existing: Vec<IdOnly> = self.call(reqwest::Method::GET, &format!("/zones/{}/dns_records?type=CNAME&name={}", zone_id, hostname), None).await?;
+        let record: IdOnly = match existing.into_iter().next() {
+            Some(record) => self.call(reqwest::Method::PUT, &format!("/zones/{}/dns_records/{}", zone_id, record.id), Some(body)).await?,
+            None => self.call(reqwest::Method::POST, &format!("/zones/{}/dns_records", zone_id), Some(body)).await?,
+        };
+        Ok(DnsRecord { zone_id: zone_id.to_string(), record_id: record.id })
+    }
+
+    pub async fn account_id(&self, zone_id: &str) -> Result<String, TunnelError> {
+        if let Ok(id) = std::env::var("CLOUDFLARE_ACCOUNT_ID") {
+            return Ok(id);
+        }
+        let zone: Zone = self.call(reqwest::Method::GET, &format!("/zones/{}", zone_id), None).await?;
+        Ok(zone.account.id)
+    }
+
+    /// Puts a self-hosted Access application in front of `hostname` and brings its allow policy
+    /// in line with `emails` and `email_domains`, creating the policy if an earlier run didn't get
+    /// to it. Called on every start, so changed flags reach Cloudflare.
+    pub async fn ensure_access_app(&self, account_id: &str, hostname: &str, emails: &[String], email_domains: &[String]) -> Result<AccessChange, TunnelError> {
+        let apps_path = format!("/accounts/{}/access/apps", account_id);
+        let apps: Vec<AccessApp> = self.call(reqwest::Method::GET, &apps_path, None).await?;
+        let (app_id, created) = match apps.into_iter().find(|a| a.domain == hostname) {
+            Some(app) => (app.id, false),
+            None => {
+                let app: AccessApp = self.call(reqwest::Method::POST, &apps_path, Some(json!({
+                    "name": format!("drcv {}", hostname),
+                    "domain": hostname,
+                    "type": "self_hosted",
+                    "session_duration": "24h",
+                }))).await?;
+                (app.id, true)
+            }
+        };
+
+        let include = serde_json::Value::Array(emails.iter()
+            .map(|e| json!({ "email": { "email": e } }))
+            .chain(email_domains.iter().map(|d| json!({ "email_domain": { "domain": d } })))
+            .collect());
+        let policy = json!({
+            "name": ACCESS_POLICY_NAME,
+            "decision": "allow",
+            "include": include,
+            "precedence": 1,
+        });
+        let policies_path = format!("{}/{}/policies", apps_path, app_id);
+        let policies: Vec<AccessPolicy> = if created { Vec::new() } else { self.call(reqwest::Method::GET, &policies_path, None).await? };
+        match policies.into_iter().find(|p| p.name == ACCESS_POLICY_NAME) {
+            Some(existing) if existing.include == include => return Ok(AccessChange::Unchanged),
+            Some(existing) => {
+                let _: IdOnly = self.call(reqwest::Method::PUT, &format!("{}/{}", policies_path, existing.id), Some(policy)).await?;
+            }
+            None => {
+                let _: IdOnly = self.call(reqwest::Method::POST, &policies_path, Some(policy)).await?;
+            }
+        }
+        Ok(if created { AccessChange::Created } else { AccessChange::PolicyUpdated })
+    }
+
+    pub async fn delete_cname(&self, zone_id: &str, hostname: &str) -> Result<bool, TunnelError> {
+        let existing: Vec<IdOnly> = self.call(reqwest::Method::GET, &format!("/zones/{}/dns_records?type=CNAME&name={}", zone_id, hostname), None).await?;
+        let Some(record) = existing.into_iter().next() else { return Ok(false) };
+        self.delete_dns_record(&DnsRecord { zone_id: zone_id.to_string(), record_id: record.id }).await?;
+        Ok(true)
+    }
+
+    pub async fn delete_dns_record(&self, record: &DnsRecord) -> Result<(), TunnelError> {
+        let _: IdOnly = self.call(reqwest::Method::DELETE, &format!("/zones/{}/dns_records/{}", record.zone_id, record.record_id), None).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+use super::{TunnelProvider, TunnelManager, TunnelRunner, TunnelConfig, TunnelError};
+use async_trait::async_trait;
+use sqlx::SqlitePool;
+
+/// No tunnel at all: the user already exposes the upload port through their own reverse proxy.
+pub struct ExternalUrlProvider {
+    pub url: String,
+}
+
+#[async_trait]
+impl TunnelProvider for ExternalUrlProvider {
+    async fn ensure(&self, _pool: &SqlitePool, _config: &TunnelConfig) -> Result<Box<dyn TunnelManager>, TunnelError> {
+        let parsed = url::Url::parse(&self.url)
+            .map_err(|e| TunnelError::ConfigError(format!("invalid public URL {}: {}", self.url, e)))?;
+        let host = parsed.host_str()
+            .ok_or_else(|| TunnelError::ConfigError(format!("public URL has no host: {}", self.url)))?;
+        let hostname = match parsed.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        Ok(Box::new(ExternalUrlManager { hostname, url: self.url.trim_end_matches('/').to_string() }))
+    }
+}
+
+struct ExternalUrlManager {
+    hostname: String,
+    url: String,
+}
+
+#[async_trait]
+impl TunnelManager for ExternalUrlManager {
+    fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    fn public_url(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn run(&self) -> Result<Box<dyn TunnelRunner>, TunnelError> {
+        Ok(Box::new(ExternalUrlRunner))
+    }
+}
+
+struct ExternalUrlRunner;
+
+#[async_trait]
+impl TunnelRunner for ExternalUrlRunner {
+    async fn shutdown(self: Box<Self>) -> Result<(), TunnelError> {
+        Ok(())
+    }
+}
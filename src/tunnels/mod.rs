@@ -25,17 +25,55 @@ impl std::error::Error for TunnelError {}
 pub struct TunnelConfig {
     pub hostname_root: String,
     pub local_port: u16,
+    pub dns: DnsOptions,
+    pub access: Option<AccessOptions>,
+}
+
+/// Identity gate placed in front of the share hostname by providers that support it.
+pub struct AccessOptions {
+    pub emails: Vec<String>,
+    pub email_domains: Vec<String>,
+}
+
+/// DNS record behavior, honored by providers that manage records through an API.
+pub struct DnsOptions {
+    pub cleanup_on_shutdown: bool,
 }
 
 #[async_trait]
 pub trait TunnelProvider: Send + Sync {
     async fn ensure(&self, db: &sqlx::SqlitePool, config: &TunnelConfig) -> Result<Box<dyn TunnelManager>, TunnelError>;
+
+    /// Switches to a freshly generated hostname and invalidates the previous one.
+    async fn rotate(&self, _db: &sqlx::SqlitePool, _config: &TunnelConfig) -> Result<Box<dyn TunnelManager>, TunnelError> {
+        Err(TunnelError::ConfigError("This tunnel provider does not support hostname rotation".to_string()))
+    }
+}
+
+#[derive(Default, serde::Serialize)]
+pub struct TunnelStats {
+    pub total_requests: u64,
+    pub request_errors: u64,
+    pub active_connections: u64,
+    pub concurrent_requests: u64,
+    pub edge_locations: Vec<String>,
+}
+
+#[async_trait]
+pub trait TunnelStatsSource: Send + Sync {
+    async fn stats(&self) -> Result<TunnelStats, TunnelError>;
 }
 
 #[async_trait]
 pub trait TunnelManager: Send + Sync {
     fn hostname(&self) -> &str;
+    fn public_url(&self) -> String {
+        format!("https://{}", self.hostname())
+    }
     async fn run(&self) -> Result<Box<dyn TunnelRunner>, TunnelError>;
+    fn stats_source(&self) -> Option<std::sync::Arc<dyn TunnelStatsSource>> {
+        None
+    }
 }
 
 #[async_trait]
@@ -43,13 +81,36 @@ pub trait TunnelRunner: Send + Sync {
     async fn shutdown(self: Box<Self>) -> Result<(), TunnelError>;
 }
 
+pub mod bore;
 pub mod cloudflare;
+pub mod cloudflare_api;
+pub mod external;
+pub mod process;
+pub mod ssh;
+pub mod supervisor;
 
+pub use bore::BoreTunnelProvider;
 pub use cloudflare::CloudflareTunnelProvider;
+pub use external::ExternalUrlProvider;
+pub use ssh::SshTunnelProvider;
+pub use supervisor::TunnelSupervisor;
 
-pub fn create_tunnel_provider(provider_name: &str) -> Result<Box<dyn TunnelProvider>, TunnelError> {
-    match provider_name.to_lowercase().as_str() {
+pub fn create_tunnel_provider(config: &crate::config::AppConfig) -> Result<Box<dyn TunnelProvider>, TunnelError> {
+    if let Some(url) = &config.public_url {
+        return Ok(Box::new(ExternalUrlProvider { url: url.clone() }));
+    }
+    match config.tunnel_provider.to_lowercase().as_str() {
         "cloudflare" => Ok(Box::new(CloudflareTunnelProvider)),
-        _ => Err(TunnelError::ConfigError(format!("Unknown tunnel provider: {}", provider_name)))
+        "ssh" => {
+            let target = config.ssh_target.clone()
+                .ok_or_else(|| TunnelError::ConfigError("--ssh-target is required for the ssh provider".to_string()))?;
+            Ok(Box::new(SshTunnelProvider { target, remote_port: config.ssh_remote_port }))
+        }
+        "bore" => {
+            let server = config.relay_server.clone()
+                .ok_or_else(|| TunnelError::ConfigError("--relay-server is required for the bore provider".to_string()))?;
+            Ok(Box::new(BoreTunnelProvider { server, secret: config.relay_secret.clone(), remote_port: config.relay_port }))
+        }
+        _ => Err(TunnelError::ConfigError(format!("Unknown tunnel provider: {}", config.tunnel_provider)))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,65 @@
+//! Shared plumbing for providers that wrap a long-running tunnel client process.
+use crate::{chaos, events::{self, Event}};
+use super::{TunnelRunner, TunnelError};
+use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Child;
+use tokio::sync::{mpsc, oneshot};
+
+/// Merges the child's stdout and stderr into one stream of lines.
+pub fn output_lines(child: &mut Child) -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    if let Some(stdout) = child.stdout.take() {
+        tokio::spawn(forward_lines(stdout, tx.clone()));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        tokio::spawn(forward_lines(stderr, tx));
+    }
+    rx
+}
+
+async fn forward_lines<R: AsyncRead + Unpin>(reader: R, tx: mpsc::UnboundedSender<String>) {
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if tx.send(line).is_err() {
+            break;
+        }
+    }
+}
+
+/// Keeps the client running until shutdown, reporting an unexpected exit as a tunnel error.
+pub fn supervise(mut child: Child, program: &'static str, hostname: String) -> Box<dyn TunnelRunner> {
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let handle = tokio::spawn(async move {
+        // Keep stdin open so interactive sessions (e.g. ssh) stay alive
+        let _stdin = child.stdin.take();
+        tokio::select! {
+            status = child.wait() => {
+                events::publish(Event::TunnelError { message: format!("{} tunnel to {} exited ({:?})", program, hostname, status) });
+            }
+            _ = stop_rx => {
+                let _ = child.kill().await;
+            }
+            _ = chaos::tunnel_kill() => {
+                let _ = child.kill().await;
+                events::publish(Event::TunnelError { message: format!("{} tunnel to {} killed by --chaos", program, hostname) });
+            }
+        }
+    });
+    Box::new(ProcessRunner { program, stop_tx, handle })
+}
+
+struct ProcessRunner {
+    program: &'static str,
+    stop_tx: oneshot::Sender<()>,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+#[async_trait]
+impl TunnelRunner for ProcessRunner {
+    async fn shutdown(self: Box<Self>) -> Result<(), TunnelError> {
+        let _ = self.stop_tx.send(());
+        self.handle.await
+            .map_err(|e| TunnelError::NetworkError(format!("failed to stop {}: {}", self.program, e)))
+    }
+}
@@ -0,0 +1,100 @@
+use super::{process, TunnelProvider, TunnelManager, TunnelRunner, TunnelConfig, TunnelError};
+use async_trait::async_trait;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+use sqlx::SqlitePool;
+use log::{debug, info, warn};
+
+/// How long to wait for the server to announce a public URL before falling back to the target host.
+const URL_ANNOUNCE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Reverse tunnel over `ssh -R`, for localhost.run-style services or a user's own SSH server.
+pub struct SshTunnelProvider {
+    pub target: String,
+    pub remote_port: u16,
+}
+
+#[async_trait]
+impl TunnelProvider for SshTunnelProvider {
+    async fn ensure(&self, _pool: &SqlitePool, config: &TunnelConfig) -> Result<Box<dyn TunnelManager>, TunnelError> {
+        let mut child = Command::new("ssh")
+            .args(["-T", "-o", "ExitOnForwardFailure=yes", "-o", "ServerAliveInterval=30", "-o", "StrictHostKeyChecking=accept-new", "-R"])
+            .arg(format!("{}:localhost:{}", self.remote_port, config.local_port))
+            .arg(&self.target)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| TunnelError::NotInstalled(format!("failed to start ssh: {}", e)))?;
+
+        let mut rx = process::output_lines(&mut child);
+
+        let announced = tokio::time::timeout(URL_ANNOUNCE_TIMEOUT, async {
+            while let Some(line) = rx.recv().await {
+                if let Some(hostname) = extract_public_host(&line) {
+                    return Some(hostname);
+                }
+            }
+            None
+        }).await;
+
+        let (hostname, url) = match announced {
+            Ok(Some(hostname)) => (hostname.clone(), format!("https://{}", hostname)),
+            Ok(None) => {
+                let status = child.wait().await.ok();
+                return Err(TunnelError::NetworkError(format!("ssh exited before the tunnel was established ({:?})", status)));
+            }
+            Err(_) => {
+                // Plain SSH servers don't announce anything; the forwarded port on the target host is the endpoint
+                let host = self.target.rsplit('@').next().unwrap_or(&self.target);
+                warn!("⚠️ {} did not announce a public URL, assuming {}:{}", self.target, host, self.remote_port);
+                let hostname = if self.remote_port == 80 { host.to_string() } else { format!("{}:{}", host, self.remote_port) };
+                (hostname.clone(), format!("http://{}", hostname))
+            }
+        };
+        tokio::spawn(async move {
+            while let Some(line) = rx.recv().await {
+                debug!("ssh: {}", line);
+            }
+        });
+
+        Ok(Box::new(SshTunnelManager { hostname, url, child: Mutex::new(Some(child)) }))
+    }
+}
+
+/// Picks the host out of the first `https://` URL on a line, e.g. localhost.run's
+/// "abc123.lhr.life tunneled with tls termination, https://abc123.lhr.life".
+fn extract_public_host(line: &str) -> Option<String> {
+    line.split_whitespace()
+        .filter_map(|word| word.strip_prefix("https://"))
+        .map(|rest| rest.trim_end_matches(|c: char| !c.is_alphanumeric()).split('/').next().unwrap_or_default())
+        .find(|host| host.contains('.'))
+        .map(str::to_string)
+}
+
+struct SshTunnelManager {
+    hostname: String,
+    url: String,
+    child: Mutex<Option<Child>>,
+}
+
+#[async_trait]
+impl TunnelManager for SshTunnelManager {
+    fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    fn public_url(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn run(&self) -> Result<Box<dyn TunnelRunner>, TunnelError> {
+        let child = self.child.lock().await.take()
+            .ok_or_else(|| TunnelError::ConfigError("ssh tunnel already running".to_string()))?;
+        info!("🔗 SSH tunnel established: {}", self.url);
+        Ok(process::supervise(child, "ssh", self.hostname.clone()))
+    }
+}
@@ -0,0 +1,77 @@
+use super::{TunnelProvider, TunnelManager, TunnelRunner, TunnelConfig, TunnelError};
+use crate::{apps::admin::TunnelInfo, events::{self, Event}};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+use log::{info, warn};
+
+/// Owns the running tunnel so it can be restarted (e.g. on hostname rotation) and stopped on shutdown.
+pub struct TunnelSupervisor {
+    provider: Box<dyn TunnelProvider>,
+    pool: SqlitePool,
+    config: TunnelConfig,
+    info: Arc<RwLock<TunnelInfo>>,
+    runner: Mutex<Option<Box<dyn TunnelRunner>>>,
+}
+
+impl TunnelSupervisor {
+    pub fn new(provider: Box<dyn TunnelProvider>, pool: SqlitePool, config: TunnelConfig, info: Arc<RwLock<TunnelInfo>>) -> Self {
+        Self { provider, pool, config, info, runner: Mutex::new(None) }
+    }
+
+    pub async fn start(&self) -> Result<(), TunnelError> {
+        let manager = self.provider.ensure(&self.pool, &self.config).await?;
+        self.launch(manager).await
+    }
+
+    async fn launch(&self, manager: Box<dyn TunnelManager>) -> Result<(), TunnelError> {
+        let hostname = manager.hostname().to_string();
+        {
+            let mut info = self.info.write().await;
+            info.hostname = Some(hostname.clone());
+            info.url = Some(manager.public_url());
+            info.stats = manager.stats_source();
+        }
+
+        let runner = manager.run().await?;
+        *self.runner.lock().await = Some(runner);
+        events::publish(Event::TunnelUp { hostname });
+        Ok(())
+    }
+
+    /// Retires the current share hostname and brings the tunnel up under a new one.
+    pub async fn rotate(&self) -> Result<String, TunnelError> {
+        self.stop().await;
+        let manager = match self.provider.rotate(&self.pool, &self.config).await {
+            Ok(manager) => manager,
+            Err(e) => {
+                // Bring the previous hostname back up rather than leaving the share offline
+                if let Err(restart) = self.start().await {
+                    warn!("⚠️  Failed to restart tunnel: {}", restart);
+                }
+                return Err(e);
+            }
+        };
+        self.launch(manager).await?;
+        let info = self.info.read().await;
+        let hostname = info.hostname.clone().unwrap_or_default();
+        info!("🔁 Share hostname rotated: {}", info.url.as_deref().unwrap_or_default());
+        Ok(hostname)
+    }
+
+    pub async fn stop(&self) {
+        let Some(runner) = self.runner.lock().await.take() else { return };
+        if let Err(e) = runner.shutdown().await {
+            warn!("⚠️  Failed to stop tunnel: {}", e);
+        }
+        let hostname = {
+            let mut info = self.info.write().await;
+            info.stats = None;
+            info.url = None;
+            info.hostname.take()
+        };
+        if let Some(hostname) = hostname {
+            events::publish(Event::TunnelDown { hostname });
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! `drcv self-update` and `--update-check`: looks up the latest GitHub release and, to update,
+//! downloads the build for this platform, checks it against the release's `SHA256SUMS` and
+//! puts it in place of the running executable. Release builds carry the public key the sums
+//! are signed with (`DRCV_RELEASE_KEY` at build time) and also require a valid
+//! `SHA256SUMS.sig`. Other builds can only check the sums, which come from the same release
+//! and so prove nothing about who published it; they refuse to update without `--insecure`.
+use std::path::PathBuf;
+use std::time::Duration;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use log::{debug, info};
+use crate::config::{AppConfig, SelfUpdateArgs};
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/lqez/drcv/releases/latest";
+const CURRENT: &str = env!("CARGO_PKG_VERSION");
+/// Base64 ed25519 public key, set when building a release
+const RELEASE_KEY: Option<&str> = option_env!("DRCV_RELEASE_KEY");
+const SUMS: &str = "SHA256SUMS";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+
+    fn is_newer(&self) -> bool {
+        version(&self.tag_name) > version(CURRENT)
+    }
+}
+
+/// The release asset built for this platform, e.g. `drcv-x86_64-linux` or
+/// `drcv-x86_64-windows.exe`.
+fn asset_name() -> String {
+    format!("drcv-{}-{}{}", std::env::consts::ARCH, std::env::consts::OS, std::env::consts::EXE_SUFFIX)
+}
+
+/// `[1, 2, 3]` for `v1.2.3`; a pre-release suffix such as `-rc.1` is ignored.
+fn version(tag: &str) -> Vec<u64> {
+    tag.trim_start_matches('v')
+        .split(['-', '+']).next().unwrap_or_default()
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        // GitHub refuses API requests without one
+        .user_agent(concat!("drcv/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(Duration::from_secs(10))
+        .build()
+        .expect("HTTP client")
+}
+
+async fn latest(client: &reqwest::Client) -> Result<Release, String> {
+    client.get(LATEST_RELEASE).send().await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Failed to look up the latest release: {}", e))?
+        .json().await
+        .map_err(|e| format!("Unexpected answer from GitHub: {}", e))
+}
+
+async fn download(client: &reqwest::Client, asset: &Asset) -> Result<Vec<u8>, String> {
+    let response = client.get(&asset.browser_download_url).send().await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Failed to download {}: {}", asset.name, e))?;
+    let body = response.bytes().await.map_err(|e| format!("Failed to download {}: {}", asset.name, e))?;
+    Ok(body.to_vec())
+}
+
+/// Logs a line at startup when a newer release is out; with `--update-check`.
+pub fn spawn_check(config: &AppConfig) {
+    if !config.update_check {
+        return;
+    }
+    tokio::spawn(async {
+        match latest(&client()).await {
+            Ok(release) if release.is_newer() => {
+                info!("⬆️ drcv {} is available (this is {}); run `drcv self-update` or see {}", release.tag_name, CURRENT, release.html_url);
+            }
+            Ok(_) => debug!("drcv {} is the latest release", CURRENT),
+            Err(e) => debug!("Update check failed: {}", e),
+        }
+    });
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("❌ {}", message);
+    std::process::exit(1);
+}
+
+/// `drcv self-update [--check] [--force] [--insecure]`.
+pub async fn run(args: &SelfUpdateArgs) {
+    let client = client();
+    let release = latest(&client).await.unwrap_or_else(|e| fail(e));
+    if !release.is_newer() && !args.force {
+        println!("✅ drcv {} is the latest release", CURRENT);
+        return;
+    }
+    println!("⬆️ drcv {} is available (this is {})", release.tag_name, CURRENT);
+    if args.check {
+        println!("   Run `drcv self-update` to install it, or see {}", release.html_url);
+        return;
+    }
+
+    if RELEASE_KEY.is_none() && !args.insecure {
+        fail(format!("This build has no release key to verify {} with; download it from {}, \
+            or pass --insecure to trust its checksums alone", release.tag_name, release.html_url));
+    }
+    let name = asset_name();
+    let (Some(binary), Some(sums)) = (release.asset(&name), release.asset(SUMS)) else {
+        fail(format!("Release {} has no {} with checksums; download it from {}", release.tag_name, name, release.html_url));
+    };
+    let sums = download(&client, sums).await.unwrap_or_else(|e| fail(e));
+    match RELEASE_KEY {
+        Some(key) => {
+            let sig_name = format!("{}.sig", SUMS);
+            let signature = match release.asset(&sig_name) {
+                Some(asset) => download(&client, asset).await.unwrap_or_else(|e| fail(e)),
+                None => fail(format!("Release {} isn't signed ({} is missing)", release.tag_name, sig_name)),
+            };
+            verify_signature(key, &sums, &signature).unwrap_or_else(|e| fail(format!("{} of release {}: {}", sig_name, release.tag_name, e)));
+        }
+        None => println!("⚠️  --insecure: this build has no release key, so only the checksum is verified"),
+    }
+    let expected = String::from_utf8_lossy(&sums).lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, file)| file.trim().trim_start_matches('*') == name)
+        .map(|(hash, _)| hash.to_lowercase())
+        .unwrap_or_else(|| fail(format!("{} of release {} has no entry for {}", SUMS, release.tag_name, name)));
+
+    println!("⬇️  Downloading {}", name);
+    let bytes = download(&client, binary).await.unwrap_or_else(|e| fail(e));
+    if format!("{:x}", Sha256::digest(&bytes)) != expected {
+        fail(format!("{} doesn't match its checksum; nothing was changed", name));
+    }
+    let exe = replace_executable(&bytes).unwrap_or_else(|e| fail(format!("Failed to replace the drcv executable: {} (is its directory writable?)", e)));
+    println!("✅ Updated {} to {}", exe.display(), release.tag_name);
+    println!("   Restart drcv to run it (`drcv service stop` and `start` for a service)");
+}
+
+fn verify_signature(key: &str, message: &[u8], signature: &[u8]) -> Result<(), String> {
+    let key: [u8; 32] = BASE64.decode(key).ok().and_then(|k| k.try_into().ok()).ok_or("invalid release key in this build")?;
+    let key = VerifyingKey::from_bytes(&key).map_err(|_| "invalid release key in this build")?;
+    let signature = BASE64.decode(String::from_utf8_lossy(signature).trim()).map_err(|_| "not a base64 signature")?;
+    let signature = Signature::from_slice(&signature).map_err(|_| "not an ed25519 signature")?;
+    key.verify(message, &signature).map_err(|_| "signature doesn't match".to_string())
+}
+
+/// Writes the new build next to the running executable and renames it into place, so the
+/// executable is never half-written. Windows can't replace a running executable, but can
+/// rename it, so the old one is left as `drcv.old.exe`.
+fn replace_executable(bytes: &[u8]) -> std::io::Result<PathBuf> {
+    let exe = std::env::current_exe()?;
+    let staged = exe.with_file_name(format!("drcv.new{}", std::env::consts::EXE_SUFFIX));
+    std::fs::write(&staged, bytes)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))?;
+    }
+    #[cfg(windows)]
+    {
+        let old = exe.with_file_name("drcv.old.exe");
+        let _ = std::fs::remove_file(&old);
+        std::fs::rename(&exe, &old)?;
+    }
+    std::fs::rename(&staged, &exe).inspect_err(|_| { let _ = std::fs::remove_file(&staged); })?;
+    Ok(exe)
+}
@@ -1,42 +1,40 @@
-use axum::{extract::{State, ConnectInfo, Query, Extension}, response::IntoResponse, http::{HeaderMap, StatusCode}, Json};
+use axum::{extract::{State, ConnectInfo, Query, Extension, FromRequest, Request}, response::{IntoResponse, Redirect, Response, Sse, sse::{Event as SseEvent, KeepAlive}}, http::{HeaderMap, StatusCode}, Json};
 use axum_typed_multipart::{TryFromMultipart, TypedMultipart, FieldData};
-use sqlx::{SqlitePool, Row};
-use std::{fs, path::PathBuf, net::SocketAddr, collections::HashMap};
-use tokio::io::AsyncWriteExt;
-use serde::Deserialize;
+use sqlx::SqlitePool;
+use std::{fs, net::SocketAddr, collections::HashMap};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use serde::{Deserialize, Serialize};
 use log::{info, warn, debug};
-use crate::{db, config::AppConfig, utils};
+use crate::{assembly, backoff, chunkmap, coalescer, compression::WireBytes, db, direct, config::AppConfig, db::UploadLink, error::ApiError, events::{self, Event}, fairness, finalize, handles, limits, links, net::extract_client_ip, page, policy, pressure, protocol::{self, Protocol}, registry, routing, sessions, trace, window};
 
-fn extract_client_ip(headers: &HeaderMap, addr: &SocketAddr) -> String {
-    let peer_ip = addr.ip();
-    // Only trust proxy headers when the peer is a trusted proxy (loopback = cloudflared local)
-    let trust_headers = peer_ip.is_loopback();
+/// When several instances share the database, an unfinished upload must keep
+/// hitting the instance holding its `.part` file.
+async fn check_upload_affinity(pool: &SqlitePool, config: &AppConfig, filename: &str, client_ip: &str, path_and_query: &str) -> Option<Response> {
+    if config.chunk_spool_dir.is_some() {
+        return None;
+    }
+    let my_id = config.instance_id.as_deref()?;
+    let owner = db::get_upload_owner(pool, filename, client_ip).await?;
+    if owner.instance_id == my_id {
+        return None;
+    }
 
-    if trust_headers {
-        // 1) CF-Connecting-IP (Cloudflare)
-        if let Some(v) = headers.get("cf-connecting-ip").and_then(|v| v.to_str().ok()) {
-            let v = v.trim();
-            if !v.is_empty() { return v.to_string(); }
-        }
-        // 2) True-Client-IP (some proxies)
-        if let Some(v) = headers.get("true-client-ip").and_then(|v| v.to_str().ok()) {
-            let v = v.trim();
-            if !v.is_empty() { return v.to_string(); }
-        }
-        // 3) X-Forwarded-For: take the left-most entry
-        if let Some(v) = headers.get("x-forwarded-for").and_then(|v| v.to_str().ok()) {
-            let first = v.split(',').next().map(|s| s.trim()).unwrap_or("");
-            if !first.is_empty() { return first.to_string(); }
+    let cutoff = (chrono::Utc::now() - chrono::Duration::seconds(config.instance_stale_timeout)).to_rfc3339();
+    let alive = owner.last_seen.as_deref().is_some_and(|seen| seen > cutoff.as_str());
+    match owner.internal_url {
+        Some(url) if alive => {
+            debug!("↪️ Redirecting {} to owning instance {}", filename, owner.instance_id);
+            Some(Redirect::temporary(&format!("{}{}", url, path_and_query)).into_response())
         }
-        // 4) X-Real-IP
-        if let Some(v) = headers.get("x-real-ip").and_then(|v| v.to_str().ok()) {
-            let v = v.trim();
-            if !v.is_empty() { return v.to_string(); }
+        _ => {
+            warn!("⚠️ Owning instance {} for {} is unavailable", owner.instance_id, filename);
+            Some(backoff::retry_later(
+                "instance_unavailable",
+                format!("Instance {} holding this upload is unavailable", owner.instance_id),
+                config.instance_stale_timeout.max(0) as u64,
+            ).into_response())
         }
     }
-
-    // Fallback to the direct peer address
-    peer_ip.to_string()
 }
 
 #[derive(Deserialize)]
@@ -44,36 +42,355 @@ pub struct HeartbeatRequest {
     pub upload_ids: Vec<i64>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HeartbeatAction {
+    #[default]
+    Continue,
+    SlowDown,
+    Pause,
+}
+
+/// Instruction returned to a sender with each heartbeat, set per client by the admin.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ClientDirective {
+    pub action: HeartbeatAction,
+    pub backoff_seconds: Option<u64>,
+    pub chunk_size: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct HeartbeatResponse {
+    pub status: &'static str,
+    pub updated: u64,
+    #[serde(flatten)]
+    pub directive: ClientDirective,
+}
+
+/// Text fields are capped well above what `limits` accepts, so an oversized one is refused
+/// while parsing instead of being buffered whole.
 #[derive(TryFromMultipart)]
 pub struct ChunkUploadRequest {
+    #[form_data(limit = "1KiB")]
     pub filename: String,
     pub chunk_index: u32,
     pub total_chunks: u32,
+    /// Size of the whole file; missing from pages cached before it was sent
+    pub file_size: Option<u64>,
+    /// Where to remind the sender if the upload stalls
+    #[form_data(limit = "1KiB")]
+    pub sender_email: Option<String>,
+    #[form_data(limit = "1KiB")]
+    pub sender_name: Option<String>,
+    /// The file's modification time in milliseconds since the epoch, as in `File.lastModified`
+    pub last_modified: Option<i64>,
+    /// SHA-256 of the whole file, checked before the final chunk completes the upload
+    #[form_data(limit = "1KiB")]
+    pub sha256: Option<String>,
     #[form_data(limit = "8GiB")]
     pub chunk: FieldData<bytes::Bytes>,
 }
 
+/// Protocol v2 chunk: placed by byte offset into a file of declared size.
+#[derive(TryFromMultipart)]
+pub struct ChunkUploadRequestV2 {
+    pub upload_id: Option<i64>,
+    #[form_data(limit = "1KiB")]
+    pub filename: String,
+    pub size: u64,
+    pub offset: u64,
+    #[form_data(limit = "1KiB")]
+    pub chunk_sha256: Option<String>,
+    #[form_data(limit = "1KiB")]
+    pub sender_email: Option<String>,
+    #[form_data(limit = "1KiB")]
+    pub sender_name: Option<String>,
+    pub last_modified: Option<i64>,
+    #[form_data(limit = "1KiB")]
+    pub sha256: Option<String>,
+    #[form_data(limit = "8GiB")]
+    pub chunk: FieldData<bytes::Bytes>,
+}
+
+/// Protocol v1 response: what the server holds after the chunk, so a client can tell at once
+/// when that isn't what it sent (a truncated partial, or chunks further on received already).
+#[derive(Serialize)]
+pub struct ChunkReceipt {
+    pub upload_id: i64,
+    /// End of the unbroken run of bytes from the start of the file
+    pub committed_bytes: i64,
+    /// The chunk after that run
+    pub next_expected_index: u32,
+}
+
+/// A v1 chunk `--strict-chunks` refuses, with the chunk the server expects instead.
+#[derive(Serialize)]
+pub struct SequenceViolation {
+    pub rule: &'static str,
+    #[serde(skip)]
+    pub message: String,
+    pub next_expected_index: u32,
+    pub total_chunks: u32,
+}
+
+/// A `chunk_sequence` error with the rest in `details`.
+impl IntoResponse for SequenceViolation {
+    fn into_response(self) -> Response {
+        ApiError::conflict("chunk_sequence", self.message.clone()).with_details(self).into_response()
+    }
+}
+
+/// Protocol v2 response: where the next chunk must start.
+#[derive(Serialize)]
+pub struct ChunkAck {
+    pub upload_id: i64,
+    pub offset: u64,
+    pub complete: bool,
+}
+
 pub async fn handle_chunk_upload(
     State(pool): State<SqlitePool>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Extension(config): Extension<AppConfig>,
     headers: HeaderMap,
-    TypedMultipart(upload_data): TypedMultipart<ChunkUploadRequest>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+    wire_bytes: Option<Extension<WireBytes>>,
+    request: Request,
+) -> Result<Response, ApiError> {
+    let version = protocol::requested(&headers, &config)?;
     let client_ip = extract_client_ip(&headers, &addr);
     let user_agent = headers.get("user-agent").and_then(|v| v.to_str().ok());
-    db::update_client_heartbeat(&pool, &client_ip, user_agent).await;
-    let client_ip_clone = client_ip.clone();
+    db::update_client_heartbeat(&pool, &client_ip, user_agent, config.instance_id.as_deref(), Some("http")).await;
+    // A sender naming the file in the query is sent to the owning instance before its chunk is
+    // read; otherwise that's only known from the body
+    let path_and_query = request.uri().path_and_query().map(|p| p.as_str().to_string()).unwrap_or_else(|| "/upload".to_string());
+    let query_filename = request.uri().query()
+        .and_then(|q| url::form_urlencoded::parse(q.as_bytes()).find(|(key, _)| key == "filename"))
+        .map(|(_, name)| name.into_owned());
+    if let Some(filename) = &query_filename {
+        if let Some(redirect) = check_upload_affinity(&pool, &config, filename, &client_ip, &path_and_query).await {
+            return Ok(redirect);
+        }
+    }
+    // Held until the chunk is written, including reading its body
+    let _slot = fairness::acquire(&client_ip).await;
     let upload_timeout = config.upload_timeout;
-    let upload_future = process_chunk_upload(pool.clone(), config, upload_data, client_ip_clone);
-    
-    match tokio::time::timeout(upload_timeout, upload_future).await {
+    let wire_bytes = wire_bytes.map(|Extension(WireBytes(n))| n);
+    let origin = headers.get("origin").and_then(|v| v.to_str().ok()).map(str::to_string);
+    let link = links::from_headers(&pool, &headers).await?;
+
+    let result = match version {
+        Protocol::V1 => {
+            let upload_data = match TypedMultipart::<ChunkUploadRequest>::from_request(request, &pool).await {
+                Ok(TypedMultipart(upload_data)) => upload_data,
+                Err(rejection) => return Err(rejection.into()),
+            };
+            limits::chunk_v1(&upload_data)?;
+            let admission = match admit(&pool, &config, &upload_data.filename, upload_data.sender_name.as_deref(), upload_data.sender_email.as_deref(), upload_data.file_size, &client_ip, link, &path_and_query).await {
+                Ok(admission) => admission,
+                Err(response) => return Ok(response),
+            };
+            let upload_future = process_chunk_upload(pool.clone(), config, upload_data, client_ip, origin, wire_bytes, admission);
+            tokio::time::timeout(upload_timeout, upload_future).await
+                .map(|result| result.map(IntoResponse::into_response))
+        }
+        Protocol::V2 => {
+            let upload_data = match TypedMultipart::<ChunkUploadRequestV2>::from_request(request, &pool).await {
+                Ok(TypedMultipart(upload_data)) => upload_data,
+                Err(rejection) => return Err(rejection.into()),
+            };
+            limits::chunk_v2(&upload_data)?;
+            let admission = match admit(&pool, &config, &upload_data.filename, upload_data.sender_name.as_deref(), upload_data.sender_email.as_deref(), Some(upload_data.size), &client_ip, link, &path_and_query).await {
+                Ok(admission) => admission,
+                Err(response) => return Ok(response),
+            };
+            let upload_future = process_chunk_upload_v2(pool.clone(), config, upload_data, client_ip, origin, wire_bytes, admission);
+            tokio::time::timeout(upload_timeout, upload_future).await
+        }
+    };
+
+    match result {
         Ok(result) => result,
         Err(_) => {
             warn!("⚠️ Upload timeout - client may have disconnected");
-            Err((StatusCode::REQUEST_TIMEOUT, "Upload timeout".to_string()))
+            Err(ApiError::new(StatusCode::REQUEST_TIMEOUT, "upload_timeout", "Upload timeout"))
+        }
+    }
+}
+
+/// What a new upload was admitted with, to record once it has an id.
+#[derive(Default)]
+struct Admission {
+    /// Notes from `upload_init` plugins
+    note: Option<String>,
+    route: routing::Route,
+    /// The upload link it was sent through
+    link: Option<i64>,
+}
+
+impl Admission {
+    async fn record(&self, pool: &SqlitePool, id: i64) {
+        if let Some(note) = &self.note {
+            db::add_annotation(pool, id, note).await;
+        }
+        if self.route.dir.is_some() || !self.route.tags.is_empty() {
+            db::set_route(pool, id, self.route.dir.as_deref(), &self.route.tags).await;
+        }
+        if let Some(link_id) = self.link {
+            db::set_link(pool, id, link_id).await;
+        }
+    }
+}
+
+/// Checks every chunk passes before it's read into an upload, whatever the protocol. A new
+/// upload is routed by `--route-script` and seen by `upload_init` plugins on the way, and one
+/// sent through an upload link is held to the link's limits.
+#[allow(clippy::too_many_arguments)]
+async fn admit(
+    pool: &SqlitePool,
+    config: &AppConfig,
+    filename: &str,
+    sender_name: Option<&str>,
+    sender_email: Option<&str>,
+    size: Option<u64>,
+    client_ip: &str,
+    link: Option<UploadLink>,
+    path_and_query: &str,
+) -> Result<Admission, Response> {
+    if let Some(redirect) = check_upload_affinity(pool, config, filename, client_ip, path_and_query).await {
+        return Err(redirect);
+    }
+    if db::find_unfinished_upload(pool, filename, client_ip).await.is_some() {
+        return Ok(Admission::default());
+    }
+    if window::is_closed() {
+        return Err(ApiError::new(StatusCode::FORBIDDEN, "drop_closed", "This drop is closed and no longer accepts new uploads").into_response());
+    }
+    if window::is_paused() {
+        return Err(backoff::retry_later("drop_paused", "This drop is paused, please retry later".to_string(), config.pressure_retry_after).into_response());
+    }
+    // Under resource pressure only uploads already in progress may continue
+    if let Some(reason) = pressure::current() {
+        return Err(backoff::busy(config, format!("Server is busy ({}), please retry later", reason)).into_response());
+    }
+    if let Err(violation) = policy::check(pool, config, filename, sender_name, client_ip).await {
+        info!("🚫 Refused {} from {}: {}", filename, client_ip, violation.message);
+        return Err(violation.into_response());
+    }
+    if let Some(link) = &link {
+        if let Err(e) = links::check(pool, link, filename, size).await {
+            info!("🚫 Refused {} from {} through link {}: {}", filename, client_ip, link.id, e.message);
+            return Err(e.into_response());
+        }
+    }
+    let route = match &config.route_script {
+        Some(script) => script.route(&routing::Upload { filename, sender_name, sender_email, client_ip, size }).map_err(|violation| {
+            info!("🚫 Refused {} from {}: {}", filename, client_ip, violation.message);
+            violation.into_response()
+        })?,
+        None => routing::Route::default(),
+    };
+    #[cfg(feature = "plugins")]
+    let note = crate::plugins::on_upload_init(filename, sender_name, sender_email, client_ip).await.map_err(IntoResponse::into_response)?;
+    #[cfg(not(feature = "plugins"))]
+    let note = None;
+    Ok(Admission { note, route, link: link.map(|link| link.id) })
+}
+
+/// What a sender said about an upload besides its data.
+struct Declared<'a> {
+    size: Option<u64>,
+    sender_name: Option<&'a str>,
+    sender_email: Option<&'a str>,
+    /// Milliseconds since the epoch, as browsers give `File.lastModified`
+    last_modified: Option<i64>,
+    /// `Origin` of the page the chunk was sent from
+    origin: Option<&'a str>,
+}
+
+/// Runs on the first chunk of an upload this process sees: records what the sender declared,
+/// reconciles state left behind by an earlier run and announces the upload.
+async fn start_tracking(
+    pool: &SqlitePool,
+    config: &AppConfig,
+    id: i64,
+    client_ip: &str,
+    declared: Declared<'_>,
+) -> Result<(), ApiError> {
+    if registry::is_tracked(id) {
+        return Ok(());
+    }
+    let Some(upload) = db::get_upload(pool, id).await else { return Ok(()) };
+    if upload.status == "received" {
+        return Err(ApiError::conflict("awaiting_finalize", format!("{} is fully received and waiting for POST /upload/finalize", upload.filename)));
+    }
+    if upload.status == "quarantined" {
+        return Err(ApiError::conflict("quarantined", format!("{} is quarantined until --validate-cmd passes it", upload.filename)));
+    }
+    if let Some(declared_size) = declared.size {
+        db::set_declared_size(pool, id, declared_size).await;
+    }
+    if let Some(sender_name) = declared.sender_name.map(str::trim).filter(|n| !n.is_empty()) {
+        db::set_sender_name(pool, id, sender_name).await;
+    }
+    if let Some(sender_email) = declared.sender_email.map(str::trim).filter(|e| !e.is_empty()) {
+        db::set_sender_email(pool, id, sender_email).await;
+    }
+    let last_modified = declared.last_modified
+        .and_then(chrono::DateTime::from_timestamp_millis)
+        .map(|t| t.to_rfc3339());
+    let origin = declared.origin.filter(|o| *o != "null");
+    if last_modified.is_some() || origin.is_some() {
+        db::set_source_metadata(pool, id, last_modified.as_deref(), origin).await;
+    }
+    if let Some(terms) = &config.terms {
+        if let Some(consent_id) = db::find_consent(pool, client_ip, &terms.version).await {
+            db::set_consent(pool, id, consent_id).await;
         }
     }
+    let state = sessions::resume_state(pool, config, &upload).await;
+    if state.received_bytes > 0 {
+        info!("🔄 Resuming upload: {} (from {} bytes)", upload.filename, state.received_bytes);
+    } else {
+        info!("▶️ Starting upload: {}", upload.filename);
+        events::publish(Event::UploadStarted { id, filename: upload.filename.clone(), client_ip: client_ip.to_string() });
+    }
+    registry::track(pool, id).await;
+    Ok(())
+}
+
+/// `--strict-chunks`: `total_chunks` must stay what the first chunk said and `chunk_index`
+/// within it. Chunks may come in any order, except that the final chunk of a spooled upload
+/// must wait until all before it are there.
+async fn check_sequence(pool: &SqlitePool, config: &AppConfig, id: i64, upload_data: &ChunkUploadRequest) -> Result<(), SequenceViolation> {
+    let index = upload_data.chunk_index;
+    let total_chunks = match db::get_total_chunks(pool, id).await {
+        Some(total_chunks) => total_chunks,
+        None => {
+            db::set_total_chunks(pool, id, upload_data.total_chunks).await;
+            upload_data.total_chunks
+        }
+    };
+    let next_expected_index = match &config.chunk_spool_dir {
+        Some(spool_dir) => assembly::first_missing_chunk(spool_dir, id).await,
+        None => chunkmap::contiguous(&db::get_upload_chunks(pool, id).await, registry::size(id).unwrap_or(0)).1.unwrap_or(0),
+    };
+    let violation = |rule, message| Err(SequenceViolation { rule, message, next_expected_index, total_chunks });
+
+    if upload_data.total_chunks != total_chunks {
+        return violation("total_chunks_changed", format!("total_chunks is {} but this upload started with {}", upload_data.total_chunks, total_chunks));
+    }
+    if index >= total_chunks {
+        return violation("index_out_of_range", format!("chunk_index {} is out of range for {} chunks", index, total_chunks));
+    }
+    if config.chunk_spool_dir.is_some() && index + 1 == total_chunks && next_expected_index < index {
+        return violation("gap", format!("Chunk {} arrived before chunk {}", index, next_expected_index));
+    }
+    Ok(())
+}
+
+fn too_large(size: u64, limit: u64) -> ApiError {
+    ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "file_too_large", format!("File too large: {} bytes exceeds limit of {} bytes", size, limit))
+        .with_details(serde_json::json!({ "size": size, "limit": limit }))
 }
 
 async fn process_chunk_upload(
@@ -81,135 +398,677 @@ async fn process_chunk_upload(
     config: AppConfig,
     upload_data: ChunkUploadRequest,
     client_ip: String,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+    origin: Option<String>,
+    wire_bytes: Option<u64>,
+    admission: Admission,
+) -> Result<Response, ApiError> {
     let save_dir = &config.upload_dir;
     fs::create_dir_all(save_dir)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create directory: {}", e)))?;
+        .map_err(|e| ApiError::internal("storage_error", format!("Failed to create directory: {}", e)))?;
 
-    let existing_upload = sqlx::query("SELECT id, size FROM uploads WHERE filename = ?1 AND client_ip = ?2 AND status != 'complete'")
-        .bind(&upload_data.filename)
-        .bind(&client_ip)
-        .fetch_optional(&pool).await
-        .expect("select failed");
-    
-    let id = db::init_upload(&pool, &upload_data.filename, &client_ip).await;
+    let id = db::init_upload(&pool, &upload_data.filename, &client_ip, config.instance_id.as_deref(), "http").await;
+    trace::note(id, Some(upload_data.chunk_index), None, Some(upload_data.chunk.contents.len()));
+    admission.record(&pool, id).await;
     
-    let estimated_file_size = (upload_data.chunk.contents.len() as u64) * (upload_data.total_chunks as u64);
+    let estimated_file_size = upload_data.file_size
+        .unwrap_or((upload_data.chunk.contents.len() as u64) * (upload_data.total_chunks as u64));
     if estimated_file_size > config.max_file_size {
-        return Err((StatusCode::PAYLOAD_TOO_LARGE, format!("File too large: {} bytes exceeds limit of {} bytes", estimated_file_size, config.max_file_size)));
+        return Err(too_large(estimated_file_size, config.max_file_size));
     }
-    
-    let tmp_path = PathBuf::from(save_dir).join(format!("{}.part", upload_data.filename));
-    let mut file = tokio::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&tmp_path)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to open file: {}", e)))?;
 
-    use std::sync::Mutex;
-    use std::collections::HashSet;
-    static LOGGED_UPLOADS: once_cell::sync::Lazy<Mutex<HashSet<i64>>> = once_cell::sync::Lazy::new(|| Mutex::new(HashSet::new()));
-    
-    {
-        let mut logged = LOGGED_UPLOADS.lock().unwrap();
-        if !logged.contains(&id) {
-            logged.insert(id);
-            
-            if let Some(row) = existing_upload {
-                let existing_size: i64 = row.get("size");
-                if existing_size > 0 {
-                    info!("🔄 Resuming upload: {} (from {} bytes, chunk {})", upload_data.filename, existing_size, upload_data.chunk_index);
-                } else {
-                    info!("▶️ Starting upload: {}", upload_data.filename);
-                }
-            } else {
-                info!("▶️ Starting upload: {}", upload_data.filename);
-            }
+    start_tracking(&pool, &config, id, &client_ip, Declared {
+        size: upload_data.file_size,
+        sender_name: upload_data.sender_name.as_deref(),
+        sender_email: upload_data.sender_email.as_deref(),
+        last_modified: upload_data.last_modified,
+        origin: origin.as_deref(),
+    }).await?;
+    if config.strict_chunks {
+        if let Err(violation) = check_sequence(&pool, &config, id, &upload_data).await {
+            warn!("🚫 Refused chunk {} of {} from {}: {}", upload_data.chunk_index, upload_data.filename, client_ip, violation.message);
+            return Ok(violation.into_response());
         }
+    } else {
+        // The latest count, for the missing chunks a HEAD probe reports
+        db::set_total_chunks(&pool, id, upload_data.total_chunks).await;
+    }
+    let Some(in_flight) = db::begin_chunk(&pool, id, config.parallel_chunks, config.upload_timeout.as_secs() as i64).await else {
+        return Err(backoff::retry_later("too_many_parallel_chunks", format!("At most {} chunks of one file may be sent at once", config.parallel_chunks), 1));
+    };
+    let stored = store_chunk_v1(&pool, &config, id, &upload_data, &client_ip, wire_bytes).await;
+    db::end_chunk(&pool, in_flight).await;
+    stored
+}
+
+/// Spools a v1 chunk, or places it in the `.part` file by its index; answers with the bytes
+/// held without a gap from the start and the chunk that comes next.
+async fn store_chunk_v1(
+    pool: &SqlitePool,
+    config: &AppConfig,
+    id: i64,
+    upload_data: &ChunkUploadRequest,
+    client_ip: &str,
+    wire_bytes: Option<u64>,
+) -> Result<Response, ApiError> {
+    let wire_bytes = wire_bytes.unwrap_or(upload_data.chunk.contents.len() as u64) as i64;
+
+    let len = upload_data.chunk.contents.len() as i64;
+
+    if let Some(spool_dir) = &config.chunk_spool_dir {
+        links::check_chunk(pool, id, assembly::spooled_bytes(spool_dir, id).await + len).await?;
+        assembly::spool_chunk(pool, spool_dir, id, upload_data, wire_bytes).await?;
+        return Ok(Json(ChunkReceipt {
+            upload_id: id,
+            committed_bytes: assembly::spooled_bytes(spool_dir, id).await,
+            next_expected_index: assembly::first_missing_chunk(spool_dir, id).await,
+        }).into_response());
+    }
+
+    // Placed against the chunks journaled so far, which another request mustn't change meanwhile
+    let writing = handles::lock(id).await;
+    // A retry of a chunk whose response was lost must not be written twice
+    if db::has_chunk(pool, id, upload_data.chunk_index).await {
+        debug!("Chunk {} of {} already stored", upload_data.chunk_index, upload_data.filename);
+    } else {
+        let chunks = db::get_upload_chunks(pool, id).await;
+        let received = registry::size(id).unwrap_or(0);
+        links::check_chunk(pool, id, received + len).await?;
+        let offset = match chunkmap::place(&chunks, received, upload_data.chunk_index, len, upload_data.total_chunks, upload_data.file_size) {
+            Ok(offset) => offset,
+            Err(violation) => {
+                warn!("🚫 Refused chunk {} of {} from {}: {}", upload_data.chunk_index, upload_data.filename, client_ip, violation.message);
+                return Ok(violation.into_response());
+            }
+        };
+        let mut placed = chunks;
+        placed.push((upload_data.chunk_index, offset, len));
+        placed.sort_by_key(|&(_, offset, _)| offset);
+        let is_final = chunkmap::is_complete(&placed, received + len, upload_data.total_chunks);
+        store_chunk(pool, config, id, &upload_data.filename, Some(upload_data.chunk_index), offset, &upload_data.chunk.contents, wire_bytes, is_final, upload_data.sha256.as_deref()).await?;
+    }
+    drop(writing);
+    // A completed upload is no longer tracked, and its size was flushed on completion
+    let received = match registry::size(id) {
+        Some(size) => size,
+        None => db::get_upload(pool, id).await.map_or(0, |upload| upload.size),
+    };
+    let (committed_bytes, next_expected_index) = chunkmap::contiguous(&db::get_upload_chunks(pool, id).await, received);
+    Ok(Json(ChunkReceipt { upload_id: id, committed_bytes, next_expected_index: next_expected_index.unwrap_or(0) }).into_response())
+}
+
+async fn process_chunk_upload_v2(
+    pool: SqlitePool,
+    config: AppConfig,
+    upload_data: ChunkUploadRequestV2,
+    client_ip: String,
+    origin: Option<String>,
+    wire_bytes: Option<u64>,
+    admission: Admission,
+) -> Result<Response, ApiError> {
+    if upload_data.size > config.max_file_size {
+        return Err(too_large(upload_data.size, config.max_file_size));
+    }
+    fs::create_dir_all(&config.upload_dir)
+        .map_err(|e| ApiError::internal("storage_error", format!("Failed to create directory: {}", e)))?;
+
+    let id = match upload_data.upload_id {
+        Some(id) => match db::get_upload(&pool, id).await {
+            Some(upload) if upload.client_ip == client_ip
+                && upload.filename == upload_data.filename
+                && !upload.is_stored()
+                && upload.status != "abandoned" => id,
+            _ => return Err(ApiError::not_found("upload_not_found", format!("No unfinished upload {}", id))),
+        },
+        None => db::init_upload(&pool, &upload_data.filename, &client_ip, config.instance_id.as_deref(), "http").await,
+    };
+    trace::note(id, None, Some(upload_data.offset), Some(upload_data.chunk.contents.len()));
+    admission.record(&pool, id).await;
+    start_tracking(&pool, &config, id, &client_ip, Declared {
+        size: Some(upload_data.size),
+        sender_name: upload_data.sender_name.as_deref(),
+        sender_email: upload_data.sender_email.as_deref(),
+        last_modified: upload_data.last_modified,
+        origin: origin.as_deref(),
+    }).await?;
+    let _writing = handles::lock(id).await;
+    let received = registry::size(id).unwrap_or(0).max(0) as u64;
+
+    let ack = |offset: u64, complete: bool| {
+        ([(protocol::HEADER, Protocol::V2.number().to_string())], Json(ChunkAck { upload_id: id, offset, complete }))
+    };
+    if upload_data.offset != received {
+        return Ok((StatusCode::CONFLICT, ack(received, false)).into_response());
     }
 
     let chunk_data = &upload_data.chunk.contents;
-    if !chunk_data.is_empty() {
-        file.write_all(chunk_data)
+    let end = upload_data.offset + chunk_data.len() as u64;
+    if end > upload_data.size {
+        return Err(ApiError::bad_request("chunk_past_end", format!("Chunk ends at byte {} past the declared size of {}", end, upload_data.size)));
+    }
+    links::check_chunk(&pool, id, end as i64).await?;
+    if let Some(expected) = &upload_data.chunk_sha256 {
+        use sha2::{Digest, Sha256};
+        let actual = format!("{:x}", Sha256::digest(chunk_data));
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "chunk_hash_mismatch", format!("Chunk hash mismatch: expected {}, got {}", expected, actual))
+                .with_details(serde_json::json!({ "expected": expected, "actual": actual })));
+        }
+    }
+
+    let wire_bytes = wire_bytes.unwrap_or(chunk_data.len() as u64) as i64;
+    let is_final = end == upload_data.size;
+    store_chunk(&pool, &config, id, &upload_data.filename, None, upload_data.offset as i64, chunk_data, wire_bytes, is_final, upload_data.sha256.as_deref()).await?;
+    // Also not complete when held back for the sender's hash, --validate-cmd or a plugin
+    let complete = is_final && db::get_upload(&pool, id).await.is_some_and(|upload| upload.is_stored());
+    Ok(ack(end, complete).into_response())
+}
+
+/// Writes a chunk at `offset` in the upload's `.part` file, or with `--direct-write` its unnamed
+/// file; the final chunk moves the file into place, once it matches `sha256` if the sender sent one.
+#[allow(clippy::too_many_arguments)]
+async fn store_chunk(
+    pool: &SqlitePool,
+    config: &AppConfig,
+    id: i64,
+    filename: &str,
+    chunk_index: Option<u32>,
+    offset: i64,
+    chunk_data: &[u8],
+    wire_bytes: i64,
+    is_final: bool,
+    sha256: Option<&str>,
+) -> Result<(), ApiError> {
+    let save_dir = config.scratch_dir();
+    let tmp_path = sessions::part_path(save_dir, id, filename)
+        .await
+        .map_err(|e| ApiError::internal("storage_error", format!("Failed to create session directory: {}", e)))?;
+    if let Some(direct) = direct::file(config, id, &tmp_path, registry::size(id).unwrap_or(0)) {
+        write_chunk(pool, id, &mut *direct.lock().await, chunk_index, offset, chunk_data, wire_bytes).await?;
+    } else {
+        let mut file = handles::checkout(id, &tmp_path)
             .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write chunk: {}", e)))?;
-        db::mark_uploading(&pool, id, chunk_data.len() as i64).await;
+            .map_err(|e| ApiError::internal("storage_error", format!("Failed to open file: {}", e)))?;
+        // A failed write drops the handle instead of returning it to the pool
+        write_chunk(pool, id, &mut file, chunk_index, offset, chunk_data, wire_bytes).await?;
+        if !is_final {
+            handles::checkin(id, &tmp_path, file, config.max_open_part_files);
+        }
     }
 
-    if upload_data.chunk_index + 1 == upload_data.total_chunks {
-        let final_path = PathBuf::from(save_dir).join(&upload_data.filename);
-        tokio::fs::rename(&tmp_path, &final_path)
+    if !is_final {
+        return Ok(());
+    }
+    if let Some(expected) = sha256 {
+        finalize::verify(pool, config, id, filename, expected).await?;
+    }
+    if config.require_finalize {
+        finalize::await_confirmation(pool, id, filename).await;
+    } else {
+        finalize::complete(pool, config, id, filename)
             .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to finalize file: {}", e)))?;
-        info!("✅ Completed upload: {:?}", final_path);
-        db::mark_complete(&pool, id).await;
+            .map_err(|e| ApiError::internal("storage_error", format!("Failed to finalize file: {}", e)))?;
     }
+    Ok(())
+}
 
-    Ok(id.to_string())
+async fn write_chunk(
+    pool: &SqlitePool,
+    id: i64,
+    file: &mut tokio::fs::File,
+    chunk_index: Option<u32>,
+    offset: i64,
+    chunk_data: &[u8],
+    wire_bytes: i64,
+) -> Result<(), ApiError> {
+    if chunk_data.is_empty() {
+        return Ok(());
+    }
+    file.seek(std::io::SeekFrom::Start(offset as u64))
+        .await
+        .map_err(|e| ApiError::internal("storage_error", format!("Failed to write chunk: {}", e)))?;
+    file.write_all(chunk_data)
+        .await
+        .map_err(|e| ApiError::internal("storage_error", format!("Failed to write chunk: {}", e)))?;
+    file.flush()
+        .await
+        .map_err(|e| ApiError::internal("storage_error", format!("Failed to write chunk: {}", e)))?;
+    db::record_chunk(pool, id, chunk_index, offset, chunk_data.len() as i64).await;
+    registry::record_progress(pool, id, chunk_data.len() as i64, wire_bytes).await;
+    Ok(())
 }
 
 pub async fn handle_upload_head(
     State(pool): State<SqlitePool>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(config): Extension<AppConfig>,
     headers: HeaderMap,
+    uri: axum::http::Uri,
     Query(params): Query<HashMap<String, String>>,
 ) -> impl IntoResponse {
     let filename = params.get("filename").unwrap_or(&"".to_string()).clone();
+    if let Err(error) = limits::filename(&filename) {
+        return error.into_response();
+    }
     let client_ip = extract_client_ip(&headers, &addr);
     let user_agent = headers.get("user-agent").and_then(|v| v.to_str().ok());
-    db::update_client_heartbeat(&pool, &client_ip, user_agent).await;
-    
-    if let Ok(Some(row)) = sqlx::query("SELECT size FROM uploads WHERE filename = ?1 AND client_ip = ?2 AND status != 'complete'")
-        .bind(&filename)
-        .bind(&client_ip)
-        .fetch_optional(&pool).await {
-        
-        let uploaded_bytes: i64 = row.try_get("size").unwrap_or(0);
-        let mut headers = HeaderMap::new();
-        headers.insert("x-uploaded-bytes", uploaded_bytes.to_string().parse().unwrap());
-        return (headers, "").into_response();
+    db::update_client_heartbeat(&pool, &client_ip, user_agent, config.instance_id.as_deref(), None).await;
+    let path_and_query = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/upload");
+    if let Some(redirect) = check_upload_affinity(&pool, &config, &filename, &client_ip, path_and_query).await {
+        return redirect;
     }
     
+    // The resume offset must include chunks whose progress hasn't been flushed yet
+    coalescer::flush(&pool).await;
+    let mut upload = match db::find_unfinished_upload(&pool, &filename, &client_ip).await {
+        Some(id) => db::get_upload(&pool, id).await,
+        None => None,
+    };
+    // A sender proving it has the same file may pick up where another device left off
+    if upload.is_none() {
+        let size = params.get("size").and_then(|s| s.parse::<u64>().ok());
+        if let (Some(size), Some(lead_sha256)) = (size, params.get("lead_sha256")) {
+            upload = sessions::recycle(&pool, &config, &filename, size, lead_sha256, &client_ip).await;
+        }
+    }
+
     let mut headers = HeaderMap::new();
-    headers.insert("x-uploaded-bytes", "0".parse().unwrap());
+    match upload {
+        Some(upload) => {
+            let state = sessions::resume_state(&pool, &config, &upload).await;
+            trace::note(upload.id, state.next_chunk_index, Some(state.received_bytes as u64), None);
+            headers.insert("x-uploaded-bytes", state.received_bytes.to_string().parse().unwrap());
+            // Protocol v2 clients resume by id
+            headers.insert("x-upload-id", upload.id.to_string().parse().unwrap());
+            headers.insert("x-upload-status", upload.status.parse().unwrap());
+            if let Some(next) = state.next_chunk_index {
+                headers.insert("x-next-chunk-index", next.to_string().parse().unwrap());
+            }
+            if let Some(declared_size) = upload.declared_size {
+                headers.insert("x-declared-size", declared_size.to_string().parse().unwrap());
+            }
+            // Chunks may be sent in parallel and arrive out of order, so holes can come before the end
+            if let Some((total_chunks, missing)) = sessions::missing_chunks(&pool, &config, &upload).await {
+                let ranges: Vec<String> = missing.iter()
+                    .map(|&(first, last)| if first == last { first.to_string() } else { format!("{}-{}", first, last) })
+                    .collect();
+                headers.insert("x-total-chunks", total_chunks.to_string().parse().unwrap());
+                headers.insert("x-missing-chunks", ranges.join(",").parse().unwrap());
+            }
+        }
+        None => {
+            headers.insert("x-uploaded-bytes", "0".parse().unwrap());
+        }
+    }
     (headers, "").into_response()
 }
 
+/// Cancels the caller's unfinished upload of `filename` and deletes what was received so far.
+pub async fn handle_upload_cancel(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(config): Extension<AppConfig>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<StatusCode, ApiError> {
+    let filename = params.get("filename").cloned().unwrap_or_default();
+    limits::filename(&filename)?;
+    let client_ip = extract_client_ip(&headers, &addr);
+    let upload = match db::find_unfinished_upload(&pool, &filename, &client_ip).await {
+        Some(id) => db::get_upload(&pool, id).await,
+        None => None,
+    };
+    if let Some(upload) = &upload {
+        trace::note(upload.id, None, None, None);
+    }
+    match upload {
+        Some(upload) if sessions::abandon(&pool, &config, &upload, "canceled").await => Ok(StatusCode::NO_CONTENT),
+        _ => Err(ApiError::not_found("upload_not_found", format!("No unfinished upload of {}", filename))),
+    }
+}
+
+/// Pushed to upload pages when the admin asks for a disconnected upload to be resumed.
+#[derive(Serialize)]
+pub struct ResumeNotice {
+    pub upload_id: i64,
+    pub filename: String,
+    pub size: i64,
+    pub declared_size: Option<i64>,
+}
+
+impl From<db::UploadData> for ResumeNotice {
+    fn from(upload: db::UploadData) -> Self {
+        ResumeNotice { upload_id: upload.id, filename: upload.filename, size: upload.size, declared_size: upload.declared_size }
+    }
+}
+
+/// `GET /notifications`: server-sent `resume` messages for the caller's disconnected uploads,
+/// first the requests still pending, then new ones as the admin makes them; and `page`
+/// messages with the upload page settings, on connecting and whenever the admin changes them.
+pub async fn handle_notifications(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Sse<impl tokio_stream::Stream<Item = Result<SseEvent, std::convert::Infallible>>> {
+    let client_ip = extract_client_ip(&headers, &addr);
+    let (tx, rx) = tokio::sync::mpsc::channel(16);
+    let mut bus = events::subscribe();
+    let mut page_settings = page::subscribe();
+    let page_event = |settings: page::PageSettings| Ok(SseEvent::default().event("page").data(serde_json::to_string(&settings).unwrap()));
+    let resume_event = |notice: ResumeNotice| Ok(SseEvent::default().event("resume").data(serde_json::to_string(&notice).unwrap()));
+
+    tokio::spawn(async move {
+        let settings = page_settings.borrow_and_update().clone();
+        if tx.send(page_event(settings)).await.is_err() {
+            return;
+        }
+        for upload in db::get_resume_requests(&pool, &client_ip).await {
+            if tx.send(resume_event(upload.into())).await.is_err() {
+                return;
+            }
+        }
+        loop {
+            let event = tokio::select! {
+                _ = tx.closed() => return,
+                changed = page_settings.changed() => {
+                    let settings = page_settings.borrow_and_update().clone();
+                    if changed.is_err() || tx.send(page_event(settings)).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                event = bus.recv() => event,
+            };
+            match event {
+                Ok(Event::ResumeRequested { id, client_ip: ip, .. }) if ip == client_ip => {
+                    let Some(upload) = db::get_upload(&pool, id).await else { continue };
+                    if tx.send(resume_event(upload.into())).await.is_err() {
+                        return;
+                    }
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+                _ => {}
+            }
+        }
+    });
+
+    Sse::new(tokio_stream::wrappers::ReceiverStream::new(rx)).keep_alive(KeepAlive::default())
+}
+
 pub async fn handle_heartbeat(
     State(pool): State<SqlitePool>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(config): Extension<AppConfig>,
     headers: HeaderMap,
     Json(request): Json<HeartbeatRequest>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+) -> Result<impl IntoResponse, ApiError> {
     let client_ip = extract_client_ip(&headers, &addr);
-    let now = utils::now();
     
     let user_agent = headers.get("user-agent")
         .and_then(|v| v.to_str().ok());
     
-    db::update_client_heartbeat(&pool, &client_ip, user_agent).await;
+    db::update_client_heartbeat(&pool, &client_ip, user_agent, config.instance_id.as_deref(), None).await;
     
     let mut updated_count = 0;
-    
     for upload_id in request.upload_ids {
-        match sqlx::query(
-            r#"UPDATE uploads 
-               SET updated_at = ?1 
-               WHERE id = ?2 AND client_ip = ?3 AND status = 'uploading'"#)
-            .bind(&now)
-            .bind(upload_id)
-            .bind(&client_ip)
-            .execute(&pool).await {
-            
-            Ok(result) => {
-                updated_count += result.rows_affected();
-            },
-            Err(e) => {
-                debug!("Heartbeat error for upload {}: {}", upload_id, e);
-            }
+        if registry::record_heartbeat(&pool, upload_id, &client_ip).await {
+            updated_count += 1;
         }
     }
     
-    Ok(format!("heartbeat_ok:{}", updated_count))
+    let directive = db::get_client_directive(&pool, &client_ip).await
+        .and_then(|d| serde_json::from_str::<ClientDirective>(&d).ok())
+        .unwrap_or_default();
+
+    Ok(Json(HeartbeatResponse { status: "ok", updated: updated_count, directive }))
+}
+
+/// Version of the tus resumable upload protocol (<https://tus.io>) served at `/files` with `--tus`.
+pub const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination,checksum";
+const TUS_CHECKSUMS: &str = "sha1,sha256";
+
+/// tus clients can't send their file's hash to `POST /upload/finalize`, and append by offset,
+/// which the shared chunk spool doesn't support.
+pub fn tus_available(config: &AppConfig) -> bool {
+    config.tus && !config.require_finalize && config.chunk_spool_dir.is_none()
+}
+
+/// Wraps the tus endpoints: `404` without `--tus`, `412` for requests of another protocol
+/// version, and `Tus-Resumable` on every response.
+pub async fn tus_protocol(Extension(config): Extension<AppConfig>, request: Request, next: axum::middleware::Next) -> Response {
+    if !tus_available(&config) {
+        return ApiError::not_found("tus_unavailable", "This drop doesn't accept tus uploads").into_response();
+    }
+    let version = request.headers().get("tus-resumable").and_then(|v| v.to_str().ok());
+    let mut response = if request.method() != axum::http::Method::OPTIONS && version != Some(TUS_VERSION) {
+        let mut response = ApiError::new(StatusCode::PRECONDITION_FAILED, "unsupported_protocol", format!("Only tus {} is supported", TUS_VERSION)).into_response();
+        response.headers_mut().insert("tus-version", TUS_VERSION.parse().unwrap());
+        response
+    } else {
+        next.run(request).await
+    };
+    response.headers_mut().insert("tus-resumable", TUS_VERSION.parse().unwrap());
+    response
+}
+
+/// `OPTIONS /files`: what this server supports of tus.
+pub async fn handle_tus_options(Extension(config): Extension<AppConfig>) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert("tus-version", TUS_VERSION.parse().unwrap());
+    headers.insert("tus-extension", TUS_EXTENSIONS.parse().unwrap());
+    headers.insert("tus-max-size", config.max_file_size.into());
+    headers.insert("tus-checksum-algorithm", TUS_CHECKSUMS.parse().unwrap());
+    (StatusCode::NO_CONTENT, headers).into_response()
+}
+
+fn tus_header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim)
+}
+
+/// `Upload-Metadata`: comma-separated keys, each with an optional base64 value.
+fn tus_metadata(headers: &HeaderMap) -> Result<HashMap<String, String>, ApiError> {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    let Some(metadata) = tus_header(headers, "upload-metadata") else { return Ok(HashMap::new()) };
+    metadata.split(',').map(str::trim).filter(|pair| !pair.is_empty()).map(|pair| {
+        let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+        let value = BASE64.decode(value.trim()).ok()
+            .and_then(|value| String::from_utf8(value).ok())
+            .ok_or_else(|| ApiError::bad_request("invalid_metadata", format!("Upload-Metadata value of {} isn't base64 UTF-8", key)))?;
+        Ok((key.to_string(), value))
+    }).collect()
+}
+
+/// `POST /files` (tus creation): starts an upload of `Upload-Length` bytes, named by the
+/// `filename` (or `name`) metadata, with optional `sender_name` and `sender_email`.
+pub async fn handle_tus_create(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(config): Extension<AppConfig>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let size = match tus_header(&headers, "upload-length") {
+        Some(length) => length.parse::<u64>().map_err(|_| ApiError::bad_request("invalid_upload_length", format!("Invalid Upload-Length: {}", length)))?,
+        None if headers.contains_key("upload-defer-length") => return Err(ApiError::bad_request("defer_length_unsupported", "Uploads of unknown length aren't supported")),
+        None => return Err(ApiError::bad_request("missing_upload_length", "Upload-Length is required")),
+    };
+    if size > config.max_file_size {
+        return Err(too_large(size, config.max_file_size));
+    }
+    let metadata = tus_metadata(&headers)?;
+    let filename = metadata.get("filename").or_else(|| metadata.get("name")).cloned().unwrap_or_default();
+    limits::filename(&filename)?;
+    let (sender_name, sender_email) = (metadata.get("sender_name").map(String::as_str), metadata.get("sender_email").map(String::as_str));
+    limits::sender(sender_name, sender_email)?;
+
+    let client_ip = extract_client_ip(&headers, &addr);
+    let user_agent = headers.get("user-agent").and_then(|v| v.to_str().ok());
+    db::update_client_heartbeat(&pool, &client_ip, user_agent, config.instance_id.as_deref(), Some("tus")).await;
+    let link = links::from_headers(&pool, &headers).await?;
+    let admission = match admit(&pool, &config, &filename, sender_name, sender_email, Some(size), &client_ip, link, "/files").await {
+        Ok(admission) => admission,
+        Err(response) => return Ok(response),
+    };
+    fs::create_dir_all(&config.upload_dir)
+        .map_err(|e| ApiError::internal("storage_error", format!("Failed to create directory: {}", e)))?;
+    let id = db::init_upload(&pool, &filename, &client_ip, config.instance_id.as_deref(), "tus").await;
+    trace::note(id, None, Some(0), None);
+    admission.record(&pool, id).await;
+    let origin = headers.get("origin").and_then(|v| v.to_str().ok());
+    start_tracking(&pool, &config, id, &client_ip, Declared { size: Some(size), sender_name, sender_email, last_modified: None, origin }).await?;
+    if size == 0 {
+        store_chunk(&pool, &config, id, &filename, None, 0, &[], 0, true, None).await?;
+    }
+    Ok((StatusCode::CREATED, [("location", format!("/files/{}", id))]).into_response())
+}
+
+/// The caller's upload `id`, unless it was abandoned.
+async fn tus_upload(pool: &SqlitePool, id: i64, client_ip: &str) -> Result<db::UploadData, ApiError> {
+    db::get_upload(pool, id).await
+        .filter(|upload| upload.client_ip == client_ip && upload.status != "abandoned")
+        .ok_or_else(|| ApiError::not_found("upload_not_found", format!("No upload {}", id)))
+}
+
+/// `HEAD /files/:id`: how many bytes of the upload are stored.
+pub async fn handle_tus_head(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(config): Extension<AppConfig>,
+    headers: HeaderMap,
+    uri: axum::http::Uri,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Response, ApiError> {
+    let client_ip = extract_client_ip(&headers, &addr);
+    let upload = tus_upload(&pool, id, &client_ip).await?;
+    if let Some(redirect) = check_upload_affinity(&pool, &config, &upload.filename, &client_ip, uri.path()).await {
+        return Ok(redirect);
+    }
+    trace::note(id, None, None, None);
+    let offset = match upload.status.as_str() {
+        "init" | "uploading" | "disconnected" => {
+            coalescer::flush(&pool).await;
+            sessions::resume_state(&pool, &config, &upload).await.received_bytes
+        }
+        _ => upload.size,
+    };
+    let length = upload.declared_size.unwrap_or(upload.size);
+    Ok(([("upload-offset", offset.to_string()), ("upload-length", length.to_string()), ("cache-control", "no-store".to_string())], "").into_response())
+}
+
+/// `Upload-Checksum`: an algorithm and the base64 digest of the request body.
+fn tus_checksum(headers: &HeaderMap) -> Result<Option<(String, Vec<u8>)>, ApiError> {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    let Some(checksum) = tus_header(headers, "upload-checksum") else { return Ok(None) };
+    let invalid = || ApiError::bad_request("invalid_checksum", format!("Invalid Upload-Checksum: {}", checksum));
+    let (algorithm, digest) = checksum.split_once(' ').ok_or_else(invalid)?;
+    if !TUS_CHECKSUMS.split(',').any(|supported| supported == algorithm) {
+        return Err(ApiError::bad_request("unsupported_checksum", format!("Unsupported checksum algorithm {}; supported: {}", algorithm, TUS_CHECKSUMS)));
+    }
+    Ok(Some((algorithm.to_string(), BASE64.decode(digest.trim()).map_err(|_| invalid())?)))
+}
+
+/// `PATCH /files/:id`: appends the body at `Upload-Offset`, which must be where the upload
+/// stands. Without `Upload-Checksum` the body may be the whole rest of the file; it's
+/// written a chunk at a time, and whatever arrived before a connection drops is kept. A body
+/// with a checksum is verified before any of it is written, so it must fit in a chunk.
+pub async fn handle_tus_patch(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(config): Extension<AppConfig>,
+    headers: HeaderMap,
+    uri: axum::http::Uri,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    body: axum::body::Body,
+) -> Result<Response, ApiError> {
+    if tus_header(&headers, "content-type") != Some("application/offset+octet-stream") {
+        return Err(ApiError::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, "invalid_content_type", "Content-Type must be application/offset+octet-stream"));
+    }
+    let offset = tus_header(&headers, "upload-offset").and_then(|v| v.parse::<u64>().ok())
+        .ok_or_else(|| ApiError::bad_request("invalid_offset", "Upload-Offset is required"))?;
+    let checksum = tus_checksum(&headers)?;
+    let client_ip = extract_client_ip(&headers, &addr);
+    let upload = tus_upload(&pool, id, &client_ip).await?;
+    if let Some(redirect) = check_upload_affinity(&pool, &config, &upload.filename, &client_ip, uri.path()).await {
+        return Ok(redirect);
+    }
+    let user_agent = headers.get("user-agent").and_then(|v| v.to_str().ok());
+    db::update_client_heartbeat(&pool, &client_ip, user_agent, config.instance_id.as_deref(), Some("tus")).await;
+    let _slot = fairness::acquire(&client_ip).await;
+    trace::note(id, None, Some(offset), None);
+
+    let length = upload.declared_size.unwrap_or(upload.size) as u64;
+    if upload.is_stored() {
+        return Err(ApiError::conflict("upload_complete", format!("{} is already complete", upload.filename)));
+    }
+    start_tracking(&pool, &config, id, &client_ip, Declared { size: None, sender_name: None, sender_email: None, last_modified: None, origin: None }).await?;
+    let _writing = handles::lock(id).await;
+    let mut received = registry::size(id).unwrap_or(0).max(0) as u64;
+    if offset != received {
+        return Err(ApiError::conflict("offset_mismatch", format!("Upload-Offset is {} but {} bytes are stored", offset, received))
+            .with_details(serde_json::json!({ "offset": received })));
+    }
+
+    let limit = config.chunk_size.saturating_add(1024 * 1024) as usize;
+    let mut stream = body.into_data_stream();
+    let mut pending = Vec::new();
+    let mut failure = None;
+    if let Some((algorithm, expected)) = checksum {
+        let body = axum::body::to_bytes(axum::body::Body::from_stream(stream), limit).await
+            .map_err(|e| ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "body_too_large", format!("A body with Upload-Checksum must fit in a chunk: {}", e)))?;
+        let actual = match algorithm.as_str() {
+            "sha1" => { use sha1::Digest; sha1::Sha1::digest(&body).to_vec() }
+            _ => { use sha2::Digest; sha2::Sha256::digest(&body).to_vec() }
+        };
+        if actual != expected {
+            return Err(ApiError::new(StatusCode::from_u16(460).unwrap(), "checksum_mismatch", format!("The body doesn't match its {} checksum", algorithm)));
+        }
+        pending = body.to_vec();
+    } else {
+        use tokio_stream::StreamExt;
+        loop {
+            let data = match tokio::time::timeout(config.upload_timeout, stream.next()).await {
+                Ok(Some(Ok(data))) => data,
+                Ok(None) => break,
+                Ok(Some(Err(e))) => { failure = Some(format!("Failed to read the body: {}", e)); break }
+                Err(_) => { failure = Some("Upload timeout".to_string()); break }
+            };
+            pending.extend_from_slice(&data);
+            if received + pending.len() as u64 > length {
+                break;
+            }
+            if pending.len() as u64 >= config.chunk_size {
+                store_chunk(&pool, &config, id, &upload.filename, None, received as i64, &pending, pending.len() as i64, false, None).await?;
+                received += pending.len() as u64;
+                pending.clear();
+            }
+        }
+    }
+    if received + pending.len() as u64 > length {
+        return Err(ApiError::bad_request("chunk_past_end", format!("The body goes past the upload's length of {}", length)));
+    }
+    let end = received + pending.len() as u64;
+    if !pending.is_empty() || end == length {
+        store_chunk(&pool, &config, id, &upload.filename, None, received as i64, &pending, pending.len() as i64, end == length, None).await?;
+    }
+    if let Some(failure) = failure {
+        warn!("⚠️ tus upload of {} stopped at {} bytes: {}", upload.filename, end, failure);
+        return Err(ApiError::new(StatusCode::REQUEST_TIMEOUT, "upload_timeout", failure));
+    }
+    Ok((StatusCode::NO_CONTENT, [("upload-offset", end.to_string())]).into_response())
+}
+
+/// `DELETE /files/:id` (tus termination): cancels an unfinished upload like `DELETE /upload`.
+pub async fn handle_tus_terminate(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(config): Extension<AppConfig>,
+    headers: HeaderMap,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let client_ip = extract_client_ip(&headers, &addr);
+    let upload = tus_upload(&pool, id, &client_ip).await?;
+    trace::note(id, None, None, None);
+    if upload.is_stored() || !sessions::abandon(&pool, &config, &upload, "canceled").await {
+        return Err(ApiError::not_found("upload_not_found", format!("No unfinished upload {}", id)));
+    }
+    Ok(StatusCode::NO_CONTENT)
 }
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::{path::Path, process::Stdio, time::Duration};
+use tokio::{io::AsyncWriteExt, process::Command};
+use log::{error, info, warn};
+use crate::{config::AppConfig, db::{self, UploadData}, events::{self, Event}, finalize, sessions, utils};
+
+/// How long a scan may take before the upload counts as rejected.
+const TIMEOUT: Duration = Duration::from_secs(600);
+
+/// What `--validate-cmd` reads as JSON on stdin.
+#[derive(Serialize)]
+struct ValidateRequest<'a> {
+    upload_id: i64,
+    filename: &'a str,
+    path: String,
+    size: i64,
+    sha256: String,
+    client_ip: &'a str,
+    sender_name: Option<&'a str>,
+    sender_email: Option<&'a str>,
+}
+
+/// What it may print on stdout; without it the exit code alone decides.
+#[derive(Deserialize)]
+struct Verdict {
+    verdict: String,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+fn shell(cmd: &str) -> Command {
+    #[cfg(windows)]
+    let mut command = { let mut command = Command::new("cmd"); command.args(["/C", cmd]); command };
+    #[cfg(not(windows))]
+    let mut command = { let mut command = Command::new("sh"); command.args(["-c", cmd]); command };
+    command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true);
+    command
+}
+
+/// Runs the command on a quarantined file; `Err` carries why it didn't pass.
+async fn check(cmd: &str, upload: &UploadData, path: &Path) -> Result<(), String> {
+    let sha256 = utils::sha256_file(&path.display().to_string())
+        .await
+        .map_err(|e| format!("failed to hash the file: {}", e))?;
+    let request = ValidateRequest {
+        upload_id: upload.id,
+        filename: &upload.filename,
+        path: path.display().to_string(),
+        size: tokio::fs::metadata(path).await.map(|m| m.len() as i64).unwrap_or(upload.size),
+        sha256,
+        client_ip: &upload.client_ip,
+        sender_name: upload.sender_name.as_deref(),
+        sender_email: upload.sender_email.as_deref(),
+    };
+
+    let mut child = shell(cmd)
+        .env("DRCV_FILE", path)
+        .env("DRCV_UPLOAD_ID", upload.id.to_string())
+        .spawn()
+        .map_err(|e| format!("failed to run --validate-cmd: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that doesn't read stdin closes it early; that's not a verdict
+        let _ = stdin.write_all(&serde_json::to_vec(&request).unwrap_or_default()).await;
+    }
+    let output = match tokio::time::timeout(TIMEOUT, child.wait_with_output()).await {
+        Ok(output) => output.map_err(|e| format!("failed to run --validate-cmd: {}", e))?,
+        Err(_) => return Err(format!("--validate-cmd gave no verdict within {}", humantime::format_duration(TIMEOUT))),
+    };
+
+    let verdict = serde_json::from_slice::<Verdict>(&output.stdout).ok();
+    let rejected = verdict.as_ref().is_some_and(|v| v.verdict != "pass");
+    if output.status.success() && !rejected {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    let reason = verdict.and_then(|v| v.reason).filter(|r| !r.trim().is_empty())
+        .or_else(|| (!stderr.is_empty()).then_some(stderr))
+        .unwrap_or_else(|| match output.status.code() {
+            Some(code) => format!("--validate-cmd exited with {}", code),
+            None => "--validate-cmd was killed".to_string(),
+        });
+    Err(reason)
+}
+
+/// Vets a quarantined upload with `--validate-cmd`, storing it like any completed upload
+/// when the command passes it and keeping it quarantined with the reason otherwise.
+pub async fn validate(pool: &SqlitePool, config: &AppConfig, id: i64) {
+    let Some(cmd) = &config.validate_cmd else { return };
+    let Some(upload) = db::get_upload(pool, id).await.filter(|upload| upload.status == "quarantined") else { return };
+    let result = match sessions::part_path(config.scratch_dir(), id, &upload.filename).await {
+        Ok(path) => check(cmd, &upload, &path).await,
+        Err(e) => Err(format!("failed to open the session directory: {}", e)),
+    };
+    match result {
+        Ok(()) => {
+            info!("🛡️ --validate-cmd passed {}", upload.filename);
+            if let Err(e) = finalize::store(pool, config, id, &upload.filename).await {
+                error!("Failed to store {} after validation: {}", upload.filename, e);
+            }
+        }
+        Err(reason) => {
+            warn!("🛡️ Quarantined {} from {}: {}", upload.filename, upload.client_ip, reason);
+            db::set_quarantine_reason(pool, id, &reason).await;
+            events::publish(Event::UploadQuarantined { id, filename: upload.filename, client_ip: upload.client_ip, reason });
+        }
+    }
+}
+
+pub fn spawn(pool: SqlitePool, config: AppConfig, id: i64) {
+    tokio::spawn(async move { validate(&pool, &config, id).await });
+}
+
+/// Picks up uploads quarantined by an earlier run that stopped before their verdict.
+pub async fn spawn_pending(pool: &SqlitePool, config: &AppConfig) {
+    if config.validate_cmd.is_none() {
+        return;
+    }
+    for upload in db::get_unvalidated_uploads(pool).await {
+        spawn(pool.clone(), config.clone(), upload.id);
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use notify::{RecursiveMode, Watcher};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+use log::{info, warn};
+use crate::{checksums, config::AppConfig, db::{self, StoredFile}};
+
+/// How long a file's size must stay the same before it counts as copied in.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// `--watch-upload-dir`: gives files that appear in the upload directory out of band an
+/// `external` row, so the admin page lists everything there. Only the top level is watched;
+/// dotfiles (drcv's own `.sessions` and lock) and checksum files are ignored.
+pub fn spawn(pool: SqlitePool, config: &AppConfig) {
+    if !config.watch_upload_dir {
+        return;
+    }
+    let dir = PathBuf::from(&config.upload_dir);
+    let (tx, rx) = mpsc::unbounded_channel();
+    let watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+        if let Ok(event) = result {
+            for path in event.paths {
+                let _ = tx.send(path);
+            }
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => return warn!("⚠️ Cannot watch {}: {}", dir.display(), e),
+    };
+    if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+        return warn!("⚠️ Cannot watch {}: {}", dir.display(), e);
+    }
+    tokio::spawn(async move {
+        // Dropping the watcher stops it
+        let _watcher = watcher;
+        run(pool, dir, rx).await;
+    });
+}
+
+async fn run(pool: SqlitePool, dir: PathBuf, mut rx: mpsc::UnboundedReceiver<PathBuf>) {
+    reconcile(&pool, &dir).await;
+
+    // Files still changing, with their last seen size; scp and Finder write in place
+    let mut pending: HashMap<String, (u64, Instant)> = HashMap::new();
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            Some(path) = rx.recv() => {
+                if let Some(name) = watched_name(&path) {
+                    pending.entry(name).or_insert((u64::MAX, Instant::now()));
+                }
+            }
+            _ = tick.tick() => {
+                let mut settled = Vec::new();
+                for (name, (size, since)) in pending.iter_mut() {
+                    match tokio::fs::metadata(dir.join(name.as_str())).await {
+                        Ok(meta) if meta.is_file() => {
+                            if meta.len() != *size {
+                                *size = meta.len();
+                                *since = Instant::now();
+                            } else if since.elapsed() >= SETTLE_TIME {
+                                settled.push((name.clone(), Some(meta.len())));
+                            }
+                        }
+                        _ => settled.push((name.clone(), None)),
+                    }
+                }
+                for (name, size) in settled {
+                    pending.remove(&name);
+                    match size {
+                        Some(size) => register(&pool, &name, size as i64).await,
+                        None => forget(&pool, &name).await,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The file name drcv would list for a path in the upload directory, if any. Paths are
+/// only compared by name, as some platforms report them canonicalized.
+fn watched_name(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    (!name.starts_with('.') && !name.ends_with(".part") && !checksums::is_checksum_file(name)).then(|| name.to_string())
+}
+
+/// Catches up with changes made while drcv wasn't running.
+async fn reconcile(pool: &SqlitePool, dir: &Path) {
+    for name in db::get_external_files(pool).await {
+        if !dir.join(&name).is_file() {
+            forget(pool, &name).await;
+        }
+    }
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else { return };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Some(name) = watched_name(&entry.path()) else { continue };
+        if let Ok(meta) = entry.metadata().await {
+            if meta.is_file() {
+                register(pool, &name, meta.len() as i64).await;
+            }
+        }
+    }
+}
+
+async fn register(pool: &SqlitePool, name: &str, size: i64) {
+    match db::find_stored_file(pool, name).await {
+        // An upload was stored under this name, possibly over a file copied in earlier
+        Some(StoredFile::Received) => {
+            db::delete_external_file(pool, name).await;
+        }
+        Some(StoredFile::External { id, size: known }) => {
+            if known != size {
+                db::update_external_file(pool, id, size).await;
+            }
+        }
+        None => {
+            db::insert_external_file(pool, name, size).await;
+            info!("📂 Found {} ({} bytes) in the upload directory", name, size);
+        }
+    }
+}
+
+async fn forget(pool: &SqlitePool, name: &str) {
+    if db::delete_external_file(pool, name).await > 0 {
+        info!("📂 {} was removed from the upload directory", name);
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use once_cell::sync::Lazy;
+use tokio::sync::Notify;
+use log::info;
+use crate::{config::AppConfig, events::{self, Event}, registry, tunnels::TunnelSupervisor};
+
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+static CLOSED: AtomicBool = AtomicBool::new(false);
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static EXIT: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// True once `--open-for` has elapsed; only uploads already in progress may continue.
+pub fn is_closed() -> bool {
+    CLOSED.load(Ordering::Relaxed)
+}
+
+/// True while new uploads are paused from the console; unlike a closed window it can reopen.
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Pauses or resumes taking new uploads, returning whether they're now paused.
+pub fn toggle_paused() -> bool {
+    !PAUSED.fetch_xor(true, Ordering::Relaxed)
+}
+
+/// Resolves when the receive window has closed and drained, if `--exit-when-closed` is set or
+/// `--expect-files` can no longer be met.
+pub async fn exit_requested() {
+    EXIT.notified().await;
+}
+
+/// Closes the receive window after `--open-for`, then takes the tunnel down once the
+/// uploads in progress have finished or gone stale.
+pub fn spawn(config: AppConfig, tunnel: Arc<TunnelSupervisor>) {
+    let Some(open_for) = config.open_for else { return };
+    let closes_at = chrono::Local::now() + chrono::Duration::from_std(open_for).unwrap_or_default();
+    info!("⏰ Accepting new uploads for {} (until {})", humantime::format_duration(open_for), closes_at.format("%Y-%m-%d %H:%M"));
+    tokio::spawn(async move {
+        tokio::time::sleep(open_for).await;
+        CLOSED.store(true, Ordering::Relaxed);
+        let in_progress = registry::active_count();
+        info!("⏰ Receive window closed; no new uploads are accepted ({} in progress)", in_progress);
+        events::publish(Event::WindowClosed { in_progress });
+
+        while registry::active_count() > 0 {
+            tokio::time::sleep(DRAIN_CHECK_INTERVAL).await;
+        }
+        tunnel.stop().await;
+        if config.exit_when_closed || config.expect_files.is_some() {
+            EXIT.notify_one();
+        }
+    });
+}
@@ -0,0 +1,72 @@
+//! Who a sender is taken to be: forwarding headers only count from the local cloudflared or a
+//! `--trusted-proxy`, never from a tunnel (ssh, bore) that forwards every sender from localhost.
+use clap::Parser;
+use drcv::{config::Args, testing::TestServer};
+use reqwest::{multipart::{Form, Part}, Client};
+use serde_json::Value;
+
+/// Sends a one-chunk file with `headers` and returns the client it was stored under.
+async fn sender_of(server: &TestServer, filename: &str, headers: &[(&str, &str)]) -> String {
+    let form = Form::new()
+        .text("filename", filename.to_string())
+        .text("chunk_index", "0")
+        .text("total_chunks", "1")
+        .text("file_size", "4")
+        .part("chunk", Part::bytes(b"data".to_vec()).file_name("blob"));
+    let mut request = Client::new().post(server.url("/upload")).multipart(form);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let receipt: Value = request.send().await.unwrap().json().await.unwrap();
+    server.upload(receipt["upload_id"].as_i64().unwrap()).await.unwrap().client_ip
+}
+
+const SPOOFED: [&str; 4] = ["cf-connecting-ip", "true-client-ip", "x-forwarded-for", "x-real-ip"];
+
+#[tokio::test]
+async fn forwarding_headers_are_ignored_behind_the_ssh_tunnel() {
+    let server = TestServer::start_with(&["--tunnel-provider", "ssh", "--ssh-target", "nokey@localhost.run"], |_| {}).await;
+    for (i, header) in SPOOFED.iter().enumerate() {
+        assert_eq!(sender_of(&server, &format!("ssh{}.bin", i), &[(header, "203.0.113.7")]).await, "127.0.0.1", "{}", header);
+    }
+}
+
+#[tokio::test]
+async fn forwarding_headers_are_ignored_behind_the_bore_relay() {
+    let server = TestServer::start_with(&["--tunnel-provider", "bore", "--relay-server", "relay.example.com"], |_| {}).await;
+    for (i, header) in SPOOFED.iter().enumerate() {
+        assert_eq!(sender_of(&server, &format!("bore{}.bin", i), &[(header, "203.0.113.7")]).await, "127.0.0.1", "{}", header);
+    }
+}
+
+#[tokio::test]
+async fn cloudflared_names_the_sender_in_cf_connecting_ip_only() {
+    let server = TestServer::start().await;
+    assert_eq!(sender_of(&server, "cf.bin", &[("cf-connecting-ip", "203.0.113.7")]).await, "203.0.113.7");
+    assert_eq!(sender_of(&server, "xff.bin", &[("x-forwarded-for", "203.0.113.7")]).await, "127.0.0.1");
+}
+
+#[tokio::test]
+async fn a_trusted_proxy_names_the_sender_in_x_forwarded_for() {
+    let server = TestServer::start_with(&["--public-url", "https://drop.example.com", "--trusted-proxy", "127.0.0.1,10.0.0.0/8"], |_| {}).await;
+    // The sender's own entry on the left is skipped along with the trusted hops on the right
+    assert_eq!(sender_of(&server, "hops.bin", &[("x-forwarded-for", "198.51.100.1, 203.0.113.7, 10.0.0.2")]).await, "203.0.113.7");
+    assert_eq!(sender_of(&server, "real.bin", &[("x-real-ip", "203.0.113.8")]).await, "203.0.113.8");
+    assert_eq!(sender_of(&server, "cf.bin", &[("cf-connecting-ip", "203.0.113.9")]).await, "127.0.0.1");
+}
+
+#[test]
+fn address_acls_are_refused_behind_loopback_tunnels() {
+    for provider in ["ssh", "bore"] {
+        let config = |extra: &[&str]| {
+            let mut argv = vec!["drcv", "--tunnel-provider", provider];
+            argv.extend_from_slice(extra);
+            Args::parse_from(argv).try_to_config()
+        };
+        assert!(config(&[]).is_ok());
+        assert!(config(&["--allow-cidr", "10.0.0.0/8"]).is_err(), "{}", provider);
+        assert!(config(&["--deny-cidr", "10.0.0.0/8"]).is_err(), "{}", provider);
+        assert!(config(&["--trusted-proxy", "::1"]).is_err(), "{}", provider);
+        assert!(config(&["--trusted-proxy", "10.0.0.0/8"]).is_ok());
+    }
+}
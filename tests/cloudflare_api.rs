@@ -0,0 +1,97 @@
+//! The Cloudflare Access reconciliation against a stand-in for the few API routes it uses.
+use std::sync::{Arc, Mutex};
+use axum::{extract::{Path, State}, routing::{get, put}, Json, Router};
+use drcv::tunnels::cloudflare_api::{AccessChange, CloudflareApi};
+use serde_json::{json, Value};
+
+#[derive(Default)]
+struct Account {
+    /// (id, domain)
+    apps: Vec<(String, String)>,
+    /// (id, app id, policy as sent)
+    policies: Vec<(String, String, Value)>,
+}
+
+type Shared = Arc<Mutex<Account>>;
+
+fn ok(result: Value) -> Json<Value> {
+    Json(json!({ "success": true, "errors": [], "result": result }))
+}
+
+async fn list_apps(State(account): State<Shared>) -> Json<Value> {
+    let account = account.lock().unwrap();
+    ok(account.apps.iter().map(|(id, domain)| json!({ "id": id, "domain": domain })).collect())
+}
+
+async fn create_app(State(account): State<Shared>, Json(app): Json<Value>) -> Json<Value> {
+    let mut account = account.lock().unwrap();
+    let id = format!("app{}", account.apps.len() + 1);
+    account.apps.push((id.clone(), app["domain"].as_str().unwrap().to_string()));
+    ok(json!({ "id": id, "domain": app["domain"] }))
+}
+
+async fn list_policies(State(account): State<Shared>, Path((_, app)): Path<(String, String)>) -> Json<Value> {
+    let account = account.lock().unwrap();
+    ok(account.policies.iter()
+        .filter(|(_, app_id, _)| *app_id == app)
+        .map(|(id, _, policy)| json!({ "id": id, "name": policy["name"], "include": policy["include"] }))
+        .collect())
+}
+
+async fn create_policy(State(account): State<Shared>, Path((_, app)): Path<(String, String)>, Json(policy): Json<Value>) -> Json<Value> {
+    let mut account = account.lock().unwrap();
+    let id = format!("policy{}", account.policies.len() + 1);
+    account.policies.push((id.clone(), app, policy));
+    ok(json!({ "id": id }))
+}
+
+async fn update_policy(State(account): State<Shared>, Path((_, _, id)): Path<(String, String, String)>, Json(policy): Json<Value>) -> Json<Value> {
+    let mut account = account.lock().unwrap();
+    let existing = account.policies.iter_mut().find(|(policy_id, _, _)| *policy_id == id).unwrap();
+    existing.2 = policy;
+    ok(json!({ "id": id }))
+}
+
+async fn serve(account: Account) -> (CloudflareApi, Shared) {
+    let account = Arc::new(Mutex::new(account));
+    let app = Router::new()
+        .route("/accounts/:account/access/apps", get(list_apps).post(create_app))
+        .route("/accounts/:account/access/apps/:app/policies", get(list_policies).post(create_policy))
+        .route("/accounts/:account/access/apps/:app/policies/:policy", put(update_policy))
+        .with_state(Arc::clone(&account));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    (CloudflareApi::with_base(&base, "token"), account)
+}
+
+fn allowed(account: &Shared) -> Vec<Value> {
+    let account = account.lock().unwrap();
+    account.policies.iter().map(|(_, _, policy)| policy["include"].clone()).collect()
+}
+
+#[tokio::test]
+async fn a_changed_email_list_updates_the_access_policy() {
+    let (api, account) = serve(Account::default()).await;
+    let emails = |list: &[&str]| list.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+
+    let change = api.ensure_access_app("acc", "a1b2c3.drcv.app", &emails(&["ann@example.com"]), &[]).await.unwrap();
+    assert_eq!(change, AccessChange::Created);
+    let change = api.ensure_access_app("acc", "a1b2c3.drcv.app", &emails(&["ann@example.com"]), &[]).await.unwrap();
+    assert_eq!(change, AccessChange::Unchanged);
+
+    let change = api.ensure_access_app("acc", "a1b2c3.drcv.app", &emails(&["bob@example.com"]), &["example.org".to_string()]).await.unwrap();
+    assert_eq!(change, AccessChange::PolicyUpdated);
+    assert_eq!(allowed(&account), [json!([{ "email": { "email": "bob@example.com" } }, { "email_domain": { "domain": "example.org" } }])]);
+    assert_eq!(account.lock().unwrap().apps.len(), 1);
+}
+
+#[tokio::test]
+async fn an_application_left_without_a_policy_gets_one() {
+    // An earlier run created the application but failed before adding its policy
+    let (api, account) = serve(Account { apps: vec![("app1".to_string(), "a1b2c3.drcv.app".to_string())], policies: Vec::new() }).await;
+
+    let change = api.ensure_access_app("acc", "a1b2c3.drcv.app", &["ann@example.com".to_string()], &[]).await.unwrap();
+    assert_eq!(change, AccessChange::PolicyUpdated);
+    assert_eq!(allowed(&account), [json!([{ "email": { "email": "ann@example.com" } }])]);
+}
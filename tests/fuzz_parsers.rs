@@ -0,0 +1,145 @@
+//! Seeded random inputs for the parsers of the public upload endpoints: mangled multipart
+//! bodies for both protocol versions and arbitrary tus headers must get a 4xx, never a 5xx.
+//! A failure prints the seed and input; rerun with `DRCV_FUZZ_SEED=<seed>` to reproduce it.
+use drcv::{limits, testing::TestServer};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use reqwest::Client;
+
+const CASES: usize = 200;
+const BOUNDARY: &str = "fuzzboundary";
+
+fn rng() -> (u64, StdRng) {
+    let seed = std::env::var("DRCV_FUZZ_SEED").ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_else(|| rand::thread_rng().gen());
+    (seed, StdRng::seed_from_u64(seed))
+}
+
+/// A field value that is often almost right: numbers at and past their bounds, hashes of the
+/// wrong length, control characters, long strings and random bytes.
+fn value(rng: &mut StdRng) -> Vec<u8> {
+    match rng.gen_range(0..8) {
+        0 => rng.gen_range(0u64..10).to_string().into_bytes(),
+        1 => rng.gen::<u64>().to_string().into_bytes(),
+        2 => format!("-{}", rng.gen::<u32>()).into_bytes(),
+        3 => b"18446744073709551616".to_vec(),
+        4 => "a".repeat(rng.gen_range(0..2000)).into_bytes(),
+        5 => "f".repeat(rng.gen_range(60..70)).into_bytes(),
+        6 => "\u{0}\r\n\u{7f}name.txt".as_bytes().to_vec(),
+        _ => (0..rng.gen_range(0..64)).map(|_| rng.gen()).collect(),
+    }
+}
+
+fn multipart(fields: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        let filename = if *name == "chunk" { "; filename=\"blob\"" } else { "" };
+        body.extend_from_slice(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"{}\r\n\r\n", BOUNDARY, name, filename).as_bytes());
+        body.extend_from_slice(value);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+    body
+}
+
+/// A well-formed chunk with some fields replaced, dropped or repeated, and now and then the
+/// body itself cut short or with bytes overwritten.
+fn mangled_body(rng: &mut StdRng, fields: &[(&str, &str)]) -> Vec<u8> {
+    let mut parts: Vec<(&str, Vec<u8>)> = Vec::new();
+    for &(name, valid) in fields {
+        match rng.gen_range(0..10) {
+            0 => {}
+            1 => parts.extend([(name, value(rng)), (name, valid.as_bytes().to_vec())]),
+            2..=4 => parts.push((name, value(rng))),
+            _ => parts.push((name, valid.as_bytes().to_vec())),
+        }
+    }
+    parts.shuffle(rng);
+    let mut body = multipart(&parts);
+    match rng.gen_range(0..6) {
+        0 => body.truncate(rng.gen_range(0..=body.len())),
+        1 => for _ in 0..rng.gen_range(1..8) {
+            let at = rng.gen_range(0..body.len());
+            body[at] = rng.gen();
+        },
+        _ => {}
+    }
+    body
+}
+
+async fn assert_no_server_error(seed: u64, request: reqwest::RequestBuilder, input: &dyn std::fmt::Debug) {
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    assert!(status.as_u16() < 500, "seed {}: {} for {:?}: {}", seed, status, input, response.text().await.unwrap_or_default());
+}
+
+#[tokio::test]
+async fn mangled_v1_chunks_are_refused_without_a_server_error() {
+    let server = TestServer::start().await;
+    let (seed, mut rng) = rng();
+    let fields = [
+        ("filename", "fuzz.bin"), ("chunk_index", "0"), ("total_chunks", "1"), ("file_size", "4"),
+        ("sender_name", "Fuzz"), ("sender_email", "fuzz@example.com"), ("last_modified", "1700000000000"),
+        ("sha256", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"), ("chunk", "data"),
+    ];
+    for _ in 0..CASES {
+        let body = mangled_body(&mut rng, &fields);
+        let request = Client::new().post(server.url("/upload"))
+            .header("content-type", format!("multipart/form-data; boundary={}", BOUNDARY))
+            .body(body.clone());
+        assert_no_server_error(seed, request, &String::from_utf8_lossy(&body)).await;
+    }
+}
+
+#[tokio::test]
+async fn mangled_v2_chunks_are_refused_without_a_server_error() {
+    let server = TestServer::start().await;
+    let (seed, mut rng) = rng();
+    let fields = [
+        ("filename", "fuzz2.bin"), ("size", "4"), ("offset", "0"), ("upload_id", "1"),
+        ("chunk_sha256", "3a6eb0790f39ac87c94f3856b2dd2c5d110e6811602261a9a923d3bb23adc8b7"), ("chunk", "data"),
+    ];
+    for _ in 0..CASES {
+        let body = mangled_body(&mut rng, &fields);
+        let request = Client::new().post(server.url("/upload"))
+            .header("x-drcv-protocol", "2")
+            .header("content-type", format!("multipart/form-data; boundary={}", BOUNDARY))
+            .body(body.clone());
+        assert_no_server_error(seed, request, &String::from_utf8_lossy(&body)).await;
+    }
+}
+
+#[tokio::test]
+async fn arbitrary_tus_creation_headers_are_refused_without_a_server_error() {
+    let server = TestServer::start_with(&["--tus"], |_| {}).await;
+    let (seed, mut rng) = rng();
+    let printable = |rng: &mut StdRng, len: usize| -> String { (0..len).map(|_| rng.gen_range(b' '..=b'~') as char).collect() };
+    for _ in 0..CASES {
+        let length = match rng.gen_range(0..4) {
+            0 => rng.gen_range(0u64..100).to_string(),
+            1 => rng.gen::<u64>().to_string(),
+            _ => { let len = rng.gen_range(0..24); printable(&mut rng, len) }
+        };
+        let metadata = (0..rng.gen_range(0..5)).map(|_| {
+            let key = ["filename", "name", "sender_name", "sender_email", "x"].choose(&mut rng).unwrap().to_string();
+            let len = rng.gen_range(0..40);
+            format!("{} {}", key, printable(&mut rng, len))
+        }).collect::<Vec<_>>().join(",");
+        let request = Client::new().post(server.url("/files"))
+            .header("tus-resumable", "1.0.0")
+            .header("upload-length", length.trim())
+            .header("upload-metadata", metadata.trim());
+        assert_no_server_error(seed, request, &(&length, &metadata)).await;
+    }
+}
+
+#[test]
+fn filenames_are_accepted_exactly_when_short_printable_and_not_blank() {
+    let (seed, mut rng) = rng();
+    let alphabet: Vec<char> = "ab. /\\\u{0}\u{1f}\u{7f}\u{85}한글😀\t\n".chars().collect();
+    for _ in 0..CASES * 10 {
+        let name: String = (0..rng.gen_range(0..300)).map(|_| *alphabet.choose(&mut rng).unwrap()).collect();
+        let expected = !name.trim().is_empty() && name.len() <= limits::MAX_FILENAME_BYTES && !name.chars().any(char::is_control);
+        assert_eq!(limits::filename(&name).is_ok(), expected, "seed {}: {:?}", seed, name);
+    }
+}
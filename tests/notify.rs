@@ -0,0 +1,28 @@
+//! Delivery to configured notification targets, against a local webhook receiver.
+use std::sync::{Arc, Mutex};
+use axum::{extract::State, http::Uri, routing::post, Router};
+use clap::Parser;
+use drcv::{config::Args, events::Event, notify};
+
+#[tokio::test]
+async fn one_of_two_targets_of_the_same_kind_is_tested_on_its_own() {
+    let hits = Arc::new(Mutex::new(Vec::new()));
+    let receiver = Router::new()
+        .route("/*path", post(|State(hits): State<Arc<Mutex<Vec<String>>>>, uri: Uri| async move { hits.lock().unwrap().push(uri.path().to_string()) }))
+        .with_state(Arc::clone(&hits));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+    let (first, second) = (format!("json://{}/first", address), format!("json://{}/second", address));
+    let config = Args::parse_from(["drcv", "--notify-url", first.as_str(), "--notify-url", second.as_str()]).to_config();
+    let registry = notify::create_notifiers(&config).unwrap();
+    let indexes: Vec<_> = registry.statuses().iter().map(|s| (s.index, s.name.clone())).collect();
+    assert_eq!(indexes, [(0, "webhook".to_string()), (1, "webhook".to_string())]);
+
+    let results = registry.deliver(&Event::Test { message: "hello".to_string() }, Some(1)).await;
+    assert_eq!(results.iter().map(|r| (r.index, r.ok)).collect::<Vec<_>>(), [(1, true)]);
+    assert_eq!(*hits.lock().unwrap(), ["/second"]);
+    assert_eq!(registry.statuses()[1].stats.delivered, 1);
+    assert_eq!(registry.statuses()[0].stats.delivered, 0);
+}
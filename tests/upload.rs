@@ -0,0 +1,372 @@
+use std::time::Duration;
+use drcv::testing::TestServer;
+use reqwest::{multipart::{Form, Part}, Client, Response};
+use serde_json::Value;
+
+async fn send_chunk(server: &TestServer, filename: &str, index: u32, total: u32, file_size: usize, data: &[u8]) -> Response {
+    let form = Form::new()
+        .text("filename", filename.to_string())
+        .text("chunk_index", index.to_string())
+        .text("total_chunks", total.to_string())
+        .text("file_size", file_size.to_string())
+        .part("chunk", Part::bytes(data.to_vec()).file_name("blob"));
+    Client::new().post(server.url("/upload")).multipart(form).send().await.unwrap()
+}
+
+async fn uploaded_bytes(server: &TestServer, filename: &str) -> u64 {
+    let response = Client::new().head(server.url(&format!("/upload?filename={}", filename))).send().await.unwrap();
+    response.headers()["x-uploaded-bytes"].to_str().unwrap().parse().unwrap()
+}
+
+fn contents(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[tokio::test]
+async fn chunks_are_assembled_into_the_stored_file() {
+    let server = TestServer::start().await;
+    let file = contents(3000);
+    let mut id = 0;
+    for (index, chunk) in file.chunks(1000).enumerate() {
+        let response = send_chunk(&server, "report.bin", index as u32, 3, file.len(), chunk).await;
+        assert!(response.status().is_success());
+        let receipt: Value = response.json().await.unwrap();
+        assert_eq!(receipt["committed_bytes"], (index + 1) * 1000);
+        assert_eq!(receipt["next_expected_index"], index + 1);
+        id = receipt["upload_id"].as_i64().unwrap();
+    }
+
+    assert_eq!(server.upload(id).await.unwrap().status, "complete");
+    assert_eq!(std::fs::read(server.stored_file("report.bin")).unwrap(), file);
+}
+
+#[tokio::test]
+async fn an_interrupted_upload_resumes_where_it_stopped() {
+    let server = TestServer::start().await;
+    let file = contents(3000);
+    send_chunk(&server, "video.bin", 0, 3, file.len(), &file[..1000]).await;
+    assert_eq!(uploaded_bytes(&server, "video.bin").await, 1000);
+
+    // A retry of a stored chunk isn't appended twice
+    send_chunk(&server, "video.bin", 0, 3, file.len(), &file[..1000]).await;
+    assert_eq!(uploaded_bytes(&server, "video.bin").await, 1000);
+
+    send_chunk(&server, "video.bin", 1, 3, file.len(), &file[1000..2000]).await;
+    send_chunk(&server, "video.bin", 2, 3, file.len(), &file[2000..]).await;
+    assert_eq!(std::fs::read(server.stored_file("video.bin")).unwrap(), file);
+}
+
+#[tokio::test]
+async fn a_silent_upload_is_disconnected_and_can_resume() {
+    let server = TestServer::start_with(&[], |config| {
+        config.upload_stale_timeout = 1;
+        config.cleanup_interval = Duration::from_millis(200);
+    }).await;
+    let file = contents(2000);
+    let receipt: Value = send_chunk(&server, "photo.bin", 0, 2, file.len(), &file[..1000]).await.json().await.unwrap();
+    let id = receipt["upload_id"].as_i64().unwrap();
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    assert_eq!(server.upload(id).await.unwrap().status, "disconnected");
+
+    assert_eq!(uploaded_bytes(&server, "photo.bin").await, 1000);
+    send_chunk(&server, "photo.bin", 1, 2, file.len(), &file[1000..]).await;
+    assert_eq!(server.upload(id).await.unwrap().status, "complete");
+    assert_eq!(std::fs::read(server.stored_file("photo.bin")).unwrap(), file);
+}
+
+#[tokio::test]
+async fn a_canceled_upload_leaves_nothing_behind() {
+    let server = TestServer::start().await;
+    let receipt: Value = send_chunk(&server, "draft.bin", 0, 2, 2000, &contents(1000)).await.json().await.unwrap();
+    let id = receipt["upload_id"].as_i64().unwrap();
+
+    let response = Client::new().delete(server.url("/upload?filename=draft.bin")).send().await.unwrap();
+    assert_eq!(response.status(), 204);
+    assert_eq!(server.upload(id).await.unwrap().status, "abandoned");
+    assert_eq!(uploaded_bytes(&server, "draft.bin").await, 0);
+}
+
+#[tokio::test]
+async fn malformed_chunks_get_a_coded_error() {
+    let server = TestServer::start().await;
+    let response = send_chunk(&server, "notes.txt", 4, 2, 2000, &contents(1000)).await;
+    assert_eq!(response.status(), 400);
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["code"], "invalid_chunk_index");
+    assert_eq!(error["retryable"], false);
+}
+
+#[tokio::test]
+async fn traced_uploads_record_each_request() {
+    let server = TestServer::start_with(&["--trace-uploads"], |_| {}).await;
+    let file = contents(2000);
+    let receipt: Value = send_chunk(&server, "trace.bin", 0, 2, file.len(), &file[..1000]).await.json().await.unwrap();
+    let id = receipt["upload_id"].as_i64().unwrap();
+    assert_eq!(uploaded_bytes(&server, "trace.bin").await, 1000);
+    send_chunk(&server, "trace.bin", 1, 2, file.len(), &file[1000..]).await;
+
+    // Entries are written after the response goes out
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let traces = drcv::db::get_traces(&server.pool, id).await;
+    let requests: Vec<_> = traces.iter().map(|t| (t.method.as_str(), t.status, t.chunk_index, t.byte_offset)).collect();
+    assert_eq!(requests, [("POST", 200, Some(0), None), ("HEAD", 200, Some(1), Some(1000)), ("POST", 200, Some(1), None)]);
+}
+
+#[tokio::test]
+async fn health_probes_bypass_the_allow_list() {
+    let server = TestServer::start_with(&["--allow-cidr", "10.0.0.0/8"], |_| {}).await;
+    let health = reqwest::get(server.url("/healthz")).await.unwrap();
+    assert_eq!(health.status(), 200);
+    assert_eq!(health.json::<Value>().await.unwrap()["status"], "ok");
+    assert_eq!(reqwest::get(server.url("/capabilities")).await.unwrap().status(), 403);
+}
+
+#[tokio::test]
+async fn an_executable_named_like_a_document_is_marked_suspicious() {
+    let server = TestServer::start_with(&["--check-file-types"], |_| {}).await;
+    let elf = [b"\x7fELF\x02\x01\x01".as_slice(), &contents(500)].concat();
+    let receipt: Value = send_chunk(&server, "invoice.pdf", 0, 1, elf.len(), &elf).await.json().await.unwrap();
+    let upload = server.upload(receipt["upload_id"].as_i64().unwrap()).await.unwrap();
+    assert_eq!(upload.status, "suspicious");
+    assert_eq!(upload.suspicious_reason.as_deref(), Some("content is a Linux executable (ELF), not a .pdf"));
+    assert_eq!(std::fs::read(server.stored_file("invoice.pdf")).unwrap(), elf);
+
+    let pdf = [b"%PDF-1.7\n".as_slice(), &contents(500)].concat();
+    let receipt: Value = send_chunk(&server, "report.pdf", 0, 1, pdf.len(), &pdf).await.json().await.unwrap();
+    assert_eq!(server.upload(receipt["upload_id"].as_i64().unwrap()).await.unwrap().status, "complete");
+}
+
+#[tokio::test]
+async fn a_tus_client_uploads_in_two_patches() {
+    let server = TestServer::start_with(&["--tus"], |_| {}).await;
+    let client = Client::new();
+    let file = contents(3000);
+    let response = client.post(server.url("/files"))
+        .header("tus-resumable", "1.0.0")
+        .header("upload-length", file.len())
+        .header("upload-metadata", "filename bm90ZXMuYmlu") // notes.bin
+        .send().await.unwrap();
+    assert_eq!(response.status(), 201);
+    let location = server.url(response.headers()["location"].to_str().unwrap());
+
+    let patch = |offset: usize, data: &[u8]| client.patch(&location)
+        .header("tus-resumable", "1.0.0")
+        .header("upload-offset", offset)
+        .header("content-type", "application/offset+octet-stream")
+        .body(data.to_vec())
+        .send();
+    assert_eq!(patch(0, &file[..1200]).await.unwrap().headers()["upload-offset"], "1200");
+    // A retry from a stale offset is refused rather than appended
+    assert_eq!(patch(0, &file[..1200]).await.unwrap().status(), 409);
+    let head = client.head(&location).header("tus-resumable", "1.0.0").send().await.unwrap();
+    assert_eq!(head.headers()["upload-offset"], "1200");
+
+    assert_eq!(patch(1200, &file[1200..]).await.unwrap().status(), 204);
+    assert_eq!(std::fs::read(server.stored_file("notes.bin")).unwrap(), file);
+    let id = location.rsplit('/').next().unwrap().parse().unwrap();
+    assert_eq!(server.upload(id).await.unwrap().transport.as_deref(), Some("tus"));
+}
+
+#[tokio::test]
+async fn a_final_chunk_with_the_wrong_sha256_discards_the_upload() {
+    use sha2::{Digest, Sha256};
+    let server = TestServer::start().await;
+    let file = contents(2000);
+    let final_chunk = |name: &str, sha256: String| Form::new()
+        .text("filename", name.to_string())
+        .text("chunk_index", "1")
+        .text("total_chunks", "2")
+        .text("file_size", file.len().to_string())
+        .text("sha256", sha256)
+        .part("chunk", Part::bytes(file[1000..].to_vec()).file_name("blob"));
+
+    let receipt: Value = send_chunk(&server, "good.bin", 0, 2, file.len(), &file[..1000]).await.json().await.unwrap();
+    let id = receipt["upload_id"].as_i64().unwrap();
+    let sha256 = format!("{:x}", Sha256::digest(&file));
+    let response = Client::new().post(server.url("/upload")).multipart(final_chunk("good.bin", sha256.clone())).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(server.upload(id).await.unwrap().sha256, Some(sha256));
+
+    let receipt: Value = send_chunk(&server, "bad.bin", 0, 2, file.len(), &file[..1000]).await.json().await.unwrap();
+    let id = receipt["upload_id"].as_i64().unwrap();
+    let response = Client::new().post(server.url("/upload")).multipart(final_chunk("bad.bin", "0".repeat(64))).send().await.unwrap();
+    assert_eq!(response.status(), 422);
+    assert_eq!(response.json::<Value>().await.unwrap()["code"], "hash_mismatch");
+    assert_eq!(server.upload(id).await.unwrap().status, "abandoned");
+    assert!(!server.stored_file("bad.bin").exists());
+}
+
+#[tokio::test]
+async fn chunks_arriving_out_of_order_land_at_their_offsets() {
+    let server = TestServer::start().await;
+    let file = contents(2500);
+    let receipt: Value = send_chunk(&server, "shuffled.bin", 2, 3, file.len(), &file[2000..]).await.json().await.unwrap();
+    assert_eq!(receipt["committed_bytes"], 0);
+    assert_eq!(receipt["next_expected_index"], 0);
+    send_chunk(&server, "shuffled.bin", 1, 3, file.len(), &file[1000..2000]).await;
+    // A resume continues from the end of the run from the start of the file
+    assert_eq!(uploaded_bytes(&server, "shuffled.bin").await, 0);
+
+    // A chunk that doesn't fit against the ones received is refused instead of written
+    let response = send_chunk(&server, "shuffled.bin", 0, 3, file.len(), &file[..900]).await;
+    assert_eq!(response.status(), 409);
+    assert_eq!(response.json::<Value>().await.unwrap()["details"]["rule"], "misplaced");
+
+    let receipt: Value = send_chunk(&server, "shuffled.bin", 0, 3, file.len(), &file[..1000]).await.json().await.unwrap();
+    let id = receipt["upload_id"].as_i64().unwrap();
+    assert_eq!(server.upload(id).await.unwrap().status, "complete");
+    assert_eq!(std::fs::read(server.stored_file("shuffled.bin")).unwrap(), file);
+}
+
+#[tokio::test]
+async fn chunks_sent_in_parallel_complete_the_file() {
+    let server = TestServer::start().await;
+    let file = contents(6000);
+    send_chunk(&server, "wide.bin", 0, 6, file.len(), &file[..1000]).await;
+    let chunk = |index: usize| &file[index * 1000..(index + 1) * 1000];
+    let (a, b, c) = tokio::join!(
+        send_chunk(&server, "wide.bin", 1, 6, file.len(), chunk(1)),
+        send_chunk(&server, "wide.bin", 2, 6, file.len(), chunk(2)),
+        send_chunk(&server, "wide.bin", 4, 6, file.len(), chunk(4)),
+    );
+    assert!([a, b, c].iter().all(|response| response.status().is_success()));
+
+    // A resume is told exactly which chunks are still missing
+    let response = Client::new().head(server.url("/upload?filename=wide.bin")).send().await.unwrap();
+    assert_eq!(response.headers()["x-total-chunks"], "6");
+    assert_eq!(response.headers()["x-missing-chunks"], "3,5");
+
+    send_chunk(&server, "wide.bin", 3, 6, file.len(), chunk(3)).await;
+    let receipt: Value = send_chunk(&server, "wide.bin", 5, 6, file.len(), chunk(5)).await.json().await.unwrap();
+    let id = receipt["upload_id"].as_i64().unwrap();
+    assert_eq!(server.upload(id).await.unwrap().status, "complete");
+    assert_eq!(std::fs::read(server.stored_file("wide.bin")).unwrap(), file);
+}
+
+#[tokio::test]
+async fn an_upload_link_admits_files_within_its_limits() {
+    let server = TestServer::start().await;
+    let link = drcv::db::UploadLink {
+        id: 0,
+        token: "t0ken".to_string(),
+        label: Some("Acme".to_string()),
+        created_at: drcv::utils::now(),
+        expires_at: None,
+        max_bytes: None,
+        max_files: Some(1),
+        allowed_extensions: Some("pdf".to_string()),
+        revoked_at: None,
+    };
+    let link_id = drcv::db::insert_link(&server.pool, &link).await.unwrap();
+    assert_eq!(Client::new().get(server.url("/u/t0ken")).send().await.unwrap().status(), 200);
+    assert_eq!(Client::new().get(server.url("/u/nope")).send().await.unwrap().status(), 404);
+
+    let send = |filename: &'static str, token: &'static str| {
+        let form = Form::new()
+            .text("filename", filename)
+            .text("chunk_index", "0")
+            .text("total_chunks", "1")
+            .text("file_size", "10")
+            .part("chunk", Part::bytes(contents(10)).file_name("blob"));
+        Client::new().post(server.url("/upload")).header("x-drcv-link", token).multipart(form).send()
+    };
+    let refused: Value = send("notes.txt", "t0ken").await.unwrap().json().await.unwrap();
+    assert_eq!(refused["details"]["rule"], "link_extension");
+    assert_eq!(send("report.pdf", "nope").await.unwrap().status(), 404);
+
+    let receipt: Value = send("report.pdf", "t0ken").await.unwrap().json().await.unwrap();
+    let id = receipt["upload_id"].as_i64().unwrap();
+    assert_eq!(server.upload(id).await.unwrap().link_id, Some(link_id));
+
+    let refused: Value = send("another.pdf", "t0ken").await.unwrap().json().await.unwrap();
+    assert_eq!(refused["details"]["rule"], "link_max_files");
+}
+
+#[tokio::test]
+async fn a_link_holds_chunks_without_a_file_size_to_its_max_bytes() {
+    let server = TestServer::start().await;
+    let link = drcv::db::UploadLink {
+        id: 0,
+        token: "budget".to_string(),
+        label: None,
+        created_at: drcv::utils::now(),
+        expires_at: None,
+        max_bytes: Some(15),
+        max_files: None,
+        allowed_extensions: None,
+        revoked_at: None,
+    };
+    drcv::db::insert_link(&server.pool, &link).await.unwrap();
+    let send = |index: u32, with_link: bool| {
+        let form = Form::new()
+            .text("filename", "big.bin")
+            .text("chunk_index", index.to_string())
+            .text("total_chunks", "2")
+            .part("chunk", Part::bytes(contents(10)).file_name("blob"));
+        let request = Client::new().post(server.url("/upload")).multipart(form);
+        if with_link { request.header("x-drcv-link", "budget") } else { request }.send()
+    };
+    assert!(send(0, true).await.unwrap().status().is_success());
+    // Leaving the header off later chunks doesn't get around the link either
+    let refused: Value = send(1, false).await.unwrap().json().await.unwrap();
+    assert_eq!(refused["details"]["rule"], "link_max_bytes");
+}
+
+#[tokio::test]
+async fn checksums_go_to_the_sums_file_of_the_day() {
+    let server = TestServer::start_with(&["--checksum-sidecars"], |_| {}).await;
+    let file = contents(1000);
+    send_chunk(&server, "daily.bin", 0, 1, file.len(), &file).await;
+
+    let sums = server.stored_file(&drcv::checksums::sums_file_name(chrono::Local::now().date_naive()));
+    let mut listed = String::new();
+    for _ in 0..50 {
+        listed = std::fs::read_to_string(&sums).unwrap_or_default();
+        if !listed.is_empty() { break; }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(listed.ends_with("  daily.bin\n"), "{:?}", listed);
+    assert!(!server.stored_file("SHA256SUMS").exists());
+}
+
+#[tokio::test]
+async fn concurrent_retries_of_a_spooled_chunk_each_land_whole() {
+    let spool = std::env::temp_dir().join(format!("drcv-test-spool-{}", std::process::id()));
+    let server = TestServer::start_with(&["--chunk-spool-dir", spool.to_str().unwrap()], |_| {}).await;
+    let file = contents(400_000);
+    let (head, tail) = file.split_at(200_000);
+    let first = send_chunk(&server, "retried.bin", 0, 2, file.len(), head).await;
+    let id = first.json::<Value>().await.unwrap()["upload_id"].as_i64().unwrap();
+
+    let retry = || send_chunk(&server, "retried.bin", 0, 2, file.len(), head);
+    let retries = tokio::join!(retry(), retry(), retry(), retry());
+    for response in [retries.0, retries.1, retries.2, retries.3] {
+        assert!(response.status().is_success(), "{}", response.text().await.unwrap());
+    }
+    let leftovers: Vec<_> = std::fs::read_dir(spool.join(id.to_string())).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(leftovers, ["00000000.chunk"]);
+
+    send_chunk(&server, "retried.bin", 1, 2, file.len(), tail).await;
+    drcv::assembly::assemble_spooled_uploads(&server.pool, &server.config).await;
+    assert_eq!(std::fs::read(server.stored_file("retried.bin")).unwrap(), file);
+    let _ = std::fs::remove_dir_all(&spool);
+}
+
+#[tokio::test]
+async fn a_chunk_for_another_instance_is_redirected_before_its_body_is_read() {
+    let server = TestServer::start_with(&["--instance-id", "a"], |_| {}).await;
+    drcv::db::touch_instance(&server.pool, "b", Some("http://b.internal:8080")).await;
+    drcv::db::init_upload(&server.pool, "held.bin", "127.0.0.1", Some("b"), "http").await;
+
+    // Not a multipart body at all, so reading it would have failed the request
+    let response = Client::builder().redirect(reqwest::redirect::Policy::none()).build().unwrap()
+        .post(server.url("/upload?filename=held.bin"))
+        .header("content-type", "multipart/form-data; boundary=x")
+        .body("never read")
+        .send().await.unwrap();
+    assert_eq!(response.status(), 307);
+    assert_eq!(response.headers()["location"], "http://b.internal:8080/upload?filename=held.bin");
+}